@@ -1,11 +1,22 @@
 pub(crate) mod alighned_box;
+pub(crate) mod capsule;
+pub(crate) mod cone;
+pub(crate) mod cylinder;
 pub(crate) mod disk;
 pub(crate) mod matrix;
 pub(crate) mod normal;
+pub(crate) mod oriented_box;
 pub(crate) mod plane;
 pub(crate) mod point;
+pub(crate) mod quad;
+pub(crate) mod quaternion;
 pub(crate) mod ray;
+pub(crate) mod ray_packet;
+pub(crate) mod sdf;
+pub(crate) mod shape;
 pub(crate) mod sphere;
+pub(crate) mod torus;
+pub(crate) mod transform;
 pub(crate) mod triangle;
 pub(crate) mod vector;
 
@@ -13,12 +24,18 @@ use matrix::Matrix;
 use normal::Normal;
 use point::Point;
 use ray::Ray;
+use transform::Transform3;
 use vector::Vector;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum Intersection {
     Intersect(f64),
     TriangleIntesersect(f64, f64, f64),
+    // Distance, index of the hit primitive inside of the instanced mesh and its barycentrics.
+    InstanceIntersect(f64, usize, f64, f64),
+    // Distance, index of the hit solid inside of the CSG tree and whether its surface
+    // is turned inside out.
+    CsgIntersect(f64, usize, bool),
 }
 
 impl Intersection {
@@ -26,16 +43,78 @@ impl Intersection {
         match *self {
             Intersection::Intersect(distance) => distance,
             Intersection::TriangleIntesersect(distance, _, _) => distance,
+            Intersection::InstanceIntersect(distance, _, _, _) => distance,
+            Intersection::CsgIntersect(distance, _, _) => distance,
         }
     }
+
+    pub(crate) fn with_distance(self, distance: f64) -> Intersection {
+        match self {
+            Intersection::Intersect(_) => Intersection::Intersect(distance),
+            Intersection::TriangleIntesersect(_, u, v) => {
+                Intersection::TriangleIntesersect(distance, u, v)
+            }
+            Intersection::InstanceIntersect(_, primitive, u, v) => {
+                Intersection::InstanceIntersect(distance, primitive, u, v)
+            }
+            Intersection::CsgIntersect(_, solid, flipped) => {
+                Intersection::CsgIntersect(distance, solid, flipped)
+            }
+        }
+    }
+}
+
+// The closest of the hits in front of the ray origin.
+pub(crate) fn closest_hit(distances: impl IntoIterator<Item = f64>) -> Option<f64> {
+    distances
+        .into_iter()
+        .filter(|&distance| distance > 0.0)
+        .min_by(f64::total_cmp)
+}
+
+// Part of the ray inside of a solid, between the distances where it enters and leaves it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Interval {
+    pub(crate) enter: f64,
+    pub(crate) exit: f64,
+}
+
+// The chord of the convex solid, between the first and the last crossing of its surface.
+pub(crate) fn convex_interval(distances: impl IntoIterator<Item = f64>) -> Vec<Interval> {
+    let (enter, exit) = distances.into_iter().fold(
+        (f64::INFINITY, f64::NEG_INFINITY),
+        |(enter, exit), distance| (enter.min(distance), exit.max(distance)),
+    );
+    if enter < exit {
+        vec![Interval { enter, exit }]
+    } else {
+        vec![]
+    }
 }
 
 pub(crate) trait Intersect {
     fn intersect(&self, ray: &Ray) -> Option<Intersection>;
+
+    // All parts of the ray inside of the solid in the ascending order, also the ones
+    // behind the origin. Surfaces that don't enclose any volume have none.
+    fn intervals(&self, _: &Ray) -> Vec<Interval> {
+        vec![]
+    }
+
+    // Whether anything is hit closer than the distance, the shadow rays need no more.
+    fn occluded(&self, ray: &Ray, max_distance: f64) -> bool {
+        self.intersect(ray)
+            .is_some_and(|intersection| intersection.distance() < max_distance)
+    }
 }
 
 pub(crate) trait NormalAtPoint {
     fn normal_at_point(&self, point: &Point, intersection: Intersection) -> Normal;
+
+    // Normal of the surface itself, the rays leaving it are offset along it.
+    fn geometric_normal(&self, point: &Point, intersection: Intersection) -> Normal {
+        self.normal_at_point(point, intersection)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -45,14 +124,29 @@ pub(crate) enum Axis {
     Z,
 }
 
-#[derive(Clone, Copy)]
+// Transformations are applied once, not per ray, so the size of the composite doesn't matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum Transformation {
     Translation(Vector),
     Rotation(Axis, f64),
     Scale(Vector),
+    // Any number of them composed into one.
+    Composite(Transform3),
 }
 
 impl Transformation {
+    pub(crate) fn inverse(&self) -> Transformation {
+        match *self {
+            Transformation::Translation(vector) => Transformation::Translation(-vector),
+            Transformation::Rotation(axis, angle) => Transformation::Rotation(axis, -angle),
+            Transformation::Scale(vector) => {
+                Transformation::Scale(Vector::new(1.0 / vector.x, 1.0 / vector.y, 1.0 / vector.z))
+            }
+            Transformation::Composite(transform) => Transformation::Composite(transform.inverse()),
+        }
+    }
+
     pub(crate) fn transformation_to_matrix(&self) -> Matrix<4, 4> {
         match *self {
             Transformation::Rotation(axis, angle) => match axis {
@@ -62,6 +156,7 @@ impl Transformation {
             },
             Transformation::Translation(vector) => Matrix::<4, 4>::translation(vector),
             Transformation::Scale(vector) => Matrix::<4, 4>::scale(vector),
+            Transformation::Composite(transform) => *transform.matrix(),
         }
     }
 }
@@ -2,11 +2,14 @@ use crate::basic_geometry::vector::Vector;
 use crate::basic_geometry::Normal;
 use crate::basic_geometry::Point;
 use crate::basic_geometry::Ray;
+use crate::basic_types::simd::{F64x4, Mask4};
 use crate::complex_structures::BoundingBox;
 
+use super::transform::Transform3;
 use super::Axis;
 use super::Intersect;
 use super::Intersection;
+use super::Interval;
 use super::NormalAtPoint;
 use super::Transform;
 
@@ -33,6 +36,13 @@ impl AlighnedBox {
         AlighnedBox::new((center - size_vector).into(), (center + size_vector).into())
     }
 
+    // Infinite primitives, like the planes, have no finite bounds.
+    pub(crate) fn is_bounded(&self) -> bool {
+        [self.min, self.max]
+            .iter()
+            .all(|p| p.x.is_finite() && p.y.is_finite() && p.z.is_finite())
+    }
+
     pub(crate) fn center(&self) -> Point {
         Point::from((Vector::from(self.min) + Vector::from(self.max)) / 2.)
     }
@@ -52,6 +62,33 @@ impl AlighnedBox {
         )
     }
 
+    pub(crate) fn corners(&self) -> [Point; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Point::new(min.x, min.y, min.z),
+            Point::new(min.x, min.y, max.z),
+            Point::new(min.x, max.y, min.z),
+            Point::new(min.x, max.y, max.z),
+            Point::new(max.x, min.y, min.z),
+            Point::new(max.x, min.y, max.z),
+            Point::new(max.x, max.y, min.z),
+            Point::new(max.x, max.y, max.z),
+        ]
+    }
+
+    // Common part of the boxes, if they overlap.
+    pub(crate) fn intersection(&self, other: &AlighnedBox) -> Option<AlighnedBox> {
+        let mut result = *self;
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            result.min[axis] = self.min[axis].max(other.min[axis]);
+            result.max[axis] = self.max[axis].min(other.max[axis]);
+            if result.min[axis] > result.max[axis] {
+                return None;
+            }
+        }
+        Some(result)
+    }
+
     pub(crate) fn union_point(&self, other: Point) -> AlighnedBox {
         AlighnedBox::new(other, other).union(self)
     }
@@ -83,83 +120,163 @@ impl AlighnedBox {
         o
     }
 
-    pub(crate) fn surface_area(&self) -> f64 {
-        let d = self.max - self.min;
-        2. * (d.x * d.y + d.x * d.z + d.y * d.z)
+    // Distance along the ray where it enters the box, or zero if it starts inside.
+    // `inverse_direction` is `1 / direction` per axis, computed once per ray.
+    // Boxes hit only behind the origin or farther than `max_distance` are skipped.
+    pub(crate) fn entry_distance(
+        &self,
+        ray: &Ray,
+        inverse_direction: Vector,
+        max_distance: f64,
+    ) -> Option<f64> {
+        self.ray_interval(ray, inverse_direction, max_distance)
+            .map(|(entry, _)| entry)
     }
-}
 
-impl Intersect for AlighnedBox {
-    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
-        let mut tmin = (self.min.x - ray.origin.x) / ray.direction.x;
-        let mut tmax = (self.max.x - ray.origin.x) / ray.direction.x;
+    // Part of the ray between zero and `max_distance` inside of the box, the entry
+    // and the exit distance. The boxes behind the origin are missed.
+    pub(crate) fn ray_interval(
+        &self,
+        ray: &Ray,
+        inverse_direction: Vector,
+        max_distance: f64,
+    ) -> Option<(f64, f64)> {
+        self.slabs(ray, inverse_direction, 0.0, max_distance)
+    }
 
-        let swap_tmin_tmax = |tmin: &mut f64, tmax: &mut f64| {
-            if tmin > tmax {
-                std::mem::swap(tmin, tmax);
+    // The slabs of the three axes clipped to the part of the ray from `entry` to `exit`.
+    fn slabs(
+        &self,
+        ray: &Ray,
+        inverse_direction: Vector,
+        mut entry: f64,
+        mut exit: f64,
+    ) -> Option<(f64, f64)> {
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            // A parallel ray has to start in the slab, the planes of the slab belong to it.
+            if inverse_direction[axis].is_infinite() {
+                let origin = ray.origin[axis];
+                if origin < self.min[axis] || origin > self.max[axis] {
+                    return None;
+                }
+                continue;
             }
-        };
-        swap_tmin_tmax(&mut tmin, &mut tmax);
-
-        let mut tymin = (self.min.y - ray.origin.y) / ray.direction.y;
-        let mut tymax = (self.max.y - ray.origin.y) / ray.direction.y;
-
-        swap_tmin_tmax(&mut tymin, &mut tymax);
-        if tmin > tymax || tymin > tmax {
-            return None;
+            let t1 = (self.min[axis] - ray.origin[axis]) * inverse_direction[axis];
+            let t2 = (self.max[axis] - ray.origin[axis]) * inverse_direction[axis];
+            // `max` and `min` would skip the slab, like the lanes of `Boxes4`, it's missed.
+            if t1.is_nan() || t2.is_nan() {
+                return None;
+            }
+            entry = entry.max(t1.min(t2));
+            exit = exit.min(t1.max(t2));
         }
-
-        if tymin > tmin {
-            tmin = tymin;
+        if entry <= exit {
+            Some((entry, exit))
+        } else {
+            None
         }
+    }
 
-        if tymax < tmax {
-            tmax = tymax;
-        }
+    pub(crate) fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        2. * (d.x * d.y + d.x * d.z + d.y * d.z)
+    }
+}
 
-        let mut tzmin = (self.min.z - ray.origin.z) / ray.direction.z;
-        let mut tzmax = (self.max.z - ray.origin.z) / ray.direction.z;
+// Four boxes in the lanes, tested against one ray or four rays at once.
+#[derive(Clone, Copy)]
+pub(crate) struct Boxes4 {
+    pub(crate) min: [F64x4; 3],
+    pub(crate) max: [F64x4; 3],
+}
 
-        swap_tmin_tmax(&mut tzmin, &mut tzmax);
-        if tmin > tzmax || tzmin > tmax {
-            return None;
-        }
-        if tzmin > tmin {
-            tmin = tzmin;
+impl Boxes4 {
+    // The same box in all the lanes.
+    pub(crate) fn splat(bounds: &AlighnedBox) -> Boxes4 {
+        let lanes = |point: Point| [point.x, point.y, point.z].map(F64x4::splat);
+        Boxes4 {
+            min: lanes(bounds.min),
+            max: lanes(bounds.max),
         }
+    }
 
-        if tmin.is_infinite() || tmin.is_nan() {
-            None
-        } else {
-            Some(Intersection::Intersect(tmin))
+    // `ray_interval` of every lane: the lanes where the ray passes through the box
+    // between zero and `max_distance`, and the entry distances.
+    pub(crate) fn ray_intervals(
+        &self,
+        origin: &[F64x4; 3],
+        inverse_direction: &[F64x4; 3],
+        max_distance: F64x4,
+    ) -> (Mask4, F64x4) {
+        let infinity = F64x4::splat(f64::INFINITY);
+        let negative_infinity = F64x4::splat(f64::NEG_INFINITY);
+        let mut entry = F64x4::splat(0.0);
+        let mut exit = max_distance;
+        let mut valid = Mask4::from_bits(0b1111);
+        for axis in 0..3 {
+            let t1 = (self.min[axis] - origin[axis]) * inverse_direction[axis];
+            let t2 = (self.max[axis] - origin[axis]) * inverse_direction[axis];
+            let (mut near, mut far) = (t1.min(t2), t1.max(t2));
+            // A parallel ray has to start in the slab, the planes of the slab belong to it.
+            let parallel = inverse_direction[axis].abs().eq(infinity);
+            if parallel.any() {
+                let inside = origin[axis].ge(self.min[axis]) & origin[axis].le(self.max[axis]);
+                near = parallel.select(inside.select(negative_infinity, infinity), near);
+                far = parallel.select(inside.select(infinity, negative_infinity), far);
+            }
+            valid = valid & (parallel | (t1.eq(t1) & t2.eq(t2)));
+            entry = entry.max(near);
+            exit = exit.min(far);
         }
+        (valid & entry.le(exit), entry)
+    }
+}
+
+impl Intersect for AlighnedBox {
+    // The entry into the box, or the exit if the ray starts inside.
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (entry, exit) = self.ray_interval(ray, ray.inverse_direction(), f64::INFINITY)?;
+        let distance = if entry > 0.0 { entry } else { exit };
+        (distance > 0.0 && distance.is_finite()).then_some(Intersection::Intersect(distance))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let slabs = self.slabs(
+            ray,
+            ray.inverse_direction(),
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+        );
+        slabs
+            .filter(|(enter, exit)| enter < exit)
+            .map(|(enter, exit)| Interval { enter, exit })
+            .into_iter()
+            .collect()
     }
 }
 
 impl NormalAtPoint for AlighnedBox {
+    // Normal of the face closest to the point, the hit point is off the face by the rounding.
     fn normal_at_point(&self, point: &Point, _: Intersection) -> Normal {
-        if (point.x - self.min.x).abs() < 0.001 {
-            Normal::new(-1., 0., 0.)
-        } else if (point.x - self.max.x).abs() < 0.001 {
-            Normal::new(1., 0., 0.)
-        } else if (point.y - self.min.y).abs() < 0.001 {
-            Normal::new(0., -1., 0.)
-        } else if (point.y - self.max.y).abs() < 0.001 {
-            Normal::new(0., 1., 0.)
-        } else if (point.z - self.min.z).abs() < 0.001 {
-            Normal::new(0., 0., -1.)
-        } else if (point.z - self.max.z).abs() < 0.001 {
-            Normal::new(0., 0., 1.)
-        } else {
-            panic!("point is not in the box {:?}", point);
+        let faces = [Axis::X, Axis::Y, Axis::Z].into_iter().flat_map(|axis| {
+            [
+                (axis, -1.0, (point[axis] - self.min[axis]).abs()),
+                (axis, 1.0, (point[axis] - self.max[axis]).abs()),
+            ]
+        });
+        let (axis, sign, _) = faces.min_by(|a, b| a.2.total_cmp(&b.2)).unwrap();
+        match axis {
+            Axis::X => Normal::new(sign, 0., 0.),
+            Axis::Y => Normal::new(0., sign, 0.),
+            Axis::Z => Normal::new(0., 0., sign),
         }
     }
 }
 
 impl Default for AlighnedBox {
     fn default() -> AlighnedBox {
-        let smallest = std::f64::MIN;
-        let largest = std::f64::MAX;
+        let smallest = f64::MIN;
+        let largest = f64::MAX;
         AlighnedBox::new(
             Point::new(largest, largest, largest),
             Point::new(smallest, smallest, smallest),
@@ -168,10 +285,9 @@ impl Default for AlighnedBox {
 }
 
 impl Transform for AlighnedBox {
+    // Box around the moved corners, it stays aligned with the axes after the rotation.
     fn transform(&mut self, tranform: super::Transformation) {
-        let matrix = tranform.transformation_to_matrix();
-        self.min = matrix * self.min;
-        self.max = matrix * self.max;
+        *self = Transform3::from(tranform).bounding_box(self);
     }
 }
 
@@ -184,6 +300,7 @@ impl BoundingBox for AlighnedBox {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::basic_types::random::random;
 
     #[test]
     fn intersection_test() {
@@ -192,4 +309,218 @@ mod tests {
         let t = box_.intersect(&ray);
         assert_eq!(t, Some(Intersection::Intersect(10.)));
     }
+
+    #[test]
+    fn lanes_match_ray_interval() {
+        let box_ = AlighnedBox::from_dimensions(Point::new(0., 0., 0.), 10., 10., 10.);
+        let boxes = Boxes4::splat(&box_);
+        let rays = [
+            Ray::new(Point::new(5., -20., 5.), Normal::new(0., 1., 0.)),
+            // Parallel to the faces, on one of them and outside.
+            Ray::new(Point::new(0., -20., 10.), Normal::new(0., 1., 0.)),
+            Ray::new(Point::new(-1., -20., 5.), Normal::new(0., 1., 0.)),
+            // Starts inside, going diagonally.
+            Ray::new(Point::new(3., 4., 5.), Vector::new(1., -1., 1.).normalize()),
+            Ray::new(
+                Point::new(-5., 15., 5.),
+                Vector::new(1., 1., 0.).normalize(),
+            ),
+        ];
+        for max_distance in [5.0, f64::INFINITY] {
+            for ray in rays {
+                let inverse = ray.inverse_direction();
+                let origin = [ray.origin.x, ray.origin.y, ray.origin.z].map(F64x4::splat);
+                let inverse_direction = [inverse.x, inverse.y, inverse.z].map(F64x4::splat);
+                let (hit, entry) =
+                    boxes.ray_intervals(&origin, &inverse_direction, F64x4::splat(max_distance));
+                let expected = box_.ray_interval(&ray, inverse, max_distance);
+                assert_eq!(hit.bits(), if expected.is_some() { 0b1111 } else { 0 });
+                if let Some((expected, _)) = expected {
+                    assert_eq!(entry.lane(2), expected);
+                }
+            }
+        }
+    }
+
+    // Random box around the origin and points inside of it and around it.
+    fn cases(seed: u64, count: usize) -> Vec<(AlighnedBox, Point, Point)> {
+        let mut random = random(seed);
+        let mut point = |scale: f64| {
+            Point::new(
+                (random() - 0.5) * scale,
+                (random() - 0.5) * scale,
+                (random() - 0.5) * scale,
+            )
+        };
+        (0..count)
+            .map(|_| {
+                let (a, b) = (point(20.), point(20.));
+                let bounds = AlighnedBox::default().union_point(a).union_point(b);
+                let (size, t) = (bounds.max - bounds.min, point(0.8));
+                let inside = bounds.min
+                    + Vector::new(
+                        size.x * (t.x + 0.5),
+                        size.y * (t.y + 0.5),
+                        size.z * (t.z + 0.5),
+                    );
+                (bounds, inside, point(60.))
+            })
+            .collect()
+    }
+
+    fn contains(bounds: &AlighnedBox, point: Point, tolerance: f64) -> bool {
+        [Axis::X, Axis::Y, Axis::Z].into_iter().all(|axis| {
+            point[axis] >= bounds.min[axis] - tolerance
+                && point[axis] <= bounds.max[axis] + tolerance
+        })
+    }
+
+    #[test]
+    fn rays_towards_the_inside_hit() {
+        for (bounds, inside, origin) in cases(0x9e37_79b9_7f4a_7c15, 2000) {
+            let ray = Ray::new(origin, (inside - origin).normalize());
+            let distance = (inside - origin).length();
+            let (entry, exit) = bounds
+                .ray_interval(&ray, ray.inverse_direction(), f64::INFINITY)
+                .unwrap();
+            assert!(entry <= distance + 1e-9 && distance <= exit + 1e-9);
+            assert!(contains(&bounds, ray.at((entry + exit) * 0.5), 1e-9));
+            if contains(&bounds, origin, 0.0) {
+                assert_eq!(entry, 0.0);
+            } else {
+                assert!(contains(&bounds, ray.at(entry), 1e-9));
+                assert!(!contains(&bounds, ray.at(entry * 0.99), 0.0));
+            }
+            assert!(contains(&bounds, ray.at(exit), 1e-9));
+            // Closer than the box.
+            if entry > 0.0 {
+                let before = bounds.ray_interval(&ray, ray.inverse_direction(), entry * 0.99);
+                assert!(before.is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn boxes_behind_the_ray_are_missed() {
+        for (bounds, inside, origin) in cases(0x2545_f491_4f6c_dd1d, 2000) {
+            if contains(&bounds, origin, 0.0) {
+                continue;
+            }
+            let ray = Ray::new(origin, (origin - inside).normalize());
+            assert!(bounds
+                .ray_interval(&ray, ray.inverse_direction(), f64::INFINITY)
+                .is_none());
+            assert!(bounds.intersect(&ray).is_none());
+        }
+    }
+
+    #[test]
+    fn axis_parallel_rays() {
+        let bounds = AlighnedBox::new(Point::new(-1., 2., 0.), Point::new(3., 5., 4.));
+        let mut random = random(0xd1b5_4a32_d192_ed03);
+        let axes = [Axis::X, Axis::Y, Axis::Z];
+        for _ in 0..3000 {
+            // Coordinates on the planes of the box, inside of the slabs and outside.
+            let mut origin = Point::new(0., 0., 0.);
+            for axis in axes {
+                let (min, max) = (bounds.min[axis], bounds.max[axis]);
+                origin[axis] = match (random() * 4.) as usize {
+                    0 => min,
+                    1 => max,
+                    2 => min + (max - min) * random(),
+                    _ => min + (max - min) * (random() * 3. - 1.),
+                };
+            }
+            let axis = axes[(random() * 3.) as usize];
+            let sign = if random() < 0.5 { -1. } else { 1. };
+            // Negative zeros in the other components.
+            let mut direction = Point::new(-0., -0., -0.);
+            direction[axis] = sign;
+            let ray = Ray::new(origin, Vector::from(direction).normalize());
+            let in_slabs = axes
+                .into_iter()
+                .filter(|&other| other != axis)
+                .all(|other| {
+                    bounds.min[other] <= origin[other] && origin[other] <= bounds.max[other]
+                });
+            let ahead = if sign > 0. {
+                origin[axis] <= bounds.max[axis]
+            } else {
+                origin[axis] >= bounds.min[axis]
+            };
+            let interval = bounds.ray_interval(&ray, ray.inverse_direction(), f64::INFINITY);
+            assert_eq!(interval.is_some(), in_slabs && ahead, "{:?}", ray);
+            if let Some((entry, exit)) = interval {
+                let (near, far) = if sign > 0. {
+                    (
+                        bounds.min[axis] - origin[axis],
+                        bounds.max[axis] - origin[axis],
+                    )
+                } else {
+                    (
+                        origin[axis] - bounds.max[axis],
+                        origin[axis] - bounds.min[axis],
+                    )
+                };
+                assert_eq!((entry, exit), (near.max(0.), far));
+            }
+        }
+    }
+
+    #[test]
+    fn box_as_primitive() {
+        for (bounds, inside, origin) in cases(0x8cb9_2ba7_2f3d_8dd7, 2000) {
+            let ray = Ray::new(origin, (inside - origin).normalize());
+            let (entry, exit) = bounds
+                .ray_interval(&ray, ray.inverse_direction(), f64::INFINITY)
+                .unwrap();
+            let intersection = bounds.intersect(&ray).unwrap();
+            let normal = bounds.normal_at_point(&ray.at(intersection.distance()), intersection);
+            let facing = normal.dot(ray.direction);
+            if contains(&bounds, origin, 0.0) {
+                // Seen from the inside, the ray leaves through the far face.
+                assert_eq!(intersection.distance(), exit);
+                assert!(facing >= 0.);
+            } else {
+                assert_eq!(intersection.distance(), entry);
+                assert!(facing <= 0.);
+            }
+        }
+        let bounds = AlighnedBox::new(Point::new(0., 0., 0.), Point::new(1., 1., 1.));
+        let mut nan = Ray::new(Point::new(0.5, 0.5, -1.), Normal::new(0., 0., 1.));
+        nan.direction.x = f64::NAN;
+        assert!(bounds.intersect(&nan).is_none());
+    }
+
+    #[test]
+    fn lanes_match_random_boxes() {
+        let cases = cases(0x94d0_49bb_1331_11eb, 400);
+        for chunk in cases.chunks(4) {
+            let boxes = Boxes4 {
+                min: [Axis::X, Axis::Y, Axis::Z]
+                    .map(|axis| F64x4::new(std::array::from_fn(|i| chunk[i].0.min[axis]))),
+                max: [Axis::X, Axis::Y, Axis::Z]
+                    .map(|axis| F64x4::new(std::array::from_fn(|i| chunk[i].0.max[axis]))),
+            };
+            for &(_, inside, origin) in chunk {
+                for target in [inside, origin + (origin - inside)] {
+                    let ray = Ray::new(origin, (target - origin).normalize());
+                    let inverse = ray.inverse_direction();
+                    let lanes = |v: Vector| [v.x, v.y, v.z].map(F64x4::splat);
+                    let (hit, entry) = boxes.ray_intervals(
+                        &lanes(Vector::from(origin)),
+                        &lanes(inverse),
+                        F64x4::splat(50.),
+                    );
+                    for (lane, (bounds, ..)) in chunk.iter().enumerate() {
+                        let expected = bounds.ray_interval(&ray, inverse, 50.);
+                        assert_eq!(hit.test(lane), expected.is_some());
+                        if let Some((expected, _)) = expected {
+                            assert_eq!(entry.lane(lane), expected);
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,202 @@
+use std::f64::consts::PI;
+
+use super::alighned_box::AlighnedBox;
+use super::transform::Transform3;
+use super::{
+    closest_hit, convex_interval, Intersect, Intersection, Interval, NormalAtPoint, Transform,
+    Transformation,
+};
+use crate::basic_geometry::normal::Normal;
+use crate::basic_geometry::point::Point;
+use crate::basic_geometry::ray::Ray;
+use crate::basic_geometry::vector::Vector;
+use crate::basic_types::polynomial::solve_quadratic;
+use crate::complex_structures::BoundingBox;
+
+// Points within the unit distance of the segment from the origin to `height` on the
+// y axis of its own space, placed in the world by the transform. Uniformly scaled the
+// hemispheres stay round, so the height is kept instead of stretching the shape.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Capsule {
+    // Object to world transform.
+    transform: Transform3,
+    height: f64,
+}
+
+impl Capsule {
+    // `None` if the radius isn't positive, with the same ends it's a sphere.
+    pub(crate) fn new(a: Point, b: Point, radius: f64) -> Option<Capsule> {
+        if radius.is_nan() || radius <= 0. {
+            return None;
+        }
+        let axis = b - a;
+        let rotation =
+            Transform3::from_to(Vector::new(0., 1., 0.), axis).unwrap_or(Transform3::identity());
+        let scale = Transformation::Scale(Vector::new(radius, radius, radius));
+        Some(Capsule {
+            transform: Transform3::translation(Vector::from(a))
+                * rotation
+                * Transform3::from(scale),
+            height: axis.length() / radius,
+        })
+    }
+
+    // The angle around the axis and the height from the bottom to the top of the caps.
+    pub(crate) fn uv_at_point(&self, point: &Point) -> (f64, f64) {
+        let local = self.transform.inverse().point(*point);
+        let u = 0.5 + local.z.atan2(local.x) / (2. * PI);
+        let v = ((local.y + 1.) / (self.height + 2.)).clamp(0., 1.);
+        (u, v)
+    }
+
+    // Distances of all crossings of the surface, also behind the origin.
+    fn crossings(&self, ray: &Ray) -> Vec<f64> {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        let (o, d) = (local.origin, local.direction);
+        let height = self.height;
+        let radial = (o.x * o.x + o.z * o.z).sqrt();
+        let side = solve_quadratic(
+            d.x * d.x + d.z * d.z,
+            2. * (o.x * d.x + o.z * d.z),
+            (radial - 1.) * (radial + 1.),
+        )
+        .into_iter()
+        .filter(|&t| (0. ..=height).contains(&(o.y + t * d.y)));
+        // Each sphere only counts on its own side of the tube.
+        let caps = [(0., true), (height, false)]
+            .into_iter()
+            .flat_map(|(y, bottom)| {
+                let k = Vector::new(o.x, o.y - y, o.z);
+                let distance = k.length();
+                solve_quadratic(
+                    1.,
+                    2. * k.dot(Vector::from(d)),
+                    (distance - 1.) * (distance + 1.),
+                )
+                .into_iter()
+                .filter(move |&t| {
+                    let hit = o.y + t * d.y;
+                    if bottom {
+                        hit <= 0.
+                    } else {
+                        hit >= y
+                    }
+                })
+            });
+        side.chain(caps).map(|t| t / scale).collect()
+    }
+}
+
+impl Intersect for Capsule {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        closest_hit(self.crossings(ray)).map(Intersection::Intersect)
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        convex_interval(self.crossings(ray))
+    }
+}
+
+impl NormalAtPoint for Capsule {
+    // From the closest point of the segment.
+    fn normal_at_point(&self, point: &Point, _: Intersection) -> Normal {
+        let local = self.transform.inverse().point(*point);
+        let axis = Point::new(0., local.y.clamp(0., self.height), 0.);
+        self.transform.normal((local - axis).normalize())
+    }
+}
+
+impl Transform for Capsule {
+    fn transform(&mut self, transformation: Transformation) {
+        self.transform = Transform3::from(transformation) * self.transform;
+    }
+}
+
+impl BoundingBox for Capsule {
+    fn bounding_box(&self) -> AlighnedBox {
+        let local = AlighnedBox::new(
+            Point::new(-1., -1., -1.),
+            Point::new(1., self.height + 1., 1.),
+        );
+        self.transform.bounding_box(&local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::close;
+
+    fn capsule() -> Capsule {
+        Capsule::new(Point::new(0., 0., 0.), Point::new(4., 0., 0.), 0.5).unwrap()
+    }
+
+    #[test]
+    fn tube_intersection() {
+        let capsule = capsule();
+        let ray = Ray::new(Point::new(2., 5., 0.), Normal::new(0., -1., 0.));
+        let intersection = capsule.intersect(&ray).unwrap();
+        assert!((intersection.distance() - 4.5).abs() < 1e-9);
+        let normal = capsule.normal_at_point(&ray.at(4.5), intersection);
+        assert!(close(Vector::from(normal), Vector::new(0., 1., 0.)));
+    }
+
+    #[test]
+    fn cap_intersection() {
+        let capsule = capsule();
+        let ray = Ray::new(Point::new(-5., 0., 0.), Normal::new(1., 0., 0.));
+        let intersection = capsule.intersect(&ray).unwrap();
+        assert!((intersection.distance() - 4.5).abs() < 1e-9);
+        let normal = capsule.normal_at_point(&ray.at(4.5), intersection);
+        assert!(close(Vector::from(normal), Vector::new(-1., 0., 0.)));
+    }
+
+    #[test]
+    fn intersection_from_inside_the_cap() {
+        let ray = Ray::new(Point::new(4.2, 0., 0.), Normal::new(1., 0., 0.));
+        assert!((capsule().intersect(&ray).unwrap().distance() - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn round_end() {
+        // Next to the end, where the box of the tube would still be hit.
+        let ray = Ray::new(Point::new(4.45, 5., 0.), Normal::new(0., -1., 0.));
+        let expected = 5. - (0.25f64 - 0.45 * 0.45).sqrt();
+        assert!((capsule().intersect(&ray).unwrap().distance() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn passes_the_round_end() {
+        let ray = Ray::new(Point::new(4.45, 5., 0.45), Normal::new(0., -1., 0.));
+        assert_eq!(capsule().intersect(&ray), None);
+    }
+
+    #[test]
+    fn bounds() {
+        let capsule = Capsule::new(Point::new(1., 1., 1.), Point::new(1., 3., 1.), 1.).unwrap();
+        let bounds = capsule.bounding_box();
+        assert!(close(Vector::from(bounds.min), Vector::new(0., 0., 0.)));
+        assert!(close(Vector::from(bounds.max), Vector::new(2., 4., 2.)));
+    }
+
+    #[test]
+    fn uv_halfway_along_the_tube() {
+        let capsule = Capsule::new(Point::new(1., 1., 1.), Point::new(1., 3., 1.), 1.).unwrap();
+        let (_, v) = capsule.uv_at_point(&Point::new(2., 2., 1.));
+        assert!((v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn degenerate_input() {
+        let a = Point::new(0., 0., 0.);
+        assert!(Capsule::new(a, Point::new(1., 0., 0.), 0.).is_none());
+    }
+
+    #[test]
+    fn same_ends_give_sphere() {
+        let a = Point::new(0., 0., 0.);
+        let sphere = Capsule::new(a, a, 2.).unwrap();
+        let ray = Ray::new(Point::new(0., 10., 0.), Normal::new(0., -1., 0.));
+        assert!((sphere.intersect(&ray).unwrap().distance() - 8.).abs() < 1e-9);
+    }
+}
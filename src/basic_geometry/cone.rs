@@ -0,0 +1,187 @@
+use std::f64::consts::PI;
+
+use super::alighned_box::AlighnedBox;
+use super::transform::Transform3;
+use super::{
+    closest_hit, convex_interval, Intersect, Intersection, Interval, NormalAtPoint, Transform,
+    Transformation,
+};
+use crate::basic_geometry::normal::Normal;
+use crate::basic_geometry::point::Point;
+use crate::basic_geometry::ray::Ray;
+use crate::basic_geometry::vector::Vector;
+use crate::basic_types::polynomial::solve_quadratic;
+use crate::complex_structures::BoundingBox;
+
+// Cone with the unit base at y = 0 and the apex at y = 1 in its own space,
+// placed in the world by the transform. The base is closed by the disk if capped.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Cone {
+    // Object to world transform.
+    transform: Transform3,
+    capped: bool,
+}
+
+impl Cone {
+    // `None` if the apex is on the base or the radius isn't positive.
+    pub(crate) fn new(base: Point, apex: Point, radius: f64, capped: bool) -> Option<Cone> {
+        Transform3::along_segment(base, apex, radius).map(|transform| Cone { transform, capped })
+    }
+
+    // Only with the base it encloses the volume.
+    pub(crate) fn capped(&self) -> bool {
+        self.capped
+    }
+
+    // The angle around the axis and the height on the side, the position on the
+    // unit square on the base.
+    pub(crate) fn uv_at_point(&self, point: &Point) -> (f64, f64) {
+        let local = self.transform.inverse().point(*point);
+        if self.on_base(local) {
+            ((local.x + 1.) / 2., (local.z + 1.) / 2.)
+        } else {
+            (0.5 + local.z.atan2(local.x) / (2. * PI), local.y)
+        }
+    }
+
+    // Whether the local point is closer to the base than to the side.
+    fn on_base(&self, local: Point) -> bool {
+        let radial = (local.x * local.x + local.z * local.z).sqrt();
+        // Distance to the side along its normal (1, 1) / sqrt(2).
+        let side = (radial - (1. - local.y)).abs() / 2f64.sqrt();
+        self.capped && local.y.abs() < side
+    }
+
+    // Distances of all crossings of the surface, also behind the origin.
+    fn crossings(&self, ray: &Ray) -> Vec<f64> {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        let (o, d) = (local.origin, local.direction);
+        // x^2 + z^2 = (1 - y)^2, the other nappe above the apex is cut off by the height.
+        let h = 1. - o.y;
+        let side = solve_quadratic(
+            d.x * d.x + d.z * d.z - d.y * d.y,
+            2. * (o.x * d.x + o.z * d.z + h * d.y),
+            o.x * o.x + o.z * o.z - h * h,
+        )
+        .into_iter()
+        .filter(|&t| (0. ..=1.).contains(&(o.y + t * d.y)));
+        let base = (self.capped && d.y != 0.).then(|| -o.y / d.y).filter(|&t| {
+            let (x, z) = (o.x + t * d.x, o.z + t * d.z);
+            x * x + z * z <= 1.
+        });
+        side.chain(base).map(|t| t / scale).collect()
+    }
+}
+
+impl Intersect for Cone {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        closest_hit(self.crossings(ray)).map(Intersection::Intersect)
+    }
+
+    // Without the caps it has no inside.
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        if !self.capped {
+            return vec![];
+        }
+        convex_interval(self.crossings(ray))
+    }
+}
+
+impl NormalAtPoint for Cone {
+    fn normal_at_point(&self, point: &Point, _: Intersection) -> Normal {
+        let local = self.transform.inverse().point(*point);
+        let radial = (local.x * local.x + local.z * local.z).sqrt();
+        let normal = if self.on_base(local) {
+            Vector::new(0., -1., 0.)
+        } else if radial < 1e-12 {
+            // The apex.
+            Vector::new(0., 1., 0.)
+        } else {
+            // The gradient, on the surface `1 - y` is the radial distance.
+            Vector::new(local.x, radial, local.z)
+        };
+        self.transform.normal(normal.normalize())
+    }
+}
+
+impl Transform for Cone {
+    fn transform(&mut self, transformation: Transformation) {
+        self.transform = Transform3::from(transformation) * self.transform;
+    }
+}
+
+impl BoundingBox for Cone {
+    fn bounding_box(&self) -> AlighnedBox {
+        let local = AlighnedBox::new(Point::new(-1., 0., -1.), Point::new(1., 1., 1.));
+        self.transform.bounding_box(&local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::close;
+
+    fn cone(capped: bool) -> Cone {
+        Cone::new(Point::new(0., 0., 0.), Point::new(0., 2., 0.), 1., capped).unwrap()
+    }
+
+    #[test]
+    fn side_intersection() {
+        let cone = cone(true);
+        // Halfway up the radius is a half.
+        let ray = Ray::new(Point::new(-5., 1., 0.), Normal::new(1., 0., 0.));
+        let intersection = cone.intersect(&ray).unwrap();
+        assert!((intersection.distance() - 4.5).abs() < 1e-9);
+        let normal = cone.normal_at_point(&ray.at(4.5), intersection);
+        let expected = Vector::from(Vector::new(-2., 1., 0.).normalize());
+        assert!(close(Vector::from(normal), expected));
+    }
+
+    #[test]
+    fn base_intersection() {
+        let cone = cone(true);
+        let ray = Ray::new(Point::new(0.5, -3., 0.), Normal::new(0., 1., 0.));
+        let intersection = cone.intersect(&ray).unwrap();
+        assert!((intersection.distance() - 3.).abs() < 1e-9);
+        let normal = cone.normal_at_point(&ray.at(3.), intersection);
+        assert!(close(Vector::from(normal), Vector::new(0., -1., 0.)));
+    }
+
+    #[test]
+    fn passes_above_the_apex() {
+        // Where the other nappe would be.
+        let ray = Ray::new(Point::new(-5., 3., 0.), Normal::new(1., 0., 0.));
+        assert_eq!(cone(true).intersect(&ray), None);
+    }
+
+    #[test]
+    fn open_base() {
+        // Through the open base to the inside of the apex.
+        let ray = Ray::new(Point::new(0.5, -3., 0.), Normal::new(0., 1., 0.));
+        let intersection = cone(false).intersect(&ray).unwrap();
+        assert!((intersection.distance() - 4.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn bounds() {
+        let cone = Cone::new(Point::new(1., 1., 1.), Point::new(4., 1., 1.), 2., true).unwrap();
+        let bounds = cone.bounding_box();
+        assert!(close(Vector::from(bounds.min), Vector::new(1., -1., -1.)));
+        assert!(close(Vector::from(bounds.max), Vector::new(4., 3., 3.)));
+    }
+
+    #[test]
+    fn uv_halfway_to_the_apex() {
+        let cone = Cone::new(Point::new(1., 1., 1.), Point::new(4., 1., 1.), 2., true).unwrap();
+        let (_, v) = cone.uv_at_point(&Point::new(2.5, 2., 1.));
+        assert!((v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn degenerate_input() {
+        let base = Point::new(0., 0., 0.);
+        assert!(Cone::new(base, base, 1., true).is_none());
+        assert!(Cone::new(base, Point::new(0., 1., 0.), -1., false).is_none());
+    }
+}
@@ -0,0 +1,205 @@
+use std::f64::consts::PI;
+
+use super::alighned_box::AlighnedBox;
+use super::transform::Transform3;
+use super::{
+    closest_hit, convex_interval, Intersect, Intersection, Interval, NormalAtPoint, Transform,
+    Transformation,
+};
+use crate::basic_geometry::normal::Normal;
+use crate::basic_geometry::point::Point;
+use crate::basic_geometry::ray::Ray;
+use crate::basic_geometry::vector::Vector;
+use crate::basic_types::polynomial::solve_quadratic;
+use crate::complex_structures::BoundingBox;
+
+// Cylinder of the unit radius around the y axis from 0 to 1 in its own space,
+// placed in the world by the transform. Without the caps it's an open tube.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Cylinder {
+    // Object to world transform.
+    transform: Transform3,
+    capped: bool,
+}
+
+impl Cylinder {
+    // `None` if the ends are the same or the radius isn't positive.
+    pub(crate) fn new(base: Point, top: Point, radius: f64, capped: bool) -> Option<Cylinder> {
+        Transform3::along_segment(base, top, radius).map(|transform| Cylinder { transform, capped })
+    }
+
+    // Only with the caps it encloses the volume.
+    pub(crate) fn capped(&self) -> bool {
+        self.capped
+    }
+
+    // The angle around the axis and the height on the side, the position on the
+    // unit square on the caps.
+    pub(crate) fn uv_at_point(&self, point: &Point) -> (f64, f64) {
+        let local = self.transform.inverse().point(*point);
+        if self.on_cap(local) {
+            ((local.x + 1.) / 2., (local.z + 1.) / 2.)
+        } else {
+            (0.5 + local.z.atan2(local.x) / (2. * PI), local.y)
+        }
+    }
+
+    // Whether the local point is closer to one of the caps than to the side.
+    fn on_cap(&self, local: Point) -> bool {
+        let side = ((local.x * local.x + local.z * local.z).sqrt() - 1.).abs();
+        self.capped && local.y.abs().min((local.y - 1.).abs()) < side
+    }
+
+    // Distances of all crossings of the surface, also behind the origin.
+    fn crossings(&self, ray: &Ray) -> Vec<f64> {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        let (o, d) = (local.origin, local.direction);
+        let radial = (o.x * o.x + o.z * o.z).sqrt();
+        let side = solve_quadratic(
+            d.x * d.x + d.z * d.z,
+            2. * (o.x * d.x + o.z * d.z),
+            (radial - 1.) * (radial + 1.),
+        )
+        .into_iter()
+        .filter(|&t| (0. ..=1.).contains(&(o.y + t * d.y)));
+        let caps = [0., 1.]
+            .into_iter()
+            .filter(|_| self.capped && d.y != 0.)
+            .map(|y| (y - o.y) / d.y)
+            .filter(|&t| {
+                let (x, z) = (o.x + t * d.x, o.z + t * d.z);
+                x * x + z * z <= 1.
+            });
+        side.chain(caps).map(|t| t / scale).collect()
+    }
+}
+
+impl Intersect for Cylinder {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        closest_hit(self.crossings(ray)).map(Intersection::Intersect)
+    }
+
+    // Without the caps it has no inside.
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        if !self.capped {
+            return vec![];
+        }
+        convex_interval(self.crossings(ray))
+    }
+}
+
+impl NormalAtPoint for Cylinder {
+    fn normal_at_point(&self, point: &Point, _: Intersection) -> Normal {
+        let local = self.transform.inverse().point(*point);
+        let normal = if self.on_cap(local) {
+            Vector::new(0., if local.y < 0.5 { -1. } else { 1. }, 0.)
+        } else {
+            Vector::new(local.x, 0., local.z)
+        };
+        self.transform.normal(normal.normalize())
+    }
+}
+
+impl Transform for Cylinder {
+    fn transform(&mut self, transformation: Transformation) {
+        self.transform = Transform3::from(transformation) * self.transform;
+    }
+}
+
+impl BoundingBox for Cylinder {
+    fn bounding_box(&self) -> AlighnedBox {
+        let local = AlighnedBox::new(Point::new(-1., 0., -1.), Point::new(1., 1., 1.));
+        self.transform.bounding_box(&local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::Axis;
+    use crate::test_utils::close;
+
+    fn cylinder() -> Cylinder {
+        Cylinder::new(Point::new(0., 1., 0.), Point::new(0., 3., 0.), 0.5, true).unwrap()
+    }
+
+    #[test]
+    fn side_intersection() {
+        let cylinder = cylinder();
+        let ray = Ray::new(Point::new(-5., 2., 0.), Normal::new(1., 0., 0.));
+        let intersection = cylinder.intersect(&ray).unwrap();
+        assert!((intersection.distance() - 4.5).abs() < 1e-9);
+        let normal = cylinder.normal_at_point(&ray.at(4.5), intersection);
+        assert!(close(Vector::from(normal), Vector::new(-1., 0., 0.)));
+    }
+
+    #[test]
+    fn cap_intersection() {
+        let cylinder = cylinder();
+        let ray = Ray::new(Point::new(0.2, 10., 0.), Normal::new(0., -1., 0.));
+        let intersection = cylinder.intersect(&ray).unwrap();
+        assert!((intersection.distance() - 7.).abs() < 1e-9);
+        let normal = cylinder.normal_at_point(&ray.at(7.), intersection);
+        assert!(close(Vector::from(normal), Vector::new(0., 1., 0.)));
+    }
+
+    #[test]
+    fn passes_above_the_top() {
+        let ray = Ray::new(Point::new(-5., 3.5, 0.), Normal::new(1., 0., 0.));
+        assert_eq!(cylinder().intersect(&ray), None);
+    }
+
+    #[test]
+    fn intersection_behind() {
+        let ray = Ray::new(Point::new(5., 2., 0.), Normal::new(1., 0., 0.));
+        assert_eq!(cylinder().intersect(&ray), None);
+    }
+
+    #[test]
+    fn open_tube_along_the_axis() {
+        let tube =
+            Cylinder::new(Point::new(0., 0., 0.), Point::new(0., 0., 4.), 1., false).unwrap();
+        // Through the missing caps.
+        let ray = Ray::new(Point::new(0., 0., -1.), Normal::new(0., 0., 1.));
+        assert_eq!(tube.intersect(&ray), None);
+    }
+
+    #[test]
+    fn open_tube_is_seen_from_inside() {
+        let tube =
+            Cylinder::new(Point::new(0., 0., 0.), Point::new(0., 0., 4.), 1., false).unwrap();
+        let ray = Ray::new(Point::new(0., 0., 2.), Normal::new(0., 1., 0.));
+        let intersection = tube.intersect(&ray).unwrap();
+        assert!((intersection.distance() - 1.).abs() < 1e-9);
+        let (u, v) = tube.uv_at_point(&ray.at(1.));
+        assert!((0. ..=1.).contains(&u) && (v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tilted_bounds() {
+        let mut cylinder =
+            Cylinder::new(Point::new(0., 0., 0.), Point::new(0., 2., 0.), 1., true).unwrap();
+        cylinder.transform(Transformation::Rotation(Axis::Z, 90.));
+        cylinder.transform(Transformation::Translation(Vector::new(0., 0., 5.)));
+        let bounds = cylinder.bounding_box();
+        assert!(close(Vector::from(bounds.min), Vector::new(-2., -1., 4.)));
+        assert!(close(Vector::from(bounds.max), Vector::new(0., 1., 6.)));
+    }
+
+    #[test]
+    fn tilted_intersection() {
+        let mut cylinder =
+            Cylinder::new(Point::new(0., 0., 0.), Point::new(0., 2., 0.), 1., true).unwrap();
+        cylinder.transform(Transformation::Rotation(Axis::Z, 90.));
+        cylinder.transform(Transformation::Translation(Vector::new(0., 0., 5.)));
+        let ray = Ray::new(Point::new(-1., 10., 5.), Normal::new(0., -1., 0.));
+        assert!((cylinder.intersect(&ray).unwrap().distance() - 9.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn degenerate_input() {
+        let base = Point::new(1., 2., 3.);
+        assert!(Cylinder::new(base, base, 1., true).is_none());
+        assert!(Cylinder::new(base, Point::new(1., 3., 3.), 0., true).is_none());
+    }
+}
@@ -2,23 +2,36 @@ use crate::basic_geometry::normal::Normal;
 use crate::basic_geometry::point::Point;
 use crate::basic_geometry::ray::Ray;
 use crate::basic_geometry::vector::Vector;
+use crate::complex_structures::BoundingBox;
 
+use super::alighned_box::AlighnedBox;
 use super::plane::Plane;
-use super::{Intersect, Intersection};
+use super::transform::Transform3;
+use super::{Intersect, Intersection, NormalAtPoint, Transform, Transformation};
 
+// Unit disk in the xy plane of its own space placed by the transform, scaled
+// non-uniformly it becomes the ellipse. The plane it lies in is kept in the world
+// space for the intersection.
+#[derive(Debug, Clone)]
 pub(crate) struct Disk {
     center: Point,
-    radius: f64,
     normal: Normal,
+    // Object to world transform.
+    transform: Transform3,
 }
 
 impl Disk {
-    #[allow(dead_code)]
     pub(crate) fn new(center: Point, radius: f64, normal: Normal) -> Disk {
+        let z = Vector::new(0., 0., 1.);
+        let rotation =
+            Transform3::from_to(z, Vector::from(normal)).unwrap_or(Transform3::identity());
+        let scale = Transformation::Scale(Vector::new(radius, radius, radius));
         Disk {
             center,
-            radius,
             normal,
+            transform: Transform3::translation(Vector::from(center))
+                * rotation
+                * Transform3::from(scale),
         }
     }
 }
@@ -28,9 +41,8 @@ impl Intersect for Disk {
         let plane = Plane::new(self.normal, self.center);
         match plane.intersect(ray) {
             Some(Intersection::Intersect(t)) if t > 0. => {
-                let point = ray.at(t);
-                let distance = (Vector::from(point) - Vector::from(self.center)).length();
-                if distance < self.radius {
+                let local = Vector::from(self.transform.inverse().point(ray.at(t)));
+                if local.x * local.x + local.y * local.y < 1. {
                     Some(Intersection::Intersect(t))
                 } else {
                     None
@@ -41,9 +53,36 @@ impl Intersect for Disk {
     }
 }
 
+impl NormalAtPoint for Disk {
+    fn normal_at_point(&self, _: &Point, _: Intersection) -> Normal {
+        self.normal
+    }
+}
+
+impl Transform for Disk {
+    fn transform(&mut self, transformation: Transformation) {
+        let transform = Transform3::from(transformation);
+        self.center = transform.point(self.center);
+        self.normal = transform.normal(self.normal);
+        self.transform = transform * self.transform;
+    }
+}
+
+impl BoundingBox for Disk {
+    // The unit circle stretched along the world axis reaches as far as the length
+    // of the corresponding row of the first two columns of the linear part.
+    fn bounding_box(&self) -> AlighnedBox {
+        let matrix = self.transform.matrix();
+        let row = |i: usize| (matrix[i][0] * matrix[i][0] + matrix[i][1] * matrix[i][1]).sqrt();
+        let extent = Vector::new(row(0), row(1), row(2));
+        AlighnedBox::new(self.center + -extent, self.center + extent)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::basic_geometry::Axis;
 
     #[test]
     fn intersection_test() {
@@ -76,4 +115,23 @@ mod tests {
 
         assert_eq!(disk.intersect(&ray), None);
     }
+
+    #[test]
+    fn tilted_and_stretched_disk() {
+        let mut disk = Disk::new(Point::new(0., 0., 0.), 1., Normal::new(0., 0., 1.));
+        disk.transform(Transformation::Scale(Vector::new(3., 1., 1.)));
+        disk.transform(Transformation::Rotation(Axis::Y, 90.));
+        disk.transform(Transformation::Translation(Vector::new(0., 0., 5.)));
+        // The long axis of the ellipse went from x to -z.
+        let bounds = disk.bounding_box();
+        let close = |a: Point, b: Point| (a - b).length() < 1e-9;
+        assert!(close(bounds.min, Point::new(0., -1., 2.)));
+        assert!(close(bounds.max, Point::new(0., 1., 8.)));
+        let normal = disk.normal_at_point(&Point::new(0., 0., 5.), Intersection::Intersect(1.));
+        assert!((normal.x - 1.).abs() < 1e-9);
+        let inside = Ray::new(Point::new(-4., 0., 7.5), Normal::new(1., 0., 0.));
+        assert!((disk.intersect(&inside).unwrap().distance() - 4.).abs() < 1e-9);
+        let outside = Ray::new(Point::new(-4., 0.9, 7.5), Normal::new(1., 0., 0.));
+        assert!(disk.intersect(&outside).is_none());
+    }
 }
@@ -1,6 +1,5 @@
 use std::ops::{Index, IndexMut, Mul};
 
-use super::normal::Normal;
 use super::point::Point;
 use super::vector::Vector;
 
@@ -16,6 +15,43 @@ impl<const ROW: usize, const COLUMN: usize> Matrix<ROW, COLUMN> {
 }
 
 impl Matrix<4, 4> {
+    pub(crate) fn identity() -> Matrix<4, 4> {
+        Self::with_data([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, `None` for the singular matrix.
+    pub(crate) fn inverse(&self) -> Option<Matrix<4, 4>> {
+        let mut m = self.data;
+        let mut inverse = Matrix::identity().data;
+        for column in 0..4 {
+            let pivot =
+                (column..4).max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))?;
+            if m[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            m.swap(column, pivot);
+            inverse.swap(column, pivot);
+            let scale = 1.0 / m[column][column];
+            for j in 0..4 {
+                m[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in (0..4).filter(|&row| row != column) {
+                let factor = m[row][column];
+                for j in 0..4 {
+                    m[row][j] -= factor * m[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+        Some(Matrix { data: inverse })
+    }
+
     pub(crate) fn rotation_x(radians: f64) -> Matrix<4, 4> {
         let c = radians.cos();
         let s = radians.sin();
@@ -23,7 +59,7 @@ impl Matrix<4, 4> {
             [1.0, 0.0, 0.0, 0.0],
             [0.0, c, -s, 0.0],
             [0.0, s, c, 0.0],
-            [0.0, 0.0, 0.0, 0.1],
+            [0.0, 0.0, 0.0, 1.0],
         ])
     }
 
@@ -34,7 +70,7 @@ impl Matrix<4, 4> {
             [c, 0.0, s, 0.0],
             [0.0, 1.0, 0.0, 0.0],
             [-s, 0.0, c, 0.0],
-            [0.0, 0.0, 0.0, 0.1],
+            [0.0, 0.0, 0.0, 1.0],
         ])
     }
 
@@ -117,22 +153,16 @@ impl Mul<Point> for Matrix<4, 4> {
     }
 }
 
-impl<const COLUMN: usize> Mul<Normal> for Matrix<4, COLUMN> {
-    type Output = Normal;
-    fn mul(self, rhs: Normal) -> Self::Output {
-        self.mul(Vector::from(rhs)).normalize()
-    }
-}
-
 pub(crate) fn from_point(point: Point) -> Matrix<4, 1> {
     Matrix {
         data: [[point.x], [point.y], [point.z], [1.0]],
     }
 }
 
+// Vectors are directions, the translation doesn't move them.
 pub(crate) fn from_vector(vector: Vector) -> Matrix<4, 1> {
     Matrix {
-        data: [[vector.x], [vector.y], [vector.z], [1.0]],
+        data: [[vector.x], [vector.y], [vector.z], [0.0]],
     }
 }
 
@@ -171,4 +201,45 @@ mod tests {
         let expected = Point::new(5.0, 7.0, 9.0);
         assert_eq!(t * p, expected);
     }
+
+    #[test]
+    fn vectors_are_not_translated() {
+        let t = Matrix::translation(Vector::new(1.0, 2.0, 3.0));
+        let v = Vector::new(4.0, 5.0, 6.0);
+        assert_eq!(t * v, v);
+    }
+
+    #[test]
+    fn rotations_keep_the_translation() {
+        let v = Vector::new(1.0, 2.0, 3.0);
+        for rotation in [
+            Matrix::rotation_x(0.5),
+            Matrix::rotation_y(0.5),
+            Matrix::rotation_z(0.5),
+        ] {
+            assert_eq!(rotation[3], [0.0, 0.0, 0.0, 1.0]);
+            let moved = Matrix::translation(v) * rotation * Point::new(0.0, 0.0, 0.0);
+            assert_eq!(moved, Point::from(v));
+        }
+    }
+
+    #[test]
+    fn inverse_test() {
+        let m = Matrix::<4, 4>::with_data([
+            [0.0, 2.0, 0.0, 1.0],
+            [3.0, 0.0, 1.0, -2.0],
+            [0.0, 0.0, 4.0, 5.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let product = m * m.inverse().unwrap();
+        let identity = Matrix::<4, 4>::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((product[i][j] - identity[i][j]).abs() < 1e-12);
+            }
+        }
+        assert!(Matrix::scale(Vector::new(1.0, 0.0, 1.0))
+            .inverse()
+            .is_none());
+    }
 }
@@ -0,0 +1,133 @@
+use super::alighned_box::AlighnedBox;
+use super::transform::Transform3;
+use super::{
+    closest_hit, Intersect, Intersection, Interval, NormalAtPoint, Transform, Transformation,
+};
+use crate::basic_geometry::normal::Normal;
+use crate::basic_geometry::point::Point;
+use crate::basic_geometry::ray::Ray;
+use crate::complex_structures::BoundingBox;
+
+// Box aligned with the axes in its own space, placed in the world by the transform,
+// so unlike the bounding box it keeps its shape when rotated.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct OrientedBox {
+    bounds: AlighnedBox,
+    // Object to world transform.
+    transform: Transform3,
+}
+
+impl OrientedBox {
+    pub(crate) fn new(bounds: AlighnedBox) -> OrientedBox {
+        OrientedBox {
+            bounds,
+            transform: Transform3::identity(),
+        }
+    }
+
+    // Parts of the ray inside of the box, the distances are along the ray in the world.
+    fn local_intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        self.bounds
+            .intervals(&local)
+            .into_iter()
+            .map(|Interval { enter, exit }| Interval {
+                enter: enter / scale,
+                exit: exit / scale,
+            })
+            .collect()
+    }
+}
+
+impl Intersect for OrientedBox {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let crossings = self
+            .local_intervals(ray)
+            .into_iter()
+            .flat_map(|Interval { enter, exit }| [enter, exit]);
+        closest_hit(crossings).map(Intersection::Intersect)
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        self.local_intervals(ray)
+    }
+}
+
+impl NormalAtPoint for OrientedBox {
+    fn normal_at_point(&self, point: &Point, intersection: Intersection) -> Normal {
+        let local = self.transform.inverse().point(*point);
+        let normal = self.bounds.normal_at_point(&local, intersection);
+        self.transform.normal(normal)
+    }
+}
+
+impl Transform for OrientedBox {
+    fn transform(&mut self, transformation: Transformation) {
+        self.transform = Transform3::from(transformation) * self.transform;
+    }
+}
+
+impl BoundingBox for OrientedBox {
+    fn bounding_box(&self) -> AlighnedBox {
+        self.transform.bounding_box(&self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::vector::Vector;
+    use crate::basic_geometry::Axis;
+    use crate::test_utils::close;
+
+    fn rotated() -> OrientedBox {
+        let mut oriented = OrientedBox::new(AlighnedBox::new(
+            Point::new(-1., -1., -1.),
+            Point::new(1., 1., 1.),
+        ));
+        oriented.transform(Transformation::Rotation(Axis::Y, 45.));
+        oriented
+    }
+
+    #[test]
+    fn rotated_corner_is_hit() {
+        let oriented = rotated();
+        // The edge turned towards -x is at the distance of the half diagonal.
+        let ray = Ray::new(Point::new(-5., 0., 0.), Normal::new(1., 0., 0.));
+        let intersection = oriented.intersect(&ray).unwrap();
+        assert!((intersection.distance() - (5. - 2f64.sqrt())).abs() < 1e-9);
+        // Past the turned edge, inside of the box around the moved corners.
+        let ray = Ray::new(Point::new(-1.2, 5., -1.2), Normal::new(0., -1., 0.));
+        assert_eq!(oriented.intersect(&ray), None);
+    }
+
+    #[test]
+    fn rotated_face_normal() {
+        let oriented = rotated();
+        let ray = Ray::new(Point::new(-5., 0., 0.7), Normal::new(1., 0., 0.));
+        let intersection = oriented.intersect(&ray).unwrap();
+        let point = ray.at(intersection.distance());
+        let normal = oriented.normal_at_point(&point, intersection);
+        let expected = Vector::new(-1., 0., 1.) / 2f64.sqrt();
+        assert!(close(Vector::from(normal), expected));
+    }
+
+    #[test]
+    fn intervals_of_the_scaled_box() {
+        let mut oriented = OrientedBox::new(AlighnedBox::new(
+            Point::new(0., 0., 0.),
+            Point::new(1., 1., 1.),
+        ));
+        oriented.transform(Transformation::Composite(
+            Transform3::scale(Vector::new(2., 2., 2.)).unwrap(),
+        ));
+        let ray = Ray::new(Point::new(1., 1., -3.), Normal::new(0., 0., 1.));
+        assert_eq!(
+            oriented.intervals(&ray),
+            vec![Interval {
+                enter: 3.,
+                exit: 5.
+            }]
+        );
+    }
+}
@@ -2,9 +2,13 @@ use crate::basic_geometry::normal::Normal;
 use crate::basic_geometry::point::Point;
 use crate::basic_geometry::ray::Ray;
 use crate::basic_geometry::vector::Vector;
+use crate::complex_structures::BoundingBox;
 
-use super::{Intersect, Intersection, NormalAtPoint};
+use super::alighned_box::AlighnedBox;
+use super::transform::Transform3;
+use super::{Intersect, Intersection, Interval, NormalAtPoint, Transform, Transformation};
 
+#[derive(Debug, Clone)]
 pub(crate) struct Plane {
     pub(crate) normal: Normal,
     pub(crate) center: Point,
@@ -42,6 +46,25 @@ impl Intersect for Plane {
             None
         }
     }
+
+    // The half-space behind the plane, so the solids can be cut by it.
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let normal: Vector = self.normal.into();
+        let k = Vector::from(self.center) - Vector::from(ray.origin);
+        let dn = Vector::from(ray.direction).dot(normal);
+        let (min, max) = (f64::NEG_INFINITY, f64::INFINITY);
+        let interval = if dn == 0. {
+            // Parallel, inside if the origin is.
+            (k.dot(normal) >= 0.).then_some((min, max))
+        } else {
+            let t = k.dot(normal) / dn;
+            Some(if dn > 0. { (min, t) } else { (t, max) })
+        };
+        interval
+            .map(|(enter, exit)| Interval { enter, exit })
+            .into_iter()
+            .collect()
+    }
 }
 
 impl NormalAtPoint for Plane {
@@ -50,10 +73,27 @@ impl NormalAtPoint for Plane {
     }
 }
 
+impl Transform for Plane {
+    fn transform(&mut self, transformation: Transformation) {
+        let transform = Transform3::from(transformation);
+        self.center = transform.point(self.center);
+        self.normal = transform.normal(self.normal);
+    }
+}
+
+// The plane is infinite, it's kept out of the trees.
+impl BoundingBox for Plane {
+    fn bounding_box(&self) -> AlighnedBox {
+        let (min, max) = (f64::NEG_INFINITY, f64::INFINITY);
+        AlighnedBox::new(Point::new(min, min, min), Point::new(max, max, max))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::basic_geometry::point::Point;
+    use crate::basic_geometry::Axis;
 
     #[test]
     fn intersection_test() {
@@ -94,4 +134,18 @@ mod tests {
 
         assert_eq!(plane.intersect(&ray), None);
     }
+
+    #[test]
+    fn transformed_plane() {
+        let mut plane = Plane::new(Normal::new(0., 1., 0.), Point::new(0., 0., 0.));
+        plane.transform(Transformation::Scale(Vector::new(1., 2., 1.)));
+        plane.transform(Transformation::Rotation(Axis::Z, 90.));
+        plane.transform(Transformation::Translation(Vector::new(-5., 0., 0.)));
+        let ray = Ray::new(Point::new(0., 0., 0.), Normal::new(-1., 0., 0.));
+        let distance = plane.intersect(&ray).unwrap().distance();
+        assert!((distance - 5.).abs() < 1e-9);
+        let normal = plane.normal_at_point(&ray.at(distance), Intersection::Intersect(distance));
+        assert!((normal.x + 1.).abs() < 1e-9);
+        assert!(!plane.bounding_box().is_bounded());
+    }
 }
@@ -1,4 +1,4 @@
-use std::ops::{Add, Index, Sub};
+use std::ops::{Add, Index, IndexMut, Sub};
 
 use crate::basic_geometry::vector::Vector;
 
@@ -62,6 +62,16 @@ impl Index<Axis> for Point {
     }
 }
 
+impl IndexMut<Axis> for Point {
+    fn index_mut(&mut self, index: Axis) -> &mut Self::Output {
+        match index {
+            Axis::X => &mut self.x,
+            Axis::Y => &mut self.y,
+            Axis::Z => &mut self.z,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::basic_geometry::point::Point;
@@ -0,0 +1,145 @@
+use super::alighned_box::AlighnedBox;
+use super::matrix::Matrix;
+use super::transform::Transform3;
+use super::{Intersect, Intersection, NormalAtPoint, Transform, Transformation};
+use crate::basic_geometry::normal::Normal;
+use crate::basic_geometry::point::Point;
+use crate::basic_geometry::ray::Ray;
+use crate::basic_geometry::vector::Vector;
+use crate::complex_structures::BoundingBox;
+
+// Unit square from the origin in the xy plane of its own space, placed in the world
+// by the transform. The edges don't have to be perpendicular, it's the parallelogram.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Quad {
+    // Object to world transform.
+    transform: Transform3,
+}
+
+impl Quad {
+    // Parallelogram spanned by the edges from the corner, `None` if they are parallel.
+    // Its normal is `u x v`.
+    pub(crate) fn new(corner: Point, u: Vector, v: Vector) -> Option<Quad> {
+        let n = u.cross(v);
+        if n.length() < 1e-12 {
+            return None;
+        }
+        let n = Vector::from(n.normalize());
+        let matrix = Matrix::with_data([
+            [u.x, v.x, n.x, corner.x],
+            [u.y, v.y, n.y, corner.y],
+            [u.z, v.z, n.z, corner.z],
+            [0., 0., 0., 1.],
+        ]);
+        Transform3::new(matrix).map(|transform| Quad { transform })
+    }
+
+    // The position along the edges from the corner.
+    pub(crate) fn uv_at_point(&self, point: &Point) -> (f64, f64) {
+        let local = self.transform.inverse().point(*point);
+        (local.x, local.y)
+    }
+}
+
+impl Intersect for Quad {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        if local.direction.z == 0. {
+            return None;
+        }
+        let t = -local.origin.z / local.direction.z;
+        let hit = local.at(t);
+        let inside = (0. ..=1.).contains(&hit.x) && (0. ..=1.).contains(&hit.y);
+        (t > 0. && inside).then_some(Intersection::Intersect(t / scale))
+    }
+}
+
+impl NormalAtPoint for Quad {
+    fn normal_at_point(&self, _: &Point, _: Intersection) -> Normal {
+        self.transform.normal(Normal::new(0., 0., 1.))
+    }
+}
+
+impl Transform for Quad {
+    fn transform(&mut self, transformation: Transformation) {
+        self.transform = Transform3::from(transformation) * self.transform;
+    }
+}
+
+impl BoundingBox for Quad {
+    fn bounding_box(&self) -> AlighnedBox {
+        let local = AlighnedBox::new(Point::new(0., 0., 0.), Point::new(1., 1., 0.));
+        self.transform.bounding_box(&local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::Axis;
+    use crate::test_utils::close;
+
+    fn parallelogram() -> Quad {
+        Quad::new(
+            Point::new(1., 0., 0.),
+            Vector::new(2., 0., 0.),
+            Vector::new(1., 0., -2.),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn intersection_test() {
+        let quad = parallelogram();
+        let ray = Ray::new(Point::new(2.5, 5., -1.), Normal::new(0., -1., 0.));
+        let intersection = quad.intersect(&ray).unwrap();
+        assert!((intersection.distance() - 5.).abs() < 1e-9);
+        let normal = quad.normal_at_point(&ray.at(5.), intersection);
+        assert!(close(Vector::from(normal), Vector::new(0., 1., 0.)));
+    }
+
+    #[test]
+    fn uv_in_the_middle() {
+        let (u, v) = parallelogram().uv_at_point(&Point::new(2.5, 0., -1.));
+        assert!((u - 0.5).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn outside_the_slanted_edge() {
+        // Inside the bounding rectangle.
+        let ray = Ray::new(Point::new(1.2, 5., -1.9), Normal::new(0., -1., 0.));
+        assert_eq!(parallelogram().intersect(&ray), None);
+    }
+
+    #[test]
+    fn intersection_behind() {
+        let ray = Ray::new(Point::new(2.5, -5., -1.), Normal::new(0., -1., 0.));
+        assert_eq!(parallelogram().intersect(&ray), None);
+    }
+
+    #[test]
+    fn degenerate_input() {
+        assert!(Quad::new(
+            Point::new(0., 0., 0.),
+            Vector::new(1., 1., 0.),
+            Vector::new(2., 2., 0.)
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn transformed_bounds() {
+        let mut quad = Quad::new(
+            Point::new(0., 0., 0.),
+            Vector::new(1., 0., 0.),
+            Vector::new(0., 1., 0.),
+        )
+        .unwrap();
+        quad.transform(Transformation::Rotation(Axis::X, 90.));
+        quad.transform(Transformation::Scale(Vector::new(2., 2., 2.)));
+        let bounds = quad.bounding_box();
+        assert!(close(bounds.max - bounds.min, Vector::new(2., 0., 2.)));
+        let ray = Ray::new(Point::new(1., 3., 1.), Normal::new(0., -1., 0.));
+        assert!(quad.intersect(&ray).is_some());
+    }
+}
@@ -0,0 +1,181 @@
+use std::ops::Mul;
+
+use super::matrix::Matrix;
+use super::vector::Vector;
+
+// Unit quaternion of the rotation. Unlike the Euler angles the rotations compose
+// into one orientation around any axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Quaternion {
+    pub(crate) w: f64,
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    pub(crate) z: f64,
+}
+
+impl Quaternion {
+    pub(crate) fn identity() -> Quaternion {
+        Quaternion {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    // Normalized, so any non-zero quaternion can be given.
+    pub(crate) fn new(w: f64, x: f64, y: f64, z: f64) -> Option<Quaternion> {
+        let length = (w * w + x * x + y * y + z * z).sqrt();
+        (length > 1e-12).then(|| Quaternion {
+            w: w / length,
+            x: x / length,
+            y: y / length,
+            z: z / length,
+        })
+    }
+
+    // Counterclockwise rotation in degrees around the axis, looking against it.
+    pub(crate) fn from_axis_angle(axis: Vector, degrees: f64) -> Option<Quaternion> {
+        if axis.length() < 1e-12 {
+            return None;
+        }
+        let axis = Vector::from(axis.normalize());
+        let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+        Some(Quaternion {
+            w: cos,
+            x: axis.x * sin,
+            y: axis.y * sin,
+            z: axis.z * sin,
+        })
+    }
+
+    // The shortest rotation turning the `from` direction into `to`.
+    pub(crate) fn from_to(from: Vector, to: Vector) -> Option<Quaternion> {
+        if from.length() < 1e-12 || to.length() < 1e-12 {
+            return None;
+        }
+        let (from, to) = (Vector::from(from.normalize()), Vector::from(to.normalize()));
+        let dot = from.dot(to);
+        if dot < -1.0 + 1e-12 {
+            // Opposite directions, any perpendicular axis will do.
+            let other = if from.x.abs() < 0.9 {
+                Vector::new(1.0, 0.0, 0.0)
+            } else {
+                Vector::new(0.0, 1.0, 0.0)
+            };
+            return Quaternion::from_axis_angle(from.cross(other), 180.0);
+        }
+        let axis = from.cross(to);
+        Quaternion::new(1.0 + dot, axis.x, axis.y, axis.z)
+    }
+
+    // Euler angles in degrees, applied in the X, Y, Z order as everywhere else.
+    pub(crate) fn from_euler(degrees: Vector) -> Quaternion {
+        let around = |x, y, z, angle| {
+            Quaternion::from_axis_angle(Vector::new(x, y, z), angle).unwrap_or_default()
+        };
+        around(0.0, 0.0, 1.0, degrees.z)
+            * around(0.0, 1.0, 0.0, degrees.y)
+            * around(1.0, 0.0, 0.0, degrees.x)
+    }
+
+    pub(crate) fn conjugate(&self) -> Quaternion {
+        Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    pub(crate) fn matrix(&self) -> Matrix<4, 4> {
+        let Quaternion { w, x, y, z } = *self;
+        Matrix::with_data([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Quaternion {
+        Quaternion::identity()
+    }
+}
+
+// `a * b` rotates by `b` first.
+impl Mul for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        let (a, b) = (self, rhs);
+        Quaternion {
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::{Axis, Transformation};
+    use crate::test_utils::close;
+
+    #[test]
+    fn matches_axis_rotations() {
+        let v = Vector::new(1.0, -2.0, 3.0);
+        let angles = Vector::new(30.0, -45.0, 120.0);
+        let expected = [
+            Transformation::Rotation(Axis::X, angles.x),
+            Transformation::Rotation(Axis::Y, angles.y),
+            Transformation::Rotation(Axis::Z, angles.z),
+        ]
+        .iter()
+        .fold(v, |v, rotation| rotation.transformation_to_matrix() * v);
+        let q = Quaternion::from_euler(angles);
+        assert!(close(q.matrix() * v, expected));
+        assert!(close(q.conjugate().matrix() * (q.matrix() * v), v));
+    }
+
+    #[test]
+    fn arbitrary_axis_and_from_to() {
+        let axis = Vector::new(1.0, 1.0, 1.0);
+        let q = Quaternion::from_axis_angle(axis, 120.0).unwrap();
+        assert!(close(
+            q.matrix() * Vector::new(1.0, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0)
+        ));
+        assert!(close(q.matrix() * axis, axis));
+        for (from, to) in [
+            (Vector::new(0.0, 0.0, 1.0), Vector::new(1.0, 2.0, -1.0)),
+            (Vector::new(1.0, 0.0, 0.0), Vector::new(-3.0, 0.0, 0.0)),
+            (Vector::new(0.0, 2.0, 0.0), Vector::new(0.0, 5.0, 0.0)),
+        ] {
+            let q = Quaternion::from_to(from, to).unwrap();
+            assert!(close(
+                Vector::from((q.matrix() * from).normalize()),
+                Vector::from(to.normalize())
+            ));
+        }
+        assert!(Quaternion::from_axis_angle(Vector::new(0.0, 0.0, 0.0), 10.0).is_none());
+    }
+}
@@ -1,24 +1,143 @@
 use crate::basic_geometry::normal::Normal;
 use crate::basic_geometry::point::Point;
+use crate::basic_geometry::transform::Transform3;
+use crate::basic_geometry::vector::Vector;
 
-#[derive(Debug)]
+use super::Axis;
+
+#[derive(Debug, Clone, Copy)]
 pub(crate) struct Ray {
     pub(crate) origin: Point,
     pub(crate) direction: Normal,
+    // Moment inside of the shutter interval, used to place the moving objects.
+    pub(crate) time: f64,
 }
 
 impl Ray {
     pub(crate) fn new(origin: Point, direction: Normal) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+        }
+    }
+
+    pub(crate) fn with_time(mut self, time: f64) -> Ray {
+        self.time = time;
+        self
     }
 
     pub(crate) fn at(&self, t: f64) -> Point {
         self.origin + self.direction * t
     }
 
-    pub(crate) fn reflect_from_normal(&self, point: Point, normal: Normal) -> Self {
+    // `1 / direction` per axis for the slab tests, infinite for the parallel axes.
+    pub(crate) fn inverse_direction(&self) -> Vector {
+        Vector::new(
+            1.0 / self.direction.x,
+            1.0 / self.direction.y,
+            1.0 / self.direction.z,
+        )
+    }
+
+    // Moves the ray into the space given by the transform. The direction stays normalized,
+    // so distances along the new ray have to be divided by the returned scale.
+    pub(crate) fn transformed(&self, transform: &Transform3) -> (Ray, f64) {
+        let origin = transform.point(self.origin);
+        // Not the difference of the moved points, it would lose the precision far from zero.
+        let direction = transform.vector(Vector::from(self.direction));
+        let ray = Ray::new(origin, direction.normalize()).with_time(self.time);
+        (ray, direction.length())
+    }
+
+    // Axes and shear moving the ray onto the positive z axis for the watertight triangle test.
+    pub(crate) fn shear(&self) -> Shear {
+        let direction = self.direction;
+        let z = [Axis::X, Axis::Y, Axis::Z]
+            .into_iter()
+            .max_by(|&a, &b| direction[a].abs().total_cmp(&direction[b].abs()))
+            .unwrap();
+        let (mut x, mut y) = match z {
+            Axis::X => (Axis::Y, Axis::Z),
+            Axis::Y => (Axis::Z, Axis::X),
+            Axis::Z => (Axis::X, Axis::Y),
+        };
+        // Swapping keeps the winding of the triangles.
+        if direction[z] < 0.0 {
+            std::mem::swap(&mut x, &mut y);
+        }
+        Shear {
+            axes: [x, y, z],
+            x: direction[x] / direction[z],
+            y: direction[y] / direction[z],
+            z: 1.0 / direction[z],
+        }
+    }
+
+    // Hit point at the distance with the bound of its rounding error. The distance from
+    // the watertight test is within a few ulps, as is the point computed from it.
+    pub(crate) fn surface_point(&self, distance: f64, normal: Normal) -> SurfacePoint {
+        let origin = Vector::from(self.origin).abs();
+        let error = (origin + (self.direction * distance).abs()) * gamma(7);
+        SurfacePoint {
+            point: self.at(distance),
+            error,
+            normal,
+        }
+    }
+
+    pub(crate) fn reflect_from_normal(&self, surface: &SurfacePoint, normal: Normal) -> Self {
         let dir = Normal::reflect(normal, self.direction);
-        let ray = Ray::new(point, dir);
-        Ray::new(ray.at(1e-4), ray.direction)
+        surface.spawn(dir).with_time(self.time)
+    }
+}
+
+// Ray space of the watertight triangle test (Woop et al.): `axes` are the permuted
+// axes with the largest component of the direction last, `x`, `y` and `z` the shear
+// coefficients taking the direction to (0, 0, 1).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Shear {
+    pub(crate) axes: [Axis; 3],
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    pub(crate) z: f64,
+}
+
+// Point where the ray hit the surface, the origin of the rays leaving it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SurfacePoint {
+    pub(crate) point: Point,
+    // Bound of the rounding error of every coordinate.
+    pub(crate) error: Vector,
+    // Geometric normal, the shading one can point to the other side.
+    pub(crate) normal: Normal,
+}
+
+impl SurfacePoint {
+    // Ray leaving the surface. Its origin is pushed along the normal out of the error box
+    // of the point, to the side of the direction, so it can't hit the surface again.
+    pub(crate) fn spawn(&self, direction: Normal) -> Ray {
+        let normal = Vector::from(self.normal);
+        let distance = normal.abs().dot(self.error);
+        let mut offset = normal * distance;
+        if normal.dot(Vector::from(direction)) < 0.0 {
+            offset = -offset;
+        }
+        let mut origin = self.point + offset;
+        // The addition rounds to the nearest, the origin has to stay outside.
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            if offset[axis] > 0.0 {
+                origin[axis] = origin[axis].next_up();
+            } else if offset[axis] < 0.0 {
+                origin[axis] = origin[axis].next_down();
+            }
+        }
+        Ray::new(origin, direction)
     }
 }
+
+// Bound of the relative error of `n` floating point operations.
+fn gamma(n: u32) -> f64 {
+    let n = f64::from(n) * f64::EPSILON * 0.5;
+    n / (1.0 - n)
+}
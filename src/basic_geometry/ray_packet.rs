@@ -0,0 +1,67 @@
+use super::ray::{Ray, Shear};
+use super::vector::Vector;
+use crate::basic_types::simd::{F64x4, Mask4};
+
+// Four rays traced together, usually through the neighbouring pixels. Their coordinates
+// are kept in the lanes for the SIMD tests, the lanes without a ray are not `active`.
+pub(crate) struct RayPacket {
+    pub(crate) rays: [Ray; 4],
+    pub(crate) active: Mask4,
+    pub(crate) origin: [F64x4; 3],
+    pub(crate) inverse_direction: [F64x4; 3],
+    // Ray spaces of the lanes for the watertight triangle test, their shear coefficients
+    // and the origins in their axes.
+    pub(crate) shear: [Shear; 4],
+    pub(crate) shear_lanes: [F64x4; 3],
+    pub(crate) permuted_origin: [F64x4; 3],
+}
+
+impl RayPacket {
+    // The inactive lanes hold copies of the active rays, so they never produce NaN.
+    pub(crate) fn new(rays: [Ray; 4], active: Mask4) -> RayPacket {
+        let lanes = |f: &dyn Fn(&Ray) -> [f64; 3]| {
+            let values = rays.map(|ray| f(&ray));
+            [0, 1, 2].map(|axis| F64x4::new(values.map(|value| value[axis])))
+        };
+        let shear = rays.map(|ray| ray.shear());
+        let lane = |f: &dyn Fn(usize) -> f64| F64x4::new(std::array::from_fn(f));
+        RayPacket {
+            active,
+            shear_lanes: [
+                lane(&|i| shear[i].x),
+                lane(&|i| shear[i].y),
+                lane(&|i| shear[i].z),
+            ],
+            permuted_origin: std::array::from_fn(|k| {
+                lane(&|i| Vector::from(rays[i].origin)[shear[i].axes[k]])
+            }),
+            shear,
+            origin: lanes(&|ray| [ray.origin.x, ray.origin.y, ray.origin.z]),
+            inverse_direction: lanes(&|ray| {
+                let inverse = ray.inverse_direction();
+                [inverse.x, inverse.y, inverse.z]
+            }),
+            rays,
+        }
+    }
+
+    // Packet of one to four rays.
+    pub(crate) fn from_rays(rays: &[Ray]) -> RayPacket {
+        assert!(!rays.is_empty() && rays.len() <= 4);
+        let lanes = std::array::from_fn(|i| rays[i.min(rays.len() - 1)]);
+        RayPacket::new(lanes, Mask4::from_bits((1 << rays.len()) - 1))
+    }
+
+    // Results of `f` for the rays in the given lanes, `None` elsewhere.
+    pub(crate) fn each<T>(
+        &self,
+        lanes: Mask4,
+        mut f: impl FnMut(&Ray) -> Option<T>,
+    ) -> [Option<T>; 4] {
+        let mut results = [None, None, None, None];
+        for lane in lanes.lanes() {
+            results[lane] = f(&self.rays[lane]);
+        }
+        results
+    }
+}
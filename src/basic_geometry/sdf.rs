@@ -0,0 +1,389 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+
+use super::alighned_box::AlighnedBox;
+use super::transform::Transform3;
+use super::{Intersect, Intersection, NormalAtPoint, Transform, Transformation};
+use crate::basic_geometry::normal::Normal;
+use crate::basic_geometry::point::Point;
+use crate::basic_geometry::ray::Ray;
+use crate::basic_geometry::vector::Vector;
+use crate::complex_structures::BoundingBox;
+
+// Most steps of the sphere tracing before the ray is taken as missed.
+const MAX_STEPS: usize = 512;
+
+// Signed distance to the implicit surface, negative inside. The distance never
+// overestimates the true one, so the ray can safely step by it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Field {
+    Sphere(f64),
+    // Half of the size along the axes.
+    Box(Vector),
+    // Major and minor radius around the y axis.
+    Torus(f64, f64),
+    // Segment between the points and the radius around it.
+    Capsule(Point, Point, f64),
+    Union(Box<Field>, Box<Field>),
+    // Union blended over the given distance.
+    SmoothUnion(Box<Field>, Box<Field>, f64),
+    Translate(Box<Field>, Vector),
+    // Copies of the field spaced along the axes, `count` more of them on each side.
+    // The field has to fit into one cell, the distance is wrong otherwise.
+    Repeat(Box<Field>, Vector, [u32; 3]),
+}
+
+impl Field {
+    pub(crate) fn distance(&self, p: Vector) -> f64 {
+        match self {
+            Field::Sphere(radius) => p.length() - radius,
+            Field::Box(half) => {
+                let q = p.abs() - *half;
+                let outside = Vector::new(q.x.max(0.), q.y.max(0.), q.z.max(0.)).length();
+                outside + q.x.max(q.y).max(q.z).min(0.)
+            }
+            Field::Torus(major, minor) => {
+                let radial = (p.x * p.x + p.z * p.z).sqrt() - major;
+                (radial * radial + p.y * p.y).sqrt() - minor
+            }
+            Field::Capsule(a, b, radius) => {
+                let (pa, ba) = (p - Vector::from(*a), *b - *a);
+                let h = (pa.dot(ba) / ba.dot(ba)).clamp(0., 1.);
+                (pa - ba * h).length() - radius
+            }
+            Field::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Field::SmoothUnion(a, b, k) => {
+                // The polynomial smooth minimum, it's below `min` by at most `k / 4`.
+                let (a, b) = (a.distance(p), b.distance(p));
+                let h = (k - (a - b).abs()).max(0.) / k;
+                a.min(b) - h * h * k / 4.
+            }
+            Field::Translate(field, offset) => field.distance(p - *offset),
+            Field::Repeat(field, spacing, count) => {
+                let cell = |p: f64, spacing: f64, count: u32| {
+                    if spacing == 0. {
+                        p
+                    } else {
+                        let count = count as f64;
+                        p - spacing * (p / spacing).round().clamp(-count, count)
+                    }
+                };
+                field.distance(Vector::new(
+                    cell(p.x, spacing.x, count[0]),
+                    cell(p.y, spacing.y, count[1]),
+                    cell(p.z, spacing.z, count[2]),
+                ))
+            }
+        }
+    }
+
+    // Box around the surface, it may be larger than the surface.
+    pub(crate) fn bounds(&self) -> AlighnedBox {
+        let around = |half: Vector| AlighnedBox::new(Point::from(-half), Point::from(half));
+        match self {
+            Field::Sphere(radius) => around(Vector::new(*radius, *radius, *radius)),
+            Field::Box(half) => around(*half),
+            Field::Torus(major, minor) => around(Vector::new(major + minor, *minor, major + minor)),
+            Field::Capsule(a, b, radius) => {
+                let r = Vector::new(*radius, *radius, *radius);
+                AlighnedBox::new(*a + -r, *a + r).union(&AlighnedBox::new(*b + -r, *b + r))
+            }
+            Field::Union(a, b) => a.bounds().union(&b.bounds()),
+            Field::SmoothUnion(a, b, k) => {
+                let bounds = a.bounds().union(&b.bounds());
+                let k = Vector::new(k / 4., k / 4., k / 4.);
+                AlighnedBox::new(bounds.min + -k, bounds.max + k)
+            }
+            Field::Translate(field, offset) => {
+                let bounds = field.bounds();
+                AlighnedBox::new(bounds.min + *offset, bounds.max + *offset)
+            }
+            Field::Repeat(field, spacing, count) => {
+                let bounds = field.bounds();
+                let reach = Vector::new(
+                    spacing.x.abs() * count[0] as f64,
+                    spacing.y.abs() * count[1] as f64,
+                    spacing.z.abs() * count[2] as f64,
+                );
+                AlighnedBox::new(bounds.min + -reach, bounds.max + reach)
+            }
+        }
+    }
+}
+
+// Prefix notation with the children in parentheses, e.g.
+// `smooth 0.5 (sphere 1) (translate 1.5 0 0 (box 1 0.5 0.5))`:
+// `sphere r`, `box half_x half_y half_z`, `torus major minor`,
+// `capsule ax ay az bx by bz r`, `union (a) (b)`, `smooth k (a) (b)`,
+// `translate x y z (a)` and `repeat sx sy sz nx ny nz (a)`.
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Field> {
+        let spaced = s.replace('(', " ( ").replace(')', " ) ");
+        let mut tokens = spaced.split_whitespace().peekable();
+        let field = parse(&mut tokens)?;
+        if let Some(token) = tokens.next() {
+            bail!("Unexpected {} after the field", token);
+        }
+        Ok(field)
+    }
+}
+
+fn parse<'a>(
+    tokens: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+) -> anyhow::Result<Field> {
+    let name = tokens.next().ok_or_else(|| anyhow!("Missing field"))?;
+    let mut v = vec![];
+    while let Some(value) = tokens.peek().and_then(|token| token.parse::<f64>().ok()) {
+        v.push(value);
+        tokens.next();
+    }
+    let mut children = vec![];
+    while tokens.peek() == Some(&"(") {
+        tokens.next();
+        children.push(Box::new(parse(tokens)?));
+        if tokens.next() != Some(")") {
+            bail!("Missing ) in {}", name);
+        }
+    }
+    let vector = |i: usize| Vector::new(v[i], v[i + 1], v[i + 2]);
+    let positive = v.iter().all(|&value| value > 0.);
+    let field = match (name, v.len(), children.len()) {
+        ("sphere", 1, 0) if positive => Field::Sphere(v[0]),
+        ("box", 3, 0) if positive => Field::Box(vector(0)),
+        ("torus", 2, 0) if positive => Field::Torus(v[0], v[1]),
+        ("capsule", 7, 0) if v[6] > 0. && vector(0) != vector(3) => {
+            Field::Capsule(Point::from(vector(0)), Point::from(vector(3)), v[6])
+        }
+        ("union", 0, 2) => Field::Union(children.remove(0), children.remove(0)),
+        ("smooth", 1, 2) if positive => {
+            Field::SmoothUnion(children.remove(0), children.remove(0), v[0])
+        }
+        ("translate", 3, 1) => Field::Translate(children.remove(0), vector(0)),
+        ("repeat", 6, 1) if v[3..].iter().all(|n| n.fract() == 0. && *n >= 0.) => Field::Repeat(
+            children.remove(0),
+            vector(0),
+            [v[3] as u32, v[4] as u32, v[5] as u32],
+        ),
+        _ => bail!("Incorrect field {} with {:?}", name, v),
+    };
+    Ok(field)
+}
+
+// Implicit surface of the field placed in the world by the transform, rendered
+// by the sphere tracing in its own space.
+#[derive(Debug, Clone)]
+pub(crate) struct Sdf {
+    field: Field,
+    // Object to world transform.
+    transform: Transform3,
+    bounds: AlighnedBox,
+    // The ray closer to the surface than this has hit it.
+    epsilon: f64,
+}
+
+impl Sdf {
+    pub(crate) fn new(field: Field) -> Sdf {
+        let bounds = field.bounds();
+        let size = bounds.max - bounds.min;
+        let epsilon = 1e-7 * size.x.max(size.y).max(size.z);
+        let margin = Vector::new(epsilon, epsilon, epsilon);
+        Sdf {
+            field,
+            transform: Transform3::identity(),
+            bounds: AlighnedBox::new(bounds.min + -margin, bounds.max + margin),
+            epsilon,
+        }
+    }
+
+    // Distance along the ray in the object space to the surface.
+    fn march(&self, ray: &Ray) -> Option<f64> {
+        let (entry, exit) =
+            self.bounds
+                .ray_interval(ray, ray.inverse_direction(), f64::INFINITY)?;
+        let distance = |t: f64| self.field.distance(Vector::from(ray.at(t)));
+        let mut t = entry;
+        let mut steps = 0..MAX_STEPS;
+        // The rays leaving the surface start on it, they first step off to see
+        // whether they go out or in. The steps along the surface count too.
+        let mut side = 1.;
+        if entry == 0. {
+            let mut d = distance(t);
+            while d.abs() < self.epsilon {
+                steps.next()?;
+                t += self.epsilon;
+                d = distance(t);
+            }
+            side = d.signum();
+        }
+        for _ in steps {
+            let d = distance(t) * side;
+            if d < self.epsilon {
+                return Some(t);
+            }
+            t += d;
+            if t > exit {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+impl Intersect for Sdf {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        self.march(&local)
+            .filter(|&t| t > 0.)
+            .map(|t| Intersection::Intersect(t / scale))
+    }
+}
+
+impl NormalAtPoint for Sdf {
+    // The gradient of the field by the central differences.
+    fn normal_at_point(&self, point: &Point, _: Intersection) -> Normal {
+        let p = Vector::from(self.transform.inverse().point(*point));
+        let h = 10. * self.epsilon;
+        let derivative =
+            |offset: Vector| self.field.distance(p + offset) - self.field.distance(p - offset);
+        let gradient = Vector::new(
+            derivative(Vector::new(h, 0., 0.)),
+            derivative(Vector::new(0., h, 0.)),
+            derivative(Vector::new(0., 0., h)),
+        );
+        self.transform.normal(gradient.normalize())
+    }
+}
+
+impl Transform for Sdf {
+    fn transform(&mut self, transformation: Transformation) {
+        self.transform = Transform3::from(transformation) * self.transform;
+    }
+}
+
+impl BoundingBox for Sdf {
+    fn bounding_box(&self) -> AlighnedBox {
+        self.transform.bounding_box(&self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::{sphere::Sphere, Axis};
+
+    #[test]
+    fn matches_analytic_sphere() {
+        let mut sdf = Sdf::new(Field::Sphere(5.));
+        sdf.transform(Transformation::Translation(Vector::new(20., 20., 20.)));
+        let sphere = Sphere::new(Point::new(20., 20., 20.), 5.);
+        let origin = Point::new(0., 0., 275.);
+        for i in 0..200 {
+            let target = Point::new(14. + 0.06 * i as f64, 25. - 0.05 * i as f64, 20.);
+            let ray = Ray::new(origin, (target - origin).normalize());
+            match (sdf.intersect(&ray), sphere.intersect(&ray)) {
+                (Some(actual), Some(expected)) => {
+                    assert!((actual.distance() - expected.distance()).abs() < 1e-4);
+                    let point = ray.at(expected.distance());
+                    let normal = Vector::from(sdf.normal_at_point(&point, actual));
+                    let exact = Vector::from(sphere.normal_at_point(&point, expected));
+                    assert!((normal - exact).length() < 1e-4);
+                }
+                // Grazing rays can pass either way.
+                (None, Some(expected)) => {
+                    let point = ray.at(expected.distance());
+                    let normal = sphere.normal_at_point(&point, expected);
+                    assert!(normal.dot(ray.direction).abs() < 0.05);
+                }
+                (actual, expected) => assert_eq!(actual, expected),
+            }
+        }
+    }
+
+    #[test]
+    fn spawned_rays_leave_the_surface() {
+        let field: Field = "smooth 1 (sphere 2) (translate 2.5 0 0 (box 1 1 1))"
+            .parse()
+            .unwrap();
+        let mut sdf = Sdf::new(field);
+        sdf.transform(Transformation::Rotation(Axis::Y, 30.));
+        let origin = Point::new(0., 1., 50.);
+        let mut hits = 0;
+        for i in 0..200 {
+            let target = Point::new(-3. + 0.04 * i as f64, 0.5, 0.);
+            let ray = Ray::new(origin, (target - origin).normalize());
+            let Some(intersection) = sdf.intersect(&ray) else {
+                continue;
+            };
+            hits += 1;
+            let point = ray.at(intersection.distance());
+            let normal = sdf.normal_at_point(&point, intersection);
+            assert!(normal.dot(ray.direction) < 0.);
+            let surface = ray.surface_point(intersection.distance(), normal);
+            let reflected = ray.reflect_from_normal(&surface, normal);
+            if let Some(next) = sdf.intersect(&reflected) {
+                assert!(next.distance() > 1e-3);
+            }
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn smooth_union_and_repetition() {
+        let separate = Field::Union(
+            Box::new(Field::Sphere(1.)),
+            Box::new(Field::Translate(
+                Box::new(Field::Sphere(1.)),
+                Vector::new(2.5, 0., 0.),
+            )),
+        );
+        let Field::Union(a, b) = separate.clone() else {
+            unreachable!()
+        };
+        let blended = Field::SmoothUnion(a, b, 2.);
+        // The gap between the spheres is filled by the blend.
+        let between = Vector::new(1.25, 0., 0.);
+        assert!(separate.distance(between) > 0.);
+        assert!(blended.distance(between) < 0.);
+        let down = Ray::new(Point::new(1.25, 5., 0.), Normal::new(0., -1., 0.));
+        assert!(Sdf::new(separate).intersect(&down).is_none());
+        assert!(Sdf::new(blended).intersect(&down).is_some());
+
+        let row: Field = "repeat 4 0 0 2 0 0 (sphere 1)".parse().unwrap();
+        let sdf = Sdf::new(row);
+        let bounds = sdf.bounding_box();
+        assert!(bounds.min.x < -9. && bounds.max.x > 9. && bounds.max.y < 1.1);
+        for (x, hit) in [(-8., true), (-6., false), (4., true), (12., false)] {
+            let ray = Ray::new(Point::new(x, 5., 0.), Normal::new(0., -1., 0.));
+            let intersection = sdf.intersect(&ray);
+            assert_eq!(intersection.is_some(), hit, "{}", x);
+            if let Some(intersection) = intersection {
+                assert!((intersection.distance() - 4.).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn ray_along_the_surface_gives_up() {
+        let sdf = Sdf::new("box 1000 1 1000".parse().unwrap());
+        let ray = Ray::new(Point::new(0., 1., 0.), Normal::new(1., 0., 0.));
+        assert_eq!(sdf.intersect(&ray), None);
+    }
+
+    #[test]
+    fn parse_fields() {
+        let field: Field = "translate 0 1 0 (union (torus 2 0.5) (capsule 0 0 0 0 2 0 0.3))"
+            .parse()
+            .unwrap();
+        assert!((field.distance(Vector::new(2., 1., 0.)) + 0.5).abs() < 1e-12);
+        assert!((field.distance(Vector::new(0., 2., 0.)) + 0.3).abs() < 1e-12);
+        assert!("sphere".parse::<Field>().is_err());
+        assert!("sphere -1".parse::<Field>().is_err());
+        assert!("capsule 1 1 1 1 1 1 0.5".parse::<Field>().is_err());
+        assert!("union (sphere 1)".parse::<Field>().is_err());
+        assert!("translate 1 2 3 (sphere 1".parse::<Field>().is_err());
+        assert!("sphere 1) (box 1 1 1".parse::<Field>().is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+
+use super::alighned_box::AlighnedBox;
+use super::capsule::Capsule;
+use super::cone::Cone;
+use super::cylinder::Cylinder;
+use super::disk::Disk;
+use super::oriented_box::OrientedBox;
+use super::quad::Quad;
+use super::sphere::Sphere;
+use super::torus::Torus;
+use super::transform::Transform3;
+use super::{Transform, Transformation};
+use crate::basic_geometry::point::Point;
+use crate::basic_geometry::vector::Vector;
+use crate::complex_structures::csg::Csg;
+use crate::ray_tracer::RayTracable;
+
+// Primitive or the CSG solid placed into the scene from the command line, written
+// in the same prefix notation as the signed distance fields.
+#[derive(Debug)]
+pub(crate) enum Shape {
+    Sphere(Sphere),
+    Box(OrientedBox),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Torus(Torus),
+    Capsule(Capsule),
+    Quad(Quad),
+    Disk(Disk),
+    Csg(Csg),
+}
+
+impl Shape {
+    pub(crate) fn into_geometry(self) -> Rc<RefCell<dyn RayTracable>> {
+        match self {
+            Shape::Sphere(sphere) => Rc::new(RefCell::new(sphere)),
+            Shape::Box(oriented) => Rc::new(RefCell::new(oriented)),
+            Shape::Cylinder(cylinder) => Rc::new(RefCell::new(cylinder)),
+            Shape::Cone(cone) => Rc::new(RefCell::new(cone)),
+            Shape::Torus(torus) => Rc::new(RefCell::new(torus)),
+            Shape::Capsule(capsule) => Rc::new(RefCell::new(capsule)),
+            Shape::Quad(quad) => Rc::new(RefCell::new(quad)),
+            Shape::Disk(disk) => Rc::new(RefCell::new(disk)),
+            Shape::Csg(csg) => Rc::new(RefCell::new(csg)),
+        }
+    }
+
+    // Only the closed surfaces have the inside for the set operations.
+    fn into_solid(self) -> Option<Csg> {
+        match self {
+            Shape::Sphere(sphere) => Some(Csg::solid(sphere)),
+            Shape::Box(oriented) => Some(Csg::solid(oriented)),
+            Shape::Cylinder(cylinder) => cylinder.capped().then(|| Csg::solid(cylinder)),
+            Shape::Cone(cone) => cone.capped().then(|| Csg::solid(cone)),
+            Shape::Torus(torus) => Some(Csg::solid(torus)),
+            Shape::Capsule(capsule) => Some(Csg::solid(capsule)),
+            Shape::Quad(_) | Shape::Disk(_) => None,
+            Shape::Csg(csg) => Some(csg),
+        }
+    }
+
+    fn transformed(mut self, transform: Transform3) -> Shape {
+        self.transform(Transformation::Composite(transform));
+        self
+    }
+}
+
+impl Transform for Shape {
+    fn transform(&mut self, transformation: Transformation) {
+        match self {
+            Shape::Sphere(sphere) => sphere.transform(transformation),
+            Shape::Box(oriented) => oriented.transform(transformation),
+            Shape::Cylinder(cylinder) => cylinder.transform(transformation),
+            Shape::Cone(cone) => cone.transform(transformation),
+            Shape::Torus(torus) => torus.transform(transformation),
+            Shape::Capsule(capsule) => capsule.transform(transformation),
+            Shape::Quad(quad) => quad.transform(transformation),
+            Shape::Disk(disk) => disk.transform(transformation),
+            Shape::Csg(csg) => csg.transform(transformation),
+        }
+    }
+}
+
+impl FromStr for Shape {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Shape> {
+        let spaced = s.replace('(', " ( ").replace(')', " ) ");
+        let mut tokens = spaced.split_whitespace().peekable();
+        let shape = parse(&mut tokens)?;
+        if let Some(token) = tokens.next() {
+            bail!("Unexpected {} after the shape", token);
+        }
+        Ok(shape)
+    }
+}
+
+fn parse<'a>(
+    tokens: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+) -> anyhow::Result<Shape> {
+    let name = tokens.next().ok_or_else(|| anyhow!("Missing shape"))?;
+    let mut v = vec![];
+    while let Some(value) = tokens.peek().and_then(|token| token.parse::<f64>().ok()) {
+        v.push(value);
+        tokens.next();
+    }
+    let mut children = vec![];
+    while tokens.peek() == Some(&"(") {
+        tokens.next();
+        children.push(parse(tokens)?);
+        if tokens.next() != Some(")") {
+            bail!("Missing ) in {}", name);
+        }
+    }
+    let vector = |i: usize| Vector::new(v[i], v[i + 1], v[i + 2]);
+    let point = |i: usize| Point::from(vector(i));
+    // The constructors give nothing for the degenerate sizes.
+    let shape = match (name, v.len(), children.len()) {
+        ("sphere", 4, 0) if v[3] > 0. => Some(Shape::Sphere(Sphere::new(point(0), v[3]))),
+        ("box", 6, 0) if (0..3).all(|i| v[i] < v[i + 3]) => {
+            let bounds = AlighnedBox::new(point(0), point(3));
+            Some(Shape::Box(OrientedBox::new(bounds)))
+        }
+        ("cylinder", 7, 0) => Cylinder::new(point(0), point(3), v[6], true).map(Shape::Cylinder),
+        ("tube", 7, 0) => Cylinder::new(point(0), point(3), v[6], false).map(Shape::Cylinder),
+        ("cone", 7, 0) => Cone::new(point(0), point(3), v[6], true).map(Shape::Cone),
+        ("open-cone", 7, 0) => Cone::new(point(0), point(3), v[6], false).map(Shape::Cone),
+        ("torus", 8, 0) => Torus::new(point(0), vector(3), v[6], v[7]).map(Shape::Torus),
+        ("capsule", 7, 0) => Capsule::new(point(0), point(3), v[6]).map(Shape::Capsule),
+        ("quad", 9, 0) => Quad::new(point(0), vector(3), vector(6)).map(Shape::Quad),
+        ("disk", 7, 0) if vector(3).length() > 0. && v[6] > 0. => Some(Shape::Disk(Disk::new(
+            point(0),
+            v[6],
+            vector(3).normalize(),
+        ))),
+        ("translate", 3, 1) => Some(
+            children
+                .remove(0)
+                .transformed(Transform3::translation(vector(0))),
+        ),
+        ("rotate", 4, 1) => Transform3::rotation(vector(0), v[3])
+            .map(|rotation| children.remove(0).transformed(rotation)),
+        ("scale", 3, 1) => {
+            Transform3::scale(vector(0)).map(|scale| children.remove(0).transformed(scale))
+        }
+        ("union" | "intersection" | "difference", 0, 2) => {
+            let operation: fn(Csg, Csg) -> Csg = match name {
+                "union" => Csg::union,
+                "intersection" => Csg::intersection,
+                _ => Csg::difference,
+            };
+            let solids: Option<Vec<_>> = children.into_iter().map(Shape::into_solid).collect();
+            let Some([a, b]) = solids.and_then(|solids| <[Csg; 2]>::try_from(solids).ok()) else {
+                bail!("Only the closed shapes can be combined by {}", name);
+            };
+            Some(Shape::Csg(operation(a, b)))
+        }
+        _ => None,
+    };
+    shape.ok_or_else(|| anyhow!("Incorrect shape {} with {:?}", name, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::normal::Normal;
+    use crate::basic_geometry::ray::Ray;
+
+    #[test]
+    fn parse_shapes() {
+        let shape: Shape = "translate 0 0 -5 (rotate 1 0 0 90 (cylinder 0 0 0 0 0 2 1))"
+            .parse()
+            .unwrap();
+        assert!(matches!(shape, Shape::Cylinder(_)));
+        let geometry = shape.into_geometry();
+        // The axis is turned from +z to -y.
+        let ray = Ray::new(Point::new(5., -1., -5.), Normal::new(-1., 0., 0.));
+        let intersection = geometry.borrow().intersect(&ray).unwrap();
+        assert!((intersection.distance() - 4.).abs() < 1e-9);
+        assert!("quad 0 0 0 1 0 0 0 1 0".parse::<Shape>().is_ok());
+        assert!("sphere 0 0 0".parse::<Shape>().is_err());
+        assert!("translate 1 2 3 (sphere 0 0 0 1".parse::<Shape>().is_err());
+    }
+
+    #[test]
+    fn reject_degenerate_shapes() {
+        assert!("sphere 0 0 0 0".parse::<Shape>().is_err());
+        assert!("box 0 0 0 1 0 1".parse::<Shape>().is_err());
+        assert!("cylinder 1 1 1 1 1 1 1".parse::<Shape>().is_err());
+        assert!("cone 0 0 0 0 1 0 -1".parse::<Shape>().is_err());
+        assert!("torus 0 0 0 0 1 0 1 0".parse::<Shape>().is_err());
+        assert!("capsule 0 0 0 0 1 0 0".parse::<Shape>().is_err());
+        assert!("quad 0 0 0 1 0 0 2 0 0".parse::<Shape>().is_err());
+        assert!("disk 0 0 0 0 0 0 1".parse::<Shape>().is_err());
+        assert!("scale 1 0 1 (sphere 0 0 0 1)".parse::<Shape>().is_err());
+        assert!("rotate 0 0 0 90 (sphere 0 0 0 1)".parse::<Shape>().is_err());
+    }
+
+    #[test]
+    fn parse_csg() {
+        let shape: Shape = "difference (box -1 -1 -1 1 1 1) (cylinder 0 0 -2 0 0 2 0.5)"
+            .parse()
+            .unwrap();
+        assert!(matches!(shape, Shape::Csg(_)));
+        let geometry = shape.into_geometry();
+        // Through the drilled hole.
+        let ray = Ray::new(Point::new(0., 0., -5.), Normal::new(0., 0., 1.));
+        assert_eq!(geometry.borrow().intersect(&ray), None);
+        let ray = Ray::new(Point::new(0.7, 0., -5.), Normal::new(0., 0., 1.));
+        assert!(geometry.borrow().intersect(&ray).is_some());
+    }
+
+    #[test]
+    fn rotated_box_in_csg() {
+        let shape: Shape = "intersection (rotate 0 1 0 45 (box -1 -1 -1 1 1 1)) (sphere 0 0 0 5)"
+            .parse()
+            .unwrap();
+        let geometry = shape.into_geometry();
+        // Past the turned edge, the box around the moved corners would be hit.
+        let ray = Ray::new(Point::new(-1.2, 5., -1.2), Normal::new(0., -1., 0.));
+        assert_eq!(geometry.borrow().intersect(&ray), None);
+        let ray = Ray::new(Point::new(-5., 0., 0.), Normal::new(1., 0., 0.));
+        let intersection = geometry.borrow().intersect(&ray).unwrap();
+        assert!((intersection.distance() - (5. - 2f64.sqrt())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reject_open_surfaces_in_csg() {
+        assert!("union (sphere 0 0 0 1) (tube 0 0 0 0 1 0 1)"
+            .parse::<Shape>()
+            .is_err());
+        assert!("difference (quad 0 0 0 1 0 0 0 1 0) (sphere 0 0 0 1)"
+            .parse::<Shape>()
+            .is_err());
+        assert!("union (sphere 0 0 0 1)".parse::<Shape>().is_err());
+    }
+}
@@ -1,102 +1,125 @@
 use super::alighned_box::AlighnedBox;
-use super::{Intersect, Intersection, NormalAtPoint, Transform, Transformation};
+use super::transform::Transform3;
+use super::{
+    closest_hit, Intersect, Intersection, Interval, NormalAtPoint, Transform, Transformation,
+};
 use crate::basic_geometry::normal::Normal;
 use crate::basic_geometry::point::Point;
 use crate::basic_geometry::ray::Ray;
 use crate::basic_geometry::vector::Vector;
 use crate::complex_structures::BoundingBox;
 
+// Unit sphere around the origin of its own space, placed in the world by the transform.
+// Non-uniform scales turn it into the ellipsoid.
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct Sphere {
-    center: Point,
-    radius: f64,
+    // Object to world transform.
+    transform: Transform3,
 }
 
 impl Sphere {
     #[allow(dead_code)]
     pub(crate) fn new(center: Point, radius: f64) -> Sphere {
-        Sphere { center, radius }
+        Sphere {
+            transform: Transform3::translation(Vector::from(center))
+                * Transform3::from(Transformation::Scale(Vector::new(radius, radius, radius))),
+        }
     }
-}
 
-impl Intersect for Sphere {
-    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
-        let k = Vector::from(ray.origin) - Vector::from(self.center);
-        let a = ray.direction.dot(ray.direction);
-        let b = 2. * k.dot(Vector::from(ray.direction));
-        let c = k.dot(k) - self.radius * self.radius;
-        let discriminant = b * b - 4. * a * c;
+    // Spherical coordinates of the point in the object space: the longitude around
+    // the y axis and the latitude from the bottom, both from 0 to 1. They turn
+    // together with the sphere, so the texture follows its rotation.
+    pub(crate) fn uv_at_point(&self, point: &Point) -> (f64, f64) {
+        let local = Vector::from(self.transform.inverse().point(*point));
+        let local = Vector::from(local.normalize());
+        let u = 0.5 + local.z.atan2(local.x) / (2.0 * std::f64::consts::PI);
+        let v = 0.5 + local.y.clamp(-1.0, 1.0).asin() / std::f64::consts::PI;
+        (u, v)
+    }
+
+    // Both distances where the ray crosses the surface, the nearer first.
+    fn crossings(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        // The rays leaving the surface start right next to it, so the terms that cancel out
+        // there are computed without the subtraction of the squares.
+        let k = Vector::from(local.origin);
+        let direction = Vector::from(local.direction);
+        let a = direction.dot(direction);
+        let b = 2. * k.dot(direction);
+        let distance = k.length();
+        let c = (distance - 1.) * (distance + 1.);
+        // Distance from the center to the line, the discriminant is `4a(r^2 - l^2)`.
+        let line = (k - direction * (b / (2. * a))).length();
+        let discriminant = 4. * a * (1. + line) * (1. - line);
         if discriminant < 0. {
             return None;
         }
         let square_descriminant = discriminant.sqrt();
-        let t1 = (-b - square_descriminant) / (2. * a);
-        let t2 = (-b + square_descriminant) / (2. * a);
-
-        if t1 > 0. && t2 > 0. {
-            Some(Intersection::Intersect(t1.min(t2)))
-        } else if t1 > 0. {
-            Some(Intersection::Intersect(t1))
-        } else if t2 > 0. {
-            Some(Intersection::Intersect(t2))
+        // The roots without the cancellation of `-b` and the square root.
+        let q = if b < 0. {
+            -0.5 * (b - square_descriminant)
         } else {
-            None
-        }
+            -0.5 * (b + square_descriminant)
+        };
+        let (t1, t2) = (q / a / scale, c / q / scale);
+        Some((t1.min(t2), t1.max(t2)))
+    }
+}
+
+impl Intersect for Sphere {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (t1, t2) = self.crossings(ray)?;
+        closest_hit([t1, t2]).map(Intersection::Intersect)
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        self.crossings(ray)
+            .map(|(enter, exit)| Interval { enter, exit })
+            .into_iter()
+            .collect()
     }
 }
 
 impl NormalAtPoint for Sphere {
     fn normal_at_point(&self, point: &Point, _: Intersection) -> Normal {
-        (Vector::from(*point) - Vector::from(self.center)).normalize()
+        let local = self.transform.inverse().point(*point);
+        self.transform.normal(Vector::from(local).normalize())
     }
 }
 
 impl Transform for Sphere {
     fn transform(&mut self, transformation: Transformation) {
-        match transformation {
-            Transformation::Translation(_) => {
-                self.center = transformation.transformation_to_matrix() * self.center;
-            }
-            Transformation::Scale(scale) => {
-                self.center = transformation.transformation_to_matrix() * self.center;
-                // TODO: if scale is not uniform, this will not work, because it suppose to become a ellipsoid
-                self.radius *= scale.x.max(scale.y).max(scale.z);
-            }
-            Transformation::Rotation(_, _) => {}
-        }
+        self.transform = Transform3::from(transformation) * self.transform;
     }
 }
 
 impl BoundingBox for Sphere {
+    // The unit sphere stretched along the world axis reaches as far as the length
+    // of the corresponding row of the linear part.
     fn bounding_box(&self) -> AlighnedBox {
-        AlighnedBox::new(
-            Point::new(
-                self.center.x - self.radius,
-                self.center.y - self.radius,
-                self.center.z - self.radius,
-            ),
-            Point::new(
-                self.center.x + self.radius,
-                self.center.y + self.radius,
-                self.center.z + self.radius,
-            ),
-        )
+        let center = self.transform.point(Point::new(0., 0., 0.));
+        let matrix = self.transform.matrix();
+        let row = |i: usize| {
+            (0..3)
+                .map(|j| matrix[i][j] * matrix[i][j])
+                .sum::<f64>()
+                .sqrt()
+        };
+        let extent = Vector::new(row(0), row(1), row(2));
+        AlighnedBox::new(center + -extent, center + extent)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::basic_geometry::normal::Normal;
+    use crate::basic_geometry::{normal::Normal, Axis};
 
     use super::*;
 
     #[test]
     fn intersection_test() {
         let ray = Ray::new(Point::new(0., 0., 0.), Normal::new(0., 1., 0.));
-        let sphere = Sphere {
-            center: Point::new(0., 5., 0.),
-            radius: 1.,
-        };
+        let sphere = Sphere::new(Point::new(0., 5., 0.), 1.);
 
         assert_eq!(sphere.intersect(&ray), Some(Intersection::Intersect(4.)));
     }
@@ -104,10 +127,7 @@ mod tests {
     #[test]
     fn intersection_fail_test() {
         let ray = Ray::new(Point::new(0., 0., 0.), Normal::new(1., 0., 0.));
-        let sphere = Sphere {
-            center: Point::new(0., 5., 0.),
-            radius: 0.5,
-        };
+        let sphere = Sphere::new(Point::new(0., 5., 0.), 0.5);
 
         assert_eq!(sphere.intersect(&ray), None);
     }
@@ -115,11 +135,82 @@ mod tests {
     #[test]
     fn intersection_behind_test() {
         let ray = Ray::new(Point::new(0., 0., 0.), Normal::new(0., -1., 0.));
-        let sphere = Sphere {
-            center: Point::new(0., 5., 0.),
-            radius: 1.,
-        };
+        let sphere = Sphere::new(Point::new(0., 5., 0.), 1.);
 
         assert_eq!(sphere.intersect(&ray), None);
     }
+
+    #[test]
+    fn spawned_rays_leave_the_surface() {
+        let sphere = Sphere::new(Point::new(20., 20., 20.), 5.);
+        let origin = Point::new(0., 0., 275.);
+        for i in 0..400 {
+            let target = Point::new(16. + 0.02 * i as f64, 24. - 0.015 * i as f64, 20.);
+            let ray = Ray::new(origin, (target - origin).normalize());
+            let Some(intersection) = sphere.intersect(&ray) else {
+                continue;
+            };
+            let point = ray.at(intersection.distance());
+            let normal = sphere.normal_at_point(&point, intersection);
+            let surface = ray.surface_point(intersection.distance(), normal);
+            let reflected = ray.reflect_from_normal(&surface, normal);
+            assert!(sphere.intersect(&reflected).is_none());
+            let light = Point::new(0., 400., 200.);
+            let shadow = surface.spawn((light - point).normalize());
+            if normal.dot((light - point).normalize()) > 0. {
+                assert!(sphere.intersect(&shadow).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn scaled_sphere_is_ellipsoid() {
+        let mut ellipsoid = Sphere::new(Point::new(0., 0., 0.), 1.);
+        ellipsoid.transform(Transformation::Scale(Vector::new(4., 1., 2.)));
+        ellipsoid.transform(Transformation::Rotation(Axis::Z, 90.));
+        ellipsoid.transform(Transformation::Translation(Vector::new(0., 0., 10.)));
+        // The long axis turned from x to y.
+        let bounds = ellipsoid.bounding_box();
+        let close = |a: Point, b: Point| (a - b).length() < 1e-9;
+        assert!(close(bounds.min, Point::new(-1., -4., 8.)));
+        assert!(close(bounds.max, Point::new(1., 4., 12.)));
+        let down = Ray::new(Point::new(0., 10., 10.), Normal::new(0., -1., 0.));
+        let distance = ellipsoid.intersect(&down).unwrap().distance();
+        assert!((distance - 6.).abs() < 1e-9);
+        let side = Ray::new(Point::new(10., 0., 10.), Normal::new(-1., 0., 0.));
+        assert!((ellipsoid.intersect(&side).unwrap().distance() - 9.).abs() < 1e-9);
+        // Off the axes the normal isn't the direction from the center.
+        let origin = Point::new(0.5, 0.5, 100.);
+        let ray = Ray::new(origin, Normal::new(0., 0., -1.));
+        let intersection = ellipsoid.intersect(&ray).unwrap();
+        let point = ray.at(intersection.distance());
+        let normal = Vector::from(ellipsoid.normal_at_point(&point, intersection));
+        // Gradient of x^2 + (y/4)^2 + ((z-10)/2)^2.
+        let gradient = Vector::from(
+            Vector::new(2. * point.x, 2. * point.y / 16., 2. * (point.z - 10.) / 4.).normalize(),
+        );
+        assert!((normal - gradient).length() < 1e-9);
+        assert!(ellipsoid
+            .intersect(&Ray::new(
+                Point::new(1.5, 0., 100.),
+                Normal::new(0., 0., -1.)
+            ))
+            .is_none());
+    }
+
+    #[test]
+    fn uv_follows_rotation() {
+        let mut sphere = Sphere::new(Point::new(1., 2., 3.), 2.);
+        let point = Point::new(3., 2., 3.);
+        let (u, v) = sphere.uv_at_point(&point);
+        assert!((u - 0.5).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+        sphere.transform(Transformation::Translation(Vector::new(-1., -2., -3.)));
+        sphere.transform(Transformation::Rotation(Axis::Y, 90.));
+        sphere.transform(Transformation::Translation(Vector::new(1., 2., 3.)));
+        // The same texel turned a quarter around the y axis.
+        let (u, v) = sphere.uv_at_point(&Point::new(1., 2., 1.));
+        assert!((u - 0.5).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+        let (u, _) = sphere.uv_at_point(&point);
+        assert!((u - 0.75).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,263 @@
+use std::f64::consts::PI;
+
+use super::alighned_box::AlighnedBox;
+use super::transform::Transform3;
+use super::{
+    closest_hit, Intersect, Intersection, Interval, NormalAtPoint, Transform, Transformation,
+};
+use crate::basic_geometry::normal::Normal;
+use crate::basic_geometry::point::Point;
+use crate::basic_geometry::ray::Ray;
+use crate::basic_geometry::vector::Vector;
+use crate::basic_types::polynomial::solve_quartic;
+use crate::complex_structures::BoundingBox;
+
+// Torus around the y axis of its own space, placed in the world by the transform.
+// The tube of the minor radius goes around the circle of the major one.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Torus {
+    // Object to world transform.
+    transform: Transform3,
+    major: f64,
+    minor: f64,
+}
+
+impl Torus {
+    // `None` without the axis or with the radii which aren't positive.
+    pub(crate) fn new(center: Point, axis: Vector, major: f64, minor: f64) -> Option<Torus> {
+        if !(major > 0. && minor > 0.) {
+            return None;
+        }
+        let rotation = Transform3::from_to(Vector::new(0., 1., 0.), axis)?;
+        Some(Torus {
+            transform: Transform3::translation(Vector::from(center)) * rotation,
+            major,
+            minor,
+        })
+    }
+
+    // The angle around the axis and the angle around the tube.
+    pub(crate) fn uv_at_point(&self, point: &Point) -> (f64, f64) {
+        let local = self.transform.inverse().point(*point);
+        let radial = (local.x * local.x + local.z * local.z).sqrt();
+        let u = 0.5 + local.z.atan2(local.x) / (2. * PI);
+        let v = 0.5 + local.y.atan2(radial - self.major) / (2. * PI);
+        (u, v)
+    }
+
+    // Distances of all crossings of the surface in the ascending order, also behind
+    // the origin.
+    fn crossings(&self, ray: &Ray) -> Vec<f64> {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        let d = Vector::from(local.direction);
+        let bound = self.major + self.minor;
+        // The quartic loses the precision far from the torus, so the origin is moved
+        // to where the line enters the bounding sphere first.
+        let closest = -Vector::from(local.origin).dot(d);
+        if (local.at(closest) - Point::new(0., 0., 0.)).length() > bound {
+            return vec![];
+        }
+        let start = closest - bound;
+        let o = Vector::from(local.at(start));
+        let (r2, m2) = (self.major * self.major, self.minor * self.minor);
+        let n = o.dot(d);
+        let k = o.dot(o) + r2 - m2;
+        let roots = solve_quartic(
+            4. * n,
+            4. * n * n + 2. * k - 4. * r2 * (d.x * d.x + d.z * d.z),
+            4. * n * k - 8. * r2 * (o.x * d.x + o.z * d.z),
+            k * k - 4. * r2 * (o.x * o.x + o.z * o.z),
+        );
+        roots.into_iter().map(|t| (t + start) / scale).collect()
+    }
+}
+
+impl Intersect for Torus {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        closest_hit(self.crossings(ray)).map(Intersection::Intersect)
+    }
+
+    // The tangent rays give the double roots, which come out as one root or as two
+    // close ones, so the crossings can't be paired in turns. The close ones are dropped
+    // and a point between each two of the rest tells whether the ray is inside of the
+    // tube there. It isn't the midpoint, which is where the symmetric rays touch it.
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let inverse = self.transform.inverse();
+        let (_, scale) = ray.transformed(&inverse);
+        let epsilon = 1e-7 * self.minor / scale;
+        let mut crossings: Vec<f64> = vec![];
+        for t in self.crossings(ray) {
+            if crossings.last().is_some_and(|last| t - last < epsilon) {
+                crossings.pop();
+            } else {
+                crossings.push(t);
+            }
+        }
+        let inside = |t: f64| {
+            let local = inverse.point(ray.at(t));
+            let radial = (local.x * local.x + local.z * local.z).sqrt() - self.major;
+            radial * radial + local.y * local.y < self.minor * self.minor
+        };
+        let mut intervals: Vec<Interval> = vec![];
+        for pair in crossings.windows(2) {
+            if !inside(pair[0] + 0.382 * (pair[1] - pair[0])) {
+                continue;
+            }
+            match intervals.last_mut() {
+                // The root between them is only the touch from the inside.
+                Some(last) if last.exit == pair[0] => last.exit = pair[1],
+                _ => intervals.push(Interval {
+                    enter: pair[0],
+                    exit: pair[1],
+                }),
+            }
+        }
+        intervals
+    }
+}
+
+impl NormalAtPoint for Torus {
+    // From the closest point of the major circle, more precise than the gradient.
+    fn normal_at_point(&self, point: &Point, _: Intersection) -> Normal {
+        let local = Vector::from(self.transform.inverse().point(*point));
+        let radial = Vector::new(local.x, 0., local.z);
+        let circle = if radial.length() < 1e-12 {
+            radial
+        } else {
+            Vector::from(radial.normalize()) * self.major
+        };
+        self.transform.normal((local - circle).normalize())
+    }
+}
+
+impl Transform for Torus {
+    fn transform(&mut self, transformation: Transformation) {
+        self.transform = Transform3::from(transformation) * self.transform;
+    }
+}
+
+impl BoundingBox for Torus {
+    fn bounding_box(&self) -> AlighnedBox {
+        let (outer, minor) = (self.major + self.minor, self.minor);
+        let local = AlighnedBox::new(
+            Point::new(-outer, -minor, -outer),
+            Point::new(outer, minor, outer),
+        );
+        self.transform.bounding_box(&local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::close;
+
+    fn torus() -> Torus {
+        Torus::new(Point::new(0., 0., 0.), Vector::new(0., 1., 0.), 3., 1.).unwrap()
+    }
+
+    #[test]
+    fn outer_side_intersection() {
+        let torus = torus();
+        let ray = Ray::new(Point::new(-10., 0., 0.), Normal::new(1., 0., 0.));
+        let intersection = torus.intersect(&ray).unwrap();
+        assert!((intersection.distance() - 6.).abs() < 1e-9);
+        let normal = torus.normal_at_point(&ray.at(6.), intersection);
+        assert!(close(Vector::from(normal), Vector::new(-1., 0., 0.)));
+    }
+
+    #[test]
+    fn intervals_through_both_sides() {
+        let ray = Ray::new(Point::new(-10., 0., 0.), Normal::new(1., 0., 0.));
+        let intervals = torus().intervals(&ray);
+        assert_eq!(intervals.len(), 2);
+        assert!((intervals[0].enter - 6.).abs() < 1e-9 && (intervals[0].exit - 8.).abs() < 1e-9);
+        assert!((intervals[1].enter - 12.).abs() < 1e-9 && (intervals[1].exit - 14.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tangent_to_the_top_encloses_nothing() {
+        let ray = Ray::new(Point::new(-10., 1., 0.), Normal::new(1., 0., 0.));
+        assert!(torus().intervals(&ray).is_empty());
+    }
+
+    #[test]
+    fn touching_the_inner_side_from_inside() {
+        // Inside of the tube all the way, the inner equator is touched halfway.
+        let ray = Ray::new(Point::new(-10., 0., 2.), Normal::new(1., 0., 0.));
+        let intervals = torus().intervals(&ray);
+        let half = 12f64.sqrt();
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter - (10. - half)).abs() < 1e-6);
+        assert!((intervals[0].exit - (10. + half)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn inner_side_from_the_hole() {
+        let ray = Ray::new(Point::new(0., 0., 0.), Normal::new(0., 0., 1.));
+        assert!((torus().intersect(&ray).unwrap().distance() - 2.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn down_through_the_hole() {
+        let ray = Ray::new(Point::new(0., 5., 0.), Normal::new(0., -1., 0.));
+        assert_eq!(torus().intersect(&ray), None);
+    }
+
+    #[test]
+    fn down_through_the_tube() {
+        let ray = Ray::new(Point::new(3.5, 5., 0.), Normal::new(0., -1., 0.));
+        let expected = 5. - (1. - 0.25f64).sqrt();
+        assert!((torus().intersect(&ray).unwrap().distance() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn intersection_behind() {
+        let ray = Ray::new(Point::new(-10., 0., 0.), Normal::new(-1., 0., 0.));
+        assert_eq!(torus().intersect(&ray), None);
+    }
+
+    #[test]
+    fn far_and_tilted() {
+        let center = Point::new(100., -50., 20.);
+        let torus = Torus::new(center, Vector::new(1., 0., 0.), 2., 0.5).unwrap();
+        let bounds = torus.bounding_box();
+        assert!(close(bounds.max - bounds.min, Vector::new(1., 5., 5.)));
+        let origin = Point::new(100., -50., 1e4);
+        let ray = Ray::new(origin, Normal::new(0., 0., -1.));
+        let intersection = torus.intersect(&ray).unwrap();
+        let point = ray.at(intersection.distance());
+        assert!((point - Point::new(100., -50., 22.5)).length() < 1e-6);
+        let (u, v) = torus.uv_at_point(&point);
+        assert!((0. ..=1.).contains(&u) && (v - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn spawned_rays_leave_the_surface() {
+        let torus = Torus::new(Point::new(0., 0., 0.), Vector::new(0., 1., 1.), 3., 1.).unwrap();
+        let origin = Point::new(1., 2., 30.);
+        for i in 0..400 {
+            let target = Point::new(-4. + 0.02 * i as f64, 4. - 0.02 * i as f64, 0.);
+            let ray = Ray::new(origin, (target - origin).normalize());
+            let Some(intersection) = torus.intersect(&ray) else {
+                continue;
+            };
+            let point = ray.at(intersection.distance());
+            let normal = torus.normal_at_point(&point, intersection);
+            assert!(normal.dot(ray.direction) < 0.);
+            let surface = ray.surface_point(intersection.distance(), normal);
+            let reflected = ray.reflect_from_normal(&surface, normal);
+            if let Some(next) = torus.intersect(&reflected) {
+                // The tube can be hit again, but not right next to the point.
+                assert!(next.distance() > 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_input() {
+        let center = Point::new(0., 0., 0.);
+        assert!(Torus::new(center, Vector::new(0., 0., 0.), 3., 1.).is_none());
+        assert!(Torus::new(center, Vector::new(0., 1., 0.), 3., 0.).is_none());
+    }
+}
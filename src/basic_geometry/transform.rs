@@ -0,0 +1,360 @@
+use std::ops::Mul;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+
+use super::alighned_box::AlighnedBox;
+use super::matrix::Matrix;
+use super::normal::Normal;
+use super::point::Point;
+use super::quaternion::Quaternion;
+use super::vector::Vector;
+use super::Transformation;
+
+// Affine transform together with its inverse, so none of them is computed per ray.
+// Points are moved with the translation, vectors without it, and normals by
+// the inverse transpose to stay perpendicular to the surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Transform3 {
+    matrix: Matrix<4, 4>,
+    inverse: Matrix<4, 4>,
+}
+
+impl Transform3 {
+    pub(crate) fn identity() -> Transform3 {
+        Transform3 {
+            matrix: Matrix::identity(),
+            inverse: Matrix::identity(),
+        }
+    }
+
+    // Transform given by the matrix, if it can be inverted.
+    pub(crate) fn new(matrix: Matrix<4, 4>) -> Option<Transform3> {
+        matrix
+            .inverse()
+            .map(|inverse| Transform3 { matrix, inverse })
+    }
+
+    // The caller guarantees that `inverse` is the inverse of `matrix`.
+    pub(crate) fn with_inverse(matrix: Matrix<4, 4>, inverse: Matrix<4, 4>) -> Transform3 {
+        Transform3 { matrix, inverse }
+    }
+
+    pub(crate) fn translation(vector: Vector) -> Transform3 {
+        Transform3::from(Transformation::Translation(vector))
+    }
+
+    // `None` if any of the factors is zero.
+    pub(crate) fn scale(vector: Vector) -> Option<Transform3> {
+        let zero = vector.x == 0.0 || vector.y == 0.0 || vector.z == 0.0;
+        (!zero).then(|| Transform3::from(Transformation::Scale(vector)))
+    }
+
+    // Rotation in degrees around the axis going through the origin.
+    pub(crate) fn rotation(axis: Vector, degrees: f64) -> Option<Transform3> {
+        Quaternion::from_axis_angle(axis, degrees).map(Transform3::from)
+    }
+
+    // Scale, then rotation, then translation, the usual placement of the model.
+    pub(crate) fn trs(
+        translation: Vector,
+        rotation: Quaternion,
+        scale: Vector,
+    ) -> Option<Transform3> {
+        Some(
+            Transform3::translation(translation)
+                * Transform3::from(rotation)
+                * Transform3::scale(scale)?,
+        )
+    }
+
+    // Places the object at `position` with its +z axis looking at the target
+    // and its +y axis as close to `up` as possible.
+    pub(crate) fn look_at(position: Point, target: Point, up: Vector) -> Option<Transform3> {
+        let forward = target - position;
+        let side = up.cross(forward);
+        if forward.length() < 1e-12 || side.length() < 1e-12 * forward.length() * up.length() {
+            return None;
+        }
+        let z = Vector::from(forward.normalize());
+        let x = Vector::from(side.normalize());
+        let y = z.cross(x);
+        let rotation = Matrix::with_data([
+            [x.x, y.x, z.x, 0.0],
+            [x.y, y.y, z.y, 0.0],
+            [x.z, y.z, z.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let inverse = Matrix::with_data([
+            [x.x, x.y, x.z, 0.0],
+            [y.x, y.y, y.z, 0.0],
+            [z.x, z.y, z.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Some(
+            Transform3::translation(Vector::from(position))
+                * Transform3::with_inverse(rotation, inverse),
+        )
+    }
+
+    // Places the object space y axis onto the segment, the y axis is scaled by its length
+    // and the other ones by the radius. `None` for the empty segment or radius.
+    pub(crate) fn along_segment(from: Point, to: Point, radius: f64) -> Option<Transform3> {
+        let axis = to - from;
+        if !(radius > 0. && axis.length() > 0.) {
+            return None;
+        }
+        let rotation = Transform3::from_to(Vector::new(0., 1., 0.), axis)?;
+        let scale = Vector::new(radius, axis.length(), radius);
+        Some(
+            Transform3::translation(Vector::from(from))
+                * rotation
+                * Transform3::from(Transformation::Scale(scale)),
+        )
+    }
+
+    // Rotation turning the `from` direction into `to`, e.g. Z-up models into Y-up.
+    pub(crate) fn from_to(from: Vector, to: Vector) -> Option<Transform3> {
+        Quaternion::from_to(from, to).map(Transform3::from)
+    }
+
+    pub(crate) fn matrix(&self) -> &Matrix<4, 4> {
+        &self.matrix
+    }
+
+    pub(crate) fn inverse(&self) -> Transform3 {
+        Transform3 {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub(crate) fn point(&self, point: Point) -> Point {
+        self.matrix * point
+    }
+
+    pub(crate) fn vector(&self, vector: Vector) -> Vector {
+        self.matrix * vector
+    }
+
+    // Box around the moved corners of the box.
+    pub(crate) fn bounding_box(&self, bounds: &AlighnedBox) -> AlighnedBox {
+        bounds
+            .corners()
+            .iter()
+            .fold(AlighnedBox::default(), |acc, &corner| {
+                acc.union_point(self.point(corner))
+            })
+    }
+
+    pub(crate) fn normal(&self, normal: Normal) -> Normal {
+        let n = [normal.x, normal.y, normal.z];
+        let row = |i: usize| (0..3).map(|j| self.inverse[j][i] * n[j]).sum::<f64>();
+        Vector::new(row(0), row(1), row(2)).normalize()
+    }
+}
+
+impl From<Transformation> for Transform3 {
+    fn from(transformation: Transformation) -> Transform3 {
+        Transform3 {
+            matrix: transformation.transformation_to_matrix(),
+            inverse: transformation.inverse().transformation_to_matrix(),
+        }
+    }
+}
+
+impl From<Quaternion> for Transform3 {
+    fn from(rotation: Quaternion) -> Transform3 {
+        Transform3 {
+            matrix: rotation.matrix(),
+            inverse: rotation.conjugate().matrix(),
+        }
+    }
+}
+
+// `a * b` applies `b` first, the same as the matrices.
+impl Mul for Transform3 {
+    type Output = Transform3;
+    fn mul(self, rhs: Transform3) -> Transform3 {
+        Transform3 {
+            matrix: self.matrix * rhs.matrix,
+            inverse: rhs.inverse * self.inverse,
+        }
+    }
+}
+
+// Transformations separated by ';' and applied in the order they are written:
+// `scale x y z`, `scale s`, `rotate axis_x axis_y axis_z degrees`, `quaternion w x y z`,
+// `translate x y z`, `from-to from_x from_y from_z to_x to_y to_z`
+// and `look-at px py pz tx ty tz up_x up_y up_z`.
+impl FromStr for Transform3 {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Transform3> {
+        let mut transform = Transform3::identity();
+        for step in s.split(';').map(str::trim).filter(|step| !step.is_empty()) {
+            let mut words = step.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let v = words
+                .map(str::parse::<f64>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| anyhow!("{}: {}", step, e))?;
+            let vector = |i: usize| Vector::new(v[i], v[i + 1], v[i + 2]);
+            let next = match (name, v.len()) {
+                ("translate", 3) => Some(Transform3::translation(vector(0))),
+                ("scale", 1) => Transform3::scale(Vector::new(v[0], v[0], v[0])),
+                ("scale", 3) => Transform3::scale(vector(0)),
+                ("rotate", 4) => Transform3::rotation(vector(0), v[3]),
+                ("quaternion", 4) => Quaternion::new(v[0], v[1], v[2], v[3]).map(Transform3::from),
+                ("from-to", 6) => Transform3::from_to(vector(0), vector(3)),
+                ("look-at", 9) => {
+                    Transform3::look_at(Point::from(vector(0)), Point::from(vector(3)), vector(6))
+                }
+                _ => bail!("Unknown transformation {}", step),
+            };
+            transform =
+                next.ok_or_else(|| anyhow!("Degenerate transformation {}", step))? * transform;
+        }
+        Ok(transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::Axis;
+    use crate::test_utils::close;
+
+    #[test]
+    fn translation_moves_only_points() {
+        let transform = Transform3::from(Transformation::Translation(Vector::new(1., 2., 3.)));
+        assert_eq!(
+            transform.point(Point::new(1., 1., 1.)),
+            Point::new(2., 3., 4.)
+        );
+        assert_eq!(
+            transform.vector(Vector::new(1., 1., 1.)),
+            Vector::new(1., 1., 1.)
+        );
+        assert_eq!(
+            Vector::from(transform.normal(Normal::new(0., 1., 0.))),
+            Vector::new(0., 1., 0.)
+        );
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let transform = Transform3::from(Transformation::Translation(Vector::new(5., 0., -2.)))
+            * Transform3::from(Transformation::Rotation(Axis::Z, 30.))
+            * Transform3::from(Transformation::Scale(Vector::new(4., 1., 0.5)));
+        // Plane x + y + z = 0 and two of its directions.
+        let normal = Vector::new(1., 1., 1.).normalize();
+        let tangents = [Vector::new(1., -1., 0.), Vector::new(0., 1., -1.)];
+        let moved = Vector::from(transform.normal(normal));
+        for tangent in tangents {
+            assert!(moved.dot(transform.vector(tangent)).abs() < 1e-9);
+        }
+        // The forward matrix would tilt the normal of the non-uniformly scaled plane.
+        let wrong = transform.vector(Vector::from(normal));
+        assert!(wrong.dot(transform.vector(tangents[0])).abs() > 1e-3);
+    }
+
+    #[test]
+    fn composition_and_inverse() {
+        let transform = Transform3::from(Transformation::Rotation(Axis::X, 40.))
+            * Transform3::from(Transformation::Scale(Vector::new(2., 3., -1.)))
+            * Transform3::from(Transformation::Translation(Vector::new(1., -7., 2.)));
+        let computed = Transform3::new(*transform.matrix()).unwrap();
+        let point = Point::new(3., -2., 5.);
+        let vector = Vector::new(-1., 4., 2.);
+        let moved = transform.point(point);
+        assert!(close(
+            Vector::from(computed.inverse().point(moved)),
+            Vector::from(point)
+        ));
+        assert!(close(
+            Vector::from(transform.inverse().point(moved)),
+            Vector::from(point)
+        ));
+        assert!(close(
+            transform.inverse().vector(transform.vector(vector)),
+            vector
+        ));
+        let singular = Transform3::from(Transformation::Scale(Vector::new(1., 0., 1.)));
+        assert!(Transform3::new(*singular.matrix()).is_none());
+    }
+
+    #[test]
+    fn look_at_and_from_to() {
+        let transform = Transform3::look_at(
+            Point::new(1., 2., 3.),
+            Point::new(1., 2., 10.),
+            Vector::new(0., 1., 0.),
+        )
+        .unwrap();
+        assert_eq!(
+            transform.point(Point::new(0., 0., 0.)),
+            Point::new(1., 2., 3.)
+        );
+        let transform = Transform3::look_at(
+            Point::new(0., 0., 0.),
+            Point::new(5., 0., 0.),
+            Vector::new(0., 1., 0.),
+        )
+        .unwrap();
+        assert!(close(
+            transform.vector(Vector::new(0., 0., 1.)),
+            Vector::new(1., 0., 0.)
+        ));
+        assert!(close(
+            transform.vector(Vector::new(0., 1., 0.)),
+            Vector::new(0., 1., 0.)
+        ));
+        assert!(close(
+            transform.inverse().vector(Vector::new(1., 0., 0.)),
+            Vector::new(0., 0., 1.)
+        ));
+        let up = Vector::new(0., 1., 0.);
+        assert!(Transform3::look_at(Point::new(0., 0., 0.), Point::new(0., 3., 0.), up).is_none());
+        let z_up = Transform3::from_to(Vector::new(0., 0., 1.), up).unwrap();
+        assert!(close(
+            z_up.vector(Vector::new(0., 0., 2.)),
+            Vector::new(0., 2., 0.)
+        ));
+    }
+
+    #[test]
+    fn parse_stack() {
+        let transform: Transform3 = "scale 2; rotate 0 0 1 90; translate 1 0 0".parse().unwrap();
+        let expected = Transform3::trs(
+            Vector::new(1., 0., 0.),
+            Quaternion::from_axis_angle(Vector::new(0., 0., 1.), 90.).unwrap(),
+            Vector::new(2., 2., 2.),
+        )
+        .unwrap();
+        let point = Point::new(1., 1., 1.);
+        assert!(close(
+            Vector::from(transform.point(point)),
+            Vector::new(-1., 2., 2.)
+        ));
+        assert!(close(
+            Vector::from(transform.point(point)),
+            Vector::from(expected.point(point))
+        ));
+        assert!("".parse::<Transform3>().is_ok());
+        assert!("scale 0".parse::<Transform3>().is_err());
+        assert!("rotate 0 0 0 90".parse::<Transform3>().is_err());
+        assert!("shear 1 2".parse::<Transform3>().is_err());
+        assert!("translate 1 x 3".parse::<Transform3>().is_err());
+    }
+
+    #[test]
+    fn along_segment() {
+        let from = Point::new(1., 1., 1.);
+        let transform = Transform3::along_segment(from, Point::new(1., 1., 4.), 2.).unwrap();
+        let top = transform.point(Point::new(0., 1., 0.));
+        assert!((top - Point::new(1., 1., 4.)).length() < 1e-9);
+        assert!(Transform3::along_segment(from, from, 2.).is_none());
+        assert!(Transform3::along_segment(from, Point::new(2., 1., 1.), 0.).is_none());
+    }
+}
@@ -1,9 +1,11 @@
 use crate::{basic_geometry::point::Point, complex_structures::BoundingBox};
 
 use super::{
-    alighned_box::AlighnedBox, normal::Normal, ray::Ray, vector::Vector, Intersect, Intersection,
-    NormalAtPoint, Transform, Transformation,
+    alighned_box::AlighnedBox, normal::Normal, ray::Ray, ray_packet::RayPacket,
+    transform::Transform3, vector::Vector, Axis, Intersect, Intersection, NormalAtPoint, Transform,
+    Transformation,
 };
+use crate::basic_types::simd::{F64x4, Mask4};
 
 #[derive(Debug, Clone)]
 pub(crate) struct Triangle {
@@ -53,41 +55,121 @@ impl Triangle {
             normal_at_point: true,
         }
     }
+
+    // Bounds of the part of the triangle inside of the box. The triangle is clipped
+    // by the box planes one by one (Sutherland-Hodgman).
+    pub(crate) fn clipped_bounds(&self, clip: &AlighnedBox) -> Option<AlighnedBox> {
+        let mut polygon = vec![self.a, self.b, self.c];
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            for (plane, inside) in [(clip.min[axis], 1.0), (clip.max[axis], -1.0)] {
+                let distance = |p: &Vector| (p[axis] - plane) * inside;
+                let mut clipped = Vec::with_capacity(polygon.len() + 1);
+                for (i, current) in polygon.iter().enumerate() {
+                    let next = &polygon[(i + 1) % polygon.len()];
+                    let (d1, d2) = (distance(current), distance(next));
+                    if d1 >= 0.0 {
+                        clipped.push(*current);
+                    }
+                    if (d1 >= 0.0) != (d2 >= 0.0) {
+                        clipped.push(*current + (*next - *current) * (d1 / (d1 - d2)));
+                    }
+                }
+                polygon = clipped;
+                if polygon.is_empty() {
+                    return None;
+                }
+            }
+        }
+        let bounds = polygon.iter().fold(AlighnedBox::default(), |acc, &p| {
+            acc.union_point(Point::from(p))
+        });
+        bounds.intersection(clip)
+    }
+}
+
+impl Triangle {
+    // `intersect` of the rays in the given lanes of the packet, computed together
+    // with the same operations, so the hits are exactly the same.
+    pub(crate) fn intersect_packet(
+        &self,
+        packet: &RayPacket,
+        lanes: Mask4,
+    ) -> [Option<Intersection>; 4] {
+        // Vertices relative to the origins, in the axes of the ray space of every lane.
+        let vertex = |vertex: Vector| -> [F64x4; 3] {
+            std::array::from_fn(|k| {
+                let axes = std::array::from_fn(|lane| vertex[packet.shear[lane].axes[k]]);
+                F64x4::new(axes) - packet.permuted_origin[k]
+            })
+        };
+        let (a, b, c) = (vertex(self.a), vertex(self.b), vertex(self.c));
+        let [shear_x, shear_y, shear_z] = packet.shear_lanes;
+        let (ax, ay) = (a[0] - shear_x * a[2], a[1] - shear_y * a[2]);
+        let (bx, by) = (b[0] - shear_x * b[2], b[1] - shear_y * b[2]);
+        let (cx, cy) = (c[0] - shear_x * c[2], c[1] - shear_y * c[2]);
+        let u = cx * by - cy * bx;
+        let v = ax * cy - ay * cx;
+        let w = bx * ay - by * ax;
+        let zero = F64x4::splat(0.0);
+        let negative = u.lt(zero) | v.lt(zero) | w.lt(zero);
+        let positive = u.gt(zero) | v.gt(zero) | w.gt(zero);
+        let det = u + v + w;
+        let mut valid = lanes & !(negative & positive) & !det.eq(zero);
+        if !valid.any() {
+            return [None; 4];
+        }
+        let (az, bz, cz) = (shear_z * a[2], shear_z * b[2], shear_z * c[2]);
+        let t = u * az + v * bz + w * cz;
+        valid = valid & ((det.lt(zero) & t.lt(zero)) | (det.gt(zero) & t.gt(zero)));
+        let inverse_det = F64x4::splat(1.0) / det;
+        let (t, v, w) = (
+            (t * inverse_det).to_array(),
+            (v * inverse_det).to_array(),
+            (w * inverse_det).to_array(),
+        );
+        std::array::from_fn(|lane| {
+            valid
+                .test(lane)
+                .then(|| Intersection::TriangleIntesersect(t[lane], v[lane], w[lane]))
+        })
+    }
 }
 
 impl Intersect for Triangle {
+    // Watertight test (Woop et al.). The triangle is moved into the space where the ray
+    // is the z axis. The edge functions of a shared edge are computed from the same numbers
+    // for both triangles, so the ray can't slip between them.
     fn intersect(&self, ray: &Ray) -> Option<Intersection> {
-        let ab = self.b - self.a;
-        let ac = self.c - self.a;
-        let normal = Vector::from(ray.direction).cross(ac);
-
-        let d = ab.dot(normal);
-        if d.abs() < f64::EPSILON {
+        let shear = ray.shear();
+        let [x, y, z] = shear.axes;
+        let origin = Vector::from(ray.origin);
+        let (a, b, c) = (self.a - origin, self.b - origin, self.c - origin);
+        let (ax, ay) = (a[x] - shear.x * a[z], a[y] - shear.y * a[z]);
+        let (bx, by) = (b[x] - shear.x * b[z], b[y] - shear.y * b[z]);
+        let (cx, cy) = (c[x] - shear.x * c[z], c[y] - shear.y * c[z]);
+        // Edge functions, the ray through an edge or a vertex gets zeros.
+        let u = cx * by - cy * bx;
+        let v = ax * cy - ay * cx;
+        let w = bx * ay - by * ax;
+        if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
             return None;
         }
-
-        let inv_d = 1.0 / d;
-        let ao = Vector::from(ray.origin) - self.a;
-        let u_coordinate = ao.dot(normal) * inv_d;
-        if !(0.0..=1.0).contains(&u_coordinate) {
+        let det = u + v + w;
+        if det == 0.0 {
             return None;
         }
-
-        let ao_cross_ab = ao.cross(ab);
-        let v_coordinate = Vector::from(ray.direction).dot(ao_cross_ab) * inv_d;
-        if v_coordinate < 0.0 || u_coordinate + v_coordinate > 1.0 {
+        let (az, bz, cz) = (shear.z * a[z], shear.z * b[z], shear.z * c[z]);
+        // Scaled distance, it has the sign of `det` in front of the origin.
+        let t = u * az + v * bz + w * cz;
+        if !(det < 0.0 && t < 0.0 || det > 0.0 && t > 0.0) {
             return None;
         }
-        let t = ac.dot(ao_cross_ab) * inv_d;
-        if t > f64::EPSILON {
-            Some(Intersection::TriangleIntesersect(
-                t,
-                u_coordinate,
-                v_coordinate,
-            ))
-        } else {
-            None
-        }
+        let inverse_det = 1.0 / det;
+        Some(Intersection::TriangleIntesersect(
+            t * inverse_det,
+            v * inverse_det,
+            w * inverse_det,
+        ))
     }
 }
 
@@ -101,20 +183,29 @@ impl NormalAtPoint for Triangle {
                     self.na
                 }
             }
-            _ => panic!("Called with wrong intersaction type"),
+            _ => panic!("Called with wrong intersection type"),
+        }
+    }
+
+    fn geometric_normal(&self, _: &Point, _: Intersection) -> Normal {
+        if self.normal_at_point {
+            (self.b - self.a).cross(self.c - self.a).normalize()
+        } else {
+            self.na
         }
     }
 }
 
 impl Transform for Triangle {
     fn transform(&mut self, transform: Transformation) {
-        let matrix = transform.transformation_to_matrix();
-        self.a = matrix * self.a;
-        self.b = matrix * self.b;
-        self.c = matrix * self.c;
-        self.na = matrix * self.na;
-        self.nb = matrix * self.nb;
-        self.nc = matrix * self.nc;
+        let transform = Transform3::from(transform);
+        let point = |vertex: Vector| Vector::from(transform.point(Point::from(vertex)));
+        self.a = point(self.a);
+        self.b = point(self.b);
+        self.c = point(self.c);
+        self.na = transform.normal(self.na);
+        self.nb = transform.normal(self.nb);
+        self.nc = transform.normal(self.nc);
     }
 }
 
@@ -138,10 +229,12 @@ impl BoundingBox for Triangle {
 
 #[cfg(test)]
 mod tests {
+    use crate::basic_geometry::alighned_box::AlighnedBox;
     use crate::basic_geometry::normal::Normal;
     use crate::basic_geometry::point::Point;
     use crate::basic_geometry::ray::Ray;
     use crate::basic_geometry::triangle::Triangle;
+    use crate::basic_geometry::vector::Vector;
     use crate::basic_geometry::*;
 
     #[test]
@@ -168,4 +261,125 @@ mod tests {
         let ray = Ray::new(Point::new(1.1, 0.5, 2.0), Normal::new(0., 0.0, -0.5));
         assert_eq!(triangle.intersect(&ray), None);
     }
+
+    #[test]
+    fn clipped_bounds() {
+        let triangle = Triangle::new(
+            Point::new(0., 0., 0.),
+            Point::new(4., 0., 0.),
+            Point::new(0., 4., 0.),
+        );
+        let clip = AlighnedBox::new(Point::new(3., -1., -1.), Point::new(5., 5., 1.));
+        let bounds = triangle.clipped_bounds(&clip).unwrap();
+        assert_eq!((bounds.min.x, bounds.min.y), (3., 0.));
+        assert_eq!((bounds.max.x, bounds.max.y), (4., 1.));
+        let outside = AlighnedBox::new(Point::new(3., 3., -1.), Point::new(5., 5., 1.));
+        assert!(triangle.clipped_bounds(&outside).is_none());
+    }
+
+    #[test]
+    fn hits_on_edges_and_vertices() {
+        let triangle = Triangle::new(
+            Point::new(0., 0., 0.),
+            Point::new(1., 0., 0.),
+            Point::new(0., 1., 0.),
+        );
+        let down = Normal::new(0., 0., -1.);
+        let hit = |x, y| triangle.intersect(&Ray::new(Point::new(x, y, 1.), down));
+        for (x, y) in [
+            (0., 0.),
+            (1., 0.),
+            (0., 1.),
+            (0.5, 0.),
+            (0., 0.5),
+            (0.5, 0.5),
+        ] {
+            assert!(hit(x, y).is_some(), "{} {}", x, y);
+        }
+        assert_eq!(
+            hit(1., 0.),
+            Some(Intersection::TriangleIntesersect(1., 1., 0.))
+        );
+        for (x, y) in [(-1e-12, 0.5), (0.5, -1e-12), (0.5 + 1e-12, 0.5)] {
+            assert!(hit(x, y).is_none(), "{} {}", x, y);
+        }
+        // Edge on, the ray lies in the plane of the triangle.
+        let along = Ray::new(Point::new(-1., 0.5, 0.), Normal::new(1., 0., 0.));
+        assert!(triangle.intersect(&along).is_none());
+        // Behind the origin.
+        let up = Ray::new(Point::new(0.2, 0.2, 1.), Normal::new(0., 0., 1.));
+        assert!(triangle.intersect(&up).is_none());
+    }
+
+    #[test]
+    fn shared_edges_are_watertight() {
+        // Quad split along the diagonal, the rays go exactly through the diagonal.
+        let (a, b, c, d) = (
+            Point::new(0.1, 0.3, 0.7),
+            Point::new(7.3, 0.2, 1.9),
+            Point::new(7.1, 5.3, 0.3),
+            Point::new(0.3, 4.9, 2.1),
+        );
+        let triangles = [Triangle::new(a, b, c), Triangle::new(a, c, d)];
+        let origin = Point::new(3.3, 2.7, 9.1);
+        for i in 1..1000 {
+            let t = i as f64 / 1000.0;
+            let target = a + (c - a) * t;
+            let ray = Ray::new(origin, (target - origin).normalize());
+            assert!(
+                triangles
+                    .iter()
+                    .any(|triangle| triangle.intersect(&ray).is_some()),
+                "{}",
+                t
+            );
+        }
+    }
+
+    #[test]
+    fn spawned_rays_leave_the_surface() {
+        // Far from the origin, where the fixed offsets were too small.
+        let offset = Vector::new(1e5, -3e5, 2e5);
+        let triangle = Triangle::new(
+            Point::new(0., 0., 0.) + offset,
+            Point::new(10., 1., 0.) + offset,
+            Point::new(0., 10., 2.) + offset,
+        );
+        let origin = Point::new(2., 3., 50.) + offset;
+        for i in 0..200 {
+            let target = Point::new(0.03 * i as f64, 0.04 * i as f64, 0.) + offset;
+            let ray = Ray::new(origin, (target - origin).normalize());
+            let Some(intersection) = triangle.intersect(&ray) else {
+                continue;
+            };
+            let point = ray.at(intersection.distance());
+            let surface = ray.surface_point(
+                intersection.distance(),
+                triangle.geometric_normal(&point, intersection),
+            );
+            let reflected =
+                ray.reflect_from_normal(&surface, triangle.normal_at_point(&point, intersection));
+            assert!(triangle.intersect(&reflected).is_none());
+            assert!(triangle.intersect(&surface.spawn(ray.direction)).is_none());
+        }
+    }
+
+    #[test]
+    fn transformed_normals_follow_the_surface() {
+        let (a, b, c) = (
+            Point::new(0., 0., 0.),
+            Point::new(1., 0., 1.),
+            Point::new(0., 1., 1.),
+        );
+        let normal = (b - a).cross(c - a).normalize();
+        let mut triangle = Triangle::with_normals(a, normal, b, normal, c, normal);
+        triangle.transform(Transformation::Scale(Vector::new(3., 1., 0.25)));
+        triangle.transform(Transformation::Translation(Vector::new(5., -2., 1.)));
+        let point = Point::new(0., 0., 0.);
+        let intersection = Intersection::TriangleIntesersect(1., 0.3, 0.3);
+        let shading = triangle.normal_at_point(&point, intersection);
+        let geometric = triangle.geometric_normal(&point, intersection);
+        assert!((shading.dot(geometric) - 1.).abs() < 1e-12);
+        assert_eq!(triangle.a, Vector::new(5., -2., 1.));
+    }
 }
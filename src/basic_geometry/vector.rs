@@ -6,7 +6,7 @@ use crate::basic_geometry::point::Point;
 
 use super::Axis;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) struct Vector {
     pub(crate) x: f64,
     pub(crate) y: f64,
@@ -42,11 +42,8 @@ impl Vector {
         Normal::new(self.x / length, self.y / length, self.z / length)
     }
 
-    pub(crate) fn inverse(&self) -> Vector {
-        let x = 1. / self.x;
-        let y = 1. / self.y;
-        let z = 1. / self.z;
-        Vector::new(x, y, z)
+    pub(crate) fn abs(&self) -> Vector {
+        Vector::new(self.x.abs(), self.y.abs(), self.z.abs())
     }
 }
 
@@ -1 +1,4 @@
 pub(crate) mod bounded;
+pub(crate) mod polynomial;
+pub(crate) mod random;
+pub(crate) mod simd;
@@ -10,6 +10,10 @@ impl<T: PartialOrd + Copy + PartialOrd + PartialEq> Bounded<T> {
         Bounded { val }
     }
 
+    pub(crate) fn value(&self) -> T {
+        self.val
+    }
+
     pub(crate) fn get_saturated(&self, min: T, max: T) -> T {
         Bounded::saturate(self.val, min, max)
     }
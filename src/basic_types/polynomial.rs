@@ -0,0 +1,147 @@
+// Real roots of the polynomials, in the ascending order. The quadratic and the
+// quadratic factors of the quartic avoid the cancellation of `-b` and the square root.
+
+pub(crate) fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 { vec![] } else { vec![-c / b] };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        // Both `b` and `c` are zero.
+        return vec![0.0, 0.0];
+    }
+    let (x1, x2) = (q / a, c / q);
+    vec![x1.min(x2), x1.max(x2)]
+}
+
+// x^3 + a*x^2 + b*x + c = 0
+pub(crate) fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let shift = a / 3.0;
+    if r * r < q * q * q {
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        let scale = -2.0 * q.sqrt();
+        let third = 2.0 * std::f64::consts::PI / 3.0;
+        let mut roots: Vec<_> = (0..3)
+            .map(|k| scale * (theta / 3.0 + third * k as f64).cos() - shift)
+            .collect();
+        roots.sort_by(f64::total_cmp);
+        roots
+    } else {
+        let big = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let small = if big == 0.0 { 0.0 } else { q / big };
+        vec![big + small - shift]
+    }
+}
+
+// x^4 + a*x^3 + b*x^2 + c*x + d = 0, by Ferrari's factorisation into two quadratics.
+// The roots are polished by the Newton's method on the original polynomial.
+pub(crate) fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // The depressed quartic y^4 + p*y^2 + q*y + r for x = y - a/4.
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = c - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+    let mut roots = if q.abs() < 1e-12 * (1.0 + p.abs() + r.abs()) {
+        // Biquadratic, a quadratic of y^2.
+        solve_quadratic(1.0, p, r)
+            .into_iter()
+            .filter(|&z| z >= 0.0)
+            .flat_map(|z| [-z.sqrt(), z.sqrt()])
+            .collect::<Vec<_>>()
+    } else {
+        // The largest root of the resolvent cubic is positive as q isn't zero.
+        let m = solve_cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return vec![];
+        }
+        let s = (2.0 * m).sqrt();
+        let mut roots = solve_quadratic(1.0, s, p / 2.0 + m - q / (2.0 * s));
+        roots.extend(solve_quadratic(1.0, -s, p / 2.0 + m + q / (2.0 * s)));
+        roots
+    };
+    for root in roots.iter_mut() {
+        *root = polish(*root - a / 4.0, [1.0, a, b, c, d]);
+    }
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+// Newton's method, stops when it doesn't improve the root.
+fn polish(mut x: f64, coefficients: [f64; 5]) -> f64 {
+    let evaluate = |x: f64| {
+        coefficients
+            .iter()
+            .fold((0.0, 0.0), |(value, derivative), &k| {
+                (value * x + k, derivative * x + value)
+            })
+    };
+    for _ in 0..4 {
+        let (value, derivative) = evaluate(x);
+        if derivative == 0.0 {
+            break;
+        }
+        let next = x - value / derivative;
+        if !next.is_finite() || evaluate(next).0.abs() >= value.abs() {
+            break;
+        }
+        x = next;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(actual: Vec<f64>, expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn quadratic_and_cubic() {
+        assert_roots(solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0]);
+        assert_roots(solve_quadratic(2.0, 0.0, 1.0), &[]);
+        assert_roots(solve_quadratic(0.0, 2.0, -4.0), &[2.0]);
+        // (x - 1)(x - 2)(x + 3)
+        assert_roots(solve_cubic(0.0, -7.0, 6.0), &[-3.0, 1.0, 2.0]);
+        // (x - 2)(x^2 + 1)
+        assert_roots(solve_cubic(-2.0, 1.0, -2.0), &[2.0]);
+    }
+
+    #[test]
+    fn quartic() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(
+            solve_quartic(-10.0, 35.0, -50.0, 24.0),
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+        // (x^2 - 4)(x^2 - 9), biquadratic
+        assert_roots(
+            solve_quartic(0.0, -13.0, 0.0, 36.0),
+            &[-3.0, -2.0, 2.0, 3.0],
+        );
+        // (x - 0.5)(x + 7)(x^2 + 1)
+        assert_roots(solve_quartic(6.5, -2.5, 6.5, -3.5), &[-7.0, 0.5]);
+        // (x^2 + 1)(x^2 + 2)
+        assert_roots(solve_quartic(0.0, 3.0, 0.0, 2.0), &[]);
+        // Roots far from each other, like the ones of the ray starting far from the torus.
+        let roots = [-1000.0, -999.5, 0.25, 0.75];
+        let [r1, r2, r3, r4] = roots;
+        let a = -(r1 + r2 + r3 + r4);
+        let b = r1 * r2 + r1 * r3 + r1 * r4 + r2 * r3 + r2 * r4 + r3 * r4;
+        let c = -(r1 * r2 * r3 + r1 * r2 * r4 + r1 * r3 * r4 + r2 * r3 * r4);
+        let d = r1 * r2 * r3 * r4;
+        assert_roots(solve_quartic(a, b, c, d), &roots);
+    }
+}
@@ -0,0 +1,10 @@
+// Xorshift numbers from 0 to 1, the same ones for every run with the seed.
+pub(crate) fn random(seed: u64) -> impl FnMut() -> f64 {
+    let mut state = seed;
+    move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,303 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::basic_geometry::{
+    point::Point, ray::Ray, ray_packet::RayPacket, vector::Vector, Intersection,
+};
+use crate::basic_types::random::random;
+use crate::complex_structures::bvh::builder::{BvhBuilder, Strategy};
+use crate::complex_structures::bvh::bvh4::Bvh4;
+use crate::complex_structures::bvh::BVHTree;
+use crate::complex_structures::grid::Grid;
+use crate::complex_structures::kd_tree::KdTree;
+use crate::io::obj_file::ObjectFile;
+use crate::ray_tracer::animation::CameraPath;
+use crate::ray_tracer::camera::Camera;
+use crate::ray_tracer::object::MeshTriangle;
+use crate::ray_tracer::viewframe::ViewFrame;
+use crate::ray_tracer::ObjectContainer;
+
+const IMAGE_SIZE: usize = 500;
+const RANDOM_RAYS: usize = IMAGE_SIZE * IMAGE_SIZE;
+const SAMPLES_DIRECTORY: &str = "samples";
+
+// All the bundled models.
+pub(crate) fn samples() -> anyhow::Result<Vec<PathBuf>> {
+    let mut models = vec![];
+    for entry in std::fs::read_dir(SAMPLES_DIRECTORY)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "obj") {
+            models.push(path);
+        }
+    }
+    models.sort();
+    Ok(models)
+}
+
+// Compares the BVH traversals, builders and the acceleration structures on the models:
+// the camera rays of the 500x500 image looking at the model, and random rays starting
+// inside of its bounding box.
+pub(crate) fn run(models: &[PathBuf]) -> anyhow::Result<()> {
+    compare_traversals(models)?;
+    println!();
+    compare_builders(models)?;
+    println!();
+    compare_structures(models)?;
+    println!();
+    compare_simd(models)
+}
+
+fn compare_traversals(models: &[PathBuf]) -> anyhow::Result<()> {
+    println!(
+        "{:<20} {:<8} {:>14} {:>14} {:>8} {:>10}",
+        "Model", "Rays", "Breadth-first", "Front-to-back", "Speedup", "Mismatches"
+    );
+    for path in models {
+        let (meshes, _) = ObjectFile::new(path.clone()).load_triangles()?;
+        let tree = BVHTree::build(meshes.concat(), BvhBuilder::default());
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for (kind, rays) in [
+            ("camera", camera_rays(&tree)),
+            ("random", random_rays(&tree)),
+        ] {
+            let (old, old_hits) = measure(&rays, |ray| tree.trace_breadth_first(ray));
+            let (new, new_hits) = measure(&rays, |ray| tree.trace(ray));
+            let mismatches = old_hits
+                .iter()
+                .zip(&new_hits)
+                .filter(|(a, b)| !same_hit(**a, **b))
+                .count();
+            println!(
+                "{:<20} {:<8} {:>9.2} Mr/s {:>9.2} Mr/s {:>7.2}x {:>10}",
+                name,
+                kind,
+                rays_per_second(rays.len(), old) / 1e6,
+                rays_per_second(rays.len(), new) / 1e6,
+                old.as_secs_f64() / new.as_secs_f64().max(f64::EPSILON),
+                mismatches
+            );
+        }
+    }
+    Ok(())
+}
+
+fn compare_builders(models: &[PathBuf]) -> anyhow::Result<()> {
+    let strategies = [
+        Strategy::Sweep,
+        Strategy::Binned(12),
+        Strategy::Binned(32),
+        Strategy::Spatial(12),
+        Strategy::Morton,
+    ];
+    println!(
+        "{:<20} {:<10} {:>10} {:>8} {:>8} {:>10} {:>10} {:>10}",
+        "Model", "Builder", "Build", "Nodes", "Refs", "SAH cost", "Steps/ray", "Rays/s"
+    );
+    for path in models {
+        let (meshes, _) = ObjectFile::new(path.clone()).load_triangles()?;
+        let triangles = meshes.concat();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for strategy in strategies {
+            let tree = BVHTree::build(triangles.clone(), BvhBuilder::with_strategy(strategy));
+            let rays = camera_rays(&tree);
+            let steps: usize = rays.iter().map(|ray| tree.traversal_steps(ray)).sum();
+            let (duration, _) = measure(&rays, |ray| tree.trace(ray));
+            let stats = tree.stats();
+            println!(
+                "{:<20} {:<10} {:>7.1} ms {:>8} {:>8} {:>10.2} {:>10.2} {:>5.2} Mr/s",
+                name,
+                strategy.to_string(),
+                stats.duration.as_secs_f64() * 1000.0,
+                stats.nodes,
+                stats.references,
+                stats.sah_cost,
+                steps as f64 / rays.len() as f64,
+                rays_per_second(rays.len(), duration) / 1e6,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn compare_structures(models: &[PathBuf]) -> anyhow::Result<()> {
+    println!(
+        "{:<20} {:<10} {:>10} {:>8} {:>8} {:>14} {:>14} {:>10}",
+        "Model", "Structure", "Build", "Nodes", "Refs", "Camera", "Random", "Mismatches"
+    );
+    for path in models {
+        let (meshes, _) = ObjectFile::new(path.clone()).load_triangles()?;
+        let triangles = meshes.concat();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let bvh = BVHTree::build(triangles.clone(), BvhBuilder::default());
+        let rays = [camera_rays(&bvh), random_rays(&bvh)];
+        let expected: Vec<_> = rays
+            .iter()
+            .map(|rays| measure(rays, |ray| bvh.trace(ray)).1)
+            .collect();
+        let start = Instant::now();
+        let kd_tree = KdTree::build(triangles.clone());
+        let kd_tree_build = start.elapsed();
+        let start = Instant::now();
+        let grid = Grid::build(triangles.clone());
+        let grid_build = start.elapsed();
+        let structures: [(&dyn ObjectContainer, Duration, usize); 3] = [
+            (&bvh, bvh.stats().duration, bvh.stats().references),
+            (&kd_tree, kd_tree_build, kd_tree.references()),
+            (&grid, grid_build, grid.references()),
+        ];
+        for (structure, build, references) in structures {
+            let mut speeds = vec![];
+            let mut mismatches = 0;
+            for (rays, expected) in rays.iter().zip(&expected) {
+                let (duration, hits) = measure(rays, |ray| structure.trace(ray));
+                speeds.push(rays_per_second(rays.len(), duration) / 1e6);
+                mismatches += hits
+                    .iter()
+                    .zip(expected)
+                    .filter(|(a, b)| !same_hit(**a, **b))
+                    .count();
+            }
+            println!(
+                "{:<20} {:<10} {:>7.1} ms {:>8} {:>8} {:>9.2} Mr/s {:>9.2} Mr/s {:>10}",
+                name,
+                structure.name(),
+                build.as_secs_f64() * 1000.0,
+                structure.nodes_count(),
+                references,
+                speeds[0],
+                speeds[1],
+                mismatches
+            );
+        }
+    }
+    Ok(())
+}
+
+// The camera rays traced one by one and in 2x2 pixel packets, with the binary and the wide tree.
+fn compare_simd(models: &[PathBuf]) -> anyhow::Result<()> {
+    println!(
+        "{:<20} {:<14} {:>14} {:>8} {:>10}",
+        "Model", "Traversal", "Camera", "Speedup", "Mismatches"
+    );
+    for path in models {
+        let (meshes, _) = ObjectFile::new(path.clone()).load_triangles()?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let bvh = BVHTree::build(meshes.concat(), BvhBuilder::default());
+        let bvh4 = Bvh4::from_tree(BVHTree::build(meshes.concat(), BvhBuilder::default()));
+        let packets = camera_packets(&camera_rays(&bvh));
+        let rays: Vec<_> = packets.iter().flat_map(|packet| packet.rays).collect();
+        // Also warms up the caches, the speedups are relative to the first traversal.
+        let (_, expected) = measure(&rays, |ray| bvh.trace(ray));
+        let mut base = None;
+        let traversals: [(&str, &dyn ObjectContainer, bool); 4] = [
+            ("BVH", &bvh, false),
+            ("BVH packets", &bvh, true),
+            ("BVH4", &bvh4, false),
+            ("BVH4 packets", &bvh4, true),
+        ];
+        for (traversal, structure, packed) in traversals {
+            let (duration, hits) = if packed {
+                let start = Instant::now();
+                let hits: Vec<_> = packets
+                    .iter()
+                    .flat_map(|packet| structure.trace_packet(packet))
+                    .collect();
+                (start.elapsed(), hits)
+            } else {
+                measure(&rays, |ray| structure.trace(ray))
+            };
+            let base = *base.get_or_insert(duration);
+            let mismatches = hits
+                .iter()
+                .zip(&expected)
+                .filter(|(a, b)| !same_hit(**a, **b))
+                .count();
+            println!(
+                "{:<20} {:<14} {:>9.2} Mr/s {:>7.2}x {:>10}",
+                name,
+                traversal,
+                rays_per_second(rays.len(), duration) / 1e6,
+                base.as_secs_f64() / duration.as_secs_f64().max(f64::EPSILON),
+                mismatches
+            );
+        }
+    }
+    Ok(())
+}
+
+fn measure(
+    rays: &[Ray],
+    trace: impl Fn(&Ray) -> Option<(usize, Intersection)>,
+) -> (Duration, Vec<Option<(usize, Intersection)>>) {
+    let start = Instant::now();
+    let hits = rays.iter().map(trace).collect();
+    (start.elapsed(), hits)
+}
+
+fn rays_per_second(rays: usize, duration: Duration) -> f64 {
+    rays as f64 / duration.as_secs_f64().max(f64::EPSILON)
+}
+
+// Both traversals have to find the same closest distance, the primitive can differ
+// only if two of them are hit at the same point.
+fn same_hit(a: Option<(usize, Intersection)>, b: Option<(usize, Intersection)>) -> bool {
+    match (a, b) {
+        (Some((_, a)), Some((_, b))) => (a.distance() - b.distance()).abs() < 1e-9,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn camera_rays(tree: &BVHTree<MeshTriangle>) -> Vec<Ray> {
+    let viewframe = ViewFrame::new(Point::new(0.0, 0.0, -25.0), 25.0, 25.0);
+    let mut camera = Camera::new(Point::new(0.0, 0.0, 0.0), viewframe);
+    CameraPath::turntable(tree.bounding_box(), camera.fov(), 1.0).apply(0.0, &mut camera);
+    (0..IMAGE_SIZE * IMAGE_SIZE)
+        .map(|i| {
+            let (x, y) = (i % IMAGE_SIZE, i / IMAGE_SIZE);
+            camera.ray_for_pixel(
+                x as f64 + 0.5,
+                (IMAGE_SIZE - y) as f64 - 0.5,
+                IMAGE_SIZE,
+                IMAGE_SIZE,
+            )
+        })
+        .collect()
+}
+
+// Camera rays of every 2x2 pixel block together.
+fn camera_packets(rays: &[Ray]) -> Vec<RayPacket> {
+    (0..IMAGE_SIZE / 2)
+        .flat_map(|y| (0..IMAGE_SIZE / 2).map(move |x| (2 * x, 2 * y)))
+        .map(|(x, y)| {
+            let ray = |dx, dy| rays[(y + dy) * IMAGE_SIZE + x + dx];
+            RayPacket::from_rays(&[ray(0, 0), ray(1, 0), ray(0, 1), ray(1, 1)])
+        })
+        .collect()
+}
+
+fn random_rays(tree: &BVHTree<MeshTriangle>) -> Vec<Ray> {
+    let bounds = tree.bounding_box();
+    let size = bounds.max - bounds.min;
+    let mut random = random(0x2545_f491_4f6c_dd1d);
+    (0..RANDOM_RAYS)
+        .map(|_| {
+            let origin =
+                bounds.min + Vector::new(size.x * random(), size.y * random(), size.z * random());
+            let direction = Vector::new(random() - 0.5, random() - 0.5, random() - 0.5);
+            Ray::new(origin, direction.normalize())
+        })
+        .collect()
+}
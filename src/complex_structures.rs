@@ -1,7 +1,206 @@
 use crate::basic_geometry::alighned_box::AlighnedBox;
 
 pub(crate) mod bvh;
+pub(crate) mod csg;
+pub(crate) mod grid;
+pub(crate) mod heightfield;
+pub(crate) mod instance;
+pub(crate) mod kd_tree;
 
 pub(crate) trait BoundingBox {
     fn bounding_box(&self) -> AlighnedBox;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+
+    use super::bvh::{builder::BvhBuilder, bvh4::Bvh4, BVHTree};
+    use super::grid::Grid;
+    use super::kd_tree::KdTree;
+
+    use crate::basic_geometry::{
+        normal::Normal, plane::Plane, point::Point, ray::Ray, ray_packet::RayPacket,
+        vector::Vector, Intersection, Transformation,
+    };
+    use crate::basic_types::random::random;
+    use crate::io::obj_file::ObjectFile;
+    use crate::ray_tracer::object::{MeshTriangle, Object};
+    use crate::ray_tracer::scene::{LinearTracer, Tracing};
+    use crate::ray_tracer::ObjectContainer;
+
+    // Every container gets its own objects, so moving one of them doesn't move the others.
+    fn containers(triangles: &[MeshTriangle]) -> Vec<Box<dyn ObjectContainer>> {
+        let objects = || -> Vec<Object> {
+            triangles
+                .iter()
+                .cloned()
+                .map(MeshTriangle::into_object)
+                .collect()
+        };
+        vec![
+            Box::new(LinearTracer::new(objects())),
+            Box::new(BVHTree::build(objects(), BvhBuilder::default())),
+            Box::new(Bvh4::new(objects(), BvhBuilder::default())),
+            Box::new(KdTree::build(objects())),
+            Box::new(Grid::build(objects())),
+        ]
+    }
+
+    fn load(path: PathBuf) -> Vec<MeshTriangle> {
+        let (meshes, _) = ObjectFile::new(path).load_triangles().unwrap();
+        meshes.concat()
+    }
+
+    // Random rays starting around the model, some of them along the axes
+    // or towards its center.
+    fn rays(container: &dyn ObjectContainer) -> Vec<Ray> {
+        let bounds = container.bounding_box();
+        let size = bounds.max - bounds.min;
+        let mut random = random(0x9e37_79b9_7f4a_7c15);
+        let mut rays = vec![];
+        for i in 0..600 {
+            let origin = bounds.min
+                + Vector::new(
+                    size.x * (random() * 1.2 - 0.1),
+                    size.y * (random() * 1.2 - 0.1),
+                    size.z * (random() * 1.2 - 0.1),
+                );
+            let direction = match i % 6 {
+                0 => Vector::new(1.0, 0.0, 0.0),
+                1 => Vector::new(0.0, -1.0, 0.0),
+                2 => Vector::new(0.0, 0.0, 1.0),
+                3 => bounds.center() - origin,
+                _ => Vector::new(random() - 0.5, random() - 0.5, random() - 0.5),
+            };
+            rays.push(Ray::new(origin, direction.normalize()));
+        }
+        rays
+    }
+
+    fn hit(container: &dyn ObjectContainer, ray: &Ray) -> Option<(usize, f64)> {
+        container
+            .trace(ray)
+            .map(|(i, intersection): (usize, Intersection)| {
+                (
+                    container.material_at(i, intersection),
+                    intersection.distance(),
+                )
+            })
+    }
+
+    fn assert_same_hits(containers: &[Box<dyn ObjectContainer>], rays: &[Ray]) {
+        for ray in rays {
+            let expected = hit(containers[0].as_ref(), ray);
+            for container in &containers[1..] {
+                let actual = hit(container.as_ref(), ray);
+                let same = match (expected, actual) {
+                    (Some(a), Some(b)) => a.0 == b.0 && (a.1 - b.1).abs() < 1e-9,
+                    (None, None) => true,
+                    _ => false,
+                };
+                assert!(
+                    same,
+                    "{}: {:?} != {:?} for {:?}",
+                    container.name(),
+                    actual,
+                    expected,
+                    ray
+                );
+                if let Some((_, distance)) = expected {
+                    assert!(
+                        !container.occluded(ray, distance * 0.99),
+                        "{}",
+                        container.name()
+                    );
+                    assert!(
+                        container.occluded(ray, distance + 1e-6),
+                        "{}",
+                        container.name()
+                    );
+                }
+            }
+        }
+        // The packets find the same hits as the single rays.
+        for container in containers {
+            for rays in rays.chunks(4) {
+                let hits = container.trace_packet(&RayPacket::from_rays(rays));
+                for (ray, actual) in rays.iter().zip(hits) {
+                    let actual = actual.map(|(i, intersection)| {
+                        (
+                            container.material_at(i, intersection),
+                            intersection.distance(),
+                        )
+                    });
+                    assert_eq!(actual, hit(container.as_ref(), ray), "{}", container.name());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn containers_find_same_hits_on_samples() {
+        for path in crate::benchmark::samples().unwrap() {
+            let containers = containers(&load(path));
+            let rays = rays(containers[0].as_ref());
+            assert_same_hits(&containers, &rays);
+        }
+    }
+
+    #[test]
+    fn containers_follow_moved_objects() {
+        let mut containers = containers(&load("samples/teddy-bear.obj".into()));
+        let rays = rays(containers[0].as_ref());
+        let ray = rays
+            .iter()
+            .find(|ray| containers[0].trace(ray).is_some())
+            .unwrap();
+        for container in &mut containers {
+            let (index, _) = container.trace(ray).unwrap();
+            let offset = Vector::new(0.0, 0.0, 3.0) * container.bounding_box().surface_area();
+            container.transform_object(index, Transformation::Translation(offset));
+        }
+        assert_same_hits(&containers, &rays);
+    }
+
+    #[test]
+    fn planes_are_traced_next_to_the_structures() {
+        let triangles = load("samples/teddy-bear.obj".into());
+        let bounds = containers(&triangles)[0].bounding_box();
+        let ground = bounds.min.y - 1.0;
+        let containers: Vec<_> = [
+            Tracing::Linear,
+            Tracing::Bvh,
+            Tracing::Bvh4,
+            Tracing::KdTree,
+            Tracing::Grid,
+        ]
+        .into_iter()
+        .map(|tracing| {
+            let mut objects: Vec<Object> = triangles
+                .iter()
+                .cloned()
+                .map(MeshTriangle::into_object)
+                .collect();
+            let plane = Plane::new(Normal::new(0.0, 1.0, 0.0), Point::new(0.0, ground, 0.0));
+            objects.push(Object::new(Rc::new(RefCell::new(plane)), 7));
+            tracing.container(objects, BvhBuilder::default())
+        })
+        .collect();
+        let mut rays = rays(containers[0].as_ref());
+        // Straight down through the middle of the model and next to it.
+        for dx in [0.0, 1e3] {
+            let origin = bounds.center() + Vector::new(dx, bounds.max.y - bounds.min.y, 0.0);
+            rays.push(Ray::new(origin, Normal::new(0.0, -1.0, 0.0)));
+        }
+        assert_same_hits(&containers, &rays);
+        for container in &containers {
+            assert_eq!(container.objects_count(), triangles.len() + 1);
+            assert_eq!(container.bounding_box().min.y, bounds.min.y);
+            let (index, intersection) = container.trace(rays.last().unwrap()).unwrap();
+            assert_eq!(container.material_at(index, intersection), 7);
+        }
+    }
+}
@@ -1,59 +1,497 @@
-use std::{collections::VecDeque, path::PathBuf};
+pub(crate) mod builder;
+pub(crate) mod bvh4;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Range,
+    time::Duration,
+};
+
+use anyhow::bail;
+use builder::{BuildStats, BvhBuilder};
 
 use crate::{
     basic_geometry::{
-        alighned_box::AlighnedBox, point::Point, ray::Ray, Axis, Intersect, Intersection,
+        alighned_box::{AlighnedBox, Boxes4},
+        normal::Normal,
+        point::Point,
+        ray::Ray,
+        ray_packet::RayPacket,
+        Intersect, Intersection, NormalAtPoint, Transform, Transformation,
     },
+    basic_types::simd::{F64x4, Mask4},
     complex_structures::BoundingBox,
-    io::Input,
-    ray_tracer::{material::Material, object::Object, ObjectContainer},
+    ray_tracer::{
+        object::{MeshTriangle, Object},
+        ObjectContainer,
+    },
 };
 
-struct BVHNode {
-    index: usize,
-    bounding_box: AlighnedBox,
-    left: Option<usize>,
-    right: Option<usize>,
-    start: usize,
-    end: usize,
+// Node of the flattened tree, 32 bytes so two of them fit into a cache line.
+// The nodes are stored depth-first: the first child follows its parent,
+// the second one is at `offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub(crate) struct FlatNode {
+    // Bounds rounded outwards, so the box still contains all the primitives.
+    min: [f32; 3],
+    max: [f32; 3],
+    // First primitive of the leaf or the second child of the interior node.
+    offset: u32,
+    // Primitives in the leaf, zero for the interior nodes.
+    count: u32,
 }
 
-pub(crate) struct BVHTree {
-    data: Vec<Object>,
-    nodes: Vec<BVHNode>,
-    max_primitives_in_node: usize,
-    root: Option<usize>,
+pub(crate) const FLAT_NODE_SIZE: usize = 32;
+const _: () = assert!(std::mem::size_of::<FlatNode>() == FLAT_NODE_SIZE);
+
+// Primitive stored inline in the leaves of the tree. Spatial splits store copies
+// of the primitive in several leaves.
+pub(crate) trait Primitive: Intersect + BoundingBox + Transform + Clone {
+    fn normal_at_point(&self, point: &Point, intersection: Intersection, time: f64) -> Normal;
+    fn geometric_normal(&self, point: &Point, intersection: Intersection, time: f64) -> Normal;
+    fn material_at(&self, intersection: Intersection) -> usize;
+    fn texture_coordinates(
+        &self,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Option<(f64, f64)>;
+    fn describe(&self, intersection: Intersection) -> String;
+
+    // Bounds of the part of the primitive inside of the box.
+    fn clipped_bounds(&self, clip: &AlighnedBox) -> Option<AlighnedBox> {
+        self.bounding_box().intersection(clip)
+    }
+
+    // Hits of the rays in the given lanes of the packet.
+    fn intersect_packet(&self, packet: &RayPacket, lanes: Mask4) -> [Option<Intersection>; 4] {
+        packet.each(lanes, |ray| self.intersect(ray))
+    }
+
+    // Primitives and the tree nodes inside of the primitive, for the instanced meshes.
+    fn primitives_count(&self) -> usize {
+        1
+    }
+
+    fn nodes_count(&self) -> usize {
+        0
+    }
 }
 
-struct BVHObjectInfo {
-    index: usize,
-    bounding_box: AlighnedBox,
-    position: Point,
+// Deeper subtrees become leaves, so the traversal stack never overflows.
+// Every level keeps at most one postponed child on the stack.
+const MAX_DEPTH: usize = 64;
+// Refitted tree is rebuilt once its cost grows by this factor since the last build.
+const REBUILD_COST_FACTOR: f64 = 2.0;
+
+pub(crate) struct BVHTree<P = Object> {
+    data: Vec<P>,
+    nodes: Vec<FlatNode>,
+    // Position of every stored primitive in the primitives the tree was built from.
+    // The callers get these indices, so they don't change when the tree is rebuilt.
+    order: Vec<u32>,
+    // Position of the first copy of every primitive the tree was built from.
+    positions: Vec<u32>,
+    // Number of the primitives the tree was built from.
+    primitives: usize,
+    // Positions of the primitives stored more than once by the spatial splits.
+    copies: HashMap<u32, Vec<u32>>,
+    builder: BvhBuilder,
+    stats: BuildStats,
+    // Cost of the tree right after it was built.
+    built_cost: f64,
 }
 
-impl ObjectContainer for BVHTree {
+impl<P: Primitive> ObjectContainer for BVHTree<P> {
     fn trace(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        self.closest_hit(ray).0
+    }
+
+    fn trace_packet(&self, packet: &RayPacket) -> [Option<(usize, Intersection)>; 4] {
+        self.closest_hits(packet)
+    }
+
+    // The order of the children doesn't matter, any hit closer than the distance is enough.
+    fn occluded(&self, ray: &Ray, max_distance: f64) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let inverse_direction = ray.inverse_direction();
+        let mut stack = [0; MAX_DEPTH];
+        let mut size = 1;
+        while size > 0 {
+            size -= 1;
+            let node_index = stack[size];
+            let node = &self.nodes[node_index];
+            if node
+                .bounds()
+                .entry_distance(ray, inverse_direction, max_distance)
+                .is_none()
+            {
+                continue;
+            }
+            if node.is_leaf() {
+                let hit = self.data[node.primitives()]
+                    .iter()
+                    .any(|object| object.occluded(ray, max_distance));
+                if hit {
+                    return true;
+                }
+                continue;
+            }
+            stack[size] = node.second_child();
+            stack[size + 1] = node_index + 1;
+            size += 2;
+        }
+        false
+    }
+
+    fn normal_at_point(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.primitive(index)
+            .normal_at_point(point, intersection, time)
+    }
+
+    fn geometric_normal(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.primitive(index)
+            .geometric_normal(point, intersection, time)
+    }
+
+    fn material_at(&self, index: usize, intersection: Intersection) -> usize {
+        self.primitive(index).material_at(intersection)
+    }
+
+    fn texture_coordinates(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Option<(f64, f64)> {
+        self.primitive(index)
+            .texture_coordinates(point, intersection, time)
+    }
+
+    fn describe(&self, index: usize, intersection: Intersection) -> String {
+        self.primitive(index).describe(intersection)
+    }
+
+    fn objects_count(&self) -> usize {
+        self.data.len()
+    }
+
+    fn unique_objects_count(&self) -> usize {
+        self.primitives
+    }
+
+    fn total_primitives_count(&self) -> usize {
+        self.unique().map(Primitive::primitives_count).sum()
+    }
+
+    fn total_nodes_count(&self) -> usize {
+        self.nodes.len() + self.unique().map(Primitive::nodes_count).sum::<usize>()
+    }
+
+    fn nodes_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn name(&self) -> &'static str {
+        "BVH"
+    }
+
+    fn bounding_box(&self) -> AlighnedBox {
+        self.nodes.first().map(FlatNode::bounds).unwrap_or_default()
+    }
+
+    // Refits the tree, or rebuilds it if the moved objects made the refitted tree too slow.
+    // Copies of the primitive left by the spatial splits are replaced by the moved one.
+    // The rebuilt tree keeps the indices of the primitives, so the picked object stays picked.
+    fn transform_object(&mut self, index: usize, transformation: Transformation) {
+        let position = self.positions[index] as usize;
+        self.data[position].transform(transformation);
+        if let Some(copies) = self.copies.get(&(index as u32)) {
+            for &i in copies.iter().filter(|&&i| i as usize != position) {
+                self.data[i as usize] = self.data[position].clone();
+            }
+        }
+        self.refit();
+        if self.cost() > self.built_cost * REBUILD_COST_FACTOR {
+            let objects = self.unique_primitives();
+            *self = BVHTree::build(objects, self.builder);
+        }
+    }
+}
+
+impl<P: Primitive> BVHTree<P> {
+    pub(crate) fn new(objects: Vec<P>, builder: BvhBuilder) -> BVHTree<P> {
+        println!("Building BVH tree ({})...", builder.strategy);
+        let tree = BVHTree::build(objects, builder);
+        println!("BVH tree built: {}", tree.stats);
+        tree
+    }
+
+    // Tree restored from the saved nodes, `order` gives the primitive stored at every position.
+    // The layout has to be checked with `check_layout` first.
+    pub(crate) fn from_parts(
+        objects: Vec<P>,
+        nodes: Vec<FlatNode>,
+        order: Vec<u32>,
+        builder: BvhBuilder,
+    ) -> BVHTree<P> {
+        let stats = BuildStats::new(&nodes, order.len(), builder.traversal_cost, Duration::ZERO);
+        // The primitive is moved to its first position and copied to the others.
+        let mut slots: Vec<_> = objects.into_iter().map(Some).collect();
+        let mut positions = vec![0; slots.len()];
+        let mut data: Vec<P> = Vec::with_capacity(order.len());
+        for (position, &i) in order.iter().enumerate() {
+            let primitive = match slots[i as usize].take() {
+                Some(primitive) => {
+                    positions[i as usize] = position as u32;
+                    primitive
+                }
+                None => data[positions[i as usize] as usize].clone(),
+            };
+            data.push(primitive);
+        }
+        let mut tree = BVHTree {
+            data,
+            nodes,
+            copies: copies(&order, slots.len()),
+            order,
+            positions,
+            primitives: slots.len(),
+            builder,
+            stats,
+            built_cost: 0.0,
+        };
+        tree.built_cost = tree.cost();
+        tree
+    }
+
+    // Depth-first traversal visiting the nearer child first. Subtrees entered farther
+    // than the closest hit found so far are skipped. Uses a fixed stack, so nothing is allocated.
+    // Also returns the number of the visited nodes.
+    fn closest_hit(&self, ray: &Ray) -> (Option<(usize, Intersection)>, usize) {
+        let Some(root) = self.nodes.first() else {
+            return (None, 0);
+        };
+        let inverse_direction = ray.inverse_direction();
+        let mut closest = None;
+        let mut max_distance = f64::INFINITY;
+        let mut stack = [(0, 0.0); MAX_DEPTH];
+        let mut size = 0;
+        let mut steps = 0;
+        if let Some(entry) = root
+            .bounds()
+            .entry_distance(ray, inverse_direction, max_distance)
+        {
+            stack[0] = (0, entry);
+            size = 1;
+        }
+        while size > 0 {
+            size -= 1;
+            let (node_index, entry) = stack[size];
+            if entry > max_distance {
+                continue;
+            }
+            let node = &self.nodes[node_index];
+            steps += 1;
+            if node.is_leaf() {
+                for i in node.primitives() {
+                    if let Some(intersection) = self.data[i].intersect(ray) {
+                        if intersection.distance() < max_distance {
+                            max_distance = intersection.distance();
+                            closest = Some((self.original(i), intersection));
+                        }
+                    }
+                }
+                continue;
+            }
+            let child_entry = |child: usize| {
+                self.nodes[child]
+                    .bounds()
+                    .entry_distance(ray, inverse_direction, max_distance)
+                    .map(|entry| (child, entry))
+            };
+            match (
+                child_entry(node_index + 1),
+                child_entry(node.second_child()),
+            ) {
+                (Some(left), Some(right)) => {
+                    let (near, far) = if left.1 <= right.1 {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+                    stack[size] = far;
+                    stack[size + 1] = near;
+                    size += 2;
+                }
+                (Some(child), None) | (None, Some(child)) => {
+                    stack[size] = child;
+                    size += 1;
+                }
+                (None, None) => {}
+            }
+        }
+        (closest, steps)
+    }
+
+    // `closest_hit` of the rays of the packet. The node is entered while any of the rays
+    // hits its box closer than its own closest hit, and the boxes are tested for all
+    // the rays at once. The child entered first is the one nearer to the packet.
+    fn closest_hits(&self, packet: &RayPacket) -> [Option<(usize, Intersection)>; 4] {
+        let mut closest = [None; 4];
+        let Some(root) = self.nodes.first() else {
+            return closest;
+        };
+        let mut max_distance = F64x4::splat(f64::INFINITY);
+        let test = |node: &FlatNode, max_distance: F64x4| {
+            let (hit, entry) = Boxes4::splat(&node.bounds()).ray_intervals(
+                &packet.origin,
+                &packet.inverse_direction,
+                max_distance,
+            );
+            (hit & packet.active, entry)
+        };
+        let mut stack = [(0, Mask4::from_bits(0), F64x4::splat(0.0)); MAX_DEPTH];
+        let mut size = 0;
+        let (hit, entry) = test(root, max_distance);
+        if hit.any() {
+            stack[0] = (0, hit, entry);
+            size = 1;
+        }
+        while size > 0 {
+            size -= 1;
+            let (node_index, lanes, entry) = stack[size];
+            let lanes = lanes & entry.le(max_distance);
+            if !lanes.any() {
+                continue;
+            }
+            let node = &self.nodes[node_index];
+            if node.is_leaf() {
+                let mut distances = max_distance.to_array();
+                for i in node.primitives() {
+                    let hits = self.data[i].intersect_packet(packet, lanes);
+                    for lane in lanes.lanes() {
+                        if let Some(intersection) = hits[lane] {
+                            if intersection.distance() < distances[lane] {
+                                distances[lane] = intersection.distance();
+                                closest[lane] = Some((self.original(i), intersection));
+                            }
+                        }
+                    }
+                }
+                max_distance = F64x4::new(distances);
+                continue;
+            }
+            let children = [node_index + 1, node.second_child()].map(|child| {
+                let (hit, entry) = test(&self.nodes[child], max_distance);
+                (child, hit & lanes, entry)
+            });
+            let nearest = |(_, hit, entry): &(usize, Mask4, F64x4)| {
+                hit.lanes()
+                    .map(|lane| entry.lane(lane))
+                    .fold(f64::INFINITY, f64::min)
+            };
+            let (near, far) = if nearest(&children[0]) <= nearest(&children[1]) {
+                (children[0], children[1])
+            } else {
+                (children[1], children[0])
+            };
+            for child in [far, near] {
+                if child.1.any() {
+                    stack[size] = child;
+                    size += 1;
+                }
+            }
+        }
+        closest
+    }
+
+    pub(crate) fn nodes(&self) -> &[FlatNode] {
+        &self.nodes
+    }
+
+    pub(crate) fn order(&self) -> &[u32] {
+        &self.order
+    }
+
+    pub(crate) fn stats(&self) -> &BuildStats {
+        &self.stats
+    }
+
+    // Nodes visited by the closest hit traversal of the ray.
+    pub(crate) fn traversal_steps(&self, ray: &Ray) -> usize {
+        self.closest_hit(ray).1
+    }
+
+    // Same as `new`, without the progress messages.
+    pub(crate) fn build(objects: Vec<P>, builder: BvhBuilder) -> BVHTree<P> {
+        let ((nodes, order), duration) = BuildStats::measure(|| builder.build(&objects));
+        let mut tree = BVHTree::from_parts(objects, nodes, order, builder);
+        tree.stats.duration = duration;
+        tree
+    }
+
+    // One copy of every primitive, in the order the tree was built from.
+    fn unique_primitives(&mut self) -> Vec<P> {
+        let mut slots: Vec<Option<P>> = (0..self.primitives).map(|_| None).collect();
+        for (primitive, &i) in std::mem::take(&mut self.data).into_iter().zip(&self.order) {
+            slots[i as usize].get_or_insert(primitive);
+        }
+        slots.into_iter().flatten().collect()
+    }
+
+    // First copy of every primitive.
+    fn unique(&self) -> impl Iterator<Item = &P> {
+        self.positions.iter().map(|&i| &self.data[i as usize])
+    }
+
+    // First copy of the primitive by its index in the primitives the tree was built from.
+    fn primitive(&self, index: usize) -> &P {
+        &self.data[self.positions[index] as usize]
+    }
+
+    // Position of the stored primitive in the primitives the tree was built from.
+    pub(crate) fn original(&self, position: usize) -> usize {
+        self.order[position] as usize
+    }
+
+    // The original breadth-first traversal testing every hit box. It is kept as
+    // the reference for the benchmark and the tests.
+    pub(crate) fn trace_breadth_first(&self, ray: &Ray) -> Option<(usize, Intersection)> {
         let mut queue = VecDeque::new();
-        queue.push_back(self.root.unwrap());
+        if !self.nodes.is_empty() {
+            queue.push_back(0);
+        }
         let mut intersections = Vec::new();
         while let Some(node_index) = queue.pop_front() {
             let node = &self.nodes[node_index];
-            if node.bounding_box.intersect(ray).is_some() {
-                if node.end != node.start {
-                    for i in node.start..node.end {
+            if node.bounds().intersect(ray).is_some() {
+                if node.is_leaf() {
+                    for i in node.primitives() {
                         let object = &self.data[i];
                         if let Some(intersection) = object.intersect(ray) {
-                            intersections.push((i, intersection));
+                            intersections.push((self.original(i), intersection));
                         }
                     }
                 } else {
-                    if let Some(left) = node.left {
-                        queue.push_back(left);
-                    }
-
-                    if let Some(right) = node.right {
-                        queue.push_back(right);
-                    }
+                    queue.push_back(node_index + 1);
+                    queue.push_back(node.second_child());
                 }
             }
         }
@@ -62,207 +500,412 @@ impl ObjectContainer for BVHTree {
             .min_by(|&(_, a), (_, b)| a.distance().total_cmp(&b.distance()))
     }
 
-    fn object_by_index(&self, index: usize) -> &Object {
-        &self.data[index]
+    // Updates the bounding boxes after the objects were moved, keeping the tree topology.
+    // Children are always stored after their parent, so one backward pass is enough.
+    pub(crate) fn refit(&mut self) {
+        for i in (0..self.nodes.len()).rev() {
+            let node = self.nodes[i];
+            let bounding_box = if node.is_leaf() {
+                self.data[node.primitives()]
+                    .iter()
+                    .fold(AlighnedBox::default(), |acc, object| {
+                        acc.union(&object.bounding_box())
+                    })
+            } else {
+                self.nodes[i + 1]
+                    .bounds()
+                    .union(&self.nodes[node.second_child()].bounds())
+            };
+            self.nodes[i].set_bounds(bounding_box);
+        }
+    }
+
+    // Total area of the nodes relative to the area of the primitives. It doesn't depend on
+    // the scene size, so it grows only when the refitted boxes start to overlap.
+    fn cost(&self) -> f64 {
+        let (nodes, leaves) = self.nodes.iter().fold((0.0, 0.0), |(nodes, leaves), node| {
+            let area = node.bounds().surface_area();
+            if node.is_leaf() {
+                (nodes + area, leaves + area)
+            } else {
+                (nodes + area, leaves)
+            }
+        });
+        if leaves > 0.0 {
+            nodes / leaves
+        } else {
+            self.nodes.len() as f64
+        }
     }
 }
 
-impl BVHTree {
-    pub(crate) fn from_obj_file(path: PathBuf) -> anyhow::Result<(BVHTree, Vec<Material>)> {
-        let loader = crate::io::obj_file::ObjectFile::new(path);
-        let (objects, material) = loader.load()?;
-        Ok((Self::new(objects, 1), material))
+// Positions of every primitive stored more than once.
+fn copies(order: &[u32], primitives: usize) -> HashMap<u32, Vec<u32>> {
+    let mut counts = vec![0; primitives];
+    for &i in order {
+        counts[i as usize] += 1;
+    }
+    let mut copies: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (position, &i) in order.iter().enumerate() {
+        if counts[i as usize] > 1 {
+            copies.entry(i).or_default().push(position as u32);
+        }
     }
+    copies
+}
 
-    pub(crate) fn new(objects: Vec<Object>, max_primitives_in_node: usize) -> BVHTree {
-        println!("Building BVH tree...");
-        let mut info: Vec<_> = objects
-            .iter()
-            .enumerate()
-            .map(|(i, o)| BVHObjectInfo::new(i, o.bounding_box()))
-            .collect();
+// Checks the saved tree of the primitives, so its traversal can't go out of the arrays
+// or the stack. Every primitive has to be stored at least once.
+pub(crate) fn check_layout(
+    nodes: &[FlatNode],
+    order: &[u32],
+    primitives: usize,
+) -> anyhow::Result<()> {
+    let mut stored = vec![false; primitives];
+    for &i in order {
+        match stored.get_mut(i as usize) {
+            Some(stored) => *stored = true,
+            None => bail!("Primitive {} doesn't exist", i),
+        }
+    }
+    if stored.contains(&false) {
+        bail!("Some primitives are missing in the tree");
+    }
+    if nodes.is_empty() != order.is_empty() {
+        bail!("Tree doesn't match the primitives");
+    }
+    // Children always follow their parent, so the depths are known before they are needed.
+    let mut depth = vec![0; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        if depth[i] >= MAX_DEPTH {
+            bail!("Tree is deeper than {} levels", MAX_DEPTH);
+        }
+        if node.is_leaf() {
+            if node.offset as usize + node.count as usize > order.len() {
+                bail!("Leaf {} refers to missing primitives", i);
+            }
+        } else {
+            let second = node.second_child();
+            if second <= i + 1 || second >= nodes.len() {
+                bail!("Node {} has incorrect children", i);
+            }
+            depth[i + 1] = depth[i] + 1;
+            depth[second] = depth[i] + 1;
+        }
+    }
+    Ok(())
+}
 
-        let mut root = BVHTree {
-            data: objects,
-            nodes: vec![],
-            max_primitives_in_node,
-            root: None,
+impl FlatNode {
+    fn leaf(bounds: AlighnedBox, start: usize, count: usize) -> FlatNode {
+        let mut node = FlatNode {
+            min: [0.0; 3],
+            max: [0.0; 3],
+            offset: start as u32,
+            count: count as u32,
         };
+        node.set_bounds(bounds);
+        node
+    }
 
-        let mut objects = vec![];
-        root.root = root.recursive_build(
-            &mut info,
-            max_primitives_in_node,
-            &mut objects,
-            0,
-            root.data.len(),
-        );
-        root.data = objects;
-        println!("BVH tree built. Nodes count: {}", root.nodes.len());
-        root
-    }
-
-    fn recursive_build(
-        &mut self,
-        info: &mut Vec<BVHObjectInfo>,
-        max_primitives_in_node: usize,
-        objects: &mut Vec<Object>,
-        start: usize,
-        end: usize,
-    ) -> Option<usize> {
-        if start >= end {
-            return None;
+    fn interior(bounds: AlighnedBox, second_child: usize) -> FlatNode {
+        FlatNode::leaf(bounds, second_child, 0)
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+
+    fn primitives(&self) -> Range<usize> {
+        self.offset as usize..(self.offset + self.count) as usize
+    }
+
+    fn second_child(&self) -> usize {
+        self.offset as usize
+    }
+
+    fn bounds(&self) -> AlighnedBox {
+        let [x0, y0, z0] = self.min.map(f64::from);
+        let [x1, y1, z1] = self.max.map(f64::from);
+        AlighnedBox::new(Point::new(x0, y0, z0), Point::new(x1, y1, z1))
+    }
+
+    fn set_bounds(&mut self, bounds: AlighnedBox) {
+        let (min, max) = (bounds.min, bounds.max);
+        self.min = [min.x, min.y, min.z].map(round_down);
+        self.max = [max.x, max.y, max.z].map(round_up);
+    }
+
+    pub(crate) fn to_bytes(self) -> [u8; FLAT_NODE_SIZE] {
+        let mut bytes = [0; FLAT_NODE_SIZE];
+        let values = self.min.into_iter().chain(self.max).map(f32::to_bits);
+        let words = values.chain([self.offset, self.count]);
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
         }
-        let n = end - start;
-        let bound = info[start + 1..end]
-            .iter()
-            .fold(info[start].bounding_box, |acc, i| {
-                acc.union(&i.bounding_box)
-            });
-        if n == 1 {
-            let index = self.nodes.len();
-            let node = BVHNode::new(index, bound, objects.len(), objects.len() + n);
-            for info in info.iter().take(end).skip(start) {
-                objects.push(self.data[info.index].clone());
-            }
-            self.nodes.push(node);
-            Some(index)
-        } else {
-            let axis = bound.longest_axis();
-            let centroid_box = info[start..end]
-                .iter()
-                .fold(AlighnedBox::default(), |acc, i| acc.union_point(i.position));
-            let mut mid = start + n / 2;
-            if centroid_box.min[axis] == centroid_box.max[axis] {
-                let index = self.nodes.len();
-                let node = BVHNode::new(index, bound, objects.len(), objects.len() + n);
-                for info in info.iter().take(end).skip(start) {
-                    objects.push(self.data[info.index].clone());
-                }
-                self.nodes.push(node);
-                return Some(index);
-            } else if n <= 4 {
-                info.as_mut_slice()[start..end]
-                    .sort_by(|a, b| a.position[axis].total_cmp(&b.position[axis]));
-            } else {
-                const N: usize = 12;
-                #[derive(Copy, Clone)]
-                struct Bucket {
-                    count: usize,
-                    bound: AlighnedBox,
-                }
-                let mut buckets: [Bucket; N] = [Bucket {
-                    count: 0,
-                    bound: AlighnedBox::default(),
-                }; N];
-
-                for info in info[start..end].iter() {
-                    let bucket_index = calculate_index(&centroid_box, info.position, axis, N - 1);
-                    let bucket = &mut buckets[bucket_index];
-                    bucket.count += 1;
-                    bucket.bound = bucket.bound.union(&info.bounding_box);
-                }
+        bytes
+    }
 
-                let mut cost = [0.0; N - 1];
-                cost.iter_mut().enumerate().for_each(|(i, c)| {
-                    let mut b0 = AlighnedBox::default();
-                    let mut b1 = AlighnedBox::default();
-                    let mut count = 0;
-                    let mut count1 = 0;
-                    for bucket in buckets.iter().take(i + 1) {
-                        b0 = b0.union(&bucket.bound);
-                        count += bucket.count;
-                    }
-                    for bucket in buckets.iter().take(N).skip(i + 1) {
-                        b1 = b1.union(&bucket.bound);
-                        count1 += bucket.count;
-                    }
-                    *c = (b0.surface_area() * (count as f64) + b1.surface_area() * (count1 as f64))
-                        / bound.surface_area()
-                        + 0.125;
-                });
-                let (min_bucket, min_cost) = cost
-                    .into_iter()
-                    .enumerate()
-                    .min_by(|&(_, c0), (_, c1)| c0.total_cmp(c1))
-                    .unwrap();
-                let leaf_cost = n as f64;
-                if n > self.max_primitives_in_node || min_cost < leaf_cost {
-                    info.as_mut_slice()[start..end].sort_by(|a, b| {
-                        let index: usize = calculate_index(&centroid_box, a.position, axis, N - 1);
-                        let result1 = index <= min_bucket;
-                        let index2 = calculate_index(&centroid_box, b.position, axis, N - 1);
-                        let result2 = index2 <= min_bucket;
-                        result1.cmp(&result2)
-                    });
-                    mid = info.as_slice()[start..end].partition_point(|a| {
-                        calculate_index(&centroid_box, a.position, axis, N - 1) > min_bucket
-                    }) + start;
-                } else {
-                    let index = self.nodes.len();
-                    let node = BVHNode::new(index, bound, objects.len(), objects.len() + n);
-                    for item in info.iter().take(end).skip(start) {
-                        objects.push(self.data[item.index].clone());
-                    }
-                    self.nodes.push(node);
-                    return Some(index);
-                }
-            }
-            let left = self.recursive_build(info, max_primitives_in_node, objects, start, mid);
-            let right = self.recursive_build(info, max_primitives_in_node, objects, mid, end);
-            let index = self.nodes.len();
-            let node = BVHNode::from_childrens(
-                &self.nodes[left.unwrap()],
-                &self.nodes[right.unwrap()],
+    pub(crate) fn from_bytes(bytes: &[u8; FLAT_NODE_SIZE]) -> FlatNode {
+        let word = |i: usize| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+        FlatNode {
+            min: [0, 1, 2].map(|i| f32::from_bits(word(i))),
+            max: [3, 4, 5].map(|i| f32::from_bits(word(i))),
+            offset: word(6),
+            count: word(7),
+        }
+    }
+}
+
+fn round_down(value: f64) -> f32 {
+    let rounded = value as f32;
+    if f64::from(rounded) > value {
+        rounded.next_down()
+    } else {
+        rounded
+    }
+}
+
+fn round_up(value: f64) -> f32 {
+    let rounded = value as f32;
+    if f64::from(rounded) < value {
+        rounded.next_up()
+    } else {
+        rounded
+    }
+}
+
+impl Primitive for Object {
+    fn intersect_packet(&self, packet: &RayPacket, lanes: Mask4) -> [Option<Intersection>; 4] {
+        Object::intersect_packet(self, packet, lanes)
+    }
+
+    fn normal_at_point(&self, point: &Point, intersection: Intersection, time: f64) -> Normal {
+        Object::normal_at_point(self, point, intersection, time)
+    }
+
+    fn geometric_normal(&self, point: &Point, intersection: Intersection, time: f64) -> Normal {
+        Object::geometric_normal(self, point, intersection, time)
+    }
+
+    fn material_at(&self, intersection: Intersection) -> usize {
+        Object::material_at(self, intersection)
+    }
+
+    fn texture_coordinates(
+        &self,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Option<(f64, f64)> {
+        Object::texture_coordinates(self, point, intersection, time)
+    }
+
+    fn describe(&self, intersection: Intersection) -> String {
+        Object::describe(self, intersection)
+    }
+
+    fn primitives_count(&self) -> usize {
+        Object::primitives_count(self)
+    }
+
+    fn nodes_count(&self) -> usize {
+        Object::nodes_count(self)
+    }
+}
+
+impl Primitive for MeshTriangle {
+    fn normal_at_point(&self, point: &Point, intersection: Intersection, _: f64) -> Normal {
+        self.triangle.normal_at_point(point, intersection)
+    }
+
+    fn geometric_normal(&self, point: &Point, intersection: Intersection, _: f64) -> Normal {
+        self.triangle.geometric_normal(point, intersection)
+    }
+
+    fn material_at(&self, _: Intersection) -> usize {
+        self.material_id
+    }
+
+    fn texture_coordinates(&self, _: &Point, _: Intersection, _: f64) -> Option<(f64, f64)> {
+        None
+    }
+
+    fn describe(&self, _: Intersection) -> String {
+        format!("{:?}", self.triangle)
+    }
+
+    fn clipped_bounds(&self, clip: &AlighnedBox) -> Option<AlighnedBox> {
+        self.triangle.clipped_bounds(clip)
+    }
+
+    fn intersect_packet(&self, packet: &RayPacket, lanes: Mask4) -> [Option<Intersection>; 4] {
+        self.triangle.intersect_packet(packet, lanes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::basic_geometry::{normal::Normal, sphere::Sphere, vector::Vector};
+    use crate::basic_types::random::random;
+    use crate::ray_tracer::scene::LinearTracer;
+
+    fn spheres() -> Vec<Object> {
+        (0..8)
+            .map(|i| {
+                let sphere = Sphere::new(Point::new(i as f64 * 3.0, 0.0, 0.0), 1.0);
+                Object::new(Rc::new(RefCell::new(sphere)), i)
+            })
+            .collect()
+    }
+
+    fn hit_material(tree: &BVHTree, origin: Point) -> Option<usize> {
+        let ray = Ray::new(origin, Normal::new(0.0, 0.0, -1.0));
+        tree.trace(&ray)
+            .map(|(index, intersection)| tree.material_at(index, intersection))
+    }
+
+    #[test]
+    fn transform_object_refits_tree() {
+        let mut tree = BVHTree::build(spheres(), BvhBuilder::default());
+        let nodes = tree.nodes.len();
+        tree.transform_object(2, Transformation::Translation(Vector::new(0.0, 2.0, 0.0)));
+        assert_eq!(tree.nodes.len(), nodes);
+        assert_eq!(hit_material(&tree, Point::new(6.0, 0.0, 10.0)), None);
+        assert_eq!(hit_material(&tree, Point::new(6.0, 2.0, 10.0)), Some(2));
+        assert_eq!(tree.bounding_box().max.y, 3.0);
+        assert!((tree.cost() - tree.built_cost).abs() < tree.built_cost);
+    }
+
+    #[test]
+    fn transform_object_rebuilds_degraded_tree() {
+        let mut tree = BVHTree::build(spheres(), BvhBuilder::default());
+        // Moving the object far away makes all boxes on its path huge.
+        tree.transform_object(0, Transformation::Translation(Vector::new(0.0, 500.0, 0.0)));
+        assert!(tree.cost() <= tree.built_cost * REBUILD_COST_FACTOR);
+        assert_eq!(hit_material(&tree, Point::new(0.0, 500.0, 10.0)), Some(0));
+        assert_eq!(hit_material(&tree, Point::new(3.0, 0.0, 10.0)), Some(1));
+    }
+
+    #[test]
+    fn dragged_object_keeps_its_index_after_rebuild() {
+        let mut tree = BVHTree::build(spheres(), BvhBuilder::default());
+        let (index, _) = tree
+            .trace(&Ray::new(
+                Point::new(9.0, 0.0, 10.0),
+                Normal::new(0.0, 0.0, -1.0),
+            ))
+            .unwrap();
+        let built_cost = tree.built_cost;
+        let mut rebuilt = false;
+        for step in 1..=20 {
+            tree.transform_object(
                 index,
+                Transformation::Translation(Vector::new(0.0, 10.0, 0.0)),
             );
-            self.nodes.push(node);
-            return Some(index);
-
-            fn calculate_index(
-                centroid: &AlighnedBox,
-                point: Point,
-                axis: Axis,
-                max_value: usize,
-            ) -> usize {
-                let index =
-                    ((centroid.offset(point)[axis] * max_value as f64) as usize).min(max_value);
-                index as usize
-            }
+            rebuilt |= tree.built_cost != built_cost;
+            let y = step as f64 * 10.0;
+            assert_eq!(hit_material(&tree, Point::new(9.0, y, 10.0)), Some(3));
+            assert_eq!(hit_material(&tree, Point::new(9.0, y - 10.0, 10.0)), None);
         }
+        assert!(rebuilt);
     }
-}
 
-impl BVHObjectInfo {
-    fn new(index: usize, bounding_box: AlighnedBox) -> BVHObjectInfo {
-        BVHObjectInfo {
-            index,
-            bounding_box,
-            position: bounding_box.center(),
+    #[test]
+    fn trace_matches_breadth_first() {
+        let objects = (0..64)
+            .map(|i| {
+                let (x, y, z) = ((i % 4) as f64, (i / 4 % 4) as f64, (i / 16) as f64);
+                let sphere = Sphere::new(Point::new(x * 3.0, y * 3.0, z * 3.0), 1.0);
+                Object::new(Rc::new(RefCell::new(sphere)), i)
+            })
+            .collect();
+        let builder = BvhBuilder {
+            max_primitives_in_node: 2,
+            ..BvhBuilder::default()
+        };
+        let tree = BVHTree::build(objects, builder);
+        let mut random = random(0x9e37_79b9_7f4a_7c15);
+        let mut coordinate = || random() * 12.0 - 1.5;
+        for _ in 0..1000 {
+            let origin = Point::new(coordinate(), coordinate(), coordinate());
+            let direction = Vector::new(coordinate(), coordinate(), coordinate()).normalize();
+            let ray = Ray::new(origin, direction);
+            let expected = tree.trace_breadth_first(&ray).map(|(_, i)| i.distance());
+            let actual = tree.trace(&ray).map(|(_, i)| i.distance());
+            assert_eq!(actual, expected);
         }
     }
-}
 
-impl BVHNode {
-    fn new(index: usize, bounding_box: AlighnedBox, start: usize, end: usize) -> BVHNode {
-        BVHNode {
-            index,
-            bounding_box,
-            left: None,
-            right: None,
-            start,
-            end,
+    #[test]
+    fn occluded_ignores_hits_beyond_distance() {
+        let tree = BVHTree::build(spheres(), BvhBuilder::default());
+        let linear = LinearTracer::new(spheres());
+        // The ray along the row of spheres, the first one is entered at 9.
+        let ray = Ray::new(Point::new(-10.0, 0.0, 0.0), Normal::new(1.0, 0.0, 0.0));
+        let containers: [&dyn ObjectContainer; 2] = [&tree, &linear];
+        for container in containers {
+            assert!(!container.occluded(&ray, 8.5));
+            assert!(container.occluded(&ray, 9.5));
+            assert!(container.occluded(&ray, f64::INFINITY));
+            let behind = Ray::new(Point::new(-10.0, 0.0, 0.0), Normal::new(-1.0, 0.0, 0.0));
+            assert!(!container.occluded(&behind, f64::INFINITY));
         }
     }
 
-    fn from_childrens(left: &BVHNode, right: &BVHNode, index: usize) -> BVHNode {
-        BVHNode {
-            index,
-            bounding_box: left.bounding_box.union(&right.bounding_box),
-            left: Some(left.index),
-            right: Some(right.index),
-            start: 0,
-            end: 0,
+    #[test]
+    fn nodes_are_stored_depth_first() {
+        let tree = BVHTree::build(spheres(), BvhBuilder::default());
+        let mut primitives = vec![];
+        for (i, node) in tree.nodes.iter().enumerate() {
+            if node.is_leaf() {
+                primitives.extend(node.primitives());
+                continue;
+            }
+            for child in [i + 1, node.second_child()] {
+                let (parent, child) = (node, tree.nodes[child]);
+                assert!(
+                    (0..3).all(|a| parent.min[a] <= child.min[a] && parent.max[a] >= child.max[a])
+                );
+            }
         }
+        assert_eq!(primitives, (0..8).collect::<Vec<_>>());
+        assert!(check_layout(&tree.nodes, &tree.order, 8).is_ok());
+    }
+
+    #[test]
+    fn node_bounds_contain_primitives() {
+        let node = FlatNode::leaf(
+            AlighnedBox::new(
+                Point::new(0.1, -0.1, 1e-9),
+                Point::new(0.3, 1e10 + 1.0, 7.0),
+            ),
+            3,
+            2,
+        );
+        let bounds = node.bounds();
+        assert!(bounds.min.x <= 0.1 && bounds.min.y <= -0.1 && bounds.min.z <= 1e-9);
+        assert!(bounds.max.x >= 0.3 && bounds.max.y >= 1e10 + 1.0 && bounds.max.z == 7.0);
+        assert_eq!(FlatNode::from_bytes(&node.to_bytes()), node);
+        assert_eq!(node.primitives(), 3..5);
+    }
+
+    #[test]
+    fn check_layout_rejects_broken_tree() {
+        let tree = BVHTree::build(spheres(), BvhBuilder::default());
+        let mut nodes = tree.nodes.clone();
+        nodes[0].offset = 0;
+        assert!(check_layout(&nodes, &tree.order, 8).is_err());
+        let mut order = tree.order.clone();
+        order[0] = order[1];
+        assert!(check_layout(&tree.nodes, &order, 8).is_err());
+        assert!(check_layout(&tree.nodes, &tree.order, 9).is_err());
+        let leaf = tree.nodes.iter().position(FlatNode::is_leaf).unwrap();
+        let mut nodes = tree.nodes.clone();
+        nodes[leaf].offset = 8;
+        assert!(check_layout(&nodes, &tree.order, 8).is_err());
     }
 }
@@ -0,0 +1,651 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+
+use super::{FlatNode, Primitive, MAX_DEPTH};
+use crate::basic_geometry::{alighned_box::AlighnedBox, point::Point, Axis};
+
+const AXES: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+// Spatial splits are tried only for the nodes whose children overlap by this part of the root.
+const SPATIAL_OVERLAP: f64 = 1e-5;
+// Spatial splits may add at most this part of the primitives as extra references.
+const SPATIAL_BUDGET: f64 = 0.5;
+// Bits of the Morton code per axis.
+const MORTON_BITS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Strategy {
+    // SAH evaluated between every two neighbouring primitives on all axes.
+    Sweep,
+    // SAH evaluated at the borders of the bins along the longest axis.
+    Binned(usize),
+    // Binned SAH which may also split the primitives between the children (SBVH).
+    // Helps with long thin triangles whose boxes overlap a lot.
+    Spatial(usize),
+    // Primitives sorted along the Morton curve, the fastest to build (LBVH).
+    Morton,
+}
+
+// How the tree is built.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BvhBuilder {
+    pub(crate) strategy: Strategy,
+    pub(crate) max_primitives_in_node: usize,
+    // Cost of visiting a node relative to intersecting a primitive.
+    pub(crate) traversal_cost: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BuildStats {
+    pub(crate) duration: Duration,
+    pub(crate) nodes: usize,
+    pub(crate) leaves: usize,
+    // Primitives stored in the leaves, spatial splits store some primitives more than once.
+    pub(crate) references: usize,
+    // Expected cost of tracing a ray hitting the root box.
+    pub(crate) sah_cost: f64,
+}
+
+#[derive(Clone, Copy)]
+struct Reference {
+    index: u32,
+    bounds: AlighnedBox,
+    centroid: Point,
+}
+
+impl Reference {
+    fn new(index: usize, bounds: AlighnedBox) -> Reference {
+        Reference {
+            index: index as u32,
+            bounds,
+            centroid: bounds.center(),
+        }
+    }
+}
+
+enum Split {
+    // References with the centroid before the position go to the first child.
+    Object(Axis, f64),
+    // References crossing the plane go to both children, clipped by it.
+    Spatial(Axis, f64),
+}
+
+struct Build<'a, P> {
+    builder: &'a BvhBuilder,
+    primitives: &'a [P],
+    nodes: Vec<FlatNode>,
+    order: Vec<u32>,
+    root_area: f64,
+    // Extra references the spatial splits may still add.
+    spatial_budget: usize,
+}
+
+impl Default for BvhBuilder {
+    fn default() -> BvhBuilder {
+        BvhBuilder {
+            strategy: Strategy::Binned(12),
+            max_primitives_in_node: 1,
+            traversal_cost: 0.125,
+        }
+    }
+}
+
+impl BvhBuilder {
+    pub(crate) fn with_strategy(strategy: Strategy) -> BvhBuilder {
+        BvhBuilder {
+            strategy,
+            ..BvhBuilder::default()
+        }
+    }
+
+    // Nodes of the tree and the primitive stored at every leaf position.
+    pub(crate) fn build<P: Primitive>(&self, primitives: &[P]) -> (Vec<FlatNode>, Vec<u32>) {
+        let references: Vec<_> = primitives
+            .iter()
+            .enumerate()
+            .map(|(i, primitive)| Reference::new(i, primitive.bounding_box()))
+            .collect();
+        let bounds = union(&references);
+        let mut build = Build {
+            builder: self,
+            primitives,
+            nodes: vec![],
+            order: Vec::with_capacity(primitives.len()),
+            root_area: bounds.surface_area(),
+            spatial_budget: (primitives.len() as f64 * SPATIAL_BUDGET) as usize,
+        };
+        if !references.is_empty() {
+            match self.strategy {
+                Strategy::Morton => build.morton(references, bounds),
+                _ => {
+                    build.node(references, 0);
+                }
+            }
+        }
+        (build.nodes, build.order)
+    }
+
+    // Leaf cost is the number of primitives, the split has to be cheaper than that.
+    fn split_cost(&self, area: f64, left: (f64, usize), right: (f64, usize)) -> f64 {
+        self.traversal_cost + (left.0 * left.1 as f64 + right.0 * right.1 as f64) / area
+    }
+}
+
+impl<P: Primitive> Build<'_, P> {
+    // Builds the subtree of the references and returns its root. The node is reserved
+    // before its children, which gives the depth-first order.
+    fn node(&mut self, references: Vec<Reference>, depth: usize) -> usize {
+        let bounds = union(&references);
+        let index = self.nodes.len();
+        self.nodes.push(FlatNode::leaf(bounds, 0, 0));
+        let split = if references.len() > 1 && depth + 1 < MAX_DEPTH {
+            self.find_split(&references, bounds)
+        } else {
+            None
+        };
+        let (left, right) = match split {
+            Some(split) => self.split(references, split),
+            None => (references, vec![]),
+        };
+        if left.is_empty() || right.is_empty() {
+            let references = [left, right].concat();
+            self.nodes[index] = FlatNode::leaf(bounds, self.order.len(), references.len());
+            self.order.extend(references.iter().map(|r| r.index));
+        } else {
+            self.node(left, depth + 1);
+            let second = self.node(right, depth + 1);
+            self.nodes[index] = FlatNode::interior(bounds, second);
+        }
+        index
+    }
+
+    fn find_split(&self, references: &[Reference], bounds: AlighnedBox) -> Option<Split> {
+        let builder = self.builder;
+        let (cost, split) = match builder.strategy {
+            Strategy::Sweep => self.sweep_split(references, bounds)?,
+            Strategy::Binned(bins) => self.binned_split(references, bounds, bins)?,
+            Strategy::Spatial(bins) => {
+                let (cost, split, overlap) = self.binned_split_overlap(references, bounds, bins)?;
+                match self.spatial_split(references, bounds, bins, overlap) {
+                    Some((spatial_cost, spatial)) if spatial_cost < cost => (spatial_cost, spatial),
+                    _ => (cost, split),
+                }
+            }
+            Strategy::Morton => unreachable!("LBVH is built by `morton`"),
+        };
+        let n = references.len();
+        (n > builder.max_primitives_in_node || cost < n as f64).then_some(split)
+    }
+
+    fn sweep_split(&self, references: &[Reference], bounds: AlighnedBox) -> Option<(f64, Split)> {
+        let n = references.len();
+        let mut best: Option<(f64, Split)> = None;
+        let mut sorted = references.to_vec();
+        for axis in AXES {
+            sorted.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+            // Area of the boxes of the last references, `right[i]` covers `i..n`.
+            let mut right = vec![0.0; n];
+            let mut acc = AlighnedBox::default();
+            for i in (1..n).rev() {
+                acc = acc.union(&sorted[i].bounds);
+                right[i] = acc.surface_area();
+            }
+            let mut left = AlighnedBox::default();
+            for i in 1..n {
+                left = left.union(&sorted[i - 1].bounds);
+                let (before, after) = (sorted[i - 1].centroid[axis], sorted[i].centroid[axis]);
+                if before == after {
+                    continue;
+                }
+                let cost = self.builder.split_cost(
+                    bounds.surface_area(),
+                    (left.surface_area(), i),
+                    (right[i], n - i),
+                );
+                if best.as_ref().is_none_or(|(best, _)| cost < *best) {
+                    best = Some((cost, Split::Object(axis, (before + after) / 2.0)));
+                }
+            }
+        }
+        best
+    }
+
+    fn binned_split(
+        &self,
+        references: &[Reference],
+        bounds: AlighnedBox,
+        bins: usize,
+    ) -> Option<(f64, Split)> {
+        self.binned_split_overlap(references, bounds, bins)
+            .map(|(cost, split, _)| (cost, split))
+    }
+
+    // Also returns the area where the boxes of the children overlap.
+    fn binned_split_overlap(
+        &self,
+        references: &[Reference],
+        bounds: AlighnedBox,
+        bins: usize,
+    ) -> Option<(f64, Split, f64)> {
+        let axis = bounds.longest_axis();
+        let centroids = references
+            .iter()
+            .fold(AlighnedBox::default(), |acc, r| acc.union_point(r.centroid));
+        let (min, max) = (centroids.min[axis], centroids.max[axis]);
+        if min == max {
+            return None;
+        }
+        let bin = |r: &Reference| {
+            (((r.centroid[axis] - min) / (max - min) * bins as f64) as usize).min(bins - 1)
+        };
+        let mut counts = vec![0; bins];
+        let mut boxes = vec![AlighnedBox::default(); bins];
+        for reference in references {
+            let i = bin(reference);
+            counts[i] += 1;
+            boxes[i] = boxes[i].union(&reference.bounds);
+        }
+        let (cost, i, left, right) = self.sweep_bins(&counts, &counts, &boxes, bounds)?;
+        let position = min + (i + 1) as f64 * (max - min) / bins as f64;
+        Some((cost, Split::Object(axis, position), overlap(&left, &right)))
+    }
+
+    // Best border between the bins: the references entering a bin are counted on the left,
+    // the ones leaving it on the right. Returns the bin before the border and the boxes.
+    fn sweep_bins(
+        &self,
+        entries: &[usize],
+        exits: &[usize],
+        boxes: &[AlighnedBox],
+        bounds: AlighnedBox,
+    ) -> Option<(f64, usize, AlighnedBox, AlighnedBox)> {
+        let bins = boxes.len();
+        let mut right = vec![(AlighnedBox::default(), 0); bins];
+        let mut acc = (AlighnedBox::default(), 0);
+        for i in (1..bins).rev() {
+            acc = (acc.0.union(&boxes[i]), acc.1 + exits[i]);
+            right[i] = acc;
+        }
+        let mut left = (AlighnedBox::default(), 0);
+        let mut best: Option<(f64, usize, AlighnedBox, AlighnedBox)> = None;
+        for i in 0..bins - 1 {
+            left = (left.0.union(&boxes[i]), left.1 + entries[i]);
+            let (right_box, right_count) = right[i + 1];
+            if left.1 == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = self.builder.split_cost(
+                bounds.surface_area(),
+                (left.0.surface_area(), left.1),
+                (right_box.surface_area(), right_count),
+            );
+            if best.is_none_or(|(best, ..)| cost < best) {
+                best = Some((cost, i, left.0, right_box));
+            }
+        }
+        best
+    }
+
+    fn spatial_split(
+        &self,
+        references: &[Reference],
+        bounds: AlighnedBox,
+        bins: usize,
+        overlap: f64,
+    ) -> Option<(f64, Split)> {
+        if overlap <= SPATIAL_OVERLAP * self.root_area || self.spatial_budget == 0 {
+            return None;
+        }
+        let mut best: Option<(f64, Split)> = None;
+        for axis in AXES {
+            let (min, max) = (bounds.min[axis], bounds.max[axis]);
+            if min == max {
+                continue;
+            }
+            let width = (max - min) / bins as f64;
+            let bin = |value: f64| (((value - min) / width) as usize).min(bins - 1);
+            let mut entries = vec![0; bins];
+            let mut exits = vec![0; bins];
+            let mut boxes = vec![AlighnedBox::default(); bins];
+            for reference in references {
+                let (first, last) = (
+                    bin(reference.bounds.min[axis]),
+                    bin(reference.bounds.max[axis]),
+                );
+                entries[first] += 1;
+                exits[last] += 1;
+                for (i, bin_box) in boxes.iter_mut().enumerate().take(last + 1).skip(first) {
+                    let slab = (min + i as f64 * width, min + (i + 1) as f64 * width);
+                    if let Some(clipped) = self.clip(reference, axis, slab) {
+                        *bin_box = bin_box.union(&clipped);
+                    }
+                }
+            }
+            if let Some((cost, i, ..)) = self.sweep_bins(&entries, &exits, &boxes, bounds) {
+                if best.as_ref().is_none_or(|(best, _)| cost < *best) {
+                    let position = min + (i + 1) as f64 * width;
+                    best = Some((cost, Split::Spatial(axis, position)));
+                }
+            }
+        }
+        best
+    }
+
+    fn split(
+        &mut self,
+        references: Vec<Reference>,
+        split: Split,
+    ) -> (Vec<Reference>, Vec<Reference>) {
+        match split {
+            Split::Object(axis, position) => references
+                .into_iter()
+                .partition(|r| r.centroid[axis] < position),
+            Split::Spatial(axis, position) => {
+                let (mut left, mut right) = (vec![], vec![]);
+                for reference in references {
+                    if reference.bounds.max[axis] <= position {
+                        left.push(reference);
+                    } else if reference.bounds.min[axis] >= position {
+                        right.push(reference);
+                    } else {
+                        let sides = [
+                            (&mut left, (f64::NEG_INFINITY, position)),
+                            (&mut right, (position, f64::INFINITY)),
+                        ];
+                        for (side, slab) in sides {
+                            if let Some(clipped) = self.clip(&reference, axis, slab) {
+                                side.push(Reference::new(reference.index as usize, clipped));
+                            }
+                        }
+                        self.spatial_budget = self.spatial_budget.saturating_sub(1);
+                    }
+                }
+                (left, right)
+            }
+        }
+    }
+
+    // Bounds of the part of the referenced primitive inside of the slab.
+    fn clip(&self, reference: &Reference, axis: Axis, slab: (f64, f64)) -> Option<AlighnedBox> {
+        let mut clip = reference.bounds;
+        clip.min[axis] = clip.min[axis].max(slab.0);
+        clip.max[axis] = clip.max[axis].min(slab.1);
+        if clip.min[axis] > clip.max[axis] {
+            return None;
+        }
+        self.primitives[reference.index as usize]
+            .clipped_bounds(&clip)
+            .and_then(|bounds| bounds.intersection(&clip))
+    }
+
+    fn morton(&mut self, references: Vec<Reference>, bounds: AlighnedBox) {
+        let mut coded: Vec<_> = references
+            .into_iter()
+            .map(|r| (morton_code(&bounds, r.centroid), r))
+            .collect();
+        coded.sort_by_key(|&(code, _)| code);
+        self.morton_node(&coded, 0);
+    }
+
+    // Splits the sorted range where the highest bit of the codes changes.
+    fn morton_node(&mut self, coded: &[(u32, Reference)], depth: usize) -> usize {
+        let bounds = coded
+            .iter()
+            .fold(AlighnedBox::default(), |acc, (_, r)| acc.union(&r.bounds));
+        let index = self.nodes.len();
+        let n = coded.len();
+        if n <= self.builder.max_primitives_in_node || depth + 1 >= MAX_DEPTH {
+            self.nodes.push(FlatNode::leaf(bounds, self.order.len(), n));
+            self.order.extend(coded.iter().map(|(_, r)| r.index));
+            return index;
+        }
+        self.nodes.push(FlatNode::leaf(bounds, 0, 0));
+        let (first, last) = (coded[0].0, coded[n - 1].0);
+        let mid = if first == last {
+            n / 2
+        } else {
+            let bit = 31 - (first ^ last).leading_zeros();
+            coded.partition_point(|&(code, _)| code & (1 << bit) == 0)
+        };
+        self.morton_node(&coded[..mid], depth + 1);
+        let second = self.morton_node(&coded[mid..], depth + 1);
+        self.nodes[index] = FlatNode::interior(bounds, second);
+        index
+    }
+}
+
+// Interleaves the bits of the point coordinates inside of the bounds.
+fn morton_code(bounds: &AlighnedBox, point: Point) -> u32 {
+    let offset = bounds.offset(point);
+    let scale = ((1 << MORTON_BITS) - 1) as f64;
+    AXES.iter().enumerate().fold(0, |code, (i, &axis)| {
+        let value = (offset[axis].clamp(0.0, 1.0) * scale) as u32;
+        (0..MORTON_BITS).fold(code, |code, bit| {
+            code | ((value >> bit) & 1) << (3 * bit + 2 - i as u32)
+        })
+    })
+}
+
+fn union(references: &[Reference]) -> AlighnedBox {
+    references
+        .iter()
+        .fold(AlighnedBox::default(), |acc, r| acc.union(&r.bounds))
+}
+
+fn overlap(a: &AlighnedBox, b: &AlighnedBox) -> f64 {
+    a.intersection(b)
+        .map_or(0.0, |overlap| overlap.surface_area())
+}
+
+impl BuildStats {
+    pub(crate) fn new(
+        nodes: &[FlatNode],
+        references: usize,
+        traversal_cost: f64,
+        duration: Duration,
+    ) -> BuildStats {
+        let root_area = nodes
+            .first()
+            .map_or(0.0, |root| root.bounds().surface_area());
+        let (leaves, cost) = nodes.iter().fold((0, 0.0), |(leaves, cost), node| {
+            let area = node.bounds().surface_area() / root_area;
+            if node.is_leaf() {
+                (leaves + 1, cost + area * node.primitives().len() as f64)
+            } else {
+                (leaves, cost + area * traversal_cost)
+            }
+        });
+        BuildStats {
+            duration,
+            nodes: nodes.len(),
+            leaves,
+            references,
+            sah_cost: if root_area > 0.0 { cost } else { 0.0 },
+        }
+    }
+
+    pub(crate) fn measure<T>(build: impl FnOnce() -> T) -> (T, Duration) {
+        let start = Instant::now();
+        let result = build();
+        (result, start.elapsed())
+    }
+}
+
+impl Display for BuildStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.1} ms, {} nodes, {} leaves, {} references, SAH cost {:.2}",
+            self.duration.as_secs_f64() * 1000.0,
+            self.nodes,
+            self.leaves,
+            self.references,
+            self.sah_cost
+        )
+    }
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strategy::Sweep => write!(f, "sweep"),
+            Strategy::Binned(bins) => write!(f, "binned:{}", bins),
+            Strategy::Spatial(bins) => write!(f, "sbvh:{}", bins),
+            Strategy::Morton => write!(f, "lbvh"),
+        }
+    }
+}
+
+// `sweep`, `binned[:bins]`, `sbvh[:bins]` or `lbvh`.
+impl FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Strategy> {
+        let (name, bins) = match s.split_once(':') {
+            Some((name, bins)) => (name, Some(bins.parse::<usize>()?)),
+            None => (s, None),
+        };
+        if bins.is_some_and(|bins| bins < 2) {
+            bail!("At least 2 bins are needed");
+        }
+        match (name, bins) {
+            ("sweep", None) => Ok(Strategy::Sweep),
+            ("binned", bins) => Ok(Strategy::Binned(bins.unwrap_or(12))),
+            ("sbvh", bins) => Ok(Strategy::Spatial(bins.unwrap_or(12))),
+            ("lbvh", None) => Ok(Strategy::Morton),
+            _ => Err(anyhow!("Unknown BVH builder {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::{normal::Normal, ray::Ray, triangle::Triangle, vector::Vector};
+    use crate::basic_types::random::random;
+    use crate::complex_structures::bvh::BVHTree;
+    use crate::ray_tracer::{object::MeshTriangle, scene::LinearTracer, ObjectContainer};
+
+    // Grid of small triangles crossed by long thin ones.
+    fn triangles() -> Vec<MeshTriangle> {
+        let mut triangles = vec![];
+        for i in 0..64 {
+            let (x, y) = ((i % 8) as f64, (i / 8) as f64);
+            let triangle = Triangle::new(
+                Point::new(x, y, 0.0),
+                Point::new(x + 0.8, y, 0.1),
+                Point::new(x, y + 0.8, 0.2),
+            );
+            triangles.push(MeshTriangle::new(triangle, i));
+        }
+        for i in 0..8 {
+            let y = i as f64 + 0.5;
+            let triangle = Triangle::new(
+                Point::new(-1.0, y, 1.0),
+                Point::new(9.0, y + 8.0, 1.0),
+                Point::new(-1.0, y + 0.1, 1.5),
+            );
+            triangles.push(MeshTriangle::new(triangle, 64 + i));
+        }
+        triangles
+    }
+
+    fn random_rays() -> Vec<Ray> {
+        let mut random = random(0x2545_f491_4f6c_dd1d);
+        (0..2000)
+            .map(|_| {
+                let origin = Point::new(random() * 10.0 - 1.0, random() * 10.0 - 1.0, 5.0);
+                let direction = Vector::new(random() - 0.5, random() - 0.5, -1.0);
+                Ray::new(origin, direction.normalize())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn all_builders_find_the_closest_hit() {
+        let linear = LinearTracer::new(
+            triangles()
+                .into_iter()
+                .map(MeshTriangle::into_object)
+                .collect(),
+        );
+        let strategies = [
+            Strategy::Sweep,
+            Strategy::Binned(4),
+            Strategy::Spatial(8),
+            Strategy::Morton,
+        ];
+        for strategy in strategies {
+            for leaf_size in [1, 4] {
+                let builder = BvhBuilder {
+                    strategy,
+                    max_primitives_in_node: leaf_size,
+                    ..BvhBuilder::default()
+                };
+                let tree = BVHTree::build(triangles(), builder);
+                for ray in random_rays() {
+                    let expected = linear
+                        .trace(&ray)
+                        .map(|(i, hit)| (linear.material_at(i, hit), hit.distance()));
+                    let actual = tree
+                        .trace(&ray)
+                        .map(|(i, hit)| (tree.material_at(i, hit), hit.distance()));
+                    assert_eq!(actual, expected, "{} builder", strategy);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spatial_splits_reduce_cost() {
+        let binned = BVHTree::build(triangles(), BvhBuilder::with_strategy(Strategy::Binned(8)));
+        let spatial = BVHTree::build(triangles(), BvhBuilder::with_strategy(Strategy::Spatial(8)));
+        assert!(spatial.stats().references > triangles().len());
+        assert!(spatial.objects_count() > triangles().len());
+        assert_eq!(spatial.unique_objects_count(), triangles().len());
+        assert!(spatial.stats().sah_cost < binned.stats().sah_cost);
+    }
+
+    #[test]
+    fn spatial_split_copies_move_together() {
+        let builder = BvhBuilder::with_strategy(Strategy::Spatial(8));
+        let mut tree = BVHTree::build(triangles(), builder);
+        let ray = Ray::new(Point::new(4.0, 8.52, 5.0), Normal::new(0.0, 0.0, -1.0));
+        let (index, hit) = tree.trace(&ray).unwrap();
+        let distance = hit.distance();
+        assert_eq!(tree.material_at(index, hit), 68);
+        tree.transform_object(
+            index,
+            crate::basic_geometry::Transformation::Translation(Vector::new(0.0, 0.0, 2.0)),
+        );
+        let (index, hit) = tree.trace(&ray).unwrap();
+        assert_eq!(tree.material_at(index, hit), 68);
+        assert!((distance - hit.distance() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parse_strategy() {
+        assert_eq!("sweep".parse::<Strategy>().unwrap(), Strategy::Sweep);
+        assert_eq!("binned".parse::<Strategy>().unwrap(), Strategy::Binned(12));
+        assert_eq!(
+            "sbvh:16".parse::<Strategy>().unwrap(),
+            Strategy::Spatial(16)
+        );
+        assert_eq!("lbvh".parse::<Strategy>().unwrap(), Strategy::Morton);
+        assert!("binned:1".parse::<Strategy>().is_err());
+        assert!("octree".parse::<Strategy>().is_err());
+        for strategy in [Strategy::Binned(7), Strategy::Spatial(3), Strategy::Morton] {
+            assert_eq!(strategy.to_string().parse::<Strategy>().unwrap(), strategy);
+        }
+    }
+
+    #[test]
+    fn morton_code_interleaves_bits() {
+        let bounds = AlighnedBox::new(Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 1.0));
+        assert_eq!(morton_code(&bounds, Point::new(0.0, 0.0, 0.0)), 0);
+        assert_eq!(morton_code(&bounds, Point::new(1.0, 0.0, 0.0)), 0x2492_4924);
+        assert_eq!(morton_code(&bounds, Point::new(1.0, 1.0, 1.0)), 0x3fff_ffff);
+    }
+}
@@ -0,0 +1,458 @@
+use super::{builder::BvhBuilder, BVHTree, FlatNode, Primitive, MAX_DEPTH};
+use crate::basic_geometry::{
+    alighned_box::{AlighnedBox, Boxes4},
+    normal::Normal,
+    point::Point,
+    ray::Ray,
+    ray_packet::RayPacket,
+    Intersection, Transformation,
+};
+use crate::basic_types::simd::{F64x4, Mask4};
+use crate::ray_tracer::{object::Object, ObjectContainer};
+
+// Node with up to four children whose boxes are tested together.
+#[derive(Debug, Clone, Copy)]
+struct Bvh4Node {
+    // Bounds of the children by axis.
+    min: [[f32; 4]; 3],
+    max: [[f32; 4]; 3],
+    // Node of the interior child or the first primitive of the leaf child. The empty slots
+    // are zero in both, the root is never a child.
+    offset: [u32; 4],
+    // Primitives of the leaf child, zero for the interior ones.
+    count: [u32; 4],
+}
+
+impl Bvh4Node {
+    fn empty() -> Bvh4Node {
+        Bvh4Node {
+            min: [[f32::INFINITY; 4]; 3],
+            max: [[f32::INFINITY; 4]; 3],
+            offset: [0; 4],
+            count: [0; 4],
+        }
+    }
+
+    fn boxes(&self) -> Boxes4 {
+        Boxes4 {
+            min: self.min.map(F64x4::from_f32),
+            max: self.max.map(F64x4::from_f32),
+        }
+    }
+
+    // Slots holding a child.
+    fn slots(&self) -> Mask4 {
+        let bits = (0..4)
+            .filter(|&slot| self.offset[slot] > 0 || self.count[slot] > 0)
+            .fold(0, |bits, slot| bits | 1 << slot);
+        Mask4::from_bits(bits)
+    }
+
+    fn child_bounds(&self, slot: usize) -> Boxes4 {
+        let lanes = |bounds: &[[f32; 4]; 3]| bounds.map(|axis| F64x4::splat(axis[slot].into()));
+        Boxes4 {
+            min: lanes(&self.min),
+            max: lanes(&self.max),
+        }
+    }
+}
+
+// Child of the node waiting on the traversal stack.
+#[derive(Clone, Copy)]
+struct Child {
+    offset: u32,
+    count: u32,
+}
+
+// Each level of the tree leaves at most three children on the stack.
+const STACK_SIZE: usize = 3 * MAX_DEPTH + 1;
+
+// Binary tree collapsed into the nodes with four children, which halves the depth
+// and tests the boxes of the children with one SIMD slab test. The binary tree is kept,
+// it's refitted or rebuilt when an object moves and collapsed again.
+pub(crate) struct Bvh4<P = Object> {
+    tree: BVHTree<P>,
+    nodes: Vec<Bvh4Node>,
+}
+
+impl<P: Primitive> ObjectContainer for Bvh4<P> {
+    fn trace(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        self.closest_hit(ray)
+    }
+
+    fn trace_packet(&self, packet: &RayPacket) -> [Option<(usize, Intersection)>; 4] {
+        self.closest_hits(packet)
+    }
+
+    fn occluded(&self, ray: &Ray, max_distance: f64) -> bool {
+        self.tree.occluded(ray, max_distance)
+    }
+
+    fn normal_at_point(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.tree.normal_at_point(index, point, intersection, time)
+    }
+
+    fn geometric_normal(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.tree.geometric_normal(index, point, intersection, time)
+    }
+
+    fn material_at(&self, index: usize, intersection: Intersection) -> usize {
+        self.tree.material_at(index, intersection)
+    }
+
+    fn texture_coordinates(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Option<(f64, f64)> {
+        self.tree
+            .texture_coordinates(index, point, intersection, time)
+    }
+
+    fn describe(&self, index: usize, intersection: Intersection) -> String {
+        self.tree.describe(index, intersection)
+    }
+
+    fn objects_count(&self) -> usize {
+        self.tree.objects_count()
+    }
+
+    fn unique_objects_count(&self) -> usize {
+        self.tree.unique_objects_count()
+    }
+
+    fn total_primitives_count(&self) -> usize {
+        self.tree.total_primitives_count()
+    }
+
+    // The nodes of the binary tree are kept, but only the wide ones are traversed.
+    fn total_nodes_count(&self) -> usize {
+        self.tree.total_nodes_count() - self.tree.nodes_count() + self.nodes.len()
+    }
+
+    fn nodes_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn name(&self) -> &'static str {
+        "BVH4"
+    }
+
+    fn bounding_box(&self) -> AlighnedBox {
+        self.tree.bounding_box()
+    }
+
+    fn transform_object(&mut self, index: usize, transformation: Transformation) {
+        self.tree.transform_object(index, transformation);
+        self.nodes = collapse(&self.tree.nodes);
+    }
+}
+
+impl<P: Primitive> Bvh4<P> {
+    pub(crate) fn new(objects: Vec<P>, builder: BvhBuilder) -> Bvh4<P> {
+        Bvh4::from_tree(BVHTree::new(objects, builder))
+    }
+
+    pub(crate) fn from_tree(tree: BVHTree<P>) -> Bvh4<P> {
+        let nodes = collapse(&tree.nodes);
+        Bvh4 { tree, nodes }
+    }
+
+    // Front-to-back traversal like `BVHTree::closest_hit`, the children hit by the ray
+    // are pushed farthest first.
+    fn closest_hit(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z].map(F64x4::splat);
+        let inverse = ray.inverse_direction();
+        let inverse_direction = [inverse.x, inverse.y, inverse.z].map(F64x4::splat);
+        let mut closest = None;
+        let mut max_distance = f64::INFINITY;
+        let mut stack = [(
+            Child {
+                offset: 0,
+                count: 0,
+            },
+            0.0,
+        ); STACK_SIZE];
+        let mut size = 1;
+        while size > 0 {
+            size -= 1;
+            let (child, entry) = stack[size];
+            if entry > max_distance {
+                continue;
+            }
+            if child.count > 0 {
+                for i in child.offset..child.offset + child.count {
+                    let i = i as usize;
+                    if let Some(intersection) = self.tree.data[i].intersect(ray) {
+                        if intersection.distance() < max_distance {
+                            max_distance = intersection.distance();
+                            closest = Some((self.tree.original(i), intersection));
+                        }
+                    }
+                }
+                continue;
+            }
+            let node = &self.nodes[child.offset as usize];
+            let (hit, entries) =
+                node.boxes()
+                    .ray_intervals(&origin, &inverse_direction, F64x4::splat(max_distance));
+            let hit = hit & node.slots();
+            let entries = entries.to_array();
+            let mut hits = [(0, 0.0); 4];
+            let mut count = 0;
+            for slot in hit.lanes() {
+                // Insertion sort, the farthest child first.
+                let mut i = count;
+                while i > 0 && hits[i - 1].1 < entries[slot] {
+                    hits[i] = hits[i - 1];
+                    i -= 1;
+                }
+                hits[i] = (slot, entries[slot]);
+                count += 1;
+            }
+            for &(slot, entry) in &hits[..count] {
+                let child = Child {
+                    offset: node.offset[slot],
+                    count: node.count[slot],
+                };
+                stack[size] = (child, entry);
+                size += 1;
+            }
+        }
+        closest
+    }
+
+    // Packet traversal like `BVHTree::closest_hits`, the children are tested one by one
+    // against all the rays of the packet.
+    fn closest_hits(&self, packet: &RayPacket) -> [Option<(usize, Intersection)>; 4] {
+        let mut closest = [None; 4];
+        if self.nodes.is_empty() {
+            return closest;
+        }
+        let mut max_distance = F64x4::splat(f64::INFINITY);
+        let root = Child {
+            offset: 0,
+            count: 0,
+        };
+        let mut stack = [(root, Mask4::from_bits(0), F64x4::splat(0.0)); STACK_SIZE];
+        stack[0] = (root, packet.active, F64x4::splat(0.0));
+        let mut size = 1;
+        while size > 0 {
+            size -= 1;
+            let (child, lanes, entry) = stack[size];
+            let lanes = lanes & entry.le(max_distance);
+            if !lanes.any() {
+                continue;
+            }
+            if child.count > 0 {
+                let mut distances = max_distance.to_array();
+                for i in child.offset..child.offset + child.count {
+                    let i = i as usize;
+                    let hits = self.tree.data[i].intersect_packet(packet, lanes);
+                    for lane in lanes.lanes() {
+                        if let Some(intersection) = hits[lane] {
+                            if intersection.distance() < distances[lane] {
+                                distances[lane] = intersection.distance();
+                                closest[lane] = Some((self.tree.original(i), intersection));
+                            }
+                        }
+                    }
+                }
+                max_distance = F64x4::new(distances);
+                continue;
+            }
+            let node = &self.nodes[child.offset as usize];
+            let mut hits = [(root, Mask4::from_bits(0), F64x4::splat(0.0), 0.0); 4];
+            let mut count = 0;
+            for slot in node.slots().lanes() {
+                let (hit, entry) = node.child_bounds(slot).ray_intervals(
+                    &packet.origin,
+                    &packet.inverse_direction,
+                    max_distance,
+                );
+                let hit = hit & lanes;
+                if !hit.any() {
+                    continue;
+                }
+                let nearest = hit
+                    .lanes()
+                    .map(|lane| entry.lane(lane))
+                    .fold(f64::INFINITY, f64::min);
+                let child = Child {
+                    offset: node.offset[slot],
+                    count: node.count[slot],
+                };
+                let mut i = count;
+                while i > 0 && hits[i - 1].3 < nearest {
+                    hits[i] = hits[i - 1];
+                    i -= 1;
+                }
+                hits[i] = (child, hit, entry, nearest);
+                count += 1;
+            }
+            for &(child, hit, entry, _) in &hits[..count] {
+                stack[size] = (child, hit, entry);
+                size += 1;
+            }
+        }
+        closest
+    }
+}
+
+// Nodes of the collapsed tree, the root first. Every node takes the children
+// of the binary node and opens the largest interior ones until it has four.
+fn collapse(nodes: &[FlatNode]) -> Vec<Bvh4Node> {
+    let mut result = vec![];
+    if !nodes.is_empty() {
+        collapse_node(nodes, 0, &mut result);
+    }
+    result
+}
+
+fn collapse_node(nodes: &[FlatNode], root: usize, result: &mut Vec<Bvh4Node>) -> u32 {
+    let index = result.len();
+    result.push(Bvh4Node::empty());
+    let mut children = if nodes[root].is_leaf() {
+        vec![root]
+    } else {
+        vec![root + 1, nodes[root].second_child()]
+    };
+    while children.len() < 4 {
+        let largest = children
+            .iter()
+            .enumerate()
+            .filter(|(_, &child)| !nodes[child].is_leaf())
+            .max_by(|(_, &a), (_, &b)| {
+                let area = |node: usize| nodes[node].bounds().surface_area();
+                area(a).total_cmp(&area(b))
+            })
+            .map(|(i, _)| i);
+        let Some(i) = largest else {
+            break;
+        };
+        let node = children[i];
+        children.splice(i..=i, [node + 1, nodes[node].second_child()]);
+    }
+    let mut node = Bvh4Node::empty();
+    for (slot, &child) in children.iter().enumerate() {
+        let child_node = &nodes[child];
+        for axis in 0..3 {
+            node.min[axis][slot] = child_node.min[axis];
+            node.max[axis][slot] = child_node.max[axis];
+        }
+        if child_node.is_leaf() {
+            node.offset[slot] = child_node.offset;
+            node.count[slot] = child_node.count;
+        } else {
+            node.offset[slot] = collapse_node(nodes, child, result);
+        }
+    }
+    result[index] = node;
+    index as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::{triangle::Triangle, vector::Vector};
+    use crate::basic_types::random::random;
+    use crate::ray_tracer::object::MeshTriangle;
+
+    fn triangles() -> Vec<MeshTriangle> {
+        (0..100)
+            .map(|i| {
+                let (x, y, z) = ((i % 5) as f64, (i / 5 % 5) as f64, (i / 25) as f64);
+                let triangle = Triangle::new(
+                    Point::new(x, y, z),
+                    Point::new(x + 0.9, y, z + 0.3),
+                    Point::new(x, y + 0.9, z + 0.6),
+                );
+                MeshTriangle::new(triangle, i)
+            })
+            .collect()
+    }
+
+    // Rays around the triangles, four of them start at the same point like
+    // the rays of the neighbouring pixels.
+    fn packets() -> Vec<RayPacket> {
+        let mut random = random(0x2545_f491_4f6c_dd1d);
+        (0..500)
+            .map(|i| {
+                let origin = Point::new(random() * 7.0 - 1.0, random() * 7.0 - 1.0, -2.0);
+                let rays: Vec<_> = (0..4)
+                    .map(|_| {
+                        let direction = match i % 5 {
+                            0 => Vector::new(0.0, 0.0, 1.0),
+                            _ => Vector::new(random() - 0.5, random() - 0.5, 1.0),
+                        };
+                        Ray::new(origin, direction.normalize())
+                    })
+                    .collect();
+                // Some of the packets are not full.
+                RayPacket::from_rays(&rays[..1 + i % 4])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn collapsed_tree_keeps_the_leaves() {
+        let builder = BvhBuilder {
+            max_primitives_in_node: 2,
+            ..BvhBuilder::default()
+        };
+        let tree = Bvh4::from_tree(BVHTree::build(triangles(), builder));
+        let mut primitives = vec![];
+        for node in &tree.nodes {
+            for slot in 0..4 {
+                if node.count[slot] > 0 {
+                    primitives.extend(node.offset[slot]..node.offset[slot] + node.count[slot]);
+                }
+            }
+        }
+        primitives.sort();
+        assert_eq!(primitives, (0..100).collect::<Vec<_>>());
+        assert!(tree.nodes.len() * 2 < tree.tree.nodes.len());
+    }
+
+    #[test]
+    fn packets_and_wide_nodes_find_the_same_hits() {
+        let tree = BVHTree::build(triangles(), BvhBuilder::default());
+        let wide = Bvh4::from_tree(BVHTree::build(triangles(), BvhBuilder::default()));
+        let mut hits = 0;
+        for packet in packets() {
+            let expected = packet.each(packet.active, |ray| tree.trace(ray));
+            let results = [
+                tree.trace_packet(&packet),
+                packet.each(packet.active, |ray| wide.trace(ray)),
+                wide.trace_packet(&packet),
+            ];
+            for result in results {
+                for lane in 0..4 {
+                    let key = |hit: Option<(usize, Intersection)>| {
+                        hit.map(|(i, intersection)| (i, intersection.distance()))
+                    };
+                    assert_eq!(key(result[lane]), key(expected[lane]));
+                }
+            }
+            hits += expected.iter().flatten().count();
+        }
+        assert!(hits > 100);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{
+    basic_geometry::{
+        alighned_box::AlighnedBox, normal::Normal, point::Point, ray::Ray, Intersect, Intersection,
+        Interval, NormalAtPoint, Transform, Transformation,
+    },
+    complex_structures::BoundingBox,
+    ray_tracer::RayTracable,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Operation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl Operation {
+    // Whether the point inside or outside of the two children is inside of the result.
+    fn contains(&self, inside: [bool; 2]) -> bool {
+        match self {
+            Operation::Union => inside[0] || inside[1],
+            Operation::Intersection => inside[0] && inside[1],
+            Operation::Difference => inside[0] && !inside[1],
+        }
+    }
+}
+
+// Solid combined from the closed primitives by the set operations, the nodes can be
+// nested. The ray hits it where it enters or leaves the intervals of the children
+// combined by the operations, so the primitives have to report all of their intervals.
+#[derive(Debug)]
+pub(crate) enum Csg {
+    Solid(Box<dyn RayTracable>),
+    Node(Operation, Box<Csg>, Box<Csg>),
+}
+
+// Surface of the solid on the boundary of the interval.
+#[derive(Debug, Clone, Copy)]
+struct Crossing {
+    distance: f64,
+    // Index of the solid in the order of the tree.
+    solid: usize,
+    // The inside of the result is on the outside of the solid, like in the subtracted one.
+    flipped: bool,
+}
+
+impl Csg {
+    pub(crate) fn solid(primitive: impl RayTracable + 'static) -> Csg {
+        Csg::Solid(Box::new(primitive))
+    }
+
+    pub(crate) fn union(a: Csg, b: Csg) -> Csg {
+        Csg::Node(Operation::Union, Box::new(a), Box::new(b))
+    }
+
+    pub(crate) fn intersection(a: Csg, b: Csg) -> Csg {
+        Csg::Node(Operation::Intersection, Box::new(a), Box::new(b))
+    }
+
+    pub(crate) fn difference(a: Csg, b: Csg) -> Csg {
+        Csg::Node(Operation::Difference, Box::new(a), Box::new(b))
+    }
+
+    // Intervals of the ray inside of the tree, `first` is the index of its first solid.
+    fn spans(&self, ray: &Ray, first: &mut usize) -> Vec<(Crossing, Crossing)> {
+        match self {
+            Csg::Solid(primitive) => {
+                let solid = *first;
+                *first += 1;
+                let crossing = |distance| Crossing {
+                    distance,
+                    solid,
+                    flipped: false,
+                };
+                primitive
+                    .intervals(ray)
+                    .into_iter()
+                    .map(|interval| (crossing(interval.enter), crossing(interval.exit)))
+                    .collect()
+            }
+            Csg::Node(operation, a, b) => {
+                let a = a.spans(ray, first);
+                let b = b.spans(ray, first);
+                combine(*operation, [a, b])
+            }
+        }
+    }
+
+    // Solid with the index in the order of the tree, the index counts down the solids
+    // passed before it.
+    fn find_solid(&self, index: &mut usize) -> Option<&dyn RayTracable> {
+        match self {
+            Csg::Solid(primitive) if *index == 0 => Some(primitive.as_ref()),
+            Csg::Solid(_) => {
+                *index -= 1;
+                None
+            }
+            Csg::Node(_, a, b) => a.find_solid(index).or_else(|| b.find_solid(index)),
+        }
+    }
+}
+
+// Walks the boundaries of both children in the order along the ray, the result starts
+// or ends where the operation on the insides changes.
+fn combine(
+    operation: Operation,
+    children: [Vec<(Crossing, Crossing)>; 2],
+) -> Vec<(Crossing, Crossing)> {
+    let mut events: Vec<_> = children
+        .into_iter()
+        .enumerate()
+        .flat_map(|(child, spans)| {
+            spans
+                .into_iter()
+                .flat_map(move |(enter, exit)| [(enter, child, true), (exit, child, false)])
+        })
+        .collect();
+    events.sort_by(|a, b| a.0.distance.total_cmp(&b.0.distance));
+    let mut inside = [false; 2];
+    let mut entry = None;
+    let mut spans = vec![];
+    for (crossing, child, enters) in events {
+        let before = operation.contains(inside);
+        inside[child] = enters;
+        let after = operation.contains(inside);
+        if before == after {
+            continue;
+        }
+        // Leaving the child enters the result or the other way around.
+        let crossing = Crossing {
+            flipped: crossing.flipped ^ (enters != after),
+            ..crossing
+        };
+        if after {
+            entry = Some(crossing);
+        } else if let Some(entry) = entry.take() {
+            spans.push((entry, crossing));
+        }
+    }
+    spans
+}
+
+impl Intersect for Csg {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        self.spans(ray, &mut 0)
+            .into_iter()
+            .flat_map(|(enter, exit)| [enter, exit])
+            .find(|crossing| crossing.distance > 0.0 && crossing.distance.is_finite())
+            .map(|crossing| {
+                Intersection::CsgIntersect(crossing.distance, crossing.solid, crossing.flipped)
+            })
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        self.spans(ray, &mut 0)
+            .into_iter()
+            .map(|(enter, exit)| Interval {
+                enter: enter.distance,
+                exit: exit.distance,
+            })
+            .collect()
+    }
+}
+
+impl NormalAtPoint for Csg {
+    fn normal_at_point(&self, point: &Point, intersection: Intersection) -> Normal {
+        let Intersection::CsgIntersect(distance, mut solid, flipped) = intersection else {
+            panic!("Called with wrong intersection type");
+        };
+        let normal = self
+            .find_solid(&mut solid)
+            .unwrap()
+            .normal_at_point(point, Intersection::Intersect(distance));
+        if flipped {
+            -normal
+        } else {
+            normal
+        }
+    }
+}
+
+impl Transform for Csg {
+    fn transform(&mut self, transformation: Transformation) {
+        match self {
+            Csg::Solid(primitive) => primitive.transform(transformation),
+            Csg::Node(_, a, b) => {
+                a.transform(transformation);
+                b.transform(transformation);
+            }
+        }
+    }
+}
+
+impl BoundingBox for Csg {
+    // The difference can only be smaller than the first child.
+    fn bounding_box(&self) -> AlighnedBox {
+        match self {
+            Csg::Solid(primitive) => primitive.bounding_box(),
+            Csg::Node(operation, a, b) => match operation {
+                Operation::Union => a.bounding_box().union(&b.bounding_box()),
+                Operation::Intersection => a
+                    .bounding_box()
+                    .intersection(&b.bounding_box())
+                    .unwrap_or_default(),
+                Operation::Difference => a.bounding_box(),
+            },
+        }
+    }
+}
+
+impl RayTracable for Csg {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::{
+        cylinder::Cylinder, plane::Plane, sphere::Sphere, vector::Vector, Axis,
+    };
+    use crate::test_utils::close;
+
+    // Distance and normal of the hit.
+    fn hit(csg: &Csg, origin: Point, direction: Normal) -> Option<(f64, Vector)> {
+        let ray = Ray::new(origin, direction);
+        csg.intersect(&ray).map(|intersection| {
+            let point = ray.at(intersection.distance());
+            let normal = csg.normal_at_point(&point, intersection);
+            (intersection.distance(), Vector::from(normal))
+        })
+    }
+
+    fn cube() -> Csg {
+        Csg::solid(AlighnedBox::new(
+            Point::new(-1., -1., -1.),
+            Point::new(1., 1., 1.),
+        ))
+    }
+
+    #[test]
+    fn drilled_cube() {
+        let drill =
+            Cylinder::new(Point::new(0., 0., -2.), Point::new(0., 0., 2.), 0.5, true).unwrap();
+        let part = Csg::difference(cube(), Csg::solid(drill));
+        // Through the hole.
+        assert!(hit(&part, Point::new(0., 0., -5.), Normal::new(0., 0., 1.)).is_none());
+        let (distance, normal) =
+            hit(&part, Point::new(0.7, 0., -5.), Normal::new(0., 0., 1.)).unwrap();
+        assert!((distance - 4.).abs() < 1e-9);
+        assert!(close(normal, Vector::new(0., 0., -1.)));
+        // From the inside of the material the wall of the hole, facing into the hole.
+        let (distance, normal) =
+            hit(&part, Point::new(-0.75, 0., 0.), Normal::new(1., 0., 0.)).unwrap();
+        assert!((distance - 0.25).abs() < 1e-9);
+        assert!(close(normal, Vector::new(1., 0., 0.)));
+        // From the hole the wall on the other side.
+        let (distance, normal) =
+            hit(&part, Point::new(0., 0., 0.), Normal::new(0., 1., 0.)).unwrap();
+        assert!((distance - 0.5).abs() < 1e-9);
+        assert!(close(normal, Vector::new(0., -1., 0.)));
+        let bounds = part.bounding_box();
+        assert!(close(bounds.max - bounds.min, Vector::new(2., 2., 2.)));
+    }
+
+    #[test]
+    fn union_and_intersection() {
+        let spheres = |operation: fn(Csg, Csg) -> Csg| {
+            operation(
+                Csg::solid(Sphere::new(Point::new(-1., 0., 0.), 2.)),
+                Csg::solid(Sphere::new(Point::new(1., 0., 0.), 2.)),
+            )
+        };
+        let union = spheres(Csg::union);
+        let lens = spheres(Csg::intersection);
+        let along = (Point::new(-10., 0., 0.), Normal::new(1., 0., 0.));
+        assert!((hit(&union, along.0, along.1).unwrap().0 - 7.).abs() < 1e-9);
+        let (distance, normal) = hit(&lens, along.0, along.1).unwrap();
+        assert!((distance - 9.).abs() < 1e-9);
+        assert!(close(normal, Vector::new(-1., 0., 0.)));
+        // Inside of the union the inner surfaces are gone.
+        let (distance, _) = hit(&union, Point::new(-1., 0., 0.), Normal::new(1., 0., 0.)).unwrap();
+        assert!((distance - 4.).abs() < 1e-9);
+        assert_eq!(union.intervals(&Ray::new(along.0, along.1)).len(), 1);
+        let bounds = lens.bounding_box();
+        assert!(close(
+            bounds.min - Point::new(-1., -2., -2.),
+            Vector::new(0., 0., 0.)
+        ));
+        assert!(close(
+            bounds.max - Point::new(1., 2., 2.),
+            Vector::new(0., 0., 0.)
+        ));
+    }
+
+    #[test]
+    fn nested_and_moved() {
+        // A hemisphere cut by the plane, with the cube taken out of its middle.
+        let ground = Plane::new(Normal::new(0., -1., 0.), Point::new(0., 0., 0.));
+        let dome = Csg::intersection(
+            Csg::solid(Sphere::new(Point::new(0., 0., 0.), 3.)),
+            Csg::solid(ground),
+        );
+        let mut part = Csg::difference(dome, cube());
+        // The flat bottom of the dome, facing down.
+        let (distance, normal) =
+            hit(&part, Point::new(2., -5., 0.), Normal::new(0., 1., 0.)).unwrap();
+        assert!((distance - 5.).abs() < 1e-9);
+        assert!(close(normal, Vector::new(0., -1., 0.)));
+        // Up through the cube cut out of the bottom.
+        let (distance, normal) =
+            hit(&part, Point::new(0., -5., 0.), Normal::new(0., 1., 0.)).unwrap();
+        assert!((distance - 6.).abs() < 1e-9);
+        assert!(close(normal, Vector::new(0., -1., 0.)));
+        part.transform(Transformation::Rotation(Axis::X, 180.));
+        part.transform(Transformation::Translation(Vector::new(0., 10., 0.)));
+        let (distance, normal) =
+            hit(&part, Point::new(0., 20., 0.), Normal::new(0., -1., 0.)).unwrap();
+        // Upside down the bottom of the cut out cube faces up.
+        assert!((distance - 11.).abs() < 1e-9);
+        assert!(close(normal, Vector::new(0., 1., 0.)));
+    }
+}
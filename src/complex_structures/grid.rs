@@ -0,0 +1,417 @@
+use std::time::Instant;
+
+use super::bvh::Primitive;
+use crate::basic_geometry::{
+    alighned_box::AlighnedBox, normal::Normal, point::Point, ray::Ray, vector::Vector, Axis,
+    Intersection, Transformation,
+};
+use crate::ray_tracer::{object::Object, ObjectContainer};
+
+const AXES: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+// Cells per primitive, the resolution along the longest side is `cbrt(DENSITY * primitives)`.
+const DENSITY: f64 = 3.0;
+const MAX_RESOLUTION: usize = 256;
+// Primitives touching the cell within this part of its size are stored in it.
+const CELL_MARGIN: f64 = 1e-6;
+
+// Cells holding more primitives get a nested grid, at most this many grids deep.
+const NESTED_PRIMITIVES: usize = 16;
+const MAX_DEPTH: usize = 3;
+
+// Bounds of the scene split into the cells of the same size, walked cell by cell
+// along the ray. Primitives overlapping several cells are referenced from each of them.
+// The crowded cells are split again by the nested grids, so the dense parts of the scene
+// get the smaller cells without making the whole grid finer.
+pub(crate) struct Grid<P = Object> {
+    data: Vec<P>,
+    root: Level,
+}
+
+// One grid of the hierarchy, the cells have either the primitives or the nested grid.
+struct Level {
+    bounds: AlighnedBox,
+    resolution: [usize; 3],
+    cell_size: [f64; 3],
+    // Primitives of the cell `i` are `indices[cells[i]..cells[i + 1]]`.
+    cells: Vec<u32>,
+    indices: Vec<u32>,
+    // Nested grids by the cell, empty if the level has none.
+    nested: Vec<Option<Box<Level>>>,
+}
+
+impl<P: Primitive> ObjectContainer for Grid<P> {
+    fn trace(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        let mut closest = None;
+        self.traverse(ray, f64::INFINITY, |indices, max_distance| {
+            for &i in indices {
+                if let Some(intersection) = self.data[i as usize].intersect(ray) {
+                    if intersection.distance() < *max_distance {
+                        *max_distance = intersection.distance();
+                        closest = Some((i as usize, intersection));
+                    }
+                }
+            }
+            false
+        });
+        closest
+    }
+
+    fn occluded(&self, ray: &Ray, max_distance: f64) -> bool {
+        let mut hit = false;
+        self.traverse(ray, max_distance, |indices, _| {
+            hit = indices
+                .iter()
+                .any(|&i| self.data[i as usize].occluded(ray, max_distance));
+            hit
+        });
+        hit
+    }
+
+    fn normal_at_point(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.data[index].normal_at_point(point, intersection, time)
+    }
+
+    fn geometric_normal(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.data[index].geometric_normal(point, intersection, time)
+    }
+
+    fn material_at(&self, index: usize, intersection: Intersection) -> usize {
+        self.data[index].material_at(intersection)
+    }
+
+    fn texture_coordinates(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Option<(f64, f64)> {
+        self.data[index].texture_coordinates(point, intersection, time)
+    }
+
+    fn describe(&self, index: usize, intersection: Intersection) -> String {
+        self.data[index].describe(intersection)
+    }
+
+    fn objects_count(&self) -> usize {
+        self.data.len()
+    }
+
+    fn total_primitives_count(&self) -> usize {
+        self.data.iter().map(Primitive::primitives_count).sum()
+    }
+
+    fn total_nodes_count(&self) -> usize {
+        self.nodes_count() + self.data.iter().map(Primitive::nodes_count).sum::<usize>()
+    }
+
+    fn nodes_count(&self) -> usize {
+        self.root.cells_count()
+    }
+
+    fn name(&self) -> &'static str {
+        "Grid"
+    }
+
+    fn bounding_box(&self) -> AlighnedBox {
+        self.root.bounds
+    }
+
+    // The moved primitive can change the bounds of the grid, so it's built again.
+    fn transform_object(&mut self, index: usize, transformation: Transformation) {
+        self.data[index].transform(transformation);
+        *self = Grid::build(std::mem::take(&mut self.data));
+    }
+}
+
+impl<P: Primitive> Grid<P> {
+    pub(crate) fn new(objects: Vec<P>) -> Grid<P> {
+        println!("Building grid...");
+        let start = Instant::now();
+        let grid = Grid::build(objects);
+        let [x, y, z] = grid.root.resolution;
+        println!(
+            "Grid built: {:.1} ms, {}x{}x{} cells, {} nested grids, {} references",
+            start.elapsed().as_secs_f64() * 1000.0,
+            x,
+            y,
+            z,
+            grid.root.nested_count(),
+            grid.references()
+        );
+        grid
+    }
+
+    pub(crate) fn build(objects: Vec<P>) -> Grid<P> {
+        let mut bounds = objects.iter().fold(AlighnedBox::default(), |acc, object| {
+            acc.union(&object.bounding_box())
+        });
+        if objects.is_empty() {
+            bounds = AlighnedBox::new(Point::new(0.0, 0.0, 0.0), Point::new(0.0, 0.0, 0.0));
+        }
+        let longest = {
+            let size = bounds.max - bounds.min;
+            size.x.max(size.y).max(size.z)
+        };
+        // Flat scenes get one layer of cells as thick as the others.
+        for axis in AXES {
+            if bounds.max[axis] - bounds.min[axis] <= longest * CELL_MARGIN {
+                bounds.min[axis] -= longest.max(1.0) * 0.5;
+                bounds.max[axis] += longest.max(1.0) * 0.5;
+            }
+        }
+        let primitives = (0..objects.len() as u32).collect();
+        Grid {
+            root: Level::build(bounds, primitives, &objects, 1),
+            data: objects,
+        }
+    }
+
+    // Primitives referenced from the cells, counting every copy.
+    pub(crate) fn references(&self) -> usize {
+        self.root.references()
+    }
+
+    // Walks the cells pierced by the ray front to back. `visit` gets the primitives
+    // of the cell and the distance of the closest hit so far, which it can lower.
+    // Returning `true` stops the walk.
+    fn traverse(
+        &self,
+        ray: &Ray,
+        mut max_distance: f64,
+        mut visit: impl FnMut(&[u32], &mut f64) -> bool,
+    ) {
+        if self.data.is_empty() {
+            return;
+        }
+        self.root
+            .traverse(ray, ray.inverse_direction(), &mut max_distance, &mut visit);
+    }
+}
+
+impl Level {
+    fn build<P: Primitive>(
+        bounds: AlighnedBox,
+        primitives: Vec<u32>,
+        objects: &[P],
+        depth: usize,
+    ) -> Level {
+        let size = bounds.max - bounds.min;
+        let cells_per_unit =
+            (DENSITY * primitives.len() as f64).cbrt() / size.x.max(size.y).max(size.z);
+        let mut resolution = [1; 3];
+        let mut cell_size = [0.0; 3];
+        for (i, axis) in AXES.into_iter().enumerate() {
+            resolution[i] =
+                ((size[axis] * cells_per_unit).round() as usize).clamp(1, MAX_RESOLUTION);
+            cell_size[i] = size[axis] / resolution[i] as f64;
+        }
+        let mut level = Level {
+            bounds,
+            resolution,
+            cell_size,
+            cells: vec![],
+            indices: vec![],
+            nested: vec![],
+        };
+        let mut cells = vec![vec![]; resolution.iter().product()];
+        for &i in &primitives {
+            let object = &objects[i as usize];
+            let object_bounds = object.bounding_box();
+            let first = level.cell_of(object_bounds.min);
+            let last = level.cell_of(object_bounds.max);
+            let single = first == last;
+            for z in first[2]..=last[2] {
+                for y in first[1]..=last[1] {
+                    for x in first[0]..=last[0] {
+                        let cell = [x, y, z];
+                        if single || object.clipped_bounds(&level.cell_bounds(cell)).is_some() {
+                            cells[level.cell_index(cell)].push(i);
+                        }
+                    }
+                }
+            }
+        }
+        // A cell holding all the primitives of the level wouldn't get any smaller.
+        let crowded = |cell: &Vec<u32>| {
+            depth < MAX_DEPTH && cell.len() > NESTED_PRIMITIVES && cell.len() < primitives.len()
+        };
+        if cells.iter().any(crowded) {
+            level.nested = (0..cells.len()).map(|_| None).collect();
+        }
+        level.cells.push(0);
+        for (index, cell) in cells.into_iter().enumerate() {
+            if crowded(&cell) {
+                let bounds = level.cell_bounds(level.cell_at(index));
+                level.nested[index] =
+                    Some(Box::new(Level::build(bounds, cell, objects, depth + 1)));
+            } else {
+                level.indices.extend(cell);
+            }
+            level.cells.push(level.indices.len() as u32);
+        }
+        level
+    }
+
+    fn references(&self) -> usize {
+        self.indices.len() + self.nested().map(Level::references).sum::<usize>()
+    }
+
+    fn cells_count(&self) -> usize {
+        self.cells.len() - 1 + self.nested().map(Level::cells_count).sum::<usize>()
+    }
+
+    fn nested_count(&self) -> usize {
+        self.nested().map(|level| 1 + level.nested_count()).sum()
+    }
+
+    fn nested(&self) -> impl Iterator<Item = &Level> {
+        self.nested.iter().flatten().map(Box::as_ref)
+    }
+
+    // Cell containing the point, the points outside of the grid get the nearest one.
+    fn cell_of(&self, point: Point) -> [usize; 3] {
+        let mut cell = [0; 3];
+        for (i, axis) in AXES.into_iter().enumerate() {
+            let position = (point[axis] - self.bounds.min[axis]) / self.cell_size[i];
+            cell[i] = (position.max(0.0) as usize).min(self.resolution[i] - 1);
+        }
+        cell
+    }
+
+    fn cell_index(&self, cell: [usize; 3]) -> usize {
+        (cell[2] * self.resolution[1] + cell[1]) * self.resolution[0] + cell[0]
+    }
+
+    fn cell_at(&self, index: usize) -> [usize; 3] {
+        let [x, y, _] = self.resolution;
+        [index % x, index / x % y, index / (x * y)]
+    }
+
+    // Slightly grown, so the primitives on the border of the cells are in both of them.
+    fn cell_bounds(&self, cell: [usize; 3]) -> AlighnedBox {
+        let mut bounds = self.bounds;
+        for (i, axis) in AXES.into_iter().enumerate() {
+            let margin = self.cell_size[i] * CELL_MARGIN;
+            bounds.min[axis] += cell[i] as f64 * self.cell_size[i] - margin;
+            bounds.max[axis] = bounds.min[axis] + self.cell_size[i] + 2.0 * margin;
+        }
+        bounds
+    }
+
+    // 3D DDA through the cells of the level, the nested grids are walked the same way
+    // inside of their cell. Returns `true` if `visit` stopped the walk.
+    fn traverse(
+        &self,
+        ray: &Ray,
+        inverse_direction: Vector,
+        max_distance: &mut f64,
+        visit: &mut impl FnMut(&[u32], &mut f64) -> bool,
+    ) -> bool {
+        let Some((entry, exit)) = self
+            .bounds
+            .ray_interval(ray, inverse_direction, *max_distance)
+        else {
+            return false;
+        };
+        let mut cell = self.cell_of(ray.at(entry));
+        // Distance to the next cell border and between the borders along every axis.
+        let mut next = [f64::INFINITY; 3];
+        let mut delta = [f64::INFINITY; 3];
+        for (i, axis) in AXES.into_iter().enumerate() {
+            let inverse = inverse_direction[axis];
+            if inverse.is_infinite() {
+                continue;
+            }
+            let border = self.bounds.min[axis]
+                + (cell[i] + usize::from(inverse > 0.0)) as f64 * self.cell_size[i];
+            next[i] = (border - ray.origin[axis]) * inverse;
+            delta[i] = self.cell_size[i] * inverse.abs();
+        }
+        loop {
+            let cell_index = self.cell_index(cell);
+            let stopped = match self.nested.get(cell_index) {
+                Some(Some(level)) => level.traverse(ray, inverse_direction, max_distance, visit),
+                _ => {
+                    let (first, last) = (self.cells[cell_index], self.cells[cell_index + 1]);
+                    visit(&self.indices[first as usize..last as usize], max_distance)
+                }
+            };
+            if stopped {
+                return true;
+            }
+            let i = (0..3).min_by(|&a, &b| next[a].total_cmp(&next[b])).unwrap();
+            // Hits beyond the cell can be behind the primitives of the next cells.
+            if *max_distance <= next[i] || next[i] > exit {
+                return false;
+            }
+            if inverse_direction[AXES[i]] > 0.0 {
+                cell[i] += 1;
+                if cell[i] == self.resolution[i] {
+                    return false;
+                }
+            } else {
+                if cell[i] == 0 {
+                    return false;
+                }
+                cell[i] -= 1;
+            }
+            next[i] += delta[i];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::basic_geometry::sphere::Sphere;
+    use crate::ray_tracer::scene::LinearTracer;
+
+    // Small spheres packed into one corner of a large scene.
+    fn cluster() -> Vec<Object> {
+        let mut spheres = vec![Sphere::new(Point::new(100.0, 100.0, 100.0), 1.0)];
+        for i in 0..512 {
+            let (x, y, z) = ((i % 8) as f64, (i / 8 % 8) as f64, (i / 64) as f64);
+            spheres.push(Sphere::new(Point::new(x * 0.25, y * 0.25, z * 0.25), 0.1));
+        }
+        spheres
+            .into_iter()
+            .enumerate()
+            .map(|(i, sphere)| Object::new(Rc::new(RefCell::new(sphere)), i))
+            .collect()
+    }
+
+    #[test]
+    fn crowded_cells_get_nested_grids() {
+        let grid = Grid::build(cluster());
+        assert!(grid.root.nested_count() > 0);
+        assert!(grid.nodes_count() > grid.root.cells.len() - 1);
+        let linear = LinearTracer::new(cluster());
+        for i in 0..64 {
+            let (x, y) = ((i % 8) as f64 * 0.25, (i / 8) as f64 * 0.25);
+            for origin in [Point::new(x, y, -5.0), Point::new(x + 0.08, y + 0.05, 5.0)] {
+                let ray = Ray::new(origin, Normal::new(0.0, 0.0, -origin.z.signum()));
+                let hit = |container: &dyn ObjectContainer| {
+                    container
+                        .trace(&ray)
+                        .map(|(i, hit)| (container.material_at(i, hit), hit.distance()))
+                };
+                assert_eq!(hit(&grid), hit(&linear));
+            }
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{
+    basic_geometry::{
+        alighned_box::AlighnedBox, normal::Normal, point::Point, ray::Ray, transform::Transform3,
+        vector::Vector, Intersect, Intersection, NormalAtPoint, Transform, Transformation,
+    },
+    complex_structures::BoundingBox,
+    io::height_map::HeightMap,
+    ray_tracer::RayTracable,
+};
+
+// Levels of the mipmap, enough for the maps with up to 2^31 samples on a side.
+const MAX_LEVELS: usize = 32;
+
+// Terrain given by the heights on the grid, every cell is split into two triangles.
+// In its own space the samples are at the integer x and z, the image columns and rows,
+// with the heights from 0 to 1 as y. The rays go down the min-max mipmap: every level
+// keeps the lowest and the highest height of the 2x2 blocks of the level below, so
+// whole areas the ray passes over or under are skipped.
+pub(crate) struct Heightfield {
+    width: usize,
+    depth: usize,
+    heights: Vec<f64>,
+    // Smooth normals of the samples in the object space.
+    normals: Vec<Vector>,
+    // From the cells up to the single block over the whole field.
+    levels: Vec<Level>,
+    // Object to world transform.
+    transform: Transform3,
+}
+
+// Lowest and highest heights of the blocks of one level of the mipmap.
+struct Level {
+    columns: usize,
+    rows: usize,
+    ranges: Vec<(f64, f64)>,
+}
+
+impl Heightfield {
+    // The longer side of the map spans `extent` centered at the origin, the white
+    // is `height` above the black at zero.
+    pub(crate) fn new(map: &HeightMap, extent: f64, height: f64) -> Heightfield {
+        let (width, depth) = (map.width, map.height);
+        let cell = extent / (width.max(depth) - 1) as f64;
+        let half = Vector::new((width - 1) as f64, 0., (depth - 1) as f64) * (cell / 2.);
+        let transform = Transform3::translation(-half)
+            * Transform3::from(Transformation::Scale(Vector::new(cell, height, cell)));
+        let mut field = Heightfield {
+            width,
+            depth,
+            heights: map.values.clone(),
+            normals: vec![],
+            levels: vec![],
+            transform,
+        };
+        field.normals = (0..depth)
+            .flat_map(|z| (0..width).map(move |x| (x, z)))
+            .map(|(x, z)| field.sample_normal(x, z))
+            .collect();
+        field.build_levels();
+        field
+    }
+
+    fn height(&self, x: usize, z: usize) -> f64 {
+        self.heights[z * self.width + x]
+    }
+
+    // Central differences, one sided on the border.
+    fn sample_normal(&self, x: usize, z: usize) -> Vector {
+        let slope = |before: usize, after: usize, at: &dyn Fn(usize) -> f64| {
+            (at(after) - at(before)) / (after - before) as f64
+        };
+        let dx = slope(x.saturating_sub(1), (x + 1).min(self.width - 1), &|x| {
+            self.height(x, z)
+        });
+        let dz = slope(z.saturating_sub(1), (z + 1).min(self.depth - 1), &|z| {
+            self.height(x, z)
+        });
+        Vector::new(-dx, 1., -dz)
+    }
+
+    fn build_levels(&mut self) {
+        let (mut columns, mut rows) = (self.width - 1, self.depth - 1);
+        let cells = (0..rows)
+            .flat_map(|z| (0..columns).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                let corners = [
+                    self.height(x, z),
+                    self.height(x + 1, z),
+                    self.height(x, z + 1),
+                    self.height(x + 1, z + 1),
+                ];
+                corners
+                    .iter()
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &h| {
+                        (lo.min(h), hi.max(h))
+                    })
+            })
+            .collect();
+        self.levels.push(Level {
+            columns,
+            rows,
+            ranges: cells,
+        });
+        while columns > 1 || rows > 1 {
+            let (next_columns, next_rows) = (columns.div_ceil(2), rows.div_ceil(2));
+            let below = &self.levels.last().unwrap().ranges;
+            let blocks = (0..next_rows)
+                .flat_map(|z| (0..next_columns).map(move |x| (x, z)))
+                .map(|(x, z)| {
+                    let mut range = (f64::INFINITY, f64::NEG_INFINITY);
+                    for (cx, cz) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let (cx, cz) = (2 * x + cx, 2 * z + cz);
+                        if cx < columns && cz < rows {
+                            let (lo, hi) = below[cz * columns + cx];
+                            range = (range.0.min(lo), range.1.max(hi));
+                        }
+                    }
+                    range
+                })
+                .collect();
+            (columns, rows) = (next_columns, next_rows);
+            self.levels.push(Level {
+                columns,
+                rows,
+                ranges: blocks,
+            });
+        }
+        assert!(
+            self.levels.len() <= MAX_LEVELS,
+            "The height map is too large"
+        );
+    }
+
+    // Box of the block of the level in the object space.
+    fn block_bounds(&self, level: usize, x: usize, z: usize) -> AlighnedBox {
+        let Level {
+            columns, ranges, ..
+        } = &self.levels[level];
+        let (lo, hi) = ranges[z * columns + x];
+        let size = 1 << level;
+        let cells = (self.width - 1, self.depth - 1);
+        AlighnedBox::new(
+            Point::new((x * size) as f64, lo, (z * size) as f64),
+            Point::new(
+                ((x + 1) * size).min(cells.0) as f64,
+                hi,
+                ((z + 1) * size).min(cells.1) as f64,
+            ),
+        )
+    }
+
+    // Closest hit of the two triangles of the cell, in the object space.
+    fn cell_hit(&self, ray: &Ray, x: usize, z: usize) -> Option<f64> {
+        let corner = |dx: usize, dz: usize| {
+            Vector::new(
+                (x + dx) as f64,
+                self.height(x + dx, z + dz),
+                (z + dz) as f64,
+            )
+        };
+        let (v00, v10, v01, v11) = (corner(0, 0), corner(1, 0), corner(0, 1), corner(1, 1));
+        [(v00, v10, v11), (v00, v11, v01)]
+            .into_iter()
+            .filter_map(|(a, b, c)| triangle_hit(ray, a, b, c))
+            .min_by(f64::total_cmp)
+    }
+
+    // Distance in the object space, the nearer blocks are visited first and the ones
+    // farther than the closest hit so far are skipped. Every level keeps at most three
+    // postponed blocks on the stack.
+    fn trace(&self, ray: &Ray) -> Option<f64> {
+        let inverse_direction = ray.inverse_direction();
+        let direction = Vector::from(ray.direction);
+        let mut closest = f64::INFINITY;
+        let mut stack = [(0, 0, 0); 3 * MAX_LEVELS + 1];
+        stack[0] = (self.levels.len() - 1, 0, 0);
+        let mut size = 1;
+        while size > 0 {
+            size -= 1;
+            let (level, x, z) = stack[size];
+            let bounds = self.block_bounds(level, x, z);
+            if bounds
+                .ray_interval(ray, inverse_direction, closest)
+                .is_none()
+            {
+                continue;
+            }
+            if level == 0 {
+                if let Some(t) = self.cell_hit(ray, x, z) {
+                    closest = closest.min(t);
+                }
+                continue;
+            }
+            let Level { columns, rows, .. } = &self.levels[level - 1];
+            let order = |positive: bool| if positive { [0, 1] } else { [1, 0] };
+            // Pushed from the farthest, so the nearest is popped first.
+            for dz in order(direction.z >= 0.).into_iter().rev() {
+                for dx in order(direction.x >= 0.).into_iter().rev() {
+                    let (cx, cz) = (2 * x + dx, 2 * z + dz);
+                    if cx < *columns && cz < *rows {
+                        stack[size] = (level - 1, cx, cz);
+                        size += 1;
+                    }
+                }
+            }
+        }
+        closest.is_finite().then_some(closest)
+    }
+
+    // The cell under the point and the position inside of it.
+    fn cell_at(&self, point: &Point) -> (usize, usize, f64, f64) {
+        let local = self.transform.inverse().point(*point);
+        let x = local.x.clamp(0., (self.width - 1) as f64);
+        let z = local.z.clamp(0., (self.depth - 1) as f64);
+        let (cx, cz) = (
+            (x.floor() as usize).min(self.width - 2),
+            (z.floor() as usize).min(self.depth - 2),
+        );
+        (cx, cz, x - cx as f64, z - cz as f64)
+    }
+
+    // Position on the map from 0 to 1, `v` goes down the image rows.
+    pub(crate) fn uv_at_point(&self, point: &Point) -> (f64, f64) {
+        let local = self.transform.inverse().point(*point);
+        (
+            (local.x / (self.width - 1) as f64).clamp(0., 1.),
+            (local.z / (self.depth - 1) as f64).clamp(0., 1.),
+        )
+    }
+}
+
+// The ray hits the triangle if the distance and the barycentrics are within it,
+// the edges belong to both triangles so there are no cracks between them.
+fn triangle_hit(ray: &Ray, a: Vector, b: Vector, c: Vector) -> Option<f64> {
+    let direction = Vector::from(ray.direction);
+    let (ab, ac) = (b - a, c - a);
+    let p = direction.cross(ac);
+    let determinant = ab.dot(p);
+    if determinant == 0. {
+        return None;
+    }
+    let s = Vector::from(ray.origin) - a;
+    let u = s.dot(p) / determinant;
+    let q = s.cross(ab);
+    let v = direction.dot(q) / determinant;
+    if u < 0. || v < 0. || u + v > 1. {
+        return None;
+    }
+    let t = ac.dot(q) / determinant;
+    (t > 0.).then_some(t)
+}
+
+impl std::fmt::Debug for Heightfield {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Heightfield {}x{}", self.width, self.depth)
+    }
+}
+
+impl Intersect for Heightfield {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        self.trace(&local)
+            .map(|t| Intersection::Intersect(t / scale))
+    }
+}
+
+impl NormalAtPoint for Heightfield {
+    // The normals of the corners blended across the cell, so the terrain looks smooth.
+    fn normal_at_point(&self, point: &Point, _: Intersection) -> Normal {
+        let (cx, cz, fx, fz) = self.cell_at(point);
+        let normal = |dx: usize, dz: usize| self.normals[(cz + dz) * self.width + cx + dx];
+        let blended = (normal(0, 0) * (1. - fx) + normal(1, 0) * fx) * (1. - fz)
+            + (normal(0, 1) * (1. - fx) + normal(1, 1) * fx) * fz;
+        self.transform.normal(blended.normalize())
+    }
+
+    fn geometric_normal(&self, point: &Point, _: Intersection) -> Normal {
+        let (cx, cz, fx, fz) = self.cell_at(point);
+        let corner = |dx: usize, dz: usize| {
+            Vector::new(
+                (cx + dx) as f64,
+                self.height(cx + dx, cz + dz),
+                (cz + dz) as f64,
+            )
+        };
+        // The cell is split along the diagonal from (0, 0) to (1, 1).
+        let (a, b, c) = if fx >= fz {
+            (corner(0, 0), corner(1, 1), corner(1, 0))
+        } else {
+            (corner(0, 0), corner(0, 1), corner(1, 1))
+        };
+        self.transform.normal((b - a).cross(c - a).normalize())
+    }
+}
+
+impl Transform for Heightfield {
+    fn transform(&mut self, transformation: Transformation) {
+        self.transform = Transform3::from(transformation) * self.transform;
+    }
+}
+
+impl BoundingBox for Heightfield {
+    fn bounding_box(&self) -> AlighnedBox {
+        let top = self.levels.len() - 1;
+        self.transform.bounding_box(&self.block_bounds(top, 0, 0))
+    }
+}
+
+impl RayTracable for Heightfield {
+    fn texture_coordinates(&self, point: &Point, _: Intersection) -> Option<(f64, f64)> {
+        Some(self.uv_at_point(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_types::random::random;
+
+    fn map(width: usize, height: usize, mut value: impl FnMut(usize, usize) -> f64) -> HeightMap {
+        let mut values = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                values.push(value(x, y));
+            }
+        }
+        HeightMap {
+            width,
+            height,
+            values,
+        }
+    }
+
+    #[test]
+    fn slope() {
+        // Rising along x from 0 to 10 over the extent of 100.
+        let field = Heightfield::new(&map(11, 6, |x, _| x as f64 / 10.), 100., 10.);
+        let bounds = field.bounding_box();
+        let close = |a: Point, b: Point| (a - b).length() < 1e-9;
+        assert!(close(bounds.min, Point::new(-50., 0., -25.)));
+        assert!(close(bounds.max, Point::new(50., 10., 25.)));
+        let down = Ray::new(Point::new(0., 100., 0.), Normal::new(0., -1., 0.));
+        let intersection = field.intersect(&down).unwrap();
+        assert!((intersection.distance() - 95.).abs() < 1e-9);
+        let point = down.at(95.);
+        let expected = Vector::from(Vector::new(-0.1, 1., 0.).normalize());
+        let normal = Vector::from(field.normal_at_point(&point, intersection));
+        assert!((normal - expected).length() < 1e-9);
+        let (u, v) = field.uv_at_point(&point);
+        assert!((u - 0.5).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+        // Outside of the map and under it.
+        let outside = Ray::new(Point::new(60., 100., 0.), Normal::new(0., -1., 0.));
+        assert!(field.intersect(&outside).is_none());
+        let under = Ray::new(Point::new(0., -1., 0.), Normal::new(0., -1., 0.));
+        assert!(field.intersect(&under).is_none());
+    }
+
+    #[test]
+    fn mipmap_matches_all_cells() {
+        let mut random = random(0x2545_f491_4f6c_dd1d);
+        let values = map(37, 23, |_, _| random());
+        let field = Heightfield::new(&values, 10., 2.);
+        let bounds = field.bounding_box();
+        let mut hits = 0;
+        for i in 0..500 {
+            let origin = Point::new(
+                (random() - 0.5) * 14.,
+                random() * 4. - 0.5,
+                (random() - 0.5) * 10.,
+            );
+            let target = match i % 3 {
+                0 => Point::new((random() - 0.5) * 10., 0., (random() - 0.5) * 6.),
+                _ => origin + Vector::new(random() - 0.5, random() - 0.5, random() - 0.5),
+            };
+            let ray = Ray::new(origin, (target - origin).normalize());
+            let (local, scale) = ray.transformed(&field.transform.inverse());
+            let expected = (0..field.depth - 1)
+                .flat_map(|z| (0..field.width - 1).map(move |x| (x, z)))
+                .filter_map(|(x, z)| field.cell_hit(&local, x, z))
+                .min_by(f64::total_cmp)
+                .map(|t| t / scale);
+            let actual = field.intersect(&ray).map(|i| i.distance());
+            match (actual, expected) {
+                (Some(a), Some(e)) => assert!((a - e).abs() < 1e-9, "{} != {}", a, e),
+                (a, e) => assert_eq!(a, e, "{:?}", ray),
+            }
+            if let Some(distance) = actual {
+                hits += 1;
+                let point = ray.at(distance);
+                assert!(point.y >= bounds.min.y - 1e-9 && point.y <= bounds.max.y + 1e-9);
+            }
+        }
+        assert!(hits > 100);
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::{
+    basic_geometry::{
+        alighned_box::AlighnedBox, normal::Normal, point::Point, ray::Ray, ray_packet::RayPacket,
+        transform::Transform3, Intersect, Intersection, NormalAtPoint, Transform, Transformation,
+    },
+    basic_types::simd::Mask4,
+    complex_structures::BoundingBox,
+    ray_tracer::{ObjectContainer, RayTracable},
+};
+
+// Placement of the shared mesh in the world. The mesh is never changed, the rays
+// are moved into its space instead, so one mesh can be placed any number of times.
+pub(crate) struct Instance {
+    mesh: Rc<dyn ObjectContainer>,
+    // Object to world transform.
+    transform: Transform3,
+    // Replaces the materials of the mesh.
+    material: Option<usize>,
+}
+
+impl Instance {
+    pub(crate) fn new(
+        mesh: Rc<dyn ObjectContainer>,
+        transform: Transform3,
+        material: Option<usize>,
+    ) -> Instance {
+        Instance {
+            mesh,
+            transform,
+            material,
+        }
+    }
+
+    // Instance with the transformations applied in the given order.
+    pub(crate) fn with_transformations(
+        mesh: Rc<dyn ObjectContainer>,
+        transformations: &[Transformation],
+        material: Option<usize>,
+    ) -> Instance {
+        let mut instance = Instance::new(mesh, Transform3::identity(), material);
+        for &transformation in transformations {
+            instance.transform(transformation);
+        }
+        instance
+    }
+
+    // Intersection with the hit primitive, as it would be returned by the primitive itself.
+    fn local_intersection(intersection: Intersection) -> (usize, Intersection) {
+        match intersection {
+            Intersection::InstanceIntersect(distance, primitive, u, v) => {
+                (primitive, Intersection::TriangleIntesersect(distance, u, v))
+            }
+            _ => panic!("Called with wrong intersection type"),
+        }
+    }
+
+    // Hit of the mesh in the world space, `scale` is the one of the transformed ray.
+    fn world_intersection(
+        primitive: usize,
+        intersection: Intersection,
+        scale: f64,
+    ) -> Intersection {
+        let (u, v) = match intersection {
+            Intersection::TriangleIntesersect(_, u, v) => (u, v),
+            _ => (0.0, 0.0),
+        };
+        Intersection::InstanceIntersect(intersection.distance() / scale, primitive, u, v)
+    }
+}
+
+impl Intersect for Instance {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        self.mesh.trace(&local).map(|(primitive, intersection)| {
+            Instance::world_intersection(primitive, intersection, scale)
+        })
+    }
+
+    // Any hit of the mesh is enough, the distance is moved into its space too.
+    fn occluded(&self, ray: &Ray, max_distance: f64) -> bool {
+        let (local, scale) = ray.transformed(&self.transform.inverse());
+        self.mesh.occluded(&local, max_distance * scale)
+    }
+}
+
+impl NormalAtPoint for Instance {
+    fn normal_at_point(&self, point: &Point, intersection: Intersection) -> Normal {
+        let (primitive, local) = Instance::local_intersection(intersection);
+        let normal = self.mesh.normal_at_point(
+            primitive,
+            &self.transform.inverse().point(*point),
+            local,
+            0.0,
+        );
+        self.transform.normal(normal)
+    }
+
+    fn geometric_normal(&self, point: &Point, intersection: Intersection) -> Normal {
+        let (primitive, local) = Instance::local_intersection(intersection);
+        let normal = self.mesh.geometric_normal(
+            primitive,
+            &self.transform.inverse().point(*point),
+            local,
+            0.0,
+        );
+        self.transform.normal(normal)
+    }
+}
+
+impl Transform for Instance {
+    fn transform(&mut self, transformation: Transformation) {
+        self.transform = Transform3::from(transformation) * self.transform;
+    }
+}
+
+impl BoundingBox for Instance {
+    fn bounding_box(&self) -> AlighnedBox {
+        self.transform.bounding_box(&self.mesh.bounding_box())
+    }
+}
+
+impl RayTracable for Instance {
+    fn material_id(&self, intersection: Intersection) -> Option<usize> {
+        let (primitive, local) = Instance::local_intersection(intersection);
+        self.material
+            .or_else(|| Some(self.mesh.material_at(primitive, local)))
+    }
+
+    fn primitives_count(&self) -> usize {
+        self.mesh.total_primitives_count()
+    }
+
+    fn nodes_count(&self) -> usize {
+        self.mesh.total_nodes_count()
+    }
+
+    fn describe(&self, intersection: Intersection) -> String {
+        let (primitive, local) = Instance::local_intersection(intersection);
+        format!(
+            "Instance of {} primitives, primitive #{} in the object space: {}",
+            self.mesh.unique_objects_count(),
+            primitive,
+            self.mesh.describe(primitive, local)
+        )
+    }
+
+    // The rays are moved into the space of the mesh one by one and traced together.
+    fn intersect_packet(&self, packet: &RayPacket, lanes: Mask4) -> [Option<Intersection>; 4] {
+        let inverse = self.transform.inverse();
+        let local = packet.rays.map(|ray| ray.transformed(&inverse));
+        let hits = self
+            .mesh
+            .trace_packet(&RayPacket::new(local.map(|(ray, _)| ray), lanes));
+        std::array::from_fn(|lane| {
+            hits[lane].map(|(primitive, intersection)| {
+                Instance::world_intersection(primitive, intersection, local[lane].1)
+            })
+        })
+    }
+}
+
+impl Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Instance")
+            .field("primitives", &self.mesh.unique_objects_count())
+            .field("transform", &self.transform)
+            .field("material", &self.material)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::basic_geometry::{triangle::Triangle, vector::Vector, Axis};
+    use crate::complex_structures::bvh::{builder::BvhBuilder, BVHTree};
+    use crate::ray_tracer::{object::Object, scene::LinearTracer};
+
+    fn mesh() -> Rc<dyn ObjectContainer> {
+        let triangle = Triangle::new(
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(1.0, 0.0, 0.0),
+            Point::new(0.0, 1.0, 0.0),
+        );
+        Rc::new(LinearTracer::new(vec![Object::new(
+            Rc::new(RefCell::new(triangle)),
+            3,
+        )]))
+    }
+
+    #[test]
+    fn instance_test() {
+        let mesh = mesh();
+        let instance = Instance::with_transformations(
+            mesh.clone(),
+            &[
+                Transformation::Scale(Vector::new(2.0, 2.0, 2.0)),
+                Transformation::Rotation(Axis::Y, 90.0),
+                Transformation::Translation(Vector::new(0.0, 0.0, 10.0)),
+            ],
+            None,
+        );
+        // The triangle now lies in the plane x = 0, from z = 10 to z = 8.
+        let ray = Ray::new(Point::new(5.0, 0.5, 9.5), Normal::new(-1.0, 0.0, 0.0));
+        let intersection = instance.intersect(&ray).unwrap();
+        assert!((intersection.distance() - 5.0).abs() < 1e-9);
+        assert_eq!(instance.material_id(intersection), Some(3));
+        let normal = instance.normal_at_point(&ray.at(intersection.distance()), intersection);
+        assert!((normal.x.abs() - 1.0).abs() < 1e-9);
+
+        let missed = Ray::new(Point::new(5.0, 0.5, 10.5), Normal::new(-1.0, 0.0, 0.0));
+        assert!(instance.intersect(&missed).is_none());
+
+        let bounds = instance.bounding_box();
+        assert!((bounds.min.z - 8.0).abs() < 1e-9 && (bounds.max.z - 10.0).abs() < 1e-9);
+        assert!((bounds.max.y - 2.0).abs() < 1e-9);
+
+        let overridden = Instance::with_transformations(mesh, &[], Some(1));
+        let ray = Ray::new(Point::new(0.2, 0.2, 1.0), Normal::new(0.0, 0.0, -1.0));
+        let intersection = overridden.intersect(&ray).unwrap();
+        assert_eq!(overridden.material_id(intersection), Some(1));
+    }
+
+    #[test]
+    fn occluded_in_world_distance() {
+        let instance = Instance::with_transformations(
+            mesh(),
+            &[
+                Transformation::Scale(Vector::new(2.0, 2.0, 2.0)),
+                Transformation::Rotation(Axis::Y, 90.0),
+                Transformation::Translation(Vector::new(0.0, 0.0, 10.0)),
+            ],
+            None,
+        );
+        // The hit is 5 away in the world and 2.5 in the space of the mesh.
+        let ray = Ray::new(Point::new(5.0, 0.5, 9.5), Normal::new(-1.0, 0.0, 0.0));
+        assert!(!instance.occluded(&ray, 4.9));
+        assert!(instance.occluded(&ray, 5.1));
+        let missed = Ray::new(Point::new(5.0, 0.5, 10.5), Normal::new(-1.0, 0.0, 0.0));
+        assert!(!instance.occluded(&missed, f64::INFINITY));
+    }
+
+    #[test]
+    fn counts_include_the_mesh() {
+        let triangles = (0..10)
+            .map(|i| {
+                let x = i as f64 * 2.0;
+                let triangle = Triangle::new(
+                    Point::new(x, 0.0, 0.0),
+                    Point::new(x + 1.0, 0.0, 0.0),
+                    Point::new(x, 1.0, 0.0),
+                );
+                Object::new(Rc::new(RefCell::new(triangle)), 0)
+            })
+            .collect();
+        let mesh: Rc<dyn ObjectContainer> =
+            Rc::new(BVHTree::build(triangles, BvhBuilder::default()));
+        let instances = (0..2)
+            .map(|_| {
+                let instance = Instance::with_transformations(mesh.clone(), &[], None);
+                Object::new(Rc::new(RefCell::new(instance)), 0)
+            })
+            .collect();
+        let scene = BVHTree::build(instances, BvhBuilder::default());
+        assert_eq!(scene.unique_objects_count(), 2);
+        assert_eq!(scene.total_primitives_count(), 20);
+        assert_eq!(
+            scene.total_nodes_count(),
+            scene.nodes_count() + 2 * mesh.nodes_count()
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use std::time::Instant;
+
+use super::bvh::Primitive;
+use crate::basic_geometry::{
+    alighned_box::AlighnedBox, normal::Normal, point::Point, ray::Ray, Axis, Intersection,
+    Transformation,
+};
+use crate::ray_tracer::{object::Object, ObjectContainer};
+
+const AXES: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+// Relative costs of a traversal step and of a primitive intersection for the SAH.
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECTION_COST: f64 = 1.5;
+// Splits cutting off empty space are cheaper by this part.
+const EMPTY_BONUS: f64 = 0.2;
+// The traversal stack holds at most one postponed child per level.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+enum KdNode {
+    // The child below the plane follows its parent, the one above it is at `above`.
+    Interior {
+        axis: Axis,
+        position: f64,
+        above: u32,
+    },
+    // Primitives of the leaf are `indices[first..first + count]`.
+    Leaf {
+        first: u32,
+        count: u32,
+    },
+}
+
+// Space split by the axis-aligned planes chosen with the surface area heuristic.
+// Primitives crossing a plane are referenced from both sides.
+pub(crate) struct KdTree<P = Object> {
+    data: Vec<P>,
+    nodes: Vec<KdNode>,
+    indices: Vec<u32>,
+    bounds: AlighnedBox,
+}
+
+impl<P: Primitive> ObjectContainer for KdTree<P> {
+    fn trace(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        let mut closest = None;
+        self.traverse(ray, f64::INFINITY, |indices, max_distance| {
+            for &i in indices {
+                if let Some(intersection) = self.data[i as usize].intersect(ray) {
+                    if intersection.distance() < *max_distance {
+                        *max_distance = intersection.distance();
+                        closest = Some((i as usize, intersection));
+                    }
+                }
+            }
+            false
+        });
+        closest
+    }
+
+    fn occluded(&self, ray: &Ray, max_distance: f64) -> bool {
+        let mut hit = false;
+        self.traverse(ray, max_distance, |indices, _| {
+            hit = indices
+                .iter()
+                .any(|&i| self.data[i as usize].occluded(ray, max_distance));
+            hit
+        });
+        hit
+    }
+
+    fn normal_at_point(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.data[index].normal_at_point(point, intersection, time)
+    }
+
+    fn geometric_normal(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.data[index].geometric_normal(point, intersection, time)
+    }
+
+    fn material_at(&self, index: usize, intersection: Intersection) -> usize {
+        self.data[index].material_at(intersection)
+    }
+
+    fn texture_coordinates(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Option<(f64, f64)> {
+        self.data[index].texture_coordinates(point, intersection, time)
+    }
+
+    fn describe(&self, index: usize, intersection: Intersection) -> String {
+        self.data[index].describe(intersection)
+    }
+
+    fn objects_count(&self) -> usize {
+        self.data.len()
+    }
+
+    fn total_primitives_count(&self) -> usize {
+        self.data.iter().map(Primitive::primitives_count).sum()
+    }
+
+    fn total_nodes_count(&self) -> usize {
+        self.nodes_count() + self.data.iter().map(Primitive::nodes_count).sum::<usize>()
+    }
+
+    fn nodes_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn name(&self) -> &'static str {
+        "k-d tree"
+    }
+
+    fn bounding_box(&self) -> AlighnedBox {
+        self.bounds
+    }
+
+    // The planes can't follow the moved primitive, so the tree is built again.
+    fn transform_object(&mut self, index: usize, transformation: Transformation) {
+        self.data[index].transform(transformation);
+        *self = KdTree::build(std::mem::take(&mut self.data));
+    }
+}
+
+impl<P: Primitive> KdTree<P> {
+    pub(crate) fn new(objects: Vec<P>) -> KdTree<P> {
+        println!("Building k-d tree...");
+        let start = Instant::now();
+        let tree = KdTree::build(objects);
+        println!(
+            "k-d tree built: {:.1} ms, {} nodes, {} references",
+            start.elapsed().as_secs_f64() * 1000.0,
+            tree.nodes.len(),
+            tree.references()
+        );
+        tree
+    }
+
+    pub(crate) fn build(objects: Vec<P>) -> KdTree<P> {
+        let bounds = objects.iter().fold(AlighnedBox::default(), |acc, object| {
+            acc.union(&object.bounding_box())
+        });
+        let references: Vec<_> = objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| (i as u32, object.bounding_box()))
+            .collect();
+        let depth = (8.0 + 1.3 * (objects.len().max(1) as f64).log2()).round() as usize;
+        let mut build = Build {
+            primitives: &objects,
+            nodes: vec![],
+            indices: vec![],
+        };
+        build.node(references, bounds, depth.min(MAX_DEPTH));
+        let (nodes, indices) = (build.nodes, build.indices);
+        KdTree {
+            data: objects,
+            nodes,
+            indices,
+            bounds,
+        }
+    }
+
+    // Primitives referenced from the leaves, counting every copy.
+    pub(crate) fn references(&self) -> usize {
+        self.indices.len()
+    }
+
+    // Visits the leaves pierced by the ray front to back. `visit` gets the primitives
+    // of the leaf and the distance of the closest hit so far, which it can lower.
+    // Returning `true` stops the traversal.
+    fn traverse(
+        &self,
+        ray: &Ray,
+        mut max_distance: f64,
+        mut visit: impl FnMut(&[u32], &mut f64) -> bool,
+    ) {
+        if self.data.is_empty() {
+            return;
+        }
+        let inverse_direction = ray.inverse_direction();
+        let Some((mut entry, mut exit)) =
+            self.bounds
+                .ray_interval(ray, inverse_direction, max_distance)
+        else {
+            return;
+        };
+        let mut stack = [(0, 0.0, 0.0); MAX_DEPTH];
+        let mut size = 0;
+        let mut node_index = 0;
+        loop {
+            match self.nodes[node_index] {
+                KdNode::Interior {
+                    axis,
+                    position,
+                    above,
+                } => {
+                    let origin = ray.origin[axis];
+                    let (below, above) = (node_index + 1, above as usize);
+                    let below_first =
+                        origin < position || (origin == position && inverse_direction[axis] < 0.0);
+                    let (near, far) = if below_first {
+                        (below, above)
+                    } else {
+                        (above, below)
+                    };
+                    node_index = near;
+                    if inverse_direction[axis].is_infinite() {
+                        // The ray lying in the plane touches both sides.
+                        if origin == position {
+                            stack[size] = (far, entry, exit);
+                            size += 1;
+                        }
+                        continue;
+                    }
+                    let split = (position - origin) * inverse_direction[axis];
+                    if split < entry && split > 0.0 {
+                        node_index = far;
+                    } else if split <= exit && split > 0.0 {
+                        stack[size] = (far, split, exit);
+                        size += 1;
+                        exit = split;
+                    }
+                }
+                KdNode::Leaf { first, count } => {
+                    let indices = &self.indices[first as usize..(first + count) as usize];
+                    if visit(indices, &mut max_distance) {
+                        return;
+                    }
+                    // Farther leaves can't have a closer hit.
+                    loop {
+                        if size == 0 {
+                            return;
+                        }
+                        size -= 1;
+                        (node_index, entry, exit) = stack[size];
+                        if entry <= max_distance {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Build<'a, P> {
+    primitives: &'a [P],
+    nodes: Vec<KdNode>,
+    indices: Vec<u32>,
+}
+
+// Planar primitives lying in the plane are kept with the part below it.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Event {
+    End,
+    Planar,
+    Start,
+}
+
+impl<P: Primitive> Build<'_, P> {
+    // `references` are the primitives overlapping the node with their bounds clipped to it.
+    fn node(&mut self, references: Vec<(u32, AlighnedBox)>, bounds: AlighnedBox, depth: usize) {
+        let split = if depth > 0 && references.len() > 1 {
+            find_split(&references, bounds)
+        } else {
+            None
+        };
+        let Some((axis, position)) = split else {
+            self.nodes.push(KdNode::Leaf {
+                first: self.indices.len() as u32,
+                count: references.len() as u32,
+            });
+            self.indices.extend(references.iter().map(|&(i, _)| i));
+            return;
+        };
+        let index = self.nodes.len();
+        self.nodes.push(KdNode::Leaf { first: 0, count: 0 });
+        let (mut below_bounds, mut above_bounds) = (bounds, bounds);
+        below_bounds.max[axis] = position;
+        above_bounds.min[axis] = position;
+        let (mut below, mut above) = (vec![], vec![]);
+        for (i, reference) in references {
+            let (min, max) = (reference.min[axis], reference.max[axis]);
+            let in_below = min < position || (min == position && max == position);
+            let in_above = max > position;
+            if in_below && in_above {
+                below.push((i, self.clip(i, reference, below_bounds, axis)));
+                above.push((i, self.clip(i, reference, above_bounds, axis)));
+            } else if in_below {
+                below.push((i, reference));
+            } else {
+                above.push((i, reference));
+            }
+        }
+        self.node(below, below_bounds, depth - 1);
+        let above_index = self.nodes.len() as u32;
+        self.node(above, above_bounds, depth - 1);
+        self.nodes[index] = KdNode::Interior {
+            axis,
+            position,
+            above: above_index,
+        };
+    }
+
+    // Bounds of the part of the primitive inside of the child. If the clipping loses
+    // the primitive to the rounding, only the split axis is cut.
+    fn clip(&self, index: u32, bounds: AlighnedBox, child: AlighnedBox, axis: Axis) -> AlighnedBox {
+        let clip = bounds.intersection(&child);
+        clip.and_then(|clip| self.primitives[index as usize].clipped_bounds(&clip))
+            .unwrap_or_else(|| {
+                let mut bounds = bounds;
+                bounds.min[axis] = bounds.min[axis].max(child.min[axis]);
+                bounds.max[axis] = bounds.max[axis].min(child.max[axis]);
+                bounds
+            })
+    }
+}
+
+// Plane with the lowest SAH cost, if splitting is cheaper than the leaf.
+fn find_split(references: &[(u32, AlighnedBox)], bounds: AlighnedBox) -> Option<(Axis, f64)> {
+    let area = bounds.surface_area();
+    if area <= 0.0 {
+        return None;
+    }
+    let count = references.len();
+    let mut best: Option<(f64, Axis, f64)> = None;
+    for axis in AXES {
+        let (min, max) = (bounds.min[axis], bounds.max[axis]);
+        if min >= max {
+            continue;
+        }
+        let mut events = Vec::with_capacity(references.len() * 2);
+        for (_, reference) in references {
+            let (start, end) = (reference.min[axis], reference.max[axis]);
+            if start == end {
+                events.push((start, Event::Planar));
+            } else {
+                events.push((start, Event::Start));
+                events.push((end, Event::End));
+            }
+        }
+        events.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.partial_cmp(&b.1).unwrap()));
+        // Primitives starting before the plane and ending after it.
+        let (mut below, mut above) = (0, count);
+        let mut i = 0;
+        while i < events.len() {
+            let position = events[i].0;
+            let mut counts = [0; 3];
+            while i < events.len() && events[i].0 == position {
+                counts[events[i].1 as usize] += 1;
+                i += 1;
+            }
+            let [ending, planar, starting] = counts;
+            above -= ending + planar;
+            if min < position && position < max {
+                let (mut below_bounds, mut above_bounds) = (bounds, bounds);
+                below_bounds.max[axis] = position;
+                above_bounds.min[axis] = position;
+                let below = below + planar;
+                let bonus = if below == 0 || above == 0 {
+                    1.0 - EMPTY_BONUS
+                } else {
+                    1.0
+                };
+                let cost = TRAVERSAL_COST
+                    + INTERSECTION_COST
+                        * bonus
+                        * (below_bounds.surface_area() * below as f64
+                            + above_bounds.surface_area() * above as f64)
+                        / area;
+                if best.as_ref().is_none_or(|&(best, ..)| cost < best) {
+                    best = Some((cost, axis, position));
+                }
+            }
+            below += starting + planar;
+        }
+    }
+    best.filter(|&(cost, ..)| cost < INTERSECTION_COST * count as f64)
+        .map(|(_, axis, position)| (axis, position))
+}
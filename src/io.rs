@@ -1,26 +1,39 @@
-use crate::ray_tracer::{color::Color, material::Material, object::Object, RayTracer};
-use std::path::PathBuf;
+use crate::ray_tracer::{animation::Animation, color::Color, RayTracer};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 
+pub(crate) mod bookmarks;
+pub(crate) mod bvh_cache;
 pub(crate) mod console;
+pub(crate) mod height_map;
+pub(crate) mod image_sequence;
+pub(crate) mod instances;
 pub(crate) mod obj_file;
+pub(crate) mod png_image;
 pub(crate) mod ppm_image;
+pub(crate) mod subdivision;
 #[cfg(feature = "windowed")]
 pub(crate) mod window;
 
 pub(crate) enum OutputType {
     Console,
     Image(PathBuf),
+    // Numbered images, one per animation frame.
+    Sequence(PathBuf, Animation),
     #[cfg(feature = "windowed")]
-    Window((usize, usize)),
+    Window(window::WindowConfig),
 }
 
 impl OutputType {
-    pub(crate) fn create_handler(&self) -> Box<dyn Output> {
+    pub(crate) fn create_handler(self) -> Box<dyn Output> {
         match self {
             OutputType::Console => Box::new(console::Console {}),
-            OutputType::Image(path) => Box::new(ppm_image::PPMImage::new(path.clone())),
+            OutputType::Image(path) => image_output(path),
+            OutputType::Sequence(path, animation) => {
+                Box::new(image_sequence::ImageSequence::new(path, animation))
+            }
             #[cfg(feature = "windowed")]
-            &OutputType::Window((width, height)) => Box::new(window::Window::new(width, height)),
+            OutputType::Window(config) => Box::new(window::Window::new(config)),
         }
     }
 }
@@ -30,6 +43,42 @@ pub(crate) trait Output {
     fn dump(&mut self, buff: &[Color], width: usize, height: usize) -> anyhow::Result<()>;
 }
 
-pub(crate) trait Input {
-    fn load(&self) -> anyhow::Result<(Vec<Object>, Vec<Material>)>;
+pub(crate) fn is_supported_image(path: &Path) -> bool {
+    [Some(OsStr::new("ppm")), Some(OsStr::new("png"))].contains(&path.extension())
+}
+
+// Picks the image format by the file extension.
+pub(crate) fn image_output(path: PathBuf) -> Box<dyn Output> {
+    if path.extension() == Some(OsStr::new("png")) {
+        Box::new(png_image::PNGImage::new(path))
+    } else {
+        Box::new(ppm_image::PPMImage::new(path))
+    }
+}
+
+// Appends the zero padded number to the file name: `image.ppm` -> `image_0007.ppm`.
+pub(crate) fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().and_then(OsStr::to_str).unwrap_or("image");
+    let name = match path.extension().and_then(OsStr::to_str) {
+        Some(extension) => format!("{}_{:04}.{}", stem, number, extension),
+        None => format!("{}_{:04}", stem, number),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_path_test() {
+        assert_eq!(
+            numbered_path(Path::new("out/frame.png"), 7),
+            PathBuf::from("out/frame_0007.png")
+        );
+        assert_eq!(
+            numbered_path(Path::new("frame"), 12345),
+            PathBuf::from("frame_12345")
+        );
+    }
 }
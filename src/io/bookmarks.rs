@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail};
+
+use crate::basic_geometry::{point::Point, vector::Vector};
+use crate::ray_tracer::camera::CameraPose;
+
+// Numbered camera poses stored in a text file. Every line has a form:
+// `slot position_x position_y position_z rotation_x rotation_y rotation_z`
+pub(crate) struct Bookmarks {
+    path: PathBuf,
+    poses: BTreeMap<usize, CameraPose>,
+}
+
+impl Bookmarks {
+    // Loads the bookmarks from the file. A missing file means there are no bookmarks yet.
+    pub(crate) fn load(path: PathBuf) -> anyhow::Result<Bookmarks> {
+        let poses = if path.exists() {
+            Bookmarks::parse(&std::fs::read_to_string(&path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Bookmarks { path, poses })
+    }
+
+    pub(crate) fn get(&self, slot: usize) -> Option<CameraPose> {
+        self.poses.get(&slot).copied()
+    }
+
+    // Stores the pose and writes all bookmarks back to the file.
+    pub(crate) fn set(&mut self, slot: usize, pose: CameraPose) -> anyhow::Result<()> {
+        self.poses.insert(slot, pose);
+        std::fs::write(&self.path, self.to_string())?;
+        Ok(())
+    }
+
+    fn parse(content: &str) -> anyhow::Result<BTreeMap<usize, CameraPose>> {
+        let mut poses = BTreeMap::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(str::parse::<f64>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| anyhow!("Line {}: {}", number + 1, e))?;
+            if values.len() != 7 || values[0] < 0.0 || values[0].fract() != 0.0 {
+                bail!(
+                    "Line {}: expected a slot number and 6 coordinates",
+                    number + 1
+                );
+            }
+            poses.insert(
+                values[0] as usize,
+                CameraPose {
+                    position: Point::new(values[1], values[2], values[3]),
+                    rotation_angles: Vector::new(values[4], values[5], values[6]),
+                },
+            );
+        }
+        Ok(poses)
+    }
+}
+
+impl Display for Bookmarks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (slot, pose) in &self.poses {
+            let (p, r) = (pose.position, pose.rotation_angles);
+            writeln!(
+                f,
+                "{} {} {} {} {} {} {}",
+                slot, p.x, p.y, p.z, r.x, r.y, r.z
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let pose = CameraPose {
+            position: Point::new(1.5, -2., 300.),
+            rotation_angles: Vector::new(10., -45.5, 0.),
+        };
+        let mut poses = BTreeMap::new();
+        poses.insert(3, pose);
+        let bookmarks = Bookmarks {
+            path: PathBuf::new(),
+            poses,
+        };
+        let parsed = Bookmarks::parse(&bookmarks.to_string()).unwrap();
+        assert_eq!(parsed.get(&3), Some(&pose));
+        assert_eq!(parsed.len(), 1);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Bookmarks::parse("1 0 0 0 0 0").is_err());
+        assert!(Bookmarks::parse("1 0 0 0 0 0 x").is_err());
+        assert!(Bookmarks::parse("1.5 0 0 0 0 0 0").is_err());
+        assert!(Bookmarks::parse("# comment\n\n").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::bail;
+
+use super::subdivision::Refinement;
+use crate::complex_structures::bvh::builder::BvhBuilder;
+use crate::complex_structures::bvh::{check_layout, BVHTree, FlatNode, FLAT_NODE_SIZE};
+use crate::ray_tracer::object::MeshTriangle;
+
+const MAGIC: &[u8; 4] = b"BVHC";
+const VERSION: u32 = 2;
+
+// Built trees of the OBJ models saved next to the file (`model.rtcache`), so the big models
+// aren't rebuilt on every run. Files without the header of the cache are never overwritten.
+// The cache is used while the OBJ file keeps its size and modification time.
+// Only the nodes and the order of the triangles are stored, the triangles come from the OBJ.
+// Trees built by another builder or over differently refined models are not used.
+pub(crate) struct BvhCache {
+    path: PathBuf,
+    stamp: Stamp,
+    builder: BvhBuilder,
+    refinement: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stamp {
+    length: u64,
+    seconds: u64,
+    nanoseconds: u32,
+}
+
+impl BvhCache {
+    pub(crate) fn new(source: &Path, builder: BvhBuilder) -> anyhow::Result<BvhCache> {
+        let metadata = std::fs::metadata(source)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok(BvhCache {
+            path: source.with_extension("rtcache"),
+            stamp: Stamp {
+                length: metadata.len(),
+                seconds: modified.as_secs(),
+                nanoseconds: modified.subsec_nanos(),
+            },
+            builder,
+            refinement: String::new(),
+        })
+    }
+
+    // Cache of the models subdivided or displaced while they were loaded.
+    pub(crate) fn refined(self, refinement: &Refinement) -> BvhCache {
+        BvhCache {
+            refinement: refinement.key(),
+            ..self
+        }
+    }
+
+    // Trees of the models from the cache, or newly built ones which are saved for the next run.
+    pub(crate) fn load_or_build(
+        &self,
+        meshes: Vec<Vec<MeshTriangle>>,
+    ) -> Vec<BVHTree<MeshTriangle>> {
+        let counts: Vec<_> = meshes.iter().map(Vec::len).collect();
+        match self.read(&counts) {
+            Ok(parts) => {
+                println!("BVH trees loaded from {}", self.path.display());
+                meshes
+                    .into_iter()
+                    .zip(parts)
+                    .map(|(mesh, (nodes, order))| {
+                        BVHTree::from_parts(mesh, nodes, order, self.builder)
+                    })
+                    .collect()
+            }
+            Err(e) => {
+                if self.path.exists() {
+                    println!("BVH cache is ignored: {}", e);
+                }
+                let trees: Vec<_> = meshes
+                    .into_iter()
+                    .map(|mesh| BVHTree::new(mesh, self.builder))
+                    .collect();
+                if self.is_foreign() {
+                    println!(
+                        "BVH cache is not saved, {} is another file",
+                        self.path.display()
+                    );
+                } else if let Err(e) = self.write(&trees) {
+                    println!("Failed to save the BVH cache: {}", e);
+                }
+                trees
+            }
+        }
+    }
+
+    // Whether something else than the cache is in its place.
+    fn is_foreign(&self) -> bool {
+        let mut header = [0; 4];
+        match std::fs::File::open(&self.path) {
+            Ok(mut file) => file.read_exact(&mut header).is_err() || &header != MAGIC,
+            Err(_) => false,
+        }
+    }
+
+    fn write(&self, trees: &[BVHTree<MeshTriangle>]) -> anyhow::Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.length.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.seconds.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.nanoseconds.to_le_bytes());
+        let settings = self.settings();
+        bytes.extend_from_slice(&(settings.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&settings);
+        bytes.extend_from_slice(&(trees.len() as u32).to_le_bytes());
+        for tree in trees {
+            bytes.extend_from_slice(&(tree.nodes().len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&(tree.order().len() as u32).to_le_bytes());
+            for node in tree.nodes() {
+                bytes.extend_from_slice(&node.to_bytes());
+            }
+            for &index in tree.order() {
+                bytes.extend_from_slice(&index.to_le_bytes());
+            }
+        }
+        std::fs::write(&self.path, bytes)?;
+        Ok(())
+    }
+
+    // Nodes and triangle order of every model, if the cache matches the models.
+    #[allow(clippy::type_complexity)]
+    fn read(&self, counts: &[usize]) -> anyhow::Result<Vec<(Vec<FlatNode>, Vec<u32>)>> {
+        let bytes = std::fs::read(&self.path)?;
+        let mut reader = Reader { bytes: &bytes };
+        if reader.take(4)? != MAGIC || reader.u32()? != VERSION {
+            bail!("unknown format");
+        }
+        let stamp = Stamp {
+            length: reader.u64()?,
+            seconds: reader.u64()?,
+            nanoseconds: reader.u32()?,
+        };
+        if stamp != self.stamp {
+            bail!("the model file was changed");
+        }
+        let settings_length = reader.u32()? as usize;
+        if reader.take(settings_length)? != self.settings() {
+            bail!("the trees were built with other settings");
+        }
+        if reader.u32()? as usize != counts.len() {
+            bail!("the file has another number of models");
+        }
+        let mut trees = Vec::with_capacity(counts.len());
+        for &count in counts {
+            let nodes_count = reader.u32()? as usize;
+            let references = reader.u32()? as usize;
+            let nodes: Vec<_> = reader
+                .take(nodes_count * FLAT_NODE_SIZE)?
+                .chunks_exact(FLAT_NODE_SIZE)
+                .map(|chunk| FlatNode::from_bytes(chunk.try_into().unwrap()))
+                .collect();
+            let order: Vec<_> = (0..references)
+                .map(|_| reader.u32())
+                .collect::<anyhow::Result<_>>()?;
+            check_layout(&nodes, &order, count)?;
+            trees.push((nodes, order));
+        }
+        if !reader.bytes.is_empty() {
+            bail!("unexpected data at the end");
+        }
+        Ok(trees)
+    }
+}
+
+impl BvhCache {
+    fn settings(&self) -> Vec<u8> {
+        let builder = &self.builder;
+        let mut bytes = builder.strategy.to_string().into_bytes();
+        bytes.extend_from_slice(&(builder.max_primitives_in_node as u32).to_le_bytes());
+        bytes.extend_from_slice(&builder.traversal_cost.to_le_bytes());
+        bytes.extend_from_slice(self.refinement.as_bytes());
+        bytes
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> anyhow::Result<&'a [u8]> {
+        if count > self.bytes.len() {
+            bail!("the file is truncated");
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::{point::Point, triangle::Triangle};
+    use crate::complex_structures::bvh::builder::Strategy;
+
+    fn meshes() -> Vec<Vec<MeshTriangle>> {
+        let triangle = |x: f64| {
+            let triangle = Triangle::new(
+                Point::new(x, 0.0, 0.0),
+                Point::new(x + 1.0, 0.0, 0.0),
+                Point::new(x, 1.0, 0.0),
+            );
+            MeshTriangle::new(triangle, 0)
+        };
+        vec![
+            (0..10).map(|i| triangle(i as f64 * 2.0)).collect(),
+            vec![triangle(-5.0)],
+        ]
+    }
+
+    #[test]
+    fn trees_round_trip() {
+        let source = std::env::temp_dir().join(format!("bvh_cache_{}.obj", std::process::id()));
+        std::fs::write(&source, "v 0 0 0").unwrap();
+        let cache = BvhCache::new(&source, BvhBuilder::default()).unwrap();
+        let built = cache.load_or_build(meshes());
+        let loaded = cache.read(&[10, 1]).unwrap();
+        for (tree, (nodes, order)) in built.iter().zip(&loaded) {
+            assert_eq!(tree.nodes(), nodes.as_slice());
+            assert_eq!(tree.order(), order.as_slice());
+        }
+        assert!(cache.read(&[10, 2]).is_err());
+        let builder = BvhBuilder::with_strategy(Strategy::Morton);
+        assert!(BvhCache::new(&source, builder)
+            .unwrap()
+            .read(&[10, 1])
+            .is_err());
+        let refinement = Refinement {
+            subdivision: Some("loop:1".parse().unwrap()),
+            ..Refinement::default()
+        };
+        let refined = BvhCache::new(&source, BvhBuilder::default())
+            .unwrap()
+            .refined(&refinement);
+        assert!(refined.read(&[10, 1]).is_err());
+        // Changed model invalidates the cache.
+        std::fs::write(&source, "v 0 0 0\nv 1 1 1").unwrap();
+        let cache = BvhCache::new(&source, BvhBuilder::default()).unwrap();
+        assert!(cache.read(&[10, 1]).is_err());
+        std::fs::remove_file(&cache.path).unwrap();
+        std::fs::remove_file(&source).unwrap();
+    }
+
+    #[test]
+    fn other_files_are_kept() {
+        let source = std::env::temp_dir().join(format!("bvh_foreign_{}.obj", std::process::id()));
+        std::fs::write(&source, "v 0 0 0").unwrap();
+        let cache = BvhCache::new(&source, BvhBuilder::default()).unwrap();
+        assert_eq!(cache.path.extension().unwrap(), "rtcache");
+        std::fs::write(&cache.path, "HIERARCHY").unwrap();
+        assert_eq!(cache.load_or_build(meshes()).len(), 2);
+        assert_eq!(std::fs::read(&cache.path).unwrap(), b"HIERARCHY");
+        std::fs::remove_file(&cache.path).unwrap();
+        std::fs::remove_file(&source).unwrap();
+    }
+}
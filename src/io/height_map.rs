@@ -0,0 +1,180 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+
+// Grayscale image with the values from 0 to 1, the rows go from the top.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HeightMap {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) values: Vec<f64>,
+}
+
+// Reads PGM and PPM (ASCII or binary) or PNG images, the colors are turned into the luminance.
+pub(crate) fn load(path: &Path) -> anyhow::Result<HeightMap> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let map = if extension.eq_ignore_ascii_case("png") {
+        load_png(path)?
+    } else {
+        parse_netpbm(&std::fs::read(path)?)?
+    };
+    if map.width < 2 || map.height < 2 {
+        bail!("The height map has to be at least 2x2 pixels");
+    }
+    Ok(map)
+}
+
+fn luminance(rgb: &[f64]) -> f64 {
+    0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]
+}
+
+fn parse_netpbm(data: &[u8]) -> anyhow::Result<HeightMap> {
+    // The header is four words, with the comments up to the end of the line.
+    let mut position = 0;
+    let mut words = vec![];
+    while words.len() < 4 {
+        match data.get(position) {
+            None => bail!("Incomplete image header"),
+            Some(b'#') => {
+                while data.get(position).is_some_and(|&c| c != b'\n') {
+                    position += 1;
+                }
+            }
+            Some(c) if c.is_ascii_whitespace() => position += 1,
+            Some(_) => {
+                let start = position;
+                while data.get(position).is_some_and(|c| !c.is_ascii_whitespace()) {
+                    position += 1;
+                }
+                words.push(std::str::from_utf8(&data[start..position])?);
+            }
+        }
+    }
+    let channels = match words[0] {
+        "P2" | "P5" => 1,
+        "P3" | "P6" => 3,
+        magic => bail!("Unsupported image type {}", magic),
+    };
+    let number = |word: &str| {
+        word.parse::<usize>()
+            .map_err(|e| anyhow!("Incorrect image header {}: {}", word, e))
+    };
+    let (width, height, max) = (number(words[1])?, number(words[2])?, number(words[3])?);
+    if max == 0 || max > 65535 {
+        bail!("Incorrect maximal value {}", max);
+    }
+    let count = width * height * channels;
+    let samples: Vec<usize> = if matches!(words[0], "P2" | "P3") {
+        std::str::from_utf8(&data[position..])?
+            .split_whitespace()
+            .take(count)
+            .map(number)
+            .collect::<anyhow::Result<_>>()?
+    } else {
+        // A single whitespace separates the header from the binary data.
+        let bytes = if max < 256 { 1 } else { 2 };
+        let data = data.get(position + 1..).unwrap_or_default();
+        data.chunks_exact(bytes)
+            .take(count)
+            .map(|sample| sample.iter().fold(0, |acc, &b| acc * 256 + b as usize))
+            .collect()
+    };
+    if samples.len() != count {
+        bail!("Expected {} values, found {}", count, samples.len());
+    }
+    let values = samples
+        .chunks_exact(channels)
+        .map(|pixel| {
+            let pixel: Vec<f64> = pixel.iter().map(|&v| v as f64 / max as f64).collect();
+            if channels == 1 {
+                pixel[0]
+            } else {
+                luminance(&pixel)
+            }
+        })
+        .collect();
+    Ok(HeightMap {
+        width,
+        height,
+        values,
+    })
+}
+
+fn load_png(path: &Path) -> anyhow::Result<HeightMap> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    // Palettes and the low bit depths are expanded to the bytes.
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data)?;
+    let channels = info.color_type.samples();
+    let samples: Vec<f64> = match info.bit_depth {
+        png::BitDepth::Sixteen => data[..info.buffer_size()]
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as f64 / 65535.0)
+            .collect(),
+        _ => data[..info.buffer_size()]
+            .iter()
+            .map(|&b| b as f64 / 255.0)
+            .collect(),
+    };
+    // The alpha is ignored.
+    let values = samples
+        .chunks_exact(channels)
+        .map(|pixel| {
+            if channels < 3 {
+                pixel[0]
+            } else {
+                luminance(pixel)
+            }
+        })
+        .collect();
+    Ok(HeightMap {
+        width: info.width as usize,
+        height: info.height as usize,
+        values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netpbm_images() {
+        let ascii = parse_netpbm(b"P2\n# terrain\n3 2\n4\n0 1 2\n3 4 4\n").unwrap();
+        assert_eq!((ascii.width, ascii.height), (3, 2));
+        assert_eq!(ascii.values[1], 0.25);
+        assert_eq!(ascii.values[3], 0.75);
+        let mut binary = b"P5 2 2 65535\n".to_vec();
+        binary.extend([0, 0, 255, 255, 128, 0, 0, 1]);
+        let binary = parse_netpbm(&binary).unwrap();
+        assert_eq!(binary.values[1], 1.0);
+        assert_eq!(binary.values[2], 32768.0 / 65535.0);
+        let color = parse_netpbm(b"P3 1 1 255 255 255 255").unwrap();
+        assert!((color.values[0] - 1.0).abs() < 1e-12);
+        assert!(parse_netpbm(b"P5 2 2 255\n\x00\x01").is_err());
+        assert!(parse_netpbm(b"P4 2 2 255").is_err());
+    }
+
+    #[test]
+    fn png_image() {
+        let path = std::env::temp_dir().join(format!("height_map_{}.png", std::process::id()));
+        {
+            let file = std::io::BufWriter::new(File::create(&path).unwrap());
+            let mut encoder = png::Encoder::new(file, 2, 2);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[0, 51, 255, 102]).unwrap();
+        }
+        let map = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(map.values, vec![0.0, 0.2, 1.0, 0.4]);
+    }
+}
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use crate::io::{image_output, numbered_path, Output};
+use crate::ray_tracer::{animation::Animation, color::Color, RayTracer};
+
+// Renders every frame of the animation into the numbered image file.
+pub(crate) struct ImageSequence {
+    path: PathBuf,
+    animation: Animation,
+    frame: usize,
+}
+
+impl ImageSequence {
+    pub(crate) fn new(path: PathBuf, animation: Animation) -> ImageSequence {
+        ImageSequence {
+            path,
+            animation,
+            frame: 0,
+        }
+    }
+}
+
+impl Output for ImageSequence {
+    fn dump(&mut self, buff: &[Color], width: usize, height: usize) -> anyhow::Result<()> {
+        image_output(numbered_path(&self.path, self.frame)).dump(buff, width, height)
+    }
+
+    fn process(&mut self, mut ray_tracer: RayTracer) -> anyhow::Result<()> {
+        let frames = self.animation.frames.clone();
+        let count = frames.len();
+        for (done, frame) in frames.enumerate() {
+            self.frame = frame;
+            let time = self.animation.frame_time(frame);
+            self.animation.path.apply(time, ray_tracer.camera_mut());
+            ray_tracer.set_time(time);
+            ray_tracer.render(self)?;
+            println!(
+                "Frame {} ({}/{}) rendered in {:.2}s",
+                frame,
+                done + 1,
+                count,
+                ray_tracer.last_frame_stats().duration.as_secs_f64()
+            );
+        }
+        Ok(())
+    }
+}
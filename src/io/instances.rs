@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+
+use crate::basic_geometry::{
+    quaternion::Quaternion, transform::Transform3, vector::Vector, Transformation,
+};
+
+// Placement of one copy of the loaded model.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Placement {
+    // Scale, rotation around X, Y, Z and translation composed into one.
+    pub(crate) transformations: Vec<Transformation>,
+    // Name of the material replacing the materials of the model.
+    pub(crate) material: Option<String>,
+}
+
+// Reads the placements from the file. Every line has a form:
+// `translation_x translation_y translation_z rotation_x rotation_y rotation_z scale_x scale_y scale_z [material]`
+// Rotation is in degrees. Empty lines and lines starting with '#' are ignored.
+pub(crate) fn load(path: &Path) -> anyhow::Result<Vec<Placement>> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+fn parse(content: &str) -> anyhow::Result<Vec<Placement>> {
+    let mut placements = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace().collect::<Vec<_>>();
+        let material = if words.len() == 10 {
+            words.pop().map(str::to_string)
+        } else {
+            None
+        };
+        let values = words
+            .into_iter()
+            .map(str::parse::<f64>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("Line {}: {}", number + 1, e))?;
+        if values.len() != 9 {
+            bail!(
+                "Line {}: expected translation, rotation, scale and an optional material",
+                number + 1
+            );
+        }
+        placements.push(Placement {
+            transformations: vec![Transformation::Composite(
+                Transform3::trs(
+                    Vector::new(values[0], values[1], values[2]),
+                    Quaternion::from_euler(Vector::new(values[3], values[4], values[5])),
+                    Vector::new(values[6], values[7], values[8]),
+                )
+                .ok_or_else(|| anyhow!("Line {}: scale can't be zero", number + 1))?,
+            )],
+            material,
+        });
+    }
+    Ok(placements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::point::Point;
+
+    #[test]
+    fn parse_test() {
+        let placements =
+            parse("# copies\n1 2 3 0 90 0 1 1 1\n\n0 0 0 0 0 0 2 2 2 reflective").unwrap();
+        assert_eq!(placements.len(), 2);
+        assert_eq!(placements[0].material, None);
+        let Transformation::Composite(transform) = placements[0].transformations[0] else {
+            panic!("Placement isn't composed");
+        };
+        // Rotated around Y by 90 degrees, then moved.
+        let moved = transform.point(Point::new(1.0, 0.0, 0.0));
+        assert!((moved - Point::new(1.0, 2.0, 2.0)).length() < 1e-9);
+        assert_eq!(placements[1].material, Some("reflective".to_string()));
+        assert!(parse("1 2 3").is_err());
+        assert!(parse("0 0 0 0 0 0 0 1 1").is_err());
+        assert!(parse("0 0 0 0 0 0 1 1 x lambert").is_err());
+    }
+}
@@ -1,28 +1,47 @@
-use std::{cell::RefCell, path::PathBuf, rc::Rc};
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
 
 use crate::{
-    basic_geometry::{normal::Normal, point::Point, triangle::Triangle},
-    ray_tracer::{material::Material, object::Object},
+    basic_geometry::{alighned_box::AlighnedBox, normal::Normal, point::Point, triangle::Triangle},
+    ray_tracer::{material::Material, object::MeshTriangle},
 };
 
-use super::Input;
+use super::{
+    height_map,
+    subdivision::{PolygonMesh, Refinement},
+};
 
 pub(crate) struct ObjectFile {
     path: PathBuf,
+    refinement: Refinement,
 }
 
 impl ObjectFile {
     pub(crate) fn new(path: PathBuf) -> ObjectFile {
-        ObjectFile { path }
+        ObjectFile::with_refinement(path, Refinement::default())
+    }
+
+    // The models are subdivided and displaced while they are loaded.
+    pub(crate) fn with_refinement(path: PathBuf, refinement: Refinement) -> ObjectFile {
+        ObjectFile { path, refinement }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn refinement(&self) -> &Refinement {
+        &self.refinement
     }
 }
 
-impl Input for ObjectFile {
-    fn load(&self) -> anyhow::Result<(Vec<Object>, Vec<Material>)> {
+impl ObjectFile {
+    pub(crate) fn load_triangles(&self) -> anyhow::Result<(Vec<Vec<MeshTriangle>>, Vec<Material>)> {
         let (models, materials) = tobj::load_obj(
             &self.path,
             &tobj::LoadOptions {
-                triangulate: true,
+                // The subdivision keeps the quads.
+                triangulate: self.refinement.is_empty(),
                 ignore_lines: true,
                 ignore_points: true,
                 single_index: true,
@@ -34,12 +53,33 @@ impl Input for ObjectFile {
         let lambert_id = materials.len();
         materials.push(Material::lambert());
 
+        if !self.refinement.is_empty() {
+            let map = match &self.refinement.displacement {
+                Some((path, _)) => Some(height_map::load(path)?),
+                None => None,
+            };
+            let data = models
+                .into_iter()
+                .map(|model| {
+                    let material_id = model.mesh.material_id.unwrap_or(lambert_id);
+                    let triangles = self
+                        .refinement
+                        .apply(polygon_mesh(&model.mesh), map.as_ref())?;
+                    Ok(triangles
+                        .into_iter()
+                        .map(|triangle| MeshTriangle::new(triangle, material_id))
+                        .collect::<Vec<_>>())
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let data = data.into_iter().filter(|mesh| !mesh.is_empty()).collect();
+            return Ok((data, materials));
+        }
+
         let data = models
             .into_iter()
-            .flat_map(|model| {
+            .map(|model| {
                 let size = model.mesh.indices.len() / 3;
-                let mut result = vec![];
-                result.reserve(size);
+                let mut result = Vec::with_capacity(size);
                 for i in 0..size {
                     let i = 3 * i;
                     let (i1, i2, i3) = (
@@ -67,15 +107,53 @@ impl Input for ObjectFile {
                     } else {
                         Triangle::new(point1, point2, point3)
                     };
-                    result.push(Object::new(Rc::new(RefCell::new(triangle)), texture_id));
+                    result.push(MeshTriangle::new(triangle, texture_id));
                 }
                 result
             })
+            .filter(|mesh| !mesh.is_empty())
             .collect::<Vec<_>>();
         Ok((data, materials))
     }
 }
 
+// Polygons of the model with their texture coordinates, the faces are triangles when
+// the file doesn't tell their sizes. Without the coordinates in the file the texture is
+// wrapped around the model like around a globe.
+fn polygon_mesh(mesh: &tobj::Mesh) -> PolygonMesh {
+    let points: Vec<_> = mesh.positions.chunks_exact(3).map(get_point).collect();
+    let bounds = points
+        .iter()
+        .fold(AlighnedBox::default(), |acc, &point| acc.union_point(point));
+    let center = bounds.min + (bounds.max - bounds.min) * 0.5;
+    let uv = |index: usize| match mesh.texcoords.get(index * 2..index * 2 + 2) {
+        Some(uv) => [uv[0] as f64, uv[1] as f64],
+        None => {
+            let direction = points[index] - center;
+            let length = direction.length().max(f64::MIN_POSITIVE);
+            [
+                0.5 + direction.z.atan2(direction.x) / (2. * PI),
+                0.5 + (direction.y / length).asin() / PI,
+            ]
+        }
+    };
+    let mut faces = vec![];
+    let mut start = 0;
+    while start < mesh.indices.len() {
+        let size = match mesh.face_arities.get(faces.len()) {
+            Some(&size) => size as usize,
+            None => 3,
+        };
+        let face = mesh.indices[start..(start + size).min(mesh.indices.len())]
+            .iter()
+            .map(|&index| (index as usize, uv(index as usize)))
+            .collect();
+        faces.push(face);
+        start += size;
+    }
+    PolygonMesh::new(&points, faces)
+}
+
 fn get_point(slice: &[f32]) -> Point {
     Point::new(slice[0] as f64, slice[1] as f64, slice[2] as f64)
 }
@@ -0,0 +1,36 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use crate::io::Output;
+use crate::ray_tracer::color::Color;
+
+pub(crate) struct PNGImage {
+    file_path: PathBuf,
+}
+
+impl PNGImage {
+    pub(crate) fn new(file_path: PathBuf) -> PNGImage {
+        PNGImage { file_path }
+    }
+}
+
+impl Output for PNGImage {
+    fn dump(&mut self, buff: &[Color], width: usize, height: usize) -> anyhow::Result<()> {
+        let stream = BufWriter::new(File::create(&self.file_path)?);
+        let mut encoder = png::Encoder::new(stream, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        let data = buff[..width * height]
+            .iter()
+            .flat_map(|color| color.rgb())
+            .collect::<Vec<_>>();
+        writer.write_image_data(&data)?;
+        Ok(())
+    }
+
+    fn process(&mut self, mut ray_tracer: crate::ray_tracer::RayTracer) -> anyhow::Result<()> {
+        ray_tracer.render(self)
+    }
+}
@@ -0,0 +1,756 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, bail};
+
+use super::height_map::HeightMap;
+use crate::basic_geometry::{point::Point, triangle::Triangle, vector::Vector};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Scheme {
+    // Triangles, the quads and the other polygons are split first.
+    Loop,
+    // Any polygons, every level gives quads.
+    CatmullClark,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Subdivision {
+    pub(crate) scheme: Scheme,
+    pub(crate) levels: usize,
+}
+
+// Faces a subdivided mesh may have, every level makes about four times more.
+const MAX_FACES: usize = 1 << 24;
+
+impl FromStr for Subdivision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Subdivision> {
+        let (name, levels) = match s.split_once(':') {
+            Some((name, levels)) => (name, levels.parse::<usize>()?),
+            None => (s, 2),
+        };
+        let scheme = match name {
+            "loop" => Scheme::Loop,
+            "catmull-clark" => Scheme::CatmullClark,
+            _ => return Err(anyhow!("Unknown subdivision scheme {}", s)),
+        };
+        Ok(Subdivision { scheme, levels })
+    }
+}
+
+impl Display for Subdivision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self.scheme {
+            Scheme::Loop => "loop",
+            Scheme::CatmullClark => "catmull-clark",
+        };
+        write!(f, "{}:{}", name, self.levels)
+    }
+}
+
+// Changes of the OBJ models made while they are loaded. The normals of the file are
+// replaced by the ones of the new surface, smoothed everywhere except the creases.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Refinement {
+    pub(crate) subdivision: Option<Subdivision>,
+    // Edges between the faces turned by more than the angle in degrees stay sharp.
+    pub(crate) crease_angle: Option<f64>,
+    // Grayscale texture moving the vertices along the normals by its value times the scale.
+    pub(crate) displacement: Option<(PathBuf, f64)>,
+}
+
+impl Refinement {
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Refinement::default()
+    }
+
+    // Text of the settings for the key of the BVH cache, with the time the texture was changed.
+    pub(crate) fn key(&self) -> String {
+        let mut key = vec![];
+        if let Some(subdivision) = self.subdivision {
+            key.push(format!("subdivide={}", subdivision));
+        }
+        if let Some(angle) = self.crease_angle {
+            key.push(format!("crease={}", angle));
+        }
+        if let Some((path, scale)) = &self.displacement {
+            let modified = std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |modified| modified.as_nanos());
+            key.push(format!(
+                "displacement={} scale={} modified={}",
+                path.display(),
+                scale,
+                modified
+            ));
+        }
+        key.join(" ")
+    }
+
+    pub(crate) fn apply(
+        &self,
+        mut mesh: PolygonMesh,
+        map: Option<&HeightMap>,
+    ) -> anyhow::Result<Vec<Triangle>> {
+        if let Some(angle) = self.crease_angle {
+            mesh.mark_creases(angle);
+        }
+        if let Some(subdivision) = self.subdivision {
+            for level in 0..subdivision.levels {
+                if mesh.subdivided_faces(subdivision.scheme) > MAX_FACES {
+                    bail!(
+                        "Subdivision level {} would make more than {} faces",
+                        level + 1,
+                        MAX_FACES
+                    );
+                }
+                mesh = match subdivision.scheme {
+                    Scheme::Loop => mesh.triangulated().loop_step(),
+                    Scheme::CatmullClark => mesh.catmull_clark_step(),
+                };
+            }
+        }
+        if let (Some(map), Some((_, scale))) = (map, &self.displacement) {
+            mesh.displace(map, *scale);
+        }
+        Ok(mesh.triangulated().triangles())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Corner {
+    vertex: usize,
+    uv: [f64; 2],
+}
+
+// Polygons sharing their vertices, the texture coordinates are kept in the corners
+// so the seams of the texture stay where they were.
+#[derive(Debug, Clone)]
+pub(crate) struct PolygonMesh {
+    positions: Vec<Vector>,
+    faces: Vec<Vec<Corner>>,
+    // Sharp edges by the ordered indices of their vertices.
+    creases: HashSet<(usize, usize)>,
+}
+
+#[derive(Debug)]
+struct Edge {
+    ends: [usize; 2],
+    faces: Vec<usize>,
+    // Creases, the open boundary and the edges of more than two faces.
+    sharp: bool,
+}
+
+// Edges in the order of the faces, so the new vertices are the same on every run.
+#[derive(Debug)]
+struct Topology {
+    edges: Vec<Edge>,
+    index: HashMap<(usize, usize), usize>,
+    // Edges and faces of every vertex.
+    vertex_edges: Vec<Vec<usize>>,
+    vertex_faces: Vec<Vec<usize>>,
+}
+
+fn key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+fn middle(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    [(a[0] + b[0]) / 2., (a[1] + b[1]) / 2.]
+}
+
+impl Topology {
+    fn edge(&self, a: usize, b: usize) -> usize {
+        self.index[&key(a, b)]
+    }
+
+    fn other_end(&self, edge: usize, vertex: usize) -> usize {
+        let ends = self.edges[edge].ends;
+        if ends[0] == vertex {
+            ends[1]
+        } else {
+            ends[0]
+        }
+    }
+}
+
+impl PolygonMesh {
+    // The points at the same place are welded into one vertex, the faces which lose
+    // their area by that are dropped.
+    pub(crate) fn new(points: &[Point], faces: Vec<Vec<(usize, [f64; 2])>>) -> PolygonMesh {
+        let mut welded = HashMap::new();
+        let mut positions = vec![];
+        let vertices: Vec<usize> = points
+            .iter()
+            .map(|point| {
+                let bits = [point.x.to_bits(), point.y.to_bits(), point.z.to_bits()];
+                *welded.entry(bits).or_insert_with(|| {
+                    positions.push(Vector::from(*point));
+                    positions.len() - 1
+                })
+            })
+            .collect();
+        let faces = faces
+            .into_iter()
+            .map(|face| {
+                face.into_iter()
+                    .map(|(index, uv)| Corner {
+                        vertex: vertices[index],
+                        uv,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|face| {
+                let unique: HashSet<_> = face.iter().map(|corner| corner.vertex).collect();
+                face.len() >= 3 && unique.len() == face.len()
+            })
+            .collect();
+        PolygonMesh {
+            positions,
+            faces,
+            creases: HashSet::new(),
+        }
+    }
+
+    fn sides(face: &[Corner]) -> impl Iterator<Item = (Corner, Corner)> + '_ {
+        face.iter()
+            .zip(face.iter().cycle().skip(1))
+            .map(|(a, b)| (*a, *b))
+    }
+
+    fn topology(&self) -> Topology {
+        let mut edges: Vec<Edge> = vec![];
+        let mut index = HashMap::new();
+        let mut vertex_edges = vec![vec![]; self.positions.len()];
+        let mut vertex_faces = vec![vec![]; self.positions.len()];
+        for (f, face) in self.faces.iter().enumerate() {
+            for (a, b) in Self::sides(face) {
+                vertex_faces[a.vertex].push(f);
+                let edge = *index.entry(key(a.vertex, b.vertex)).or_insert_with(|| {
+                    edges.push(Edge {
+                        ends: [a.vertex, b.vertex],
+                        faces: vec![],
+                        sharp: self.creases.contains(&key(a.vertex, b.vertex)),
+                    });
+                    vertex_edges[a.vertex].push(edges.len() - 1);
+                    vertex_edges[b.vertex].push(edges.len() - 1);
+                    edges.len() - 1
+                });
+                edges[edge].faces.push(f);
+            }
+        }
+        for edge in &mut edges {
+            edge.sharp |= edge.faces.len() != 2;
+        }
+        Topology {
+            edges,
+            index,
+            vertex_edges,
+            vertex_faces,
+        }
+    }
+
+    // Newell's normal, its length is twice the area of the face.
+    fn face_normal(&self, face: &[Corner]) -> Vector {
+        Self::sides(face).fold(Vector::new(0., 0., 0.), |acc, (a, b)| {
+            acc + self.positions[a.vertex].cross(self.positions[b.vertex])
+        })
+    }
+
+    fn mark_creases(&mut self, angle: f64) {
+        let cos = angle.to_radians().cos();
+        let topology = self.topology();
+        for edge in topology.edges.iter().filter(|edge| edge.faces.len() == 2) {
+            let a = self.face_normal(&self.faces[edge.faces[0]]);
+            let b = self.face_normal(&self.faces[edge.faces[1]]);
+            if a.dot(b) < cos * a.length() * b.length() {
+                self.creases.insert(key(edge.ends[0], edge.ends[1]));
+            }
+        }
+    }
+
+    // The old vertices on the creases: moved along two sharp edges or kept in the corners
+    // where more of them meet. None for the smooth rule of the scheme.
+    fn sharp_vertex(&self, topology: &Topology, vertex: usize) -> Option<Vector> {
+        let sharp: Vec<_> = topology.vertex_edges[vertex]
+            .iter()
+            .filter(|&&edge| topology.edges[edge].sharp)
+            .map(|&edge| self.positions[topology.other_end(edge, vertex)])
+            .collect();
+        let position = self.positions[vertex];
+        match sharp.len() {
+            0 | 1 => None,
+            2 => Some(position * 0.75 + (sharp[0] + sharp[1]) * 0.125),
+            _ => Some(position),
+        }
+    }
+
+    // The children of the crease edges are creases too.
+    fn split_creases(
+        &self,
+        topology: &Topology,
+        first_edge_point: usize,
+    ) -> HashSet<(usize, usize)> {
+        self.creases
+            .iter()
+            .flat_map(|&(a, b)| {
+                let middle = first_edge_point + topology.edge(a, b);
+                [key(a, middle), key(middle, b)]
+            })
+            .collect()
+    }
+
+    fn loop_step(&self) -> PolygonMesh {
+        let topology = self.topology();
+        let count = self.positions.len();
+        let vertices = (0..count).map(|vertex| {
+            self.sharp_vertex(&topology, vertex).unwrap_or_else(|| {
+                let edges = &topology.vertex_edges[vertex];
+                let n = edges.len() as f64;
+                let beta = if edges.len() == 3 {
+                    3. / 16.
+                } else {
+                    3. / (8. * n)
+                };
+                edges
+                    .iter()
+                    .fold(self.positions[vertex] * (1. - n * beta), |acc, &edge| {
+                        acc + self.positions[topology.other_end(edge, vertex)] * beta
+                    })
+            })
+        });
+        let edge_points = topology.edges.iter().map(|edge| {
+            let [a, b] = edge.ends.map(|vertex| self.positions[vertex]);
+            if edge.sharp {
+                return (a + b) * 0.5;
+            }
+            let opposite = edge.faces.iter().fold(Vector::new(0., 0., 0.), |acc, &f| {
+                let corner = self.faces[f]
+                    .iter()
+                    .find(|corner| !edge.ends.contains(&corner.vertex))
+                    .unwrap();
+                acc + self.positions[corner.vertex]
+            });
+            (a + b) * 0.375 + opposite * 0.125
+        });
+        let positions = vertices.chain(edge_points).collect();
+        let faces = self
+            .faces
+            .iter()
+            .flat_map(|face| {
+                let middles: Vec<_> = Self::sides(face)
+                    .map(|(a, b)| Corner {
+                        vertex: count + topology.edge(a.vertex, b.vertex),
+                        uv: middle(a.uv, b.uv),
+                    })
+                    .collect();
+                [
+                    vec![face[0], middles[0], middles[2]],
+                    vec![face[1], middles[1], middles[0]],
+                    vec![face[2], middles[2], middles[1]],
+                    middles,
+                ]
+            })
+            .collect();
+        PolygonMesh {
+            positions,
+            faces,
+            creases: self.split_creases(&topology, count),
+        }
+    }
+
+    fn catmull_clark_step(&self) -> PolygonMesh {
+        let topology = self.topology();
+        let count = self.positions.len();
+        let face_points: Vec<_> = self
+            .faces
+            .iter()
+            .map(|face| {
+                face.iter().fold(Vector::new(0., 0., 0.), |acc, corner| {
+                    acc + self.positions[corner.vertex]
+                }) / face.len() as f64
+            })
+            .collect();
+        let vertices = (0..count).map(|vertex| {
+            self.sharp_vertex(&topology, vertex).unwrap_or_else(|| {
+                let faces = &topology.vertex_faces[vertex];
+                let edges = &topology.vertex_edges[vertex];
+                if faces.is_empty() {
+                    return self.positions[vertex];
+                }
+                let n = edges.len() as f64;
+                let average = faces
+                    .iter()
+                    .fold(Vector::new(0., 0., 0.), |acc, &f| acc + face_points[f])
+                    / faces.len() as f64;
+                let middles = edges.iter().fold(Vector::new(0., 0., 0.), |acc, &edge| {
+                    let [a, b] = topology.edges[edge].ends;
+                    acc + (self.positions[a] + self.positions[b]) * 0.5
+                }) / n;
+                (average + middles * 2. + self.positions[vertex] * (n - 3.)) / n
+            })
+        });
+        let edge_points = topology.edges.iter().map(|edge| {
+            let [a, b] = edge.ends.map(|vertex| self.positions[vertex]);
+            if edge.sharp {
+                (a + b) * 0.5
+            } else {
+                (a + b + face_points[edge.faces[0]] + face_points[edge.faces[1]]) * 0.25
+            }
+        });
+        let first_face_point = count + topology.edges.len();
+        let positions = vertices
+            .chain(edge_points)
+            .chain(face_points.iter().copied())
+            .collect();
+        let faces = self
+            .faces
+            .iter()
+            .enumerate()
+            .flat_map(|(f, face)| {
+                let uv = face.iter().fold([0., 0.], |acc, corner| {
+                    [acc[0] + corner.uv[0], acc[1] + corner.uv[1]]
+                });
+                let center = Corner {
+                    vertex: first_face_point + f,
+                    uv: uv.map(|value| value / face.len() as f64),
+                };
+                let middles: Vec<_> = Self::sides(face)
+                    .map(|(a, b)| Corner {
+                        vertex: count + topology.edge(a.vertex, b.vertex),
+                        uv: middle(a.uv, b.uv),
+                    })
+                    .collect();
+                (0..face.len())
+                    .map(|i| {
+                        let previous = middles[(i + face.len() - 1) % face.len()];
+                        vec![face[i], middles[i], center, previous]
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        PolygonMesh {
+            positions,
+            faces,
+            creases: self.split_creases(&topology, count),
+        }
+    }
+
+    // Faces after one more level, Loop splits the triangulated faces in four and
+    // Catmull-Clark makes a quad at every corner.
+    fn subdivided_faces(&self, scheme: Scheme) -> usize {
+        match scheme {
+            Scheme::Loop => self.faces.iter().map(|face| 4 * (face.len() - 2)).sum(),
+            Scheme::CatmullClark => self.faces.iter().map(Vec::len).sum(),
+        }
+    }
+
+    // Fans of triangles, the diagonals are never sharp.
+    fn triangulated(self) -> PolygonMesh {
+        let faces = self
+            .faces
+            .iter()
+            .flat_map(|face| (1..face.len() - 1).map(|i| vec![face[0], face[i], face[i + 1]]))
+            .collect();
+        PolygonMesh { faces, ..self }
+    }
+
+    // Every vertex moves along its normal smoothed over all its faces so the creases don't
+    // open, the texture coordinates of its first corner are used.
+    fn displace(&mut self, map: &HeightMap, scale: f64) {
+        let mut normals = vec![Vector::new(0., 0., 0.); self.positions.len()];
+        let mut uvs = vec![None; self.positions.len()];
+        for face in &self.faces {
+            let normal = self.face_normal(face);
+            for corner in face {
+                normals[corner.vertex] = normals[corner.vertex] + normal;
+                uvs[corner.vertex].get_or_insert(corner.uv);
+            }
+        }
+        for ((position, normal), uv) in self.positions.iter_mut().zip(normals).zip(uvs) {
+            if let (Some(uv), true) = (uv, normal.length() > 0.) {
+                let offset = Vector::from(normal.normalize()) * (sample(map, uv) * scale);
+                *position = *position + offset;
+            }
+        }
+    }
+
+    // The normals of the corners are averaged over the faces around the vertex which
+    // are reached without crossing the sharp edges, weighted by the area.
+    fn triangles(&self) -> Vec<Triangle> {
+        let topology = self.topology();
+        let corner = |f: usize, vertex: usize| {
+            3 * f
+                + self.faces[f]
+                    .iter()
+                    .position(|c| c.vertex == vertex)
+                    .unwrap()
+        };
+        let mut groups: Vec<usize> = (0..3 * self.faces.len()).collect();
+        fn root(groups: &mut [usize], mut i: usize) -> usize {
+            while groups[i] != i {
+                groups[i] = groups[groups[i]];
+                i = groups[i];
+            }
+            i
+        }
+        for edge in topology.edges.iter().filter(|edge| !edge.sharp) {
+            for vertex in edge.ends {
+                let a = root(&mut groups, corner(edge.faces[0], vertex));
+                let b = root(&mut groups, corner(edge.faces[1], vertex));
+                groups[a] = b;
+            }
+        }
+        let face_normals: Vec<_> = self
+            .faces
+            .iter()
+            .map(|face| self.face_normal(face))
+            .collect();
+        let mut sums = vec![Vector::new(0., 0., 0.); groups.len()];
+        for i in 0..groups.len() {
+            let group = root(&mut groups, i);
+            sums[group] = sums[group] + face_normals[i / 3];
+        }
+        self.faces
+            .iter()
+            .enumerate()
+            .map(|(f, face)| {
+                let [a, b, c] = [0, 1, 2].map(|i| {
+                    let sum = sums[root(&mut groups, 3 * f + i)];
+                    let normal = if sum.length() > 0. {
+                        sum
+                    } else {
+                        face_normals[f]
+                    };
+                    (
+                        Point::from(self.positions[face[i].vertex]),
+                        normal.normalize(),
+                    )
+                });
+                Triangle::with_normals(a.0, a.1, b.0, b.1, c.0, c.1)
+            })
+            .collect()
+    }
+}
+
+// Bilinear value of the repeated texture, `v` goes up from the bottom row.
+fn sample(map: &HeightMap, uv: [f64; 2]) -> f64 {
+    let wrap = |t: f64| {
+        if (0. ..=1.).contains(&t) {
+            t
+        } else {
+            t.rem_euclid(1.)
+        }
+    };
+    let x = wrap(uv[0]) * (map.width - 1) as f64;
+    let y = (1. - wrap(uv[1])) * (map.height - 1) as f64;
+    let (column, row) = (
+        (x as usize).min(map.width - 2),
+        (y as usize).min(map.height - 2),
+    );
+    let (fx, fy) = (x - column as f64, y - row as f64);
+    let at = |column: usize, row: usize| map.values[row * map.width + column];
+    let top = at(column, row) * (1. - fx) + at(column + 1, row) * fx;
+    let bottom = at(column, row + 1) * (1. - fx) + at(column + 1, row + 1) * fx;
+    top * (1. - fy) + bottom * fy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_geometry::{normal::Normal, ray::Ray, Intersect, NormalAtPoint};
+    use crate::test_utils::close;
+
+    // Interpolated normal where the ray hits the triangles first.
+    fn nearest_normal(triangles: &[Triangle], ray: Ray) -> Vector {
+        let (triangle, intersection) = triangles
+            .iter()
+            .filter_map(|triangle| Some((triangle, triangle.intersect(&ray)?)))
+            .min_by(|a, b| a.1.distance().total_cmp(&b.1.distance()))
+            .unwrap();
+        let point = ray.at(intersection.distance());
+        Vector::from(triangle.normal_at_point(&point, intersection))
+    }
+
+    // Cube from -1 to 1 with its own four points in every face, as OBJ files often have.
+    fn cube() -> PolygonMesh {
+        let corners = [
+            [0, 1, 3, 2],
+            [4, 6, 7, 5],
+            [0, 4, 5, 1],
+            [2, 3, 7, 6],
+            [0, 2, 6, 4],
+            [1, 5, 7, 3],
+        ];
+        let mut points = vec![];
+        let mut faces = vec![];
+        for face in corners {
+            let mut polygon = vec![];
+            for (i, corner) in face.into_iter().enumerate() {
+                let coordinate = |bit: usize| if corner & bit == 0 { -1. } else { 1. };
+                points.push(Point::new(coordinate(4), coordinate(2), coordinate(1)));
+                polygon.push((points.len() - 1, [(i % 2) as f64, (i / 2) as f64]));
+            }
+            faces.push(polygon);
+        }
+        PolygonMesh::new(&points, faces)
+    }
+
+    fn tetrahedron() -> PolygonMesh {
+        let points = [
+            Point::new(1., 1., 1.),
+            Point::new(1., -1., -1.),
+            Point::new(-1., 1., -1.),
+            Point::new(-1., -1., 1.),
+        ];
+        let faces = [[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]]
+            .into_iter()
+            .map(|face| face.into_iter().map(|i| (i, [0., 0.])).collect())
+            .collect();
+        PolygonMesh::new(&points, faces)
+    }
+
+    #[test]
+    fn parse_subdivision() {
+        let loop_2 = Subdivision {
+            scheme: Scheme::Loop,
+            levels: 2,
+        };
+        assert_eq!("loop".parse::<Subdivision>().unwrap(), loop_2);
+        let subdivision: Subdivision = "catmull-clark:3".parse().unwrap();
+        assert_eq!(subdivision.scheme, Scheme::CatmullClark);
+        assert_eq!(subdivision.levels, 3);
+        assert_eq!(
+            subdivision.to_string().parse::<Subdivision>().unwrap(),
+            subdivision
+        );
+        assert!("butterfly".parse::<Subdivision>().is_err());
+    }
+
+    #[test]
+    fn loop_tetrahedron() {
+        let mut mesh = tetrahedron();
+        for _ in 0..2 {
+            mesh = mesh.loop_step();
+        }
+        // Every level adds a vertex on each edge, the closed surface keeps V - E + F = 2.
+        assert_eq!(mesh.positions.len(), 34);
+        assert_eq!(mesh.faces.len(), 64);
+        assert_eq!(mesh.subdivided_faces(Scheme::Loop), 256);
+        assert_eq!(mesh.topology().edges.len(), 96);
+        // The surface shrinks towards the center and stays symmetric.
+        let lengths: Vec<_> = mesh.positions[..4].iter().map(Vector::length).collect();
+        assert!(lengths.iter().all(|&l| l < 3f64.sqrt() && l > 0.));
+        assert!(lengths.iter().all(|&l| (l - lengths[0]).abs() < 1e-12));
+    }
+
+    #[test]
+    fn catmull_clark_cube() {
+        let mesh = cube();
+        assert_eq!(mesh.positions.len(), 8);
+        let mesh = mesh.catmull_clark_step();
+        assert_eq!(mesh.positions.len(), 26);
+        assert_eq!(mesh.faces.len(), 24);
+        assert_eq!(cube().subdivided_faces(Scheme::CatmullClark), 24);
+        assert!(mesh.faces.iter().all(|face| face.len() == 4));
+        let corner = Vector::new(5. / 9., 5. / 9., 5. / 9.);
+        assert!(mesh.positions.iter().any(|&p| close(p, corner)));
+        let edge = Vector::new(0.75, 0.75, 0.);
+        assert!(mesh.positions.iter().any(|&p| close(p, edge)));
+        // Next to the corner the smooth normal points out of the cube diagonally.
+        let triangles = mesh.triangulated().triangles();
+        let normal = nearest_normal(
+            &triangles,
+            Ray::new(Point::new(5.01, 5., 4.99), Normal::new(-1., -1., -1.)),
+        );
+        assert!(normal.dot(Vector::new(1., 1., 1.) / 3f64.sqrt()) > 0.999);
+    }
+
+    #[test]
+    fn creases_stay_sharp() {
+        let mut mesh = cube();
+        mesh.mark_creases(30.);
+        assert_eq!(mesh.creases.len(), 12);
+        for _ in 0..2 {
+            mesh = mesh.catmull_clark_step();
+        }
+        // With every edge sharp the cube keeps its shape and its flat faces.
+        for position in &mesh.positions {
+            let largest = position.x.abs().max(position.y.abs()).max(position.z.abs());
+            assert!((largest - 1.).abs() < 1e-12);
+        }
+        assert!(mesh.positions.contains(&Vector::new(1., 1., 1.)));
+        let triangles = mesh.triangulated().triangles();
+        let ray = Ray::new(Point::new(0.9, 0.9, 5.), Normal::new(0., 0., -1.));
+        let normal = nearest_normal(&triangles, ray);
+        assert!(close(normal, Vector::new(0., 0., 1.)));
+        // Loop splits the quads and keeps the same creases.
+        let mut mesh = cube();
+        mesh.mark_creases(30.);
+        let mesh = mesh.triangulated().loop_step();
+        assert!(mesh.positions.contains(&Vector::new(1., 1., 1.)));
+        assert_eq!(mesh.creases.len(), 24);
+    }
+
+    #[test]
+    fn too_many_faces() {
+        let refinement = Refinement {
+            subdivision: Some("loop:20".parse().unwrap()),
+            ..Refinement::default()
+        };
+        let mut mesh = tetrahedron();
+        mesh.faces = mesh
+            .faces
+            .iter()
+            .cycle()
+            .take(MAX_FACES / 4 + 1)
+            .cloned()
+            .collect();
+        assert!(refinement.apply(mesh, None).is_err());
+    }
+
+    #[test]
+    fn key_lists_the_settings() {
+        assert_eq!(Refinement::default().key(), "");
+        let refinement = Refinement {
+            subdivision: Some("catmull-clark".parse().unwrap()),
+            crease_angle: Some(30.),
+            displacement: Some((PathBuf::from("missing.png"), -0.5)),
+        };
+        assert_eq!(
+            refinement.key(),
+            "subdivide=catmull-clark:2 crease=30 displacement=missing.png scale=-0.5 modified=0"
+        );
+    }
+
+    #[test]
+    fn displacement_along_normals() {
+        let points = [
+            Point::new(0., 0., 0.),
+            Point::new(1., 0., 0.),
+            Point::new(1., 1., 0.),
+            Point::new(0., 1., 0.),
+        ];
+        let face = vec![(0, [0., 0.]), (1, [1., 0.]), (2, [1., 1.]), (3, [0., 1.])];
+        // The bottom row of the texture is at the v of 0.
+        let map = HeightMap {
+            width: 2,
+            height: 2,
+            values: vec![1., 1., 0., 0.5],
+        };
+        assert!((sample(&map, [0.5, 0.5]) - 0.625).abs() < 1e-12);
+        assert!((sample(&map, [1.25, -0.5]) - 0.5625).abs() < 1e-12);
+        let mut mesh = PolygonMesh::new(&points, vec![face]);
+        mesh.displace(&map, 2.);
+        let heights: Vec<_> = mesh.positions.iter().map(|p| p.z).collect();
+        assert_eq!(heights, vec![0., 1., 2., 2.]);
+        assert!(close(mesh.positions[2], Vector::new(1., 1., 2.)));
+    }
+}
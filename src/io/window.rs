@@ -1,108 +1,266 @@
-use super::Output;
-use crate::{
-    basic_geometry::{vector::Vector, Axis, Transformation},
-    ray_tracer::{color::Color, RayTracer},
-};
+pub(crate) mod bindings;
+mod controller;
+mod font;
+mod hud;
 
-use minifb::{Key, Window as WindowHandler, WindowOptions};
+use std::path::PathBuf;
+
+use super::{bookmarks::Bookmarks, image_output, numbered_path, Output};
+use crate::basic_geometry::Transformation;
+use crate::ray_tracer::{color::Color, RayTracer};
+
+use bindings::{Action, Bindings};
+use controller::CameraController;
+use hud::Hud;
+use minifb::{Key, KeyRepeat, MouseMode, Window as WindowHandler, WindowOptions};
+
+// Mouse movement (in pixels) after which the press is a drag and not a click.
+const CLICK_TOLERANCE: f32 = 3.0;
+
+// Frame saved by the screenshot key, rendered again with the better quality.
+pub(crate) struct ScreenshotConfig {
+    pub(crate) path: PathBuf,
+    // Multiplier of the window resolution.
+    pub(crate) scale: usize,
+    pub(crate) samples: usize,
+}
+
+impl Default for ScreenshotConfig {
+    fn default() -> ScreenshotConfig {
+        ScreenshotConfig {
+            path: PathBuf::from("screenshot.png"),
+            scale: 2,
+            samples: 4,
+        }
+    }
+}
+
+pub(crate) struct WindowConfig {
+    pub(crate) size: (usize, usize),
+    pub(crate) bindings: Bindings,
+    pub(crate) screenshot: ScreenshotConfig,
+    pub(crate) bookmarks: Bookmarks,
+}
 
 pub(crate) struct Window {
     window: WindowHandler,
+    bindings: Bindings,
+    screenshot: ScreenshotConfig,
+    screenshots_taken: usize,
+    bookmarks: Bookmarks,
+    hud: Hud,
+    // Where the pick button was pressed and whether the mouse was dragged since.
+    press: Option<((f32, f32), bool)>,
+    picked: Option<usize>,
+    // Distance from the camera to the picked point, sets the speed of dragging.
+    picked_distance: f64,
+    last_drag: Option<(f32, f32)>,
+    frame: Vec<u32>,
+    width: usize,
+    height: usize,
 }
 
 impl Window {
-    pub(crate) fn new(width: usize, height: usize) -> Window {
+    pub(crate) fn new(config: WindowConfig) -> Window {
+        let (width, height) = config.size;
         let mut window =
             WindowHandler::new("Raytracer", width, height, WindowOptions::default()).unwrap();
         window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
 
-        Window { window }
+        Window {
+            window,
+            bindings: config.bindings,
+            screenshot: config.screenshot,
+            screenshots_taken: 0,
+            bookmarks: config.bookmarks,
+            hud: Hud::new(),
+            press: None,
+            picked: None,
+            picked_distance: 0.0,
+            last_drag: None,
+            frame: vec![],
+            width,
+            height,
+        }
     }
 
-    fn handle_events(&mut self, ray_tracer: &mut RayTracer) -> bool {
-        let mut handled_event = false;
-        let keys = self.window.get_keys();
-        keys.iter().for_each(|key| match key {
-            Key::W => {
-                ray_tracer.transform_camera(Transformation::Translation(-movement(
-                    ray_tracer.rotation_vector(),
-                )));
-                handled_event = true;
+    // Handles the pressed keys that aren't moving the camera continuously.
+    // Returns whether the camera was changed and whether the overlay was changed.
+    fn handle_commands(&mut self, ray_tracer: &mut RayTracer) -> anyhow::Result<(bool, bool)> {
+        let (mut camera_changed, mut overlay_changed) = (false, false);
+        for key in self.window.get_keys_pressed(KeyRepeat::No) {
+            if let Some(slot) = Bindings::bookmark_slot(key) {
+                if self.window.is_key_down(self.bindings.bookmark_modifier) {
+                    self.bookmarks.set(slot, ray_tracer.camera().pose())?;
+                    println!("Camera bookmark {} saved", slot);
+                } else if let Some(pose) = self.bookmarks.get(slot) {
+                    ray_tracer.camera_mut().set_pose(pose);
+                    camera_changed = true;
+                } else {
+                    println!("Camera bookmark {} is empty", slot);
+                }
+                continue;
             }
-            Key::S => {
-                ray_tracer.transform_camera(Transformation::Translation(movement(
-                    ray_tracer.rotation_vector(),
-                )));
-                handled_event = true;
+            match self.bindings.action(key) {
+                Some(Action::ToggleHud) => {
+                    self.hud.visible = !self.hud.visible;
+                    overlay_changed = true;
+                }
+                Some(Action::ToggleHelp) => {
+                    self.hud.help = !self.hud.help;
+                    overlay_changed = true;
+                }
+                Some(Action::Screenshot) => self.take_screenshot(ray_tracer)?,
+                _ => {}
             }
-            Key::A => {
-                ray_tracer
-                    .transform_camera(Transformation::Translation(Vector::new(-0.5, 0.0, 0.0)));
-                handled_event = true;
-            }
-            Key::D => {
-                ray_tracer
-                    .transform_camera(Transformation::Translation(Vector::new(0.5, 0.0, 0.0)));
-                handled_event = true;
-            }
-            Key::LeftShift => {
-                ray_tracer
-                    .transform_camera(Transformation::Translation(Vector::new(0.0, 0.5, 0.0)));
-                handled_event = true;
-            }
-            Key::LeftCtrl => {
-                ray_tracer
-                    .transform_camera(Transformation::Translation(Vector::new(0.0, -0.5, 0.0)));
-                handled_event = true;
-            }
-            Key::Left => {
-                ray_tracer.transform_camera(Transformation::Rotation(Axis::Y, 5.0));
-                handled_event = true;
-            }
-            Key::Right => {
-                ray_tracer.transform_camera(Transformation::Rotation(Axis::Y, -5.0));
-                handled_event = true;
+        }
+        Ok((camera_changed, overlay_changed))
+    }
+
+    // Returns the pixel clicked by the pick button, if the button was released.
+    fn handle_click(&mut self) -> Option<(usize, usize)> {
+        let down = self.window.get_mouse_down(self.bindings.pick);
+        let position = self.window.get_mouse_pos(MouseMode::Discard);
+        match (down, self.press, position) {
+            (true, None, Some(position)) => self.press = Some((position, false)),
+            (true, Some((start, false)), Some((x, y)))
+                if (x - start.0).abs() > CLICK_TOLERANCE
+                    || (y - start.1).abs() > CLICK_TOLERANCE =>
+            {
+                self.press = Some((start, true));
             }
-            Key::Up => {
-                ray_tracer.transform_camera(Transformation::Rotation(Axis::X, -5.0));
-                handled_event = true;
+            (false, Some((start, dragged)), _) => {
+                self.press = None;
+                if !dragged {
+                    return Some((start.0 as usize, start.1 as usize));
+                }
             }
-            Key::Down => {
-                ray_tracer.transform_camera(Transformation::Rotation(Axis::X, 5.0));
-                handled_event = true;
+            _ => {}
+        }
+        None
+    }
+
+    fn pick(&mut self, ray_tracer: &RayTracer, (x, y): (usize, usize)) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        match ray_tracer.pick(x, y) {
+            Some(report) => {
+                println!("{}\n", report);
+                self.picked = Some(report.object_index);
+                self.picked_distance = report.intersection.distance();
             }
-            Key::RightCtrl => {
-                ray_tracer.transform_camera(Transformation::Rotation(Axis::Z, 5.0));
-                handled_event = true;
+            None => {
+                println!("Pixel: {:?}\nNothing was hit\n", (x, y));
+                self.picked = None;
             }
-            Key::RightShift => {
-                ray_tracer.transform_camera(Transformation::Rotation(Axis::Z, -5.0));
-                handled_event = true;
+        }
+    }
+
+    // Moves the picked object with the mouse. Returns true if it was moved.
+    fn drag_object(&mut self, ray_tracer: &mut RayTracer) -> bool {
+        let picked = match self.picked {
+            Some(picked) if self.window.get_mouse_down(self.bindings.drag) => picked,
+            _ => {
+                self.last_drag = None;
+                return false;
             }
-            _ => {}
-        });
-        handled_event
+        };
+        let position = self.window.get_mouse_pos(MouseMode::Pass);
+        let last = std::mem::replace(&mut self.last_drag, position);
+        let ((x, y), (last_x, last_y)) = match (position, last) {
+            (Some(position), Some(last)) if position != last => (position, last),
+            _ => return false,
+        };
+        let camera = ray_tracer.camera();
+        // Size of the pixel at the distance of the picked point.
+        let pixel_size = 2.0 * self.picked_distance * (camera.fov().to_radians() / 2.0).tan()
+            / self.height.max(1) as f64;
+        let (dx, dy) = ((x - last_x) as f64, (y - last_y) as f64);
+        let offset = (camera.right() * dx - camera.up() * dy) * pixel_size;
+        ray_tracer.transform_object(picked, Transformation::Translation(offset));
+        true
+    }
+
+    fn take_screenshot(&mut self, ray_tracer: &mut RayTracer) -> anyhow::Result<()> {
+        let (width, height) = (
+            self.width * self.screenshot.scale.max(1),
+            self.height * self.screenshot.scale.max(1),
+        );
+        let mut path = numbered_path(&self.screenshot.path, self.screenshots_taken);
+        while path.exists() {
+            self.screenshots_taken += 1;
+            path = numbered_path(&self.screenshot.path, self.screenshots_taken);
+        }
+        println!(
+            "Saving {}x{} screenshot with {} samples per pixel...",
+            width, height, self.screenshot.samples
+        );
+        let buff = ray_tracer.render_image(width, height, self.screenshot.samples);
+        image_output(path.clone()).dump(&buff, width, height)?;
+        println!("Screenshot saved to {}", path.display());
+        Ok(())
+    }
+
+    fn present(&mut self, ray_tracer: &RayTracer) -> anyhow::Result<()> {
+        let mut buff = self.frame.clone();
+        if let Some(picked) = self.picked {
+            hud::draw_outline(
+                &mut buff,
+                self.width,
+                self.height,
+                ray_tracer.object_ids(),
+                picked,
+            );
+        }
+        self.hud.draw(
+            &mut buff,
+            self.width,
+            self.height,
+            ray_tracer,
+            &self.bindings,
+        );
+        self.window
+            .update_with_buffer(&buff, self.width, self.height)?;
+        Ok(())
     }
 }
 
 impl Output for Window {
     fn dump(&mut self, buff: &[Color], width: usize, height: usize) -> anyhow::Result<()> {
-        let buff = buff
+        self.frame = buff
             .iter()
             .map(|color| color.rgb())
             .map(|[r, g, b]| (r as u32) << 16 | (g as u32) << 8 | b as u32)
             .collect::<Vec<_>>();
-        self.window
-            .update_with_buffer(&buff, width, height)
-            .unwrap();
+        self.width = width;
+        self.height = height;
         Ok(())
     }
 
     fn process(&mut self, mut ray_tracer: RayTracer) -> anyhow::Result<()> {
+        let mut controller = CameraController::new(
+            ray_tracer.camera(),
+            ray_tracer.scene().objects().bounding_box(),
+        );
         ray_tracer.render(self)?;
+        self.present(&ray_tracer)?;
         while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
-            if self.handle_events(&mut ray_tracer) {
+            let (camera_changed, mut overlay_changed) = self.handle_commands(&mut ray_tracer)?;
+            if let Some(pixel) = self.handle_click() {
+                self.pick(&ray_tracer, pixel);
+                overlay_changed = true;
+            }
+            if camera_changed {
+                controller.reset(ray_tracer.camera());
+            }
+            let moved = controller.update(&self.window, &self.bindings, ray_tracer.camera_mut());
+            let dragged = self.drag_object(&mut ray_tracer);
+            if camera_changed || moved || dragged {
                 ray_tracer.render(self)?;
+                self.present(&ray_tracer)?;
+            } else if overlay_changed {
+                self.present(&ray_tracer)?;
             } else {
                 self.window.update();
             }
@@ -110,15 +268,3 @@ impl Output for Window {
         Ok(())
     }
 }
-
-fn movement(camera_angles: Vector) -> Vector {
-    let movement = 0.5;
-    let pitch = camera_angles.x.to_radians();
-    let yaw = camera_angles.y.to_radians();
-
-    let x = movement * yaw.sin() * pitch.cos();
-    let y = movement * -pitch.sin();
-    let z = movement * yaw.cos() * pitch.cos();
-
-    Vector::new(x, y, z)
-}
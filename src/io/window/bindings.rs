@@ -0,0 +1,314 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+use minifb::{Key, MouseButton};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    YawLeft,
+    YawRight,
+    PitchUp,
+    PitchDown,
+    RollLeft,
+    RollRight,
+    ToggleHud,
+    ToggleHelp,
+    Screenshot,
+}
+
+const ACTIONS: [Action; 15] = [
+    Action::MoveForward,
+    Action::MoveBackward,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::MoveUp,
+    Action::MoveDown,
+    Action::YawLeft,
+    Action::YawRight,
+    Action::PitchUp,
+    Action::PitchDown,
+    Action::RollLeft,
+    Action::RollRight,
+    Action::ToggleHud,
+    Action::ToggleHelp,
+    Action::Screenshot,
+];
+
+// Keys that can be used in the bindings file. Named the same way as in minifb.
+const KEYS: [Key; 74] = [
+    Key::Key0,
+    Key::Key1,
+    Key::Key2,
+    Key::Key3,
+    Key::Key4,
+    Key::Key5,
+    Key::Key6,
+    Key::Key7,
+    Key::Key8,
+    Key::Key9,
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+    Key::Down,
+    Key::Left,
+    Key::Right,
+    Key::Up,
+    Key::Space,
+    Key::Tab,
+    Key::Enter,
+    Key::Backspace,
+    Key::Delete,
+    Key::Insert,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+    Key::LeftShift,
+    Key::RightShift,
+    Key::LeftCtrl,
+    Key::RightCtrl,
+    Key::LeftAlt,
+    Key::RightAlt,
+    Key::Comma,
+    Key::Period,
+    Key::Minus,
+    Key::Equal,
+    Key::LeftBracket,
+    Key::RightBracket,
+];
+
+impl Action {
+    pub(crate) fn description(&self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move forward",
+            Action::MoveBackward => "Move backward",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::YawLeft => "Turn left",
+            Action::YawRight => "Turn right",
+            Action::PitchUp => "Look up",
+            Action::PitchDown => "Look down",
+            Action::RollLeft => "Roll left",
+            Action::RollRight => "Roll right",
+            Action::ToggleHud => "Show/hide stats overlay",
+            Action::ToggleHelp => "Show/hide this help",
+            Action::Screenshot => "Save screenshot",
+        }
+    }
+
+    fn parse(name: &str) -> anyhow::Result<Action> {
+        ACTIONS
+            .into_iter()
+            .find(|action| format!("{:?}", action).eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow!("Unknown action '{}'", name))
+    }
+}
+
+fn parse_key(name: &str) -> anyhow::Result<Key> {
+    KEYS.into_iter()
+        .find(|key| format!("{:?}", key).eq_ignore_ascii_case(name))
+        .ok_or_else(|| anyhow!("Unknown key '{}'", name))
+}
+
+fn parse_mouse_button(name: &str) -> anyhow::Result<MouseButton> {
+    [MouseButton::Left, MouseButton::Middle, MouseButton::Right]
+        .into_iter()
+        .find(|button| format!("{:?}", button).eq_ignore_ascii_case(name))
+        .ok_or_else(|| anyhow!("Unknown mouse button '{}'", name))
+}
+
+// Keyboard and mouse controls of the window.
+pub(crate) struct Bindings {
+    pub(crate) keys: Vec<(Key, Action)>,
+    pub(crate) orbit: MouseButton,
+    pub(crate) pan: MouseButton,
+    // Click without dragging prints what is visible under the cursor.
+    pub(crate) pick: MouseButton,
+    // Moves the picked object in the view plane.
+    pub(crate) drag: MouseButton,
+    // Holding this key while pressing a digit saves the camera bookmark.
+    pub(crate) bookmark_modifier: Key,
+    // Degrees of rotation per pixel of mouse movement.
+    pub(crate) mouse_sensitivity: f64,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings {
+            keys: vec![
+                (Key::W, Action::MoveForward),
+                (Key::S, Action::MoveBackward),
+                (Key::A, Action::MoveLeft),
+                (Key::D, Action::MoveRight),
+                (Key::LeftShift, Action::MoveUp),
+                (Key::LeftCtrl, Action::MoveDown),
+                (Key::Left, Action::YawLeft),
+                (Key::Right, Action::YawRight),
+                (Key::Up, Action::PitchUp),
+                (Key::Down, Action::PitchDown),
+                (Key::RightCtrl, Action::RollLeft),
+                (Key::RightShift, Action::RollRight),
+                (Key::H, Action::ToggleHud),
+                (Key::F1, Action::ToggleHelp),
+                (Key::P, Action::Screenshot),
+            ],
+            orbit: MouseButton::Left,
+            pan: MouseButton::Right,
+            pick: MouseButton::Left,
+            drag: MouseButton::Middle,
+            bookmark_modifier: Key::LeftAlt,
+            mouse_sensitivity: 0.3,
+        }
+    }
+}
+
+impl Bindings {
+    // Reads bindings from the file. Every line has a form `name = value`:
+    //   MoveForward = Up      - binds a key to the action, replaces the default keys
+    //   Orbit = Left          - mouse button used to orbit around the pivot
+    //   Pan = Middle          - mouse button used to pan the camera
+    //   Pick = Left           - mouse button clicked to inspect the object
+    //   Drag = Middle         - mouse button moving the picked object
+    //   BookmarkModifier = B  - hold with a digit to save the camera bookmark
+    //   MouseSensitivity = 0.5
+    // Empty lines and lines starting with '#' are ignored.
+    pub(crate) fn from_file(path: &Path) -> anyhow::Result<Bindings> {
+        Bindings::parse(&std::fs::read_to_string(path)?)
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Bindings> {
+        let mut bindings = Bindings::default();
+        let mut overridden = vec![];
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => bail!("Line {}: expected 'name = value'", number + 1),
+            };
+            let error = |e: anyhow::Error| anyhow!("Line {}: {}", number + 1, e);
+            if name.eq_ignore_ascii_case("orbit") {
+                bindings.orbit = parse_mouse_button(value).map_err(error)?;
+            } else if name.eq_ignore_ascii_case("pan") {
+                bindings.pan = parse_mouse_button(value).map_err(error)?;
+            } else if name.eq_ignore_ascii_case("pick") {
+                bindings.pick = parse_mouse_button(value).map_err(error)?;
+            } else if name.eq_ignore_ascii_case("drag") {
+                bindings.drag = parse_mouse_button(value).map_err(error)?;
+            } else if name.eq_ignore_ascii_case("bookmarkmodifier") {
+                bindings.bookmark_modifier = parse_key(value).map_err(error)?;
+            } else if name.eq_ignore_ascii_case("mousesensitivity") {
+                bindings.mouse_sensitivity = value.parse().map_err(|e| error(anyhow!("{}", e)))?;
+            } else {
+                let action = Action::parse(name).map_err(error)?;
+                let key = parse_key(value).map_err(error)?;
+                if !overridden.contains(&action) {
+                    bindings.keys.retain(|&(_, bound)| bound != action);
+                    overridden.push(action);
+                }
+                // The key can trigger only one action, the latest binding wins.
+                bindings.keys.retain(|&(bound, _)| bound != key);
+                bindings.keys.push((key, action));
+            }
+        }
+        Ok(bindings)
+    }
+
+    // Bookmark slot selected by the digit key.
+    pub(crate) fn bookmark_slot(key: Key) -> Option<usize> {
+        KEYS[1..10]
+            .iter()
+            .position(|&digit| digit == key)
+            .map(|index| index + 1)
+    }
+
+    pub(crate) fn action(&self, key: Key) -> Option<Action> {
+        self.keys
+            .iter()
+            .find(|&&(bound, _)| bound == key)
+            .map(|&(_, action)| action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings() {
+        let bindings = Bindings::default();
+        assert_eq!(bindings.action(Key::W), Some(Action::MoveForward));
+        assert_eq!(bindings.action(Key::Q), None);
+        assert_eq!(bindings.orbit, MouseButton::Left);
+        assert_eq!(Bindings::bookmark_slot(Key::Key1), Some(1));
+        assert_eq!(Bindings::bookmark_slot(Key::Key9), Some(9));
+        assert_eq!(Bindings::bookmark_slot(Key::Key0), None);
+    }
+
+    #[test]
+    fn parse_overrides_defaults() {
+        let bindings = Bindings::parse(
+            "# comment\nmoveforward = Up\nMoveForward = I\n\nPan = Middle\nMouseSensitivity = 1.5",
+        )
+        .unwrap();
+        assert_eq!(bindings.action(Key::W), None);
+        assert_eq!(bindings.action(Key::Up), Some(Action::MoveForward));
+        assert_eq!(bindings.action(Key::I), Some(Action::MoveForward));
+        assert_eq!(bindings.action(Key::S), Some(Action::MoveBackward));
+        assert!(!bindings.keys.contains(&(Key::Up, Action::PitchUp)));
+        assert_eq!(bindings.pan, MouseButton::Middle);
+        assert_eq!(bindings.mouse_sensitivity, 1.5);
+    }
+
+    #[test]
+    fn parse_reports_errors() {
+        assert!(Bindings::parse("MoveForward = NoSuchKey").is_err());
+        assert!(Bindings::parse("Jump = Space").is_err());
+        assert!(Bindings::parse("Orbit").is_err());
+    }
+}
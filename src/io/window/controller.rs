@@ -0,0 +1,160 @@
+use std::time::Instant;
+
+use minifb::{MouseMode, Window as WindowHandler};
+
+use crate::{
+    basic_geometry::{alighned_box::AlighnedBox, point::Point, vector::Vector},
+    ray_tracer::camera::Camera,
+};
+
+use super::bindings::{Action, Bindings};
+
+// Part of the scene size travelled per second with the keyboard.
+const MOVE_SPEED: f64 = 0.25;
+// Degrees per second of the keyboard rotation.
+const ROTATION_SPEED: f64 = 90.0;
+// Slow frames are limited to this step (in seconds), so the camera doesn't jump.
+const MAX_TIME_STEP: f64 = 0.25;
+// Distance multiplier for one step of the scroll wheel.
+const ZOOM_STEP: f64 = 0.9;
+const MAX_PITCH: f64 = 89.0;
+
+// Moves the camera in response to the keyboard and the mouse.
+// The camera orbits around the pivot which is kept `distance` units in front of it.
+pub(crate) struct CameraController {
+    pivot: Point,
+    distance: f64,
+    scene_size: f64,
+    last_update: Instant,
+    last_mouse: Option<(f32, f32)>,
+}
+
+impl CameraController {
+    pub(crate) fn new(camera: &Camera, scene_bounds: AlighnedBox) -> CameraController {
+        let (center, scene_size) = if scene_bounds.min.x <= scene_bounds.max.x {
+            (
+                scene_bounds.center(),
+                (scene_bounds.max - scene_bounds.min).length(),
+            )
+        } else {
+            (Point::default(), 1.0)
+        };
+        // The camera may not look at the center, so the pivot is only as far as the center
+        // and straight ahead, otherwise the first orbit would jump the view.
+        let mut controller = CameraController {
+            pivot: center,
+            distance: (center - camera.position()).length().max(f64::EPSILON),
+            scene_size: scene_size.max(f64::EPSILON),
+            last_update: Instant::now(),
+            last_mouse: None,
+        };
+        controller.reset(camera);
+        controller
+    }
+
+    // Keeps the pivot in front of the camera after it was moved from the outside.
+    pub(crate) fn reset(&mut self, camera: &Camera) {
+        self.pivot = camera.position() + camera.forward() * self.distance;
+    }
+
+    // Returns true if the camera was moved.
+    pub(crate) fn update(
+        &mut self,
+        window: &WindowHandler,
+        bindings: &Bindings,
+        camera: &mut Camera,
+    ) -> bool {
+        let time_step = self.last_update.elapsed().as_secs_f64().min(MAX_TIME_STEP);
+        self.last_update = Instant::now();
+
+        let mut moved = false;
+        for action in window
+            .get_keys()
+            .into_iter()
+            .filter_map(|key| bindings.action(key))
+        {
+            moved |= self.apply_action(action, time_step, camera);
+        }
+
+        let mouse = window.get_mouse_pos(MouseMode::Pass);
+        if let (Some((x, y)), Some((last_x, last_y))) = (mouse, self.last_mouse) {
+            let (dx, dy) = ((x - last_x) as f64, (y - last_y) as f64);
+            if dx != 0.0 || dy != 0.0 {
+                if window.get_mouse_down(bindings.orbit) {
+                    self.orbit(
+                        -dx * bindings.mouse_sensitivity,
+                        -dy * bindings.mouse_sensitivity,
+                        camera,
+                    );
+                    moved = true;
+                } else if window.get_mouse_down(bindings.pan) {
+                    // The view frame is about as high as the distance to the pivot.
+                    let pixel_size = self.distance / window.get_size().1.max(1) as f64;
+                    self.translate(
+                        (camera.up() * dy - camera.right() * dx) * pixel_size,
+                        camera,
+                    );
+                    moved = true;
+                }
+            }
+        }
+        self.last_mouse = mouse;
+
+        if let Some((_, scroll)) = window.get_scroll_wheel() {
+            if scroll != 0.0 {
+                self.dolly(ZOOM_STEP.powf(scroll as f64), camera);
+                moved = true;
+            }
+        }
+        moved
+    }
+
+    fn apply_action(&mut self, action: Action, time_step: f64, camera: &mut Camera) -> bool {
+        let step = self.scene_size * MOVE_SPEED * time_step;
+        let angle = ROTATION_SPEED * time_step;
+        match action {
+            Action::MoveForward => self.translate(camera.forward() * step, camera),
+            Action::MoveBackward => self.translate(-camera.forward() * step, camera),
+            Action::MoveLeft => self.translate(-camera.right() * step, camera),
+            Action::MoveRight => self.translate(camera.right() * step, camera),
+            Action::MoveUp => self.translate(Vector::new(0.0, step, 0.0), camera),
+            Action::MoveDown => self.translate(Vector::new(0.0, -step, 0.0), camera),
+            Action::YawLeft => self.rotate(Vector::new(0.0, angle, 0.0), camera),
+            Action::YawRight => self.rotate(Vector::new(0.0, -angle, 0.0), camera),
+            Action::PitchUp => self.rotate(Vector::new(angle, 0.0, 0.0), camera),
+            Action::PitchDown => self.rotate(Vector::new(-angle, 0.0, 0.0), camera),
+            Action::RollLeft => self.rotate(Vector::new(0.0, 0.0, angle), camera),
+            Action::RollRight => self.rotate(Vector::new(0.0, 0.0, -angle), camera),
+            Action::ToggleHud | Action::ToggleHelp | Action::Screenshot => return false,
+        }
+        true
+    }
+
+    fn translate(&mut self, offset: Vector, camera: &mut Camera) {
+        self.pivot = self.pivot + offset;
+        camera.move_to(camera.position() + offset);
+    }
+
+    // Rotates the camera in place, the pivot follows the view direction.
+    fn rotate(&mut self, angles: Vector, camera: &mut Camera) {
+        camera.set_rotation(clamp_pitch(camera.rotation_vector() + angles));
+        self.pivot = camera.position() + camera.forward() * self.distance;
+    }
+
+    // Rotates the camera around the pivot.
+    fn orbit(&mut self, yaw: f64, pitch: f64, camera: &mut Camera) {
+        camera.set_rotation(clamp_pitch(
+            camera.rotation_vector() + Vector::new(pitch, yaw, 0.0),
+        ));
+        camera.move_to(self.pivot + -camera.forward() * self.distance);
+    }
+
+    fn dolly(&mut self, factor: f64, camera: &mut Camera) {
+        self.distance = (self.distance * factor).max(self.scene_size * 1e-3);
+        camera.move_to(self.pivot + -camera.forward() * self.distance);
+    }
+}
+
+fn clamp_pitch(angles: Vector) -> Vector {
+    Vector::new(angles.x.clamp(-MAX_PITCH, MAX_PITCH), angles.y, angles.z)
+}
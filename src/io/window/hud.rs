@@ -0,0 +1,209 @@
+use crate::ray_tracer::RayTracer;
+
+use super::bindings::Bindings;
+use super::font::{draw_text, text_width, GLYPH_HEIGHT, LINE_ADVANCE};
+
+const TEXT_COLOR: u32 = 0xFFFFFF;
+const HIGHLIGHT_COLOR: u32 = 0xFFD700;
+const OUTLINE_COLOR: u32 = 0xFF00FF;
+const MARGIN: usize = 6;
+
+// Overlay drawn on top of the rendered frame.
+pub(crate) struct Hud {
+    pub(crate) visible: bool,
+    pub(crate) help: bool,
+}
+
+impl Hud {
+    pub(crate) fn new() -> Hud {
+        Hud {
+            visible: true,
+            help: false,
+        }
+    }
+
+    pub(crate) fn draw(
+        &self,
+        buff: &mut [u32],
+        width: usize,
+        height: usize,
+        ray_tracer: &RayTracer,
+        bindings: &Bindings,
+    ) {
+        if self.help {
+            draw_panel(
+                buff,
+                width,
+                height,
+                (0, 0),
+                &help_lines(bindings),
+                HIGHLIGHT_COLOR,
+            );
+        } else if self.visible {
+            draw_panel(
+                buff,
+                width,
+                height,
+                (0, 0),
+                &stats_lines(ray_tracer),
+                TEXT_COLOR,
+            );
+        }
+    }
+}
+
+// Highlights the border of the picked object using the per-pixel object indices.
+pub(crate) fn draw_outline(
+    buff: &mut [u32],
+    width: usize,
+    height: usize,
+    object_ids: &[Option<usize>],
+    picked: usize,
+) {
+    if object_ids.len() != width * height {
+        return;
+    }
+    let is_picked = |x: usize, y: usize| object_ids[y * width + x] == Some(picked);
+    for y in 0..height {
+        for x in 0..width {
+            let border = x == 0
+                || y == 0
+                || x + 1 == width
+                || y + 1 == height
+                || !is_picked(x - 1, y)
+                || !is_picked(x + 1, y)
+                || !is_picked(x, y - 1)
+                || !is_picked(x, y + 1);
+            if is_picked(x, y) && border {
+                buff[y * width + x] = OUTLINE_COLOR;
+            }
+        }
+    }
+}
+
+fn stats_lines(ray_tracer: &RayTracer) -> Vec<String> {
+    let stats = ray_tracer.last_frame_stats();
+    let objects = ray_tracer.scene().objects();
+    let position = ray_tracer.camera_position();
+    let rotation = ray_tracer.rotation_vector();
+    vec![
+        format!(
+            "FPS: {:.1} ({:.1} ms/frame)",
+            stats.fps(),
+            stats.duration.as_secs_f64() * 1000.
+        ),
+        format!("Rays/s: {:.2}M", stats.rays_per_second() / 1e6),
+        format!(
+            "Camera: ({:.1}, {:.1}, {:.1})",
+            position.x, position.y, position.z
+        ),
+        format!(
+            "Rotation: ({:.0}, {:.0}, {:.0})",
+            rotation.x, rotation.y, rotation.z
+        ),
+        format!(
+            "Objects: {} Primitives: {} {} nodes: {}",
+            objects.unique_objects_count(),
+            objects.total_primitives_count(),
+            objects.name(),
+            objects.total_nodes_count()
+        ),
+        format!("Integrator: {}", ray_tracer.integrator_name()),
+        "F1 - help".to_string(),
+    ]
+}
+
+fn help_lines(bindings: &Bindings) -> Vec<String> {
+    let key_width = bindings
+        .keys
+        .iter()
+        .map(|(key, _)| format!("{:?}", key).len())
+        .max()
+        .unwrap_or_default();
+    let mut lines = vec!["Controls:".to_string()];
+    lines.extend(bindings.keys.iter().map(|(key, action)| {
+        format!(
+            "{:width$}  {}",
+            format!("{:?}", key),
+            action.description(),
+            width = key_width
+        )
+    }));
+    lines.push("1-9 - restore camera bookmark".to_string());
+    lines.push(format!(
+        "{:?}+1-9 - save camera bookmark",
+        bindings.bookmark_modifier
+    ));
+    lines.push(format!("Mouse {:?} click - inspect object", bindings.pick));
+    lines.push(format!(
+        "Mouse {:?} drag - move picked object",
+        bindings.drag
+    ));
+    lines.push(format!("Mouse {:?} drag - orbit", bindings.orbit));
+    lines.push(format!("Mouse {:?} drag - pan", bindings.pan));
+    lines.push("Mouse wheel - zoom".to_string());
+    lines.push("Escape - quit".to_string());
+    lines
+}
+
+fn draw_panel(
+    buff: &mut [u32],
+    width: usize,
+    height: usize,
+    (x, y): (usize, usize),
+    lines: &[String],
+    color: u32,
+) {
+    let panel_width = lines.iter().map(|l| text_width(l)).max().unwrap_or(0) + 2 * MARGIN;
+    let panel_height = lines.len() * LINE_ADVANCE + 2 * MARGIN - (LINE_ADVANCE - GLYPH_HEIGHT);
+    for py in y..(y + panel_height).min(height) {
+        for px in x..(x + panel_width).min(width) {
+            // Darken the background so the text stays readable on bright frames.
+            let pixel = &mut buff[py * width + px];
+            *pixel = (*pixel >> 2) & 0x3F3F3F;
+        }
+    }
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            buff,
+            width,
+            height,
+            (x + MARGIN, y + MARGIN + i * LINE_ADVANCE),
+            line,
+            color,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outline_keeps_inner_pixels() {
+        let ids = [
+            None,
+            None,
+            None,
+            None, //
+            None,
+            Some(1),
+            Some(1),
+            Some(1), //
+            None,
+            Some(1),
+            Some(1),
+            Some(1), //
+            None,
+            Some(1),
+            Some(1),
+            Some(1), //
+        ];
+        let mut buff = vec![0; 16];
+        draw_outline(&mut buff, 4, 4, &ids, 1);
+        let outlined = buff.iter().filter(|&&p| p == OUTLINE_COLOR).count();
+        // Everything but the empty pixels and the single inner one.
+        assert_eq!(outlined, 8);
+        assert_eq!(buff[2 * 4 + 2], 0);
+    }
+}
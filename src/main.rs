@@ -1,44 +1,199 @@
 mod basic_geometry;
 mod basic_types;
+mod benchmark;
 mod complex_structures;
 mod io;
 mod ray_tracer;
+#[cfg(test)]
+mod test_utils;
 
 use std::cell::RefCell;
 use std::ffi::OsStr;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::str::FromStr;
 
+use basic_geometry::alighned_box::AlighnedBox;
 use basic_geometry::normal::Normal;
+use basic_geometry::plane::Plane;
 use basic_geometry::point::Point;
+use basic_geometry::sdf::{Field, Sdf};
+use basic_geometry::shape::Shape;
 use basic_geometry::sphere::Sphere;
-use basic_geometry::{Axis, Transform};
-use io::Input;
-use ray_tracer::camera::Camera;
+use basic_geometry::transform::Transform3;
+use basic_geometry::vector::Vector;
+use basic_geometry::{Transform, Transformation};
+use complex_structures::bvh::builder::{BvhBuilder, Strategy};
+use complex_structures::bvh::bvh4::Bvh4;
+use complex_structures::bvh::BVHTree;
+use complex_structures::heightfield::Heightfield;
+use complex_structures::instance::Instance;
+use complex_structures::BoundingBox;
+use ray_tracer::animation::{Animation, CameraPath, Interpolation, ObjectMotion};
+use ray_tracer::camera::{Camera, CameraPose};
 use ray_tracer::color::Color;
 use ray_tracer::light::Light;
 use ray_tracer::material::Material;
-use ray_tracer::object::Object;
+use ray_tracer::object::{MeshTriangle, Object};
 use ray_tracer::scene::{Scene, Tracing};
 use ray_tracer::viewframe::ViewFrame;
 use ray_tracer::{ObjectContainer, RayTracer};
 
+use crate::io::subdivision::Refinement;
 use crate::io::OutputType;
 
-type IsSphereNeeded = bool;
-
-fn parse_args() -> (PathBuf, OutputType, Tracing, IsSphereNeeded) {
-    const HELP_MSG: &str = "./graphics --source=path_to_object.obj [--output=path_to_result.ppm, --windowed, --console]\n 
+const HELP_MSG: &str =
+    "./graphics --source=path_to_object.obj [--output=path_to_result.ppm, --windowed, --console]\n 
 The ratracer takes two arguments: the input file and the output file.
 The input file is a object file in the Wavefront OBJ format.
-The output is either a file or one of the other output formats (window, console).
+The output is either a file (.ppm or .png) or one of the other output formats (window, console).
 Optional arguments:
---add-sphere - add predefined sphere";
+The trees of the models are cached next to the input file (path_to_object.rtcache).
+--add-sphere - add predefined sphere with the checker texture
+--add-sdf=\"smooth 10 (sphere 20) (translate 25 0 0 (box 10 10 10))\" - add the implicit surface rendered
+  by the sphere tracing, can be repeated: `sphere r`, `box half_x half_y half_z`, `torus major minor`,
+  `capsule ax ay az bx by bz r`, `union (a) (b)`, `smooth k (a) (b)`, `translate x y z (a)` and
+  `repeat sx sy sz nx ny nz (a)` with nx copies more on each side along x
+--add-shape=\"rotate 1 0 0 90 (torus 0 0 0 0 1 0 20 5)\" - add the primitive with the checker texture, can be
+  repeated: `sphere cx cy cz r`, `box min_x min_y min_z max_x max_y max_z`, `cylinder bx by bz tx ty tz r`
+  (`tube` without the caps), `cone bx by bz ax ay az r` (`open-cone` without the base),
+  `torus cx cy cz axis_x axis_y axis_z major minor`, `capsule ax ay az bx by bz r`,
+  `quad px py pz ux uy uz vx vy vz`, `disk cx cy cz nx ny nz r`, the wrappers `translate x y z (a)`,
+  `rotate axis_x axis_y axis_z degrees (a)` and `scale x y z (a)` and the CSG of the closed shapes
+  `union (a) (b)`, `intersection (a) (b)` and `difference (a) (b)`
+--ground-plane - add the infinite plane right under the model
+--heightfield=path_to_heights.png - add the terrain under the model from the grayscale PNG, PGM or PPM image
+--heightfield-size=N - length of the longer side of the terrain, 200 by default
+--heightfield-height=N - height of the white above the black, 20 by default
+--subdivide=loop|catmull-clark[:N] - smooth the model by N levels of subdivision (2 by default) while loading it
+--crease-angle=N - edges between the faces turned by more than N degrees stay sharp
+--displacement=path_to_heights.png - move the vertices of the model along the normals by the grayscale texture,
+  wrapped around the model like around a globe when the file has no texture coordinates
+--displacement-scale=N - displacement of the white, 1 by default, negative values push inwards
+--samples=N - rays per pixel, 1 by default
+--bookmarks=path_to_bookmarks.txt - camera bookmarks file, bookmarks.txt by default
+--bookmark=N - render from the camera bookmark N
+--camera-path=path_to_keyframes.txt - render the animation, every line is `time px py pz tx ty tz fov`
+--turntable - render the animation orbiting around the model
+--interpolation=linear|catmull-rom|bezier - camera path interpolation, linear by default
+--duration=N - turntable duration in seconds, 4 by default
+--fps=N - animation frames per second, 24 by default
+--frames=A-B - render only the animation frames from A to B inclusive
+  The animation frames are numbered automatically: --output=frame.png gives frame_0000.png, ...
+--instances=path_to_instances.txt - place copies of the model sharing one tree, every line is
+  `tx ty tz rx ry rz sx sy sz [material_name]` with rotation in degrees
+--transform=\"scale 2; rotate 0 1 0 180; translate 0 0 10\" - place the loaded model, the steps are applied
+  in order: `translate x y z`, `scale s`, `scale x y z`, `rotate axis_x axis_y axis_z degrees`,
+  `quaternion w x y z`, `from-to from_x from_y from_z to_x to_y to_z` (e.g. Z-up to Y-up) and
+  `look-at px py pz tx ty tz up_x up_y up_z` (the model +z looks at the target)
+--motion=path_to_keyframes.txt - move the loaded model, every line is
+  `time tx ty tz rx ry rz sx sy sz` with rotation in degrees around the model center
+--time=N - moment of the rendered image in seconds, 0 by default
+--shutter=N - seconds the shutter stays open, moving objects are blurred (use with --samples)
+--structure=bvh|bvh4|kdtree|grid|linear - acceleration structure of the scene, bvh by default,
+  bvh4 collapses the trees into the nodes with four children tested with SIMD
+--no-packets - trace the primary rays one by one instead of the 2x2 pixel packets
+--bvh=sweep|binned[:N]|sbvh[:N]|lbvh - tree builder: full-sweep SAH, binned SAH with N bins (12 by default),
+  binned SAH with spatial splits or Morton code LBVH, binned by default
+--leaf-size=N - most primitives in the tree leaf, 1 by default
+--benchmark - compare the tree traversals, builders and acceleration structures on the source model or on all the bundled samples
+--bindings=path_to_bindings.txt - keyboard and mouse controls for the windowed mode
+--screenshot=path_to_screenshot.png - screenshot path for the windowed mode, numbered automatically
+--screenshot-scale=N - screenshot resolution multiplier, 2 by default
+--screenshot-samples=N - screenshot rays per pixel, 4 by default
+In the windowed mode press F1 to see the controls.";
+
+struct AnimationArguments {
+    // Turntable around the model if the path isn't given.
+    camera_path: Option<PathBuf>,
+    interpolation: Interpolation,
+    duration: f64,
+    fps: f64,
+    frames: Option<Range<usize>>,
+}
+
+struct Arguments {
+    source: PathBuf,
+    output: OutputType,
+    tracing: Tracing,
+    add_sphere: bool,
+    sdfs: Vec<Field>,
+    shapes: Vec<Shape>,
+    ground_plane: bool,
+    heightfield: Option<(PathBuf, f64, f64)>,
+    refinement: Refinement,
+    samples: usize,
+    bookmark: Option<CameraPose>,
+    animation: Option<AnimationArguments>,
+    instances: Option<PathBuf>,
+    transform: Transform3,
+    motion: Option<PathBuf>,
+    time: f64,
+    shutter: f64,
+    builder: BvhBuilder,
+    packets: bool,
+}
+
+fn exit_with_error(message: &str) -> ! {
+    println!("{}\n\n{}", message, HELP_MSG);
+    std::process::exit(1);
+}
 
+fn parse_value<T: FromStr>(arg: &str) -> T {
+    arg.split_once('=')
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or_else(|| exit_with_error(&format!("Incorrect value of the argument {}", arg)))
+}
+
+fn parse_frames(arg: &str) -> Range<usize> {
+    let range: Option<Range<usize>> = arg
+        .split_once('=')
+        .and_then(|(_, value)| value.split_once('-'))
+        .and_then(|(start, end)| Some(start.parse().ok()?..end.parse::<usize>().ok()? + 1));
+    match range {
+        Some(range) if !range.is_empty() => range,
+        _ => exit_with_error(&format!("Incorrect value of the argument {}", arg)),
+    }
+}
+
+fn parse_args() -> Arguments {
     let mut source: Option<PathBuf> = None;
     let mut output: Option<OutputType> = None;
     let mut tracing = Tracing::Bvh;
     let mut add_sphere = false;
+    let mut sdfs = vec![];
+    let mut shapes = vec![];
+    let mut ground_plane = false;
+    let mut heightfield = None;
+    let mut heightfield_size = 200.0;
+    let mut heightfield_height = 20.0;
+    let mut refinement = Refinement::default();
+    let mut displacement = None;
+    let mut displacement_scale = None;
+    let mut samples = 1;
+    let mut bookmarks_path = PathBuf::from("bookmarks.txt");
+    let mut bookmark: Option<usize> = None;
+    let mut camera_path: Option<PathBuf> = None;
+    let mut turntable = false;
+    let mut interpolation = Interpolation::Linear;
+    let mut duration = 4.0;
+    let mut fps = 24.0;
+    let mut frames = None;
+    let mut instances = None;
+    let mut transform = Transform3::identity();
+    let mut motion = None;
+    let mut time = 0.0;
+    let mut shutter = 0.0;
+    let mut benchmark = false;
+    let mut builder = BvhBuilder::default();
+    let mut packets = true;
+    #[cfg(feature = "windowed")]
+    let mut windowed = false;
+    #[cfg(feature = "windowed")]
+    let mut bindings = io::window::bindings::Bindings::default();
+    #[cfg(feature = "windowed")]
+    let mut screenshot = io::window::ScreenshotConfig::default();
     for arg in std::env::args() {
         if arg == "--help" {
             println!("{}", HELP_MSG);
@@ -50,70 +205,405 @@ Optional arguments:
                     if path.exists() {
                         source = Some(path);
                     } else {
-                        println!("The source file does not exist.\n\n{}", HELP_MSG);
-                        std::process::exit(1);
+                        exit_with_error("The source file does not exist.");
                     }
                 } else {
-                    println!("Incorrect input file format\n\n{}", HELP_MSG);
-                    std::process::exit(0);
+                    exit_with_error("Incorrect input file format");
                 }
             }
         } else if arg.starts_with("--output=") {
             if let Some(path) = arg.split('=').nth(1) {
                 let path = PathBuf::from(path);
-                if Some(OsStr::new("ppm")) == path.extension() {
+                if io::is_supported_image(&path) {
                     output = Some(OutputType::Image(path));
                 } else {
-                    println!("Incorrect output file format\n\n{}", HELP_MSG);
-                    std::process::exit(0);
+                    exit_with_error("Incorrect output file format");
                 }
             }
         } else if arg.eq("--without-tree") {
             tracing = Tracing::Linear;
+        } else if arg.starts_with("--structure=") {
+            tracing = parse_value::<Tracing>(&arg);
         } else if arg.eq("--add-sphere") {
             add_sphere = true;
+        } else if arg.starts_with("--add-sdf=") {
+            let (_, value) = arg.split_once('=').unwrap_or_default();
+            sdfs.push(value.parse().unwrap_or_else(|e| {
+                exit_with_error(&format!("Incorrect value of the argument {}: {}", arg, e))
+            }));
+        } else if arg.starts_with("--add-shape=") {
+            let (_, value) = arg.split_once('=').unwrap_or_default();
+            shapes.push(value.parse().unwrap_or_else(|e| {
+                exit_with_error(&format!("Incorrect value of the argument {}: {}", arg, e))
+            }));
+        } else if arg.eq("--ground-plane") {
+            ground_plane = true;
+        } else if arg.starts_with("--heightfield=") {
+            heightfield = Some(parse_value::<PathBuf>(&arg));
+        } else if arg.starts_with("--heightfield-size=") {
+            heightfield_size = parse_value(&arg);
+        } else if arg.starts_with("--heightfield-height=") {
+            heightfield_height = parse_value(&arg);
+        } else if arg.starts_with("--subdivide=") {
+            refinement.subdivision = Some(parse_value(&arg));
+        } else if arg.starts_with("--crease-angle=") {
+            refinement.crease_angle = Some(parse_value(&arg));
+        } else if arg.starts_with("--displacement=") {
+            displacement = Some(parse_value::<PathBuf>(&arg));
+        } else if arg.starts_with("--displacement-scale=") {
+            displacement_scale = Some(parse_value(&arg));
         } else if arg.eq("--console") {
             output = Some(OutputType::Console);
+        } else if arg.starts_with("--samples=") {
+            samples = parse_value(&arg);
+        } else if arg.starts_with("--bookmarks=") {
+            bookmarks_path = parse_value(&arg);
+        } else if arg.starts_with("--bookmark=") {
+            bookmark = Some(parse_value(&arg));
+        } else if arg.starts_with("--camera-path=") {
+            camera_path = Some(parse_value(&arg));
+        } else if arg.eq("--turntable") {
+            turntable = true;
+        } else if arg.starts_with("--interpolation=") {
+            let name: String = parse_value(&arg);
+            interpolation =
+                Interpolation::parse(&name).unwrap_or_else(|e| exit_with_error(&e.to_string()));
+        } else if arg.starts_with("--duration=") {
+            duration = parse_value(&arg);
+        } else if arg.starts_with("--fps=") {
+            fps = parse_value(&arg);
+        } else if arg.starts_with("--frames=") {
+            frames = Some(parse_frames(&arg));
+        } else if arg.starts_with("--instances=") {
+            instances = Some(parse_value(&arg));
+        } else if arg.starts_with("--transform=") {
+            let (_, value) = arg.split_once('=').unwrap_or_default();
+            transform = value.parse().unwrap_or_else(|e| {
+                exit_with_error(&format!("Incorrect value of the argument {}: {}", arg, e))
+            });
+        } else if arg.starts_with("--motion=") {
+            motion = Some(parse_value(&arg));
+        } else if arg.starts_with("--time=") {
+            time = parse_value(&arg);
+        } else if arg.starts_with("--shutter=") {
+            shutter = parse_value(&arg);
+        } else if arg.eq("--benchmark") {
+            benchmark = true;
+        } else if arg.starts_with("--bvh=") {
+            builder.strategy = parse_value::<Strategy>(&arg);
+        } else if arg.starts_with("--leaf-size=") {
+            builder.max_primitives_in_node = parse_value::<usize>(&arg);
+            if builder.max_primitives_in_node == 0 {
+                exit_with_error("The leaf size must be positive");
+            }
+        } else if arg.eq("--no-packets") {
+            packets = false;
         }
 
         #[cfg(feature = "windowed")]
         if arg.starts_with("--windowed") {
-            output = Some(OutputType::Window((500, 500)));
+            windowed = true;
+        } else if arg.starts_with("--bindings=") {
+            let path: PathBuf = parse_value(&arg);
+            bindings = io::window::bindings::Bindings::from_file(&path).unwrap_or_else(|e| {
+                exit_with_error(&format!("Failed to read the bindings file: {}", e))
+            });
+        } else if arg.starts_with("--screenshot=") {
+            screenshot.path = parse_value(&arg);
+            if !io::is_supported_image(&screenshot.path) {
+                exit_with_error("Incorrect screenshot file format");
+            }
+        } else if arg.starts_with("--screenshot-scale=") {
+            screenshot.scale = parse_value(&arg);
+        } else if arg.starts_with("--screenshot-samples=") {
+            screenshot.samples = parse_value(&arg);
         }
     }
 
-    if source.is_none() || output.is_none() {
-        println!("All required arguments is not provided.\n\n{}", HELP_MSG);
-        std::process::exit(0);
+    if benchmark {
+        run_benchmark(source);
+    }
+
+    let bookmarks = io::bookmarks::Bookmarks::load(bookmarks_path)
+        .unwrap_or_else(|e| exit_with_error(&format!("Failed to read the bookmarks file: {}", e)));
+    let bookmark = bookmark.map(|slot| {
+        bookmarks
+            .get(slot)
+            .unwrap_or_else(|| exit_with_error(&format!("Camera bookmark {} is not found", slot)))
+    });
+
+    #[cfg(feature = "windowed")]
+    if windowed {
+        output = Some(OutputType::Window(io::window::WindowConfig {
+            size: (500, 500),
+            bindings,
+            screenshot,
+            bookmarks,
+        }));
+    }
+
+    let animation = (turntable || camera_path.is_some()).then_some(AnimationArguments {
+        camera_path,
+        interpolation,
+        duration,
+        fps,
+        frames,
+    });
+    if animation.is_some() && !matches!(output, Some(OutputType::Image(_))) {
+        exit_with_error("The animation can be rendered only into the image files");
+    }
+    if heightfield_size <= 0.0 || heightfield_height <= 0.0 {
+        exit_with_error("The heightfield size and height must be positive");
+    }
+    let heightfield = heightfield.map(|path| (path, heightfield_size, heightfield_height));
+    if displacement.is_none() && displacement_scale.is_some() {
+        exit_with_error("The displacement scale needs the --displacement texture");
+    }
+    refinement.displacement = displacement.map(|path| (path, displacement_scale.unwrap_or(1.0)));
+    if fps <= 0.0 || duration <= 0.0 {
+        exit_with_error("The animation duration and fps must be positive");
+    }
+
+    match (source, output) {
+        (Some(source), Some(output)) => Arguments {
+            source,
+            output,
+            tracing,
+            add_sphere,
+            sdfs,
+            shapes,
+            ground_plane,
+            heightfield,
+            refinement,
+            samples,
+            bookmark,
+            animation,
+            instances,
+            transform,
+            motion,
+            time,
+            shutter,
+            builder,
+            packets,
+        },
+        _ => {
+            println!("All required arguments is not provided.\n\n{}", HELP_MSG);
+            std::process::exit(0);
+        }
+    }
+}
+
+fn run_benchmark(source: Option<PathBuf>) -> ! {
+    let models = match source {
+        Some(source) => Ok(vec![source]),
+        None => benchmark::samples(),
+    };
+    if let Err(e) = models.and_then(|models| benchmark::run(&models)) {
+        println!("Benchmark failed:\n{}", e);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+// Trees of the models, loaded from the cache next to the model file when it's up to date.
+fn mesh_trees(
+    loader: &io::obj_file::ObjectFile,
+    meshes: Vec<Vec<MeshTriangle>>,
+    builder: BvhBuilder,
+) -> Vec<BVHTree<MeshTriangle>> {
+    match io::bvh_cache::BvhCache::new(loader.path(), builder) {
+        Ok(cache) => cache.refined(loader.refinement()).load_or_build(meshes),
+        Err(_) => meshes
+            .into_iter()
+            .map(|mesh| BVHTree::new(mesh, builder))
+            .collect(),
+    }
+}
+
+// Container of the model tree, the wide one goes with the wide scene tree.
+fn mesh_container(mesh: BVHTree<MeshTriangle>, tracing: Tracing) -> Rc<dyn ObjectContainer> {
+    match tracing {
+        Tracing::Bvh4 => Rc::new(Bvh4::from_tree(mesh)),
+        _ => Rc::new(mesh),
     }
-    (source.unwrap(), output.unwrap(), tracing, add_sphere)
+}
+
+// Places the copies of the model sharing one tree, `transform` places the model
+// before the copies are moved.
+fn instantiate(
+    mesh: BVHTree<MeshTriangle>,
+    materials: &[Material],
+    path: &Path,
+    transform: Transform3,
+    tracing: Tracing,
+) -> Vec<Object> {
+    let placements = io::instances::load(path).unwrap_or_else(|e| {
+        println!("Failed to read the instances file:\n{}", e);
+        std::process::exit(1);
+    });
+    let mesh = mesh_container(mesh, tracing);
+    placements
+        .into_iter()
+        .map(|placement| {
+            let material = placement.material.map(|name| {
+                materials
+                    .iter()
+                    .position(|material| material.name == name)
+                    .unwrap_or_else(|| {
+                        println!("Material {} is not found", name);
+                        std::process::exit(1);
+                    })
+            });
+            let transformations = [
+                &[Transformation::Composite(transform)][..],
+                &placement.transformations,
+            ]
+            .concat();
+            let instance = Instance::with_transformations(mesh.clone(), &transformations, material);
+            Object::new(Rc::new(RefCell::new(instance)), material.unwrap_or(0))
+        })
+        .collect()
 }
 
 fn main() {
-    let (source, output, tracing, sphere) = parse_args();
-    let loader = io::obj_file::ObjectFile::new(source);
-    match loader.load() {
+    let Arguments {
+        source,
+        output,
+        tracing,
+        add_sphere,
+        sdfs,
+        shapes,
+        ground_plane,
+        heightfield,
+        refinement,
+        samples,
+        bookmark,
+        animation,
+        instances,
+        transform,
+        motion,
+        time,
+        shutter,
+        builder,
+        packets,
+    } = parse_args();
+    let loader = io::obj_file::ObjectFile::with_refinement(source, refinement);
+    match loader.load_triangles() {
         Err(e) => {
             println!("Failed to process object file:\n{}", e);
             std::process::exit(1);
         }
-        Ok((mut objects, mut materials)) => {
+        Ok((meshes, mut materials)) => {
             materials.push(Material::reflective());
-            if sphere {
+            let reflective = materials.len() - 1;
+            // Every model gets its own tree, the scene tree is built over the models.
+            let mut objects = match (instances, &tracing) {
+                // All models of the file go into one tree, which is cached like the separate ones.
+                (Some(path), _) => instantiate(
+                    mesh_trees(&loader, vec![meshes.concat()], builder)
+                        .pop()
+                        .unwrap(),
+                    &materials,
+                    &path,
+                    transform,
+                    tracing,
+                ),
+                (None, Tracing::Bvh | Tracing::Bvh4) => mesh_trees(&loader, meshes, builder)
+                    .into_iter()
+                    .map(|mesh| {
+                        let mesh = mesh_container(mesh, tracing);
+                        // The whole model is moved at once, the tree stays as it was cached.
+                        let instance = Instance::with_transformations(
+                            mesh,
+                            &[Transformation::Composite(transform)],
+                            None,
+                        );
+                        Object::new(Rc::new(RefCell::new(instance)), 0)
+                    })
+                    .collect(),
+                (None, _) => meshes
+                    .into_iter()
+                    .flatten()
+                    .map(|mut triangle| {
+                        triangle.transform(Transformation::Composite(transform));
+                        triangle.into_object()
+                    })
+                    .collect(),
+            };
+            if let Some(path) = motion {
+                let pivot = objects
+                    .iter()
+                    .fold(AlighnedBox::default(), |acc, object| {
+                        acc.union(&object.bounding_box())
+                    })
+                    .center();
+                let mut motion = ObjectMotion::from_file(&path, pivot).unwrap_or_else(|e| {
+                    println!("Failed to read the object motion:\n{}", e);
+                    std::process::exit(1);
+                });
+                // Animations build one tree for all frames, so the bounds cover every frame.
+                if animation.is_none() {
+                    motion.set_shutter_interval(time, time + shutter);
+                }
+                let motion = Rc::new(motion);
+                objects
+                    .iter_mut()
+                    .for_each(|object| object.set_motion(motion.clone()));
+            }
+            if add_sphere {
+                materials.push(Material::checker());
                 objects.push(Object::new(
                     Rc::new(RefCell::new(Sphere::new(Point::new(20., 20., 20.0), 5.0))),
                     materials.len() - 1,
                 ));
             }
-            // objects.iter_mut().for_each(|elem| {
-            //     elem.transform(basic_geometry::Transformation::Rotation(Axis::Y, 180.))w
-            // });
-
-            let tracer: Box<dyn ObjectContainer> = match tracing {
-                Tracing::Bvh => Box::new(complex_structures::bvh::BVHTree::new(objects, 1)),
-                Tracing::Linear => Box::new(ray_tracer::scene::LinearTracer::new(objects)),
-            };
-            let mut scene = Scene::new(tracer, materials);
+            for field in sdfs {
+                objects.push(Object::new(
+                    Rc::new(RefCell::new(Sdf::new(field))),
+                    reflective,
+                ));
+            }
+            if !shapes.is_empty() {
+                materials.push(Material::checker());
+                let checker = materials.len() - 1;
+                for shape in shapes {
+                    objects.push(Object::new(shape.into_geometry(), checker));
+                }
+            }
+            if let Some((path, size, height)) = heightfield {
+                let map = io::height_map::load(&path).unwrap_or_else(|e| {
+                    println!("Failed to read the height map:\n{}", e);
+                    std::process::exit(1);
+                });
+                let bounds = objects.iter().fold(AlighnedBox::default(), |acc, object| {
+                    acc.union(&object.bounding_box())
+                });
+                // Centered under the model with the highest possible point at its bottom.
+                let center = bounds.center();
+                let mut terrain = Heightfield::new(&map, size, height);
+                terrain.transform(Transformation::Translation(Vector::new(
+                    center.x,
+                    bounds.min.y - height,
+                    center.z,
+                )));
+                materials.push(Material::lambert());
+                objects.push(Object::new(
+                    Rc::new(RefCell::new(terrain)),
+                    materials.len() - 1,
+                ));
+            }
+            if ground_plane {
+                let bounds = objects.iter().fold(AlighnedBox::default(), |acc, object| {
+                    acc.union(&object.bounding_box())
+                });
+                let center = Point::new(0.0, bounds.min.y, 0.0);
+                materials.push(Material::lambert());
+                objects.push(Object::new(
+                    Rc::new(RefCell::new(Plane::new(Normal::new(0., 1., 0.), center))),
+                    materials.len() - 1,
+                ));
+            }
+            let mut scene = Scene::new(tracing.container(objects, builder), materials);
             scene.add_light(Light::Point(
                 Point::new(0.0, 400.0, 200.0),
                 Color::white(),
@@ -127,8 +617,36 @@ fn main() {
             ));
 
             let viewframe = ViewFrame::new(Point::new(0.0, 0.0, 250.0), 25.0, 25.0);
-            let camera = Camera::new(Point::new(0.0, 0.0, 275.0), viewframe);
-            let ray_tracer = RayTracer::new(scene, camera, 500, 500);
+            let mut camera = Camera::new(Point::new(0.0, 0.0, 275.0), viewframe);
+            if let Some(pose) = bookmark {
+                camera.set_pose(pose);
+            }
+            let output = match (animation, output) {
+                (Some(animation), OutputType::Image(path)) => {
+                    let camera_path = match animation.camera_path {
+                        Some(file) => CameraPath::from_file(&file, animation.interpolation)
+                            .unwrap_or_else(|e| {
+                                println!("Failed to read the camera path:\n{}", e);
+                                std::process::exit(1);
+                            }),
+                        None => CameraPath::turntable(
+                            scene.objects().bounding_box(),
+                            camera.fov(),
+                            animation.duration,
+                        ),
+                    };
+                    OutputType::Sequence(
+                        path,
+                        Animation::new(camera_path, animation.fps, animation.frames),
+                    )
+                }
+                (_, output) => output,
+            };
+            let mut ray_tracer = RayTracer::new(scene, camera, 500, 500);
+            ray_tracer.set_samples(samples);
+            ray_tracer.set_time(time);
+            ray_tracer.set_shutter(shutter);
+            ray_tracer.set_packets(packets);
             let mut output = output.create_handler();
             output.process(ray_tracer).unwrap()
         }
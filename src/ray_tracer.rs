@@ -1,18 +1,38 @@
+pub(crate) mod animation;
 pub(crate) mod camera;
 pub(crate) mod color;
 pub(crate) mod light;
 pub(crate) mod material;
 pub(crate) mod object;
+pub(crate) mod pick;
 pub(crate) mod scene;
 pub(crate) mod viewframe;
 
+use std::cell::Cell;
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
 use camera::Camera;
 use material::Material;
+use pick::PickReport;
 use scene::Scene;
 
+use crate::basic_geometry::alighned_box::AlighnedBox;
+use crate::basic_geometry::capsule::Capsule;
+use crate::basic_geometry::cone::Cone;
+use crate::basic_geometry::cylinder::Cylinder;
+use crate::basic_geometry::disk::Disk;
 use crate::basic_geometry::normal::Normal;
+use crate::basic_geometry::oriented_box::OrientedBox;
+use crate::basic_geometry::plane::Plane;
 use crate::basic_geometry::point::Point;
-use crate::basic_geometry::ray::Ray;
+use crate::basic_geometry::quad::Quad;
+use crate::basic_geometry::ray::{Ray, SurfacePoint};
+use crate::basic_geometry::ray_packet::RayPacket;
+use crate::basic_geometry::sdf::Sdf;
+use crate::basic_geometry::sphere::Sphere;
+use crate::basic_geometry::torus::Torus;
+use crate::basic_geometry::triangle::Triangle;
 use crate::basic_geometry::Intersect;
 use crate::basic_geometry::Intersection;
 use crate::basic_geometry::NormalAtPoint;
@@ -20,9 +40,9 @@ use crate::basic_geometry::Transform;
 use crate::basic_geometry::Transformation;
 
 use crate::basic_geometry::vector::Vector;
+use crate::basic_types::simd::Mask4;
 use crate::complex_structures::BoundingBox;
 use crate::io::Output;
-use object::Object;
 
 use self::color::Color;
 use self::light::Light;
@@ -31,13 +51,143 @@ const MIRROR_RECURSION_LIMIT: u32 = 4;
 
 const DEFAULT_BACKGROUND_COLOR: Color = Color::new(0.18, 0.39, 0.);
 
-pub(crate) trait RayTracable: Intersect + NormalAtPoint + Transform + BoundingBox {}
+const INTEGRATOR_NAME: &str = "Whitted";
+
+pub(crate) trait RayTracable:
+    Intersect + NormalAtPoint + Transform + BoundingBox + Debug
+{
+    // Material of the hit point, if it isn't the material of the object.
+    fn material_id(&self, _: Intersection) -> Option<usize> {
+        None
+    }
+
+    fn describe(&self, _: Intersection) -> String {
+        format!("{:?}", self)
+    }
+
+    // Coordinates of the texture at the hit point, for the surfaces which have them.
+    fn texture_coordinates(&self, _: &Point, _: Intersection) -> Option<(f64, f64)> {
+        None
+    }
+
+    // Hits of the rays in the given lanes of the packet.
+    fn intersect_packet(&self, packet: &RayPacket, lanes: Mask4) -> [Option<Intersection>; 4] {
+        packet.each(lanes, |ray| self.intersect(ray))
+    }
 
-impl<T> RayTracable for T where T: Intersect + NormalAtPoint + Transform + BoundingBox {}
+    // Primitives and the tree nodes of the geometry, more than one for the instanced meshes.
+    fn primitives_count(&self) -> usize {
+        1
+    }
+
+    fn nodes_count(&self) -> usize {
+        0
+    }
+}
+
+impl RayTracable for Triangle {
+    fn intersect_packet(&self, packet: &RayPacket, lanes: Mask4) -> [Option<Intersection>; 4] {
+        Triangle::intersect_packet(self, packet, lanes)
+    }
+}
+impl RayTracable for Sphere {
+    fn texture_coordinates(&self, point: &Point, _: Intersection) -> Option<(f64, f64)> {
+        Some(self.uv_at_point(point))
+    }
+}
+impl RayTracable for AlighnedBox {}
+impl RayTracable for OrientedBox {}
+impl RayTracable for Plane {}
+impl RayTracable for Disk {}
+impl RayTracable for Cylinder {
+    fn texture_coordinates(&self, point: &Point, _: Intersection) -> Option<(f64, f64)> {
+        Some(self.uv_at_point(point))
+    }
+}
+impl RayTracable for Cone {
+    fn texture_coordinates(&self, point: &Point, _: Intersection) -> Option<(f64, f64)> {
+        Some(self.uv_at_point(point))
+    }
+}
+impl RayTracable for Torus {
+    fn texture_coordinates(&self, point: &Point, _: Intersection) -> Option<(f64, f64)> {
+        Some(self.uv_at_point(point))
+    }
+}
+impl RayTracable for Capsule {
+    fn texture_coordinates(&self, point: &Point, _: Intersection) -> Option<(f64, f64)> {
+        Some(self.uv_at_point(point))
+    }
+}
+impl RayTracable for Quad {
+    fn texture_coordinates(&self, point: &Point, _: Intersection) -> Option<(f64, f64)> {
+        Some(self.uv_at_point(point))
+    }
+}
+impl RayTracable for Sdf {}
 
 pub(crate) trait ObjectContainer {
     fn trace(&self, ray: &Ray) -> Option<(usize, Intersection)>;
-    fn object_by_index(&self, index: usize) -> &Object;
+    // Closest hits of the active rays of the packet.
+    fn trace_packet(&self, packet: &RayPacket) -> [Option<(usize, Intersection)>; 4] {
+        packet.each(packet.active, |ray| self.trace(ray))
+    }
+    // Whether anything is hit closer than `max_distance`, stops at the first such hit.
+    fn occluded(&self, ray: &Ray, max_distance: f64) -> bool;
+    // Shading data of the primitive hit by the traced ray.
+    fn normal_at_point(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal;
+    fn geometric_normal(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal;
+    fn material_at(&self, index: usize, intersection: Intersection) -> usize;
+    fn texture_coordinates(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Option<(f64, f64)>;
+    fn describe(&self, index: usize, intersection: Intersection) -> String;
+    // Upper bound of the indices, which can count some objects more than once.
+    fn objects_count(&self) -> usize;
+    // Objects the structure was built from, each counted once.
+    fn unique_objects_count(&self) -> usize {
+        self.objects_count()
+    }
+    fn nodes_count(&self) -> usize;
+    // Primitives and nodes together with the ones of the instanced meshes.
+    fn total_primitives_count(&self) -> usize;
+    fn total_nodes_count(&self) -> usize;
+    fn name(&self) -> &'static str;
+    fn bounding_box(&self) -> AlighnedBox;
+    // Moves the object and updates the structure accordingly.
+    fn transform_object(&mut self, index: usize, transformation: Transformation);
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct FrameStats {
+    pub(crate) duration: Duration,
+    pub(crate) rays: usize,
+}
+
+impl FrameStats {
+    pub(crate) fn fps(&self) -> f64 {
+        1.0 / self.duration.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub(crate) fn rays_per_second(&self) -> f64 {
+        self.rays as f64 / self.duration.as_secs_f64().max(f64::EPSILON)
+    }
 }
 
 pub(crate) struct RayTracer {
@@ -45,6 +195,15 @@ pub(crate) struct RayTracer {
     camera: Camera,
     width: usize,
     height: usize,
+    samples: usize,
+    // Moment of the frame and the time the shutter stays open after it.
+    time: f64,
+    shutter: f64,
+    rays: Cell<usize>,
+    // Primary rays are traced in packets.
+    packets: bool,
+    last_frame: FrameStats,
+    object_ids: Vec<Option<usize>>,
 }
 
 impl RayTracer {
@@ -54,41 +213,218 @@ impl RayTracer {
             camera,
             width,
             height,
+            samples: 1,
+            time: 0.0,
+            shutter: 0.0,
+            rays: Cell::new(0),
+            packets: true,
+            last_frame: FrameStats::default(),
+            object_ids: vec![],
         }
     }
 
-    pub(crate) fn transform_camera(&mut self, transformation: Transformation) {
-        self.camera.transform(transformation)
-    }
-
     pub(crate) fn rotation_vector(&self) -> Vector {
         self.camera.rotation_vector()
     }
 
+    pub(crate) fn camera_position(&self) -> Point {
+        self.camera.position()
+    }
+
+    pub(crate) fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub(crate) fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    pub(crate) fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub(crate) fn transform_object(&mut self, index: usize, transformation: Transformation) {
+        self.scene
+            .objects_mut()
+            .transform_object(index, transformation);
+    }
+
+    pub(crate) fn integrator_name(&self) -> &'static str {
+        INTEGRATOR_NAME
+    }
+
+    pub(crate) fn last_frame_stats(&self) -> FrameStats {
+        self.last_frame
+    }
+
+    pub(crate) fn set_samples(&mut self, samples: usize) {
+        self.samples = samples.max(1);
+    }
+
+    pub(crate) fn set_packets(&mut self, packets: bool) {
+        self.packets = packets;
+    }
+
+    pub(crate) fn set_time(&mut self, time: f64) {
+        self.time = time;
+    }
+
+    // Objects moving while the shutter is open are blurred.
+    pub(crate) fn set_shutter(&mut self, shutter: f64) {
+        self.shutter = shutter.max(0.0);
+    }
+
     pub(crate) fn render(&mut self, output: &mut dyn Output) -> anyhow::Result<()> {
-        let mut buff = vec![DEFAULT_BACKGROUND_COLOR; self.width * self.height];
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let ray = self
-                    .camera
-                    .ray_for_pixel(x, self.height - y, self.width, self.height);
-                buff[y * self.width + x] = self.get_color_for_ray(ray, 0);
+        let start = Instant::now();
+        let (buff, object_ids) = self.render_buffers(self.width, self.height, self.samples);
+        self.object_ids = object_ids;
+        self.last_frame = FrameStats {
+            duration: start.elapsed(),
+            rays: self.rays.get(),
+        };
+        output.dump(&buff, self.width, self.height)
+    }
+
+    // Renders the image of the given size, averaging `samples` rays per pixel.
+    pub(crate) fn render_image(&self, width: usize, height: usize, samples: usize) -> Vec<Color> {
+        self.render_buffers(width, height, samples).0
+    }
+
+    // Indices of the objects seen through every pixel of the last rendered frame.
+    pub(crate) fn object_ids(&self) -> &[Option<usize>] {
+        &self.object_ids
+    }
+
+    // Traces the primary ray through the pixel of the rendered frame and describes the hit.
+    pub(crate) fn pick(&self, x: usize, y: usize) -> Option<PickReport<'_>> {
+        let ray = self
+            .camera
+            .ray_for_pixel(
+                x as f64 + 0.5,
+                (self.height - y) as f64 - 0.5,
+                self.width,
+                self.height,
+            )
+            .with_time(self.sample_time(0));
+        let objects = self.scene.objects();
+        let (index, intersection) = objects.trace(&ray)?;
+        let point = ray.at(intersection.distance());
+        let material_id = objects.material_at(index, intersection);
+        Some(PickReport {
+            pixel: (x, y),
+            object_index: index,
+            geometry: objects.describe(index, intersection),
+            material_id,
+            material: self.scene.materials(material_id),
+            intersection,
+            point,
+            normal: objects.normal_at_point(index, &point, intersection, ray.time),
+        })
+    }
+
+    fn render_buffers(
+        &self,
+        width: usize,
+        height: usize,
+        samples: usize,
+    ) -> (Vec<Color>, Vec<Option<usize>>) {
+        self.rays.set(0);
+        let samples = samples.max(1);
+        let mut buff = vec![DEFAULT_BACKGROUND_COLOR; width * height];
+        let mut object_ids = vec![None; width * height];
+        // The primary rays of the neighbouring pixels are traced together, 2x2 pixels at once.
+        for y0 in (0..height).step_by(2) {
+            for x0 in (0..width).step_by(2) {
+                let pixels: Vec<_> = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .iter()
+                    .map(|(dx, dy)| (x0 + dx, y0 + dy))
+                    .filter(|&(x, y)| x < width && y < height)
+                    .collect();
+                let mut colors = [Color::black(); 4];
+                for i in 0..samples {
+                    let (dx, dy) = sample_offset(i);
+                    let rays: Vec<_> = pixels
+                        .iter()
+                        .map(|&(x, y)| {
+                            self.camera
+                                .ray_for_pixel(
+                                    x as f64 + dx,
+                                    (height - y) as f64 - dy,
+                                    width,
+                                    height,
+                                )
+                                .with_time(self.sample_time(i))
+                        })
+                        .collect();
+                    let traced = self.trace_primary(&rays);
+                    for (lane, &(x, y)) in pixels.iter().enumerate() {
+                        if i == 0 {
+                            object_ids[y * width + x] = traced[lane].map(|(object, _)| object);
+                        }
+                        colors[lane] = colors[lane] + self.shade(rays[lane], traced[lane], 0);
+                    }
+                }
+                for (lane, &(x, y)) in pixels.iter().enumerate() {
+                    buff[y * width + x] = colors[lane] * (1.0 / samples as f64);
+                }
             }
         }
-        output.dump(&buff, self.width, self.height)
+        (buff, object_ids)
+    }
+
+    // Moment inside of the shutter interval for the sample. The golden ratio sequence
+    // spreads the samples evenly over time, the first one is in the middle.
+    fn sample_time(&self, index: usize) -> f64 {
+        const GOLDEN_RATIO: f64 = 1.618_033_988_749_895;
+        self.time + self.shutter * (0.5 + index as f64 / GOLDEN_RATIO).fract()
+    }
+
+    fn trace(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        self.rays.set(self.rays.get() + 1);
+        self.scene.objects().trace(ray)
+    }
+
+    // Up to four rays starting the paths of the neighbouring pixels.
+    fn trace_primary(&self, rays: &[Ray]) -> [Option<(usize, Intersection)>; 4] {
+        if !self.packets {
+            return std::array::from_fn(|lane| rays.get(lane).and_then(|ray| self.trace(ray)));
+        }
+        self.rays.set(self.rays.get() + rays.len());
+        self.scene
+            .objects()
+            .trace_packet(&RayPacket::from_rays(rays))
     }
 
     fn get_color_for_ray(&self, ray: Ray, nonce: u32) -> Color {
-        let traced = self.scene.objects().trace(&ray);
+        let traced = self.trace(&ray);
+        self.shade(ray, traced, nonce)
+    }
 
-        if let Some((object, intersection)) = traced {
-            let object = self.scene.objects().object_by_index(object);
+    fn shade(&self, ray: Ray, traced: Option<(usize, Intersection)>, nonce: u32) -> Color {
+        if let Some((index, intersection)) = traced {
+            let objects = self.scene.objects();
             let intersection_point = ray.at(intersection.distance());
-            let normal = object.normal_at_point(&intersection_point, intersection);
-            let material = self.scene.materials(object.material_id);
-            let color = self.get_color(intersection_point, normal, material, &ray);
+            let normal =
+                objects.normal_at_point(index, &intersection_point, intersection, ray.time);
+            let surface = ray.surface_point(
+                intersection.distance(),
+                objects.geometric_normal(index, &intersection_point, intersection, ray.time),
+            );
+            let material = self
+                .scene
+                .materials(objects.material_at(index, intersection));
+            let diffuse = match material.checker {
+                Some(_) => material.diffuse_at(objects.texture_coordinates(
+                    index,
+                    &intersection_point,
+                    intersection,
+                    ray.time,
+                )),
+                None => material.diffuse,
+            };
+            let color = self.get_color(&surface, normal, material, diffuse, &ray);
             let color = if material.dissolve < 1.0 {
-                let ray = Ray::new(ray.at(intersection.distance() + 1e-4), ray.direction);
+                let ray = surface.spawn(ray.direction).with_time(ray.time);
                 // We have to trace another object behind this one.
                 self.get_color_for_ray(ray, 0) * (1. - material.dissolve)
                     + color * material.dissolve
@@ -97,7 +433,7 @@ impl RayTracer {
             };
 
             if material.specular > Color::black() && nonce < MIRROR_RECURSION_LIMIT {
-                let ray = ray.reflect_from_normal(intersection_point, normal);
+                let ray = ray.reflect_from_normal(&surface, normal);
                 color * (Color::white() - material.specular)
                     + material.specular * self.get_color_for_ray(ray, nonce + 1)
             } else {
@@ -112,11 +448,13 @@ impl RayTracer {
 
     fn get_color(
         &self,
-        intersection_point: Point,
+        surface: &SurfacePoint,
         normal: Normal,
         material: &Material,
+        diffuse: Color,
         ray: &Ray,
     ) -> Color {
+        let intersection_point = surface.point;
         self.scene
             .lights()
             .iter()
@@ -124,27 +462,36 @@ impl RayTracer {
                 Light::Environment(color, coof) => color * coof * material.ambient,
                 Light::Point(point, color, coof)
                     if !self.is_shadowed(
-                        intersection_point,
+                        surface,
                         (point - intersection_point).normalize(),
+                        (point - intersection_point).length(),
+                        ray.time,
                     ) =>
                 {
                     let light_dir = (intersection_point - point).normalize(); // In direction from Light to Intersection
-                    RayTracer::phong_color(color * coof, light_dir, normal, ray, material)
+                    RayTracer::phong_color(color * coof, light_dir, normal, ray, material, diffuse)
                 }
                 Light::Directed(light_dir, color, coof)
-                    if !self.is_shadowed(intersection_point, -light_dir) =>
+                    if !self.is_shadowed(surface, -light_dir, f64::INFINITY, ray.time) =>
                 {
-                    RayTracer::phong_color(color * coof, light_dir, normal, ray, material)
+                    RayTracer::phong_color(color * coof, light_dir, normal, ray, material, diffuse)
                 }
                 _ => Color::black(),
             })
             .sum::<Color>()
     }
 
-    fn is_shadowed(&self, intersection_point: Point, dir_to_light: Normal) -> bool {
-        let ray = Ray::new(intersection_point, dir_to_light);
-        let ray = Ray::new(ray.at(1e-4), dir_to_light);
-        self.scene.objects().trace(&ray).is_some()
+    // Only the objects between the point and the light cast the shadow.
+    fn is_shadowed(
+        &self,
+        surface: &SurfacePoint,
+        dir_to_light: Normal,
+        light_distance: f64,
+        time: f64,
+    ) -> bool {
+        let ray = surface.spawn(dir_to_light).with_time(time);
+        self.rays.set(self.rays.get() + 1);
+        self.scene.objects().occluded(&ray, light_distance)
     }
 
     fn phong_color(
@@ -153,9 +500,10 @@ impl RayTracer {
         normal: Normal,
         ray: &Ray,
         material: &Material,
+        diffuse: Color,
     ) -> Color {
         if material.illumination >= 1 {
-            let diffuse = intensity * normal.dot(-light_dir).max(0.0) * material.diffuse;
+            let diffuse = intensity * normal.dot(-light_dir).max(0.0) * diffuse;
             let specular = if material.illumination == 2 {
                 let reflection_light = Normal::reflect(normal, light_dir);
                 intensity
@@ -169,7 +517,16 @@ impl RayTracer {
             };
             diffuse + specular
         } else {
-            intensity * material.diffuse
+            intensity * diffuse
         }
     }
 }
+
+// Offset of the sample inside of the pixel. The R2 low-discrepancy sequence
+// spreads any number of samples evenly over the pixel area.
+fn sample_offset(index: usize) -> (f64, f64) {
+    const PLASTIC: f64 = 1.324_717_957_244_746;
+    let (a1, a2) = (1.0 / PLASTIC, 1.0 / (PLASTIC * PLASTIC));
+    let i = index as f64;
+    ((0.5 + a1 * i).fract(), (0.5 + a2 * i).fract())
+}
@@ -0,0 +1,643 @@
+use std::cell::Cell;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+
+use crate::basic_geometry::matrix::Matrix;
+use crate::basic_geometry::{
+    alighned_box::AlighnedBox, point::Point, transform::Transform3, vector::Vector, Axis,
+    Transformation,
+};
+
+use super::camera::Camera;
+
+// Steps of the motion sampled to bound the moving object.
+const MOTION_BOUND_STEPS: usize = 32;
+// Keyframes placed on the turntable orbit.
+const TURNTABLE_KEYFRAMES: usize = 36;
+// Degrees the turntable camera is raised above the model center.
+const TURNTABLE_ELEVATION: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Keyframe {
+    // Seconds from the start of the animation.
+    pub(crate) time: f64,
+    pub(crate) position: Point,
+    pub(crate) target: Point,
+    // Vertical field of view in degrees.
+    pub(crate) fov: f64,
+}
+
+impl Keyframe {
+    fn values(&self) -> [f64; 7] {
+        let (p, t) = (self.position, self.target);
+        [p.x, p.y, p.z, t.x, t.y, t.z, self.fov]
+    }
+
+    fn from_values(time: f64, v: [f64; 7]) -> Keyframe {
+        Keyframe {
+            time,
+            position: Point::new(v[0], v[1], v[2]),
+            target: Point::new(v[3], v[4], v[5]),
+            fov: v[6],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Interpolation {
+    Linear,
+    // Smooth curve passing through every keyframe.
+    CatmullRom,
+    // Keyframes are the control points of a single Bezier curve,
+    // only the first and the last of them are passed through.
+    Bezier,
+}
+
+impl Interpolation {
+    pub(crate) fn parse(name: &str) -> anyhow::Result<Interpolation> {
+        match name.to_ascii_lowercase().as_str() {
+            "linear" => Ok(Interpolation::Linear),
+            "catmull-rom" | "catmullrom" => Ok(Interpolation::CatmullRom),
+            "bezier" => Ok(Interpolation::Bezier),
+            _ => Err(anyhow!("Unknown interpolation '{}'", name)),
+        }
+    }
+}
+
+// Camera movement defined by keyframes sorted by time.
+pub(crate) struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    interpolation: Interpolation,
+    // The last keyframe repeats the first one, so the animation can be played in a loop.
+    looped: bool,
+}
+
+impl CameraPath {
+    pub(crate) fn new(
+        keyframes: Vec<Keyframe>,
+        interpolation: Interpolation,
+    ) -> anyhow::Result<CameraPath> {
+        if keyframes.is_empty() {
+            bail!("Camera path has no keyframes");
+        }
+        if keyframes.windows(2).any(|w| w[0].time >= w[1].time) {
+            bail!("Camera path keyframes must have increasing time");
+        }
+        Ok(CameraPath {
+            keyframes,
+            interpolation,
+            looped: false,
+        })
+    }
+
+    // Reads keyframes from the file. Every line has a form:
+    // `time position_x position_y position_z target_x target_y target_z fov`
+    // Empty lines and lines starting with '#' are ignored.
+    pub(crate) fn from_file(
+        path: &Path,
+        interpolation: Interpolation,
+    ) -> anyhow::Result<CameraPath> {
+        CameraPath::new(
+            CameraPath::parse(&std::fs::read_to_string(path)?)?,
+            interpolation,
+        )
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Vec<Keyframe>> {
+        let mut keyframes = vec![];
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(str::parse::<f64>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| anyhow!("Line {}: {}", number + 1, e))?;
+            if values.len() != 8 {
+                bail!(
+                    "Line {}: expected time, position, target and fov",
+                    number + 1
+                );
+            }
+            let mut v = [0.0; 7];
+            v.copy_from_slice(&values[1..]);
+            keyframes.push(Keyframe::from_values(values[0], v));
+        }
+        Ok(keyframes)
+    }
+
+    // Orbits around the bounding box in `duration` seconds, keeping the whole box in view.
+    pub(crate) fn turntable(bounds: AlighnedBox, fov: f64, duration: f64) -> CameraPath {
+        let center = bounds.center();
+        let radius = ((bounds.max - bounds.min).length() / 2.0).max(f64::EPSILON);
+        let distance = radius / (fov.to_radians() / 2.0).sin();
+        let elevation = TURNTABLE_ELEVATION.to_radians();
+        let keyframes = (0..=TURNTABLE_KEYFRAMES)
+            .map(|i| {
+                let part = i as f64 / TURNTABLE_KEYFRAMES as f64;
+                let angle = part * std::f64::consts::TAU;
+                let offset = Vector::new(
+                    angle.sin() * elevation.cos(),
+                    elevation.sin(),
+                    angle.cos() * elevation.cos(),
+                );
+                Keyframe {
+                    time: part * duration,
+                    position: center + offset * distance,
+                    target: center,
+                    fov,
+                }
+            })
+            .collect();
+        CameraPath {
+            keyframes,
+            interpolation: Interpolation::CatmullRom,
+            looped: true,
+        }
+    }
+
+    pub(crate) fn duration(&self) -> f64 {
+        self.keyframes[self.keyframes.len() - 1].time - self.keyframes[0].time
+    }
+
+    // Number of frames covering the path. The looped path doesn't repeat the first frame at the end.
+    pub(crate) fn frames_count(&self, fps: f64) -> usize {
+        let frames = (self.duration() * fps).round() as usize;
+        if self.looped {
+            frames.max(1)
+        } else {
+            frames + 1
+        }
+    }
+
+    pub(crate) fn sample(&self, time: f64) -> Keyframe {
+        let keyframes = &self.keyframes;
+        let (first, last) = (keyframes[0].time, keyframes[keyframes.len() - 1].time);
+        let time = time.clamp(first, last);
+        if keyframes.len() == 1 {
+            return keyframes[0];
+        }
+        let values = match self.interpolation {
+            Interpolation::Bezier => bezier(
+                keyframes.iter().map(Keyframe::values).collect(),
+                (time - first) / (last - first),
+            ),
+            Interpolation::Linear | Interpolation::CatmullRom => {
+                let segment = keyframes
+                    .windows(2)
+                    .position(|w| time <= w[1].time)
+                    .unwrap_or(keyframes.len() - 2);
+                let (start, end) = (keyframes[segment], keyframes[segment + 1]);
+                let s = (time - start.time) / (end.time - start.time);
+                if self.interpolation == Interpolation::Linear {
+                    lerp(start.values(), end.values(), s)
+                } else {
+                    catmull_rom(
+                        self.neighbour(segment, -1).values(),
+                        start.values(),
+                        end.values(),
+                        self.neighbour(segment + 1, 1).values(),
+                        s,
+                    )
+                }
+            }
+        };
+        Keyframe::from_values(time, values)
+    }
+
+    // Places the camera at the point of the path.
+    pub(crate) fn apply(&self, time: f64, camera: &mut Camera) {
+        let keyframe = self.sample(time);
+        camera.move_to(keyframe.position);
+        camera.look_at(keyframe.target);
+        camera.set_fov(keyframe.fov);
+    }
+
+    // Keyframe next to the given one. The ends are repeated, or wrapped around for the looped path.
+    fn neighbour(&self, index: usize, step: isize) -> Keyframe {
+        let last = self.keyframes.len() - 1;
+        let index = index as isize + step;
+        let index = if index < 0 {
+            if self.looped {
+                last - 1
+            } else {
+                0
+            }
+        } else if index as usize > last {
+            if self.looped {
+                1
+            } else {
+                last
+            }
+        } else {
+            index as usize
+        };
+        self.keyframes[index.min(last)]
+    }
+}
+
+// Camera path rendered with the given frame rate.
+pub(crate) struct Animation {
+    pub(crate) path: CameraPath,
+    pub(crate) fps: f64,
+    pub(crate) frames: Range<usize>,
+}
+
+impl Animation {
+    // Animation of the whole path, unless the frame range is given.
+    pub(crate) fn new(path: CameraPath, fps: f64, frames: Option<Range<usize>>) -> Animation {
+        let frames = frames.unwrap_or(0..path.frames_count(fps));
+        Animation { path, fps, frames }
+    }
+
+    pub(crate) fn frame_time(&self, frame: usize) -> f64 {
+        self.path.keyframes[0].time + frame as f64 / self.fps
+    }
+}
+
+// Placement of the moving object at the moment of time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TransformKeyframe {
+    pub(crate) time: f64,
+    pub(crate) translation: Vector,
+    // Euler angles in degrees, applied in the X, Y, Z order.
+    pub(crate) rotation: Vector,
+    pub(crate) scale: Vector,
+}
+
+impl TransformKeyframe {
+    fn values(&self) -> [f64; 9] {
+        let (t, r, s) = (self.translation, self.rotation, self.scale);
+        [t.x, t.y, t.z, r.x, r.y, r.z, s.x, s.y, s.z]
+    }
+
+    fn from_values(time: f64, v: [f64; 9]) -> TransformKeyframe {
+        TransformKeyframe {
+            time,
+            translation: Vector::new(v[0], v[1], v[2]),
+            rotation: Vector::new(v[3], v[4], v[5]),
+            scale: Vector::new(v[6], v[7], v[8]),
+        }
+    }
+}
+
+// Time-varying transform of the object, linearly interpolated between the keyframes.
+// Rotation and scale are done around the pivot. The geometry itself is never changed,
+// instead the rays are moved into the object space at the time they carry.
+#[derive(Debug)]
+pub(crate) struct ObjectMotion {
+    keyframes: Vec<TransformKeyframe>,
+    pivot: Point,
+    // The last computed transform. All triangles of the mesh share the motion
+    // and are tested with the same ray, so the matrices are rarely recomputed.
+    last_transform: Cell<Option<(f64, Transform3)>>,
+    shutter_interval: Option<(f64, f64)>,
+}
+
+impl ObjectMotion {
+    pub(crate) fn new(
+        keyframes: Vec<TransformKeyframe>,
+        pivot: Point,
+    ) -> anyhow::Result<ObjectMotion> {
+        if keyframes.is_empty() {
+            bail!("Object motion has no keyframes");
+        }
+        if keyframes.windows(2).any(|w| w[0].time >= w[1].time) {
+            bail!("Object motion keyframes must have increasing time");
+        }
+        let zero_scale = |s: Vector| s.x == 0.0 || s.y == 0.0 || s.z == 0.0;
+        if keyframes.iter().any(|keyframe| zero_scale(keyframe.scale)) {
+            bail!("Object motion scale can't be zero");
+        }
+        Ok(ObjectMotion {
+            keyframes,
+            pivot,
+            last_transform: Cell::new(None),
+            shutter_interval: None,
+        })
+    }
+
+    // Reads keyframes from the file. Every line has a form:
+    // `time translation_x translation_y translation_z rotation_x rotation_y rotation_z scale_x scale_y scale_z`
+    // Empty lines and lines starting with '#' are ignored.
+    pub(crate) fn from_file(path: &Path, pivot: Point) -> anyhow::Result<ObjectMotion> {
+        ObjectMotion::new(ObjectMotion::parse(&std::fs::read_to_string(path)?)?, pivot)
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Vec<TransformKeyframe>> {
+        let mut keyframes = vec![];
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(str::parse::<f64>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| anyhow!("Line {}: {}", number + 1, e))?;
+            if values.len() != 10 {
+                bail!(
+                    "Line {}: expected time, translation, rotation and scale",
+                    number + 1
+                );
+            }
+            let mut v = [0.0; 9];
+            v.copy_from_slice(&values[1..]);
+            keyframes.push(TransformKeyframe::from_values(values[0], v));
+        }
+        Ok(keyframes)
+    }
+
+    pub(crate) fn sample(&self, time: f64) -> TransformKeyframe {
+        let keyframes = &self.keyframes;
+        let (first, last) = (keyframes[0].time, keyframes[keyframes.len() - 1].time);
+        let time = time.clamp(first, last);
+        let segment = keyframes.windows(2).position(|w| time <= w[1].time);
+        match segment {
+            Some(segment) => {
+                let (start, end) = (keyframes[segment], keyframes[segment + 1]);
+                let s = (time - start.time) / (end.time - start.time);
+                TransformKeyframe::from_values(time, lerp(start.values(), end.values(), s))
+            }
+            None => keyframes[keyframes.len() - 1],
+        }
+    }
+
+    // Object to world transform.
+    pub(crate) fn matrix(&self, time: f64) -> Matrix<4, 4> {
+        let keyframe = self.sample(time);
+        let r = keyframe.rotation;
+        Transformation::Translation(Vector::from(self.pivot) + keyframe.translation)
+            .transformation_to_matrix()
+            * Transformation::Rotation(Axis::Z, r.z).transformation_to_matrix()
+            * Transformation::Rotation(Axis::Y, r.y).transformation_to_matrix()
+            * Transformation::Rotation(Axis::X, r.x).transformation_to_matrix()
+            * Transformation::Scale(keyframe.scale).transformation_to_matrix()
+            * Transformation::Translation(-Vector::from(self.pivot)).transformation_to_matrix()
+    }
+
+    // Object to world transform with its inverse.
+    pub(crate) fn transform(&self, time: f64) -> Transform3 {
+        match self.last_transform.get() {
+            Some((last_time, transform)) if last_time == time => transform,
+            _ => {
+                let transform = Transform3::with_inverse(self.matrix(time), self.inverse(time));
+                self.last_transform.set(Some((time, transform)));
+                transform
+            }
+        }
+    }
+
+    // World to object transform.
+    fn inverse(&self, time: f64) -> Matrix<4, 4> {
+        let keyframe = self.sample(time);
+        let (r, s) = (keyframe.rotation, keyframe.scale);
+        Transformation::Translation(Vector::from(self.pivot)).transformation_to_matrix()
+            * Transformation::Scale(Vector::new(1.0 / s.x, 1.0 / s.y, 1.0 / s.z))
+                .transformation_to_matrix()
+            * Transformation::Rotation(Axis::X, -r.x).transformation_to_matrix()
+            * Transformation::Rotation(Axis::Y, -r.y).transformation_to_matrix()
+            * Transformation::Rotation(Axis::Z, -r.z).transformation_to_matrix()
+            * Transformation::Translation(-(Vector::from(self.pivot) + keyframe.translation))
+                .transformation_to_matrix()
+    }
+
+    // Limits the time the object is bounded for, by default the whole motion is covered.
+    pub(crate) fn set_shutter_interval(&mut self, open: f64, close: f64) {
+        self.shutter_interval = Some((open, close.max(open)));
+    }
+
+    // Box containing the object while the shutter is open.
+    pub(crate) fn bounding_box(&self, bounds: AlighnedBox) -> AlighnedBox {
+        let keyframes = &self.keyframes;
+        let (open, close) = self
+            .shutter_interval
+            .unwrap_or((keyframes[0].time, keyframes[keyframes.len() - 1].time));
+        // The transform is linear between the keyframes, so the interval is split by them.
+        let mut times = vec![open];
+        times.extend(
+            keyframes
+                .iter()
+                .map(|keyframe| keyframe.time)
+                .filter(|&time| open < time && time < close),
+        );
+        times.push(close);
+
+        let corners = bounds.corners();
+        let transformed = |time: f64| {
+            let matrix = self.matrix(time);
+            corners.iter().fold(AlighnedBox::default(), |acc, &corner| {
+                acc.union_point(matrix * corner)
+            })
+        };
+        let radius = corners
+            .iter()
+            .map(|&corner| (corner - self.pivot).length())
+            .fold(0.0, f64::max);
+
+        let mut result = transformed(open);
+        for w in times.windows(2) {
+            let (start, end) = (self.sample(w[0]), self.sample(w[1]));
+            let delta = end.rotation - start.rotation;
+            let angle = (delta.x.abs() + delta.y.abs() + delta.z.abs()).to_radians()
+                / MOTION_BOUND_STEPS as f64;
+            let scale = [start.scale, end.scale]
+                .iter()
+                .map(|s| s.x.abs().max(s.y.abs()).max(s.z.abs()))
+                .fold(0.0, f64::max);
+            // Between two samples a rotating corner moves along an arc, which sags
+            // away from the straight line by at most r * (1 - cos(angle / 2)).
+            let sag = radius * scale * (1.0 - (angle / 2.0).cos());
+            let padding = Vector::new(sag, sag, sag);
+            for i in 1..=MOTION_BOUND_STEPS {
+                let part = i as f64 / MOTION_BOUND_STEPS as f64;
+                let sampled = transformed(w[0] + (w[1] - w[0]) * part);
+                result = result.union(&AlighnedBox::new(
+                    sampled.min + -padding,
+                    sampled.max + padding,
+                ));
+            }
+        }
+        result
+    }
+}
+
+fn lerp<const N: usize>(a: [f64; N], b: [f64; N], s: f64) -> [f64; N] {
+    let mut result = a;
+    for i in 0..N {
+        result[i] = a[i] + (b[i] - a[i]) * s;
+    }
+    result
+}
+
+fn catmull_rom(p0: [f64; 7], p1: [f64; 7], p2: [f64; 7], p3: [f64; 7], s: f64) -> [f64; 7] {
+    let mut result = p1;
+    for i in 0..7 {
+        result[i] = 0.5
+            * (2.0 * p1[i]
+                + (p2[i] - p0[i]) * s
+                + (2.0 * p0[i] - 5.0 * p1[i] + 4.0 * p2[i] - p3[i]) * s * s
+                + (3.0 * p1[i] - p0[i] - 3.0 * p2[i] + p3[i]) * s * s * s);
+    }
+    result
+}
+
+// De Casteljau's algorithm.
+fn bezier(mut points: Vec<[f64; 7]>, s: f64) -> [f64; 7] {
+    while points.len() > 1 {
+        points = points.windows(2).map(|w| lerp(w[0], w[1], s)).collect();
+    }
+    points[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f64, x: f64) -> Keyframe {
+        Keyframe {
+            time,
+            position: Point::new(x, 0.0, 100.0),
+            target: Point::new(0.0, 0.0, 0.0),
+            fov: 45.0 + x,
+        }
+    }
+
+    fn path(interpolation: Interpolation) -> CameraPath {
+        CameraPath::new(
+            vec![
+                keyframe(0.0, 0.0),
+                keyframe(1.0, 10.0),
+                keyframe(3.0, 30.0),
+                keyframe(4.0, 0.0),
+            ],
+            interpolation,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn linear_test() {
+        let path = path(Interpolation::Linear);
+        assert_eq!(path.sample(0.5).position.x, 5.0);
+        assert_eq!(path.sample(2.0).position.x, 20.0);
+        assert_eq!(path.sample(2.0).fov, 65.0);
+        assert_eq!(path.sample(-1.0), keyframe(0.0, 0.0));
+        assert_eq!(path.sample(10.0), keyframe(4.0, 0.0));
+    }
+
+    #[test]
+    fn catmull_rom_passes_keyframes() {
+        let path = path(Interpolation::CatmullRom);
+        for keyframe in &path.keyframes {
+            let sample = path.sample(keyframe.time);
+            assert!((sample.position.x - keyframe.position.x).abs() < 1e-9);
+        }
+        let x = path.sample(3.5).position.x;
+        assert!(x > 0.0 && x < 30.0);
+    }
+
+    #[test]
+    fn bezier_test() {
+        let path = path(Interpolation::Bezier);
+        assert_eq!(path.sample(0.0).position.x, 0.0);
+        assert_eq!(path.sample(4.0).position.x, 0.0);
+        // (0 + 3 * 10 + 3 * 30 + 0) * 0.5^3 at the middle of the curve.
+        assert!((path.sample(2.0).position.x - 15.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn turntable_test() {
+        let bounds = AlighnedBox::new(Point::new(-1.0, 0.0, -1.0), Point::new(1.0, 2.0, 1.0));
+        let path = CameraPath::turntable(bounds, 60.0, 4.0);
+        assert_eq!(path.frames_count(24.0), 96);
+        let start = path.sample(0.0);
+        let end = path.sample(4.0);
+        assert!((start.position - end.position).length() < 1e-9);
+        for frame in 0..96 {
+            let sample = path.sample(frame as f64 / 24.0);
+            let distance = (sample.position - bounds.center()).length();
+            // The radius of the box is sqrt(3), it has to fit into the half of the fov.
+            assert!((distance - 2.0 * 3f64.sqrt()).abs() < 0.01);
+            assert_eq!(sample.target, bounds.center());
+        }
+    }
+
+    #[test]
+    fn parse_test() {
+        let keyframes = CameraPath::parse(
+            "# time position target fov\n0 0 0 10 0 0 0 45\n\n2.5 1 2 3 4 5 6 30",
+        )
+        .unwrap();
+        assert_eq!(keyframes.len(), 2);
+        assert_eq!(keyframes[1].time, 2.5);
+        assert_eq!(keyframes[1].target, Point::new(4.0, 5.0, 6.0));
+        assert!(CameraPath::parse("0 0 0 0 0 0 0").is_err());
+        assert!(CameraPath::new(
+            vec![keyframe(1.0, 0.0), keyframe(1.0, 0.0)],
+            Interpolation::Linear
+        )
+        .is_err());
+        assert!(Interpolation::parse("Catmull-Rom").is_ok());
+        assert!(Interpolation::parse("cubic").is_err());
+    }
+
+    fn motion() -> ObjectMotion {
+        let keyframe = |time, x, angle, scale| TransformKeyframe {
+            time,
+            translation: Vector::new(x, 0.0, 0.0),
+            rotation: Vector::new(0.0, angle, angle / 2.0),
+            scale: Vector::new(scale, 1.0, 1.0),
+        };
+        ObjectMotion::new(
+            vec![keyframe(0.0, 0.0, 0.0, 1.0), keyframe(1.0, 10.0, 90.0, 2.0)],
+            Point::new(1.0, 1.0, 1.0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn motion_inverse_test() {
+        let motion = motion();
+        let point = Point::new(3.0, -2.0, 5.0);
+        for time in [0.0, 0.3, 0.5, 1.0] {
+            let transform = motion.transform(time);
+            let restored = transform.inverse().point(transform.point(point));
+            assert!((restored - point).length() < 1e-9);
+        }
+        let moved = motion.matrix(0.5) * Point::new(1.0, 1.0, 1.0);
+        assert!((moved - Point::new(6.0, 1.0, 1.0)).length() < 1e-9);
+        assert!(ObjectMotion::parse("0 0 0 0 0 0 0 1 1").is_err());
+        assert_eq!(
+            ObjectMotion::parse("0.5 1 2 3 0 90 0 1 1 1").unwrap()[0].rotation,
+            Vector::new(0.0, 90.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn motion_bounds_contain_object() {
+        let mut motion = motion();
+        let bounds = AlighnedBox::new(Point::new(0.0, 0.0, 0.0), Point::new(2.0, 3.0, 1.0));
+        let contains = |motion: &ObjectMotion, moving: AlighnedBox, open: f64, close: f64| {
+            for step in 0..=1000 {
+                let matrix = motion.matrix(open + (close - open) * step as f64 / 1000.0);
+                for corner in bounds.corners() {
+                    let p = matrix * corner;
+                    assert!(p.x >= moving.min.x && p.y >= moving.min.y && p.z >= moving.min.z);
+                    assert!(p.x <= moving.max.x && p.y <= moving.max.y && p.z <= moving.max.z);
+                }
+            }
+        };
+        let whole = motion.bounding_box(bounds);
+        contains(&motion, whole, 0.0, 1.0);
+        motion.set_shutter_interval(0.2, 0.4);
+        let shutter = motion.bounding_box(bounds);
+        contains(&motion, shutter, 0.2, 0.4);
+        assert!(shutter.surface_area() < whole.surface_area());
+    }
+}
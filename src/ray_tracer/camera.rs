@@ -1,9 +1,17 @@
 use crate::basic_geometry::point::Point;
 use crate::basic_geometry::ray::Ray;
+use crate::basic_geometry::transform::Transform3;
 use crate::basic_geometry::vector::Vector;
 use crate::basic_geometry::{Axis, Transform, Transformation};
 use crate::ray_tracer::viewframe::ViewFrame;
 
+// Position and orientation of the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CameraPose {
+    pub(crate) position: Point,
+    pub(crate) rotation_angles: Vector,
+}
+
 // Ray-tracing camera.
 pub(crate) struct Camera {
     // Camera position.
@@ -22,14 +30,75 @@ impl Camera {
         }
     }
 
+    pub(crate) fn position(&self) -> Point {
+        self.position
+    }
+
     pub(crate) fn rotation_vector(&self) -> Vector {
         self.rotation_angles
     }
 
+    pub(crate) fn move_to(&mut self, position: Point) {
+        self.transform(Transformation::Translation(position - self.position));
+    }
+
+    pub(crate) fn set_rotation(&mut self, rotation_angles: Vector) {
+        self.rotation_angles = rotation_angles;
+    }
+
+    pub(crate) fn pose(&self) -> CameraPose {
+        CameraPose {
+            position: self.position,
+            rotation_angles: self.rotation_angles,
+        }
+    }
+
+    pub(crate) fn set_pose(&mut self, pose: CameraPose) {
+        self.move_to(pose.position);
+        self.set_rotation(pose.rotation_angles);
+    }
+
+    // Vertical field of view in degrees.
+    pub(crate) fn fov(&self) -> f64 {
+        let distance = (self.view_frame.origin() - self.position).length();
+        2.0 * (self.view_frame.height() / 2.0)
+            .atan2(distance)
+            .to_degrees()
+    }
+
+    // Resizes the view frame keeping its aspect ratio.
+    pub(crate) fn set_fov(&mut self, fov: f64) {
+        let distance = (self.view_frame.origin() - self.position).length();
+        let height = 2.0 * distance * (fov.to_radians() / 2.0).tan();
+        let aspect = self.view_frame.width() / self.view_frame.height();
+        self.view_frame.resize(height * aspect, height);
+    }
+
+    // Turns the camera to the target without the roll.
+    pub(crate) fn look_at(&mut self, target: Point) {
+        let direction = (target - self.position).normalize();
+        let pitch = direction.y.clamp(-1.0, 1.0).asin().to_degrees();
+        let yaw = (-direction.x).atan2(-direction.z).to_degrees();
+        self.set_rotation(Vector::new(pitch, yaw, 0.0));
+    }
+
+    // Direction the camera is looking at.
+    pub(crate) fn forward(&self) -> Vector {
+        self.to_world(Vector::new(0.0, 0.0, -1.0))
+    }
+
+    pub(crate) fn right(&self) -> Vector {
+        self.to_world(Vector::new(1.0, 0.0, 0.0))
+    }
+
+    pub(crate) fn up(&self) -> Vector {
+        self.to_world(Vector::new(0.0, 1.0, 0.0))
+    }
+
     pub(crate) fn ray_for_pixel(
         &self,
-        x: usize,
-        y: usize,
+        x: f64,
+        y: f64,
         image_width: usize,
         image_height: usize,
     ) -> Ray {
@@ -40,14 +109,15 @@ impl Camera {
     }
 
     fn rotate_ray(&self, position: Point, direction: Vector) -> Ray {
-        let x =
-            Transformation::Rotation(Axis::X, self.rotation_angles.x).transformation_to_matrix();
-        let y =
-            Transformation::Rotation(Axis::Y, self.rotation_angles.y).transformation_to_matrix();
-        let z =
-            Transformation::Rotation(Axis::Z, self.rotation_angles.z).transformation_to_matrix();
-        let direction = (z * (y * (x * direction))).normalize();
-        Ray::new(position, direction)
+        Ray::new(position, self.to_world(direction).normalize())
+    }
+
+    // Rotates the vector from the camera space to the world space.
+    fn to_world(&self, direction: Vector) -> Vector {
+        let x = Transform3::from(Transformation::Rotation(Axis::X, self.rotation_angles.x));
+        let y = Transform3::from(Transformation::Rotation(Axis::Y, self.rotation_angles.y));
+        let z = Transform3::from(Transformation::Rotation(Axis::Z, self.rotation_angles.z));
+        z.vector(y.vector(x.vector(direction)))
     }
 }
 
@@ -60,10 +130,46 @@ impl Transform for Camera {
                 Axis::Z => self.rotation_angles.z += angle,
             },
             _ => {
-                let matrix = transform.transformation_to_matrix();
-                self.position = matrix * self.position;
+                self.position = Transform3::from(transform).point(self.position);
                 self.view_frame.transform(transform);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        let viewframe = ViewFrame::new(Point::new(0.0, 0.0, 250.0), 25.0, 25.0);
+        Camera::new(Point::new(0.0, 0.0, 275.0), viewframe)
+    }
+
+    #[test]
+    fn look_at_test() {
+        let mut camera = camera();
+        for target in [
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(100.0, 50.0, 275.0),
+            Point::new(-30.0, -200.0, 400.0),
+        ] {
+            camera.look_at(target);
+            let expected = (target - camera.position()).normalize();
+            let forward = camera.forward().normalize();
+            assert!((forward.x - expected.x).abs() < 1e-9);
+            assert!((forward.y - expected.y).abs() < 1e-9);
+            assert!((forward.z - expected.z).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn fov_test() {
+        let mut camera = camera();
+        assert!((camera.fov() - 53.130_102_354_156).abs() < 1e-9);
+        camera.set_fov(90.0);
+        assert!((camera.fov() - 90.0).abs() < 1e-9);
+        camera.move_to(Point::new(10.0, 20.0, 30.0));
+        assert!((camera.fov() - 90.0).abs() < 1e-9);
+    }
+}
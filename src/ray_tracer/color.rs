@@ -1,4 +1,5 @@
 use std::{
+    fmt::Display,
     iter::Sum,
     ops::{Add, Mul, Sub},
 };
@@ -94,3 +95,15 @@ impl Sum for Color {
         iter.fold(Color::black(), |a, b| a + b)
     }
 }
+
+impl Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "({}, {}, {})",
+            self.r.value(),
+            self.g.value(),
+            self.b.value()
+        )
+    }
+}
@@ -1,6 +1,9 @@
+use std::fmt::Display;
+
 use super::color::Color;
 
 pub(crate) struct Material {
+    pub(crate) name: String,
     pub(crate) ambient: Color,
     pub(crate) diffuse: Color,
     pub(crate) specular: Color,
@@ -8,11 +11,20 @@ pub(crate) struct Material {
     pub(crate) illumination: u8,
     pub(crate) optical_density: f64,
     pub(crate) dissolve: f64,
+    pub(crate) checker: Option<Checker>,
+}
+
+// Diffuse squares laid out by the texture coordinates, every other one has the second color.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Checker {
+    pub(crate) squares: f64,
+    pub(crate) color: Color,
 }
 
 impl Material {
     pub(crate) fn lambert() -> Self {
         Material {
+            name: "lambert".to_string(),
             ambient: [0.2, 0.2, 0.2].into(),
             diffuse: [0.8, 0.8, 0.8].into(),
             specular: [0.0, 0.0, 0.0].into(),
@@ -20,11 +32,13 @@ impl Material {
             illumination: 1,
             optical_density: 1.0,
             dissolve: 1.0,
+            checker: None,
         }
     }
 
     pub(crate) fn reflective() -> Self {
         Material {
+            name: "reflective".to_string(),
             ambient: [0.2, 0.2, 0.2].into(),
             diffuse: [0.8, 0.8, 0.8].into(),
             specular: [0.5, 0.5, 0.5].into(),
@@ -32,6 +46,35 @@ impl Material {
             illumination: 2,
             optical_density: 1.0,
             dissolve: 1.0,
+            checker: None,
+        }
+    }
+
+    // Slightly reflective checkerboard, which shows how the texture follows the surface.
+    pub(crate) fn checker() -> Self {
+        Material {
+            name: "checker".to_string(),
+            specular: [0.2, 0.2, 0.2].into(),
+            checker: Some(Checker {
+                squares: 16.,
+                color: [0.1, 0.1, 0.4].into(),
+            }),
+            ..Material::reflective()
+        }
+    }
+
+    // Diffuse color at the texture coordinates, the surfaces without them get the plain one.
+    pub(crate) fn diffuse_at(&self, uv: Option<(f64, f64)>) -> Color {
+        match (self.checker, uv) {
+            (Some(checker), Some((u, v))) => {
+                let square = (u * checker.squares).floor() + (v * checker.squares).floor();
+                if square.rem_euclid(2.) == 0. {
+                    self.diffuse
+                } else {
+                    checker.color
+                }
+            }
+            _ => self.diffuse,
         }
     }
 }
@@ -39,6 +82,7 @@ impl Material {
 impl From<tobj::Material> for Material {
     fn from(mat: tobj::Material) -> Self {
         Material {
+            name: mat.name,
             ambient: mat.ambient.into(),
             diffuse: mat.diffuse.into(),
             specular: mat.specular.into(),
@@ -46,6 +90,47 @@ impl From<tobj::Material> for Material {
             illumination: mat.illumination_model.unwrap_or(2),
             optical_density: mat.optical_density.into(),
             dissolve: mat.dissolve.into(),
+            checker: None,
+        }
+    }
+}
+
+impl Display for Material {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.name)?;
+        writeln!(f, "  ambient: {}", self.ambient)?;
+        writeln!(f, "  diffuse: {}", self.diffuse)?;
+        writeln!(f, "  specular: {}", self.specular)?;
+        writeln!(f, "  shininess: {}", self.shininess)?;
+        writeln!(f, "  illumination: {}", self.illumination)?;
+        writeln!(f, "  optical density: {}", self.optical_density)?;
+        if let Some(checker) = self.checker {
+            writeln!(
+                f,
+                "  checker: {} squares of {}",
+                checker.squares, checker.color
+            )?;
         }
+        write!(f, "  dissolve: {}", self.dissolve)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_squares() {
+        let material = Material::checker();
+        let dark = material.checker.unwrap().color;
+        assert_eq!(material.diffuse_at(Some((0.01, 0.01))), material.diffuse);
+        assert_eq!(material.diffuse_at(Some((0.07, 0.01))), dark);
+        assert_eq!(material.diffuse_at(Some((0.07, 0.07))), material.diffuse);
+        // Without the coordinates and without the texture the color is plain.
+        assert_eq!(material.diffuse_at(None), material.diffuse);
+        assert_eq!(
+            Material::lambert().diffuse_at(Some((0.07, 0.01))),
+            Material::lambert().diffuse
+        );
     }
 }
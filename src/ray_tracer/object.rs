@@ -1,16 +1,21 @@
 use std::{cell::RefCell, rc::Rc};
 
 use crate::{
-    basic_geometry::{Intersect, NormalAtPoint, Transform},
+    basic_geometry::{
+        alighned_box::AlighnedBox, normal::Normal, point::Point, ray::Ray, ray_packet::RayPacket,
+        triangle::Triangle, Intersect, Intersection, Transform, Transformation,
+    },
+    basic_types::simd::Mask4,
     complex_structures::BoundingBox,
 };
 
-use super::RayTracable;
+use super::{animation::ObjectMotion, RayTracable};
 
 #[derive(Clone)]
 pub(crate) struct Object {
     geometry: Rc<RefCell<dyn RayTracable>>,
     pub(crate) material_id: usize,
+    motion: Option<Rc<ObjectMotion>>,
 }
 
 impl Object {
@@ -18,37 +23,186 @@ impl Object {
         Self {
             geometry,
             material_id,
+            motion: None,
         }
     }
-}
 
-impl Intersect for Object {
-    fn intersect(
+    // Makes the object move over time. The motion is usually shared by all triangles of the mesh.
+    pub(crate) fn set_motion(&mut self, motion: Rc<ObjectMotion>) {
+        self.motion = Some(motion);
+    }
+
+    pub(crate) fn primitives_count(&self) -> usize {
+        self.geometry.borrow().primitives_count()
+    }
+
+    pub(crate) fn nodes_count(&self) -> usize {
+        self.geometry.borrow().nodes_count()
+    }
+
+    // Human readable description of the hit geometry.
+    pub(crate) fn describe(&self, intersection: Intersection) -> String {
+        self.geometry.borrow().describe(intersection)
+    }
+
+    // Material at the hit point. The geometry can override the material of the object.
+    pub(crate) fn material_at(&self, intersection: Intersection) -> usize {
+        self.geometry
+            .borrow()
+            .material_id(intersection)
+            .unwrap_or(self.material_id)
+    }
+
+    // Texture coordinates of the geometry placed where the object is at the moment.
+    pub(crate) fn texture_coordinates(
         &self,
-        ray: &crate::basic_geometry::ray::Ray,
-    ) -> Option<crate::basic_geometry::Intersection> {
-        self.geometry.borrow().intersect(ray)
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Option<(f64, f64)> {
+        let geometry = self.geometry.borrow();
+        match &self.motion {
+            Some(motion) => geometry.texture_coordinates(
+                &motion.transform(time).inverse().point(*point),
+                intersection,
+            ),
+            None => geometry.texture_coordinates(point, intersection),
+        }
+    }
+
+    // Shading normal at the point where the ray hit the object.
+    pub(crate) fn normal_at_point(
+        &self,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.normal_at_time(point, time, |geometry, point| {
+            geometry.normal_at_point(point, intersection)
+        })
+    }
+
+    pub(crate) fn geometric_normal(
+        &self,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.normal_at_time(point, time, |geometry, point| {
+            geometry.geometric_normal(point, intersection)
+        })
+    }
+
+    // Normal of the geometry placed where the object is at the moment.
+    fn normal_at_time(
+        &self,
+        point: &Point,
+        time: f64,
+        normal: impl Fn(&dyn RayTracable, &Point) -> Normal,
+    ) -> Normal {
+        match &self.motion {
+            Some(motion) => {
+                let transform = motion.transform(time);
+                let local = normal(&*self.geometry.borrow(), &transform.inverse().point(*point));
+                transform.normal(local)
+            }
+            None => normal(&*self.geometry.borrow(), point),
+        }
     }
 }
 
-impl NormalAtPoint for Object {
-    fn normal_at_point(
+impl Object {
+    // Moving objects place every ray at its own moment, so they are tested one by one.
+    pub(crate) fn intersect_packet(
         &self,
-        point: &crate::basic_geometry::point::Point,
-        intersection: crate::basic_geometry::Intersection,
-    ) -> crate::basic_geometry::normal::Normal {
-        self.geometry.borrow().normal_at_point(point, intersection)
+        packet: &RayPacket,
+        lanes: Mask4,
+    ) -> [Option<Intersection>; 4] {
+        match &self.motion {
+            Some(_) => packet.each(lanes, |ray| self.intersect(ray)),
+            None => self.geometry.borrow().intersect_packet(packet, lanes),
+        }
+    }
+}
+
+impl Intersect for Object {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        match &self.motion {
+            Some(motion) => {
+                let (local, scale) = ray.transformed(&motion.transform(ray.time).inverse());
+                self.geometry
+                    .borrow()
+                    .intersect(&local)
+                    .map(|intersection| intersection.with_distance(intersection.distance() / scale))
+            }
+            None => self.geometry.borrow().intersect(ray),
+        }
+    }
+
+    fn occluded(&self, ray: &Ray, max_distance: f64) -> bool {
+        match &self.motion {
+            Some(motion) => {
+                let (local, scale) = ray.transformed(&motion.transform(ray.time).inverse());
+                self.geometry
+                    .borrow()
+                    .occluded(&local, max_distance * scale)
+            }
+            None => self.geometry.borrow().occluded(ray, max_distance),
+        }
     }
 }
 
 impl BoundingBox for Object {
-    fn bounding_box(&self) -> crate::basic_geometry::alighned_box::AlighnedBox {
-        self.geometry.borrow().bounding_box()
+    fn bounding_box(&self) -> AlighnedBox {
+        let bounds = self.geometry.borrow().bounding_box();
+        match &self.motion {
+            Some(motion) => motion.bounding_box(bounds),
+            None => bounds,
+        }
     }
 }
 
 impl Transform for Object {
-    fn transform(&mut self, tranform: crate::basic_geometry::Transformation) {
+    fn transform(&mut self, tranform: Transformation) {
         self.geometry.borrow_mut().transform(tranform)
     }
 }
+
+// Triangle of the loaded mesh with its material. Mesh trees store them inline
+// instead of the shared geometry of the `Object`.
+#[derive(Debug, Clone)]
+pub(crate) struct MeshTriangle {
+    pub(crate) triangle: Triangle,
+    pub(crate) material_id: usize,
+}
+
+impl MeshTriangle {
+    pub(crate) fn new(triangle: Triangle, material_id: usize) -> MeshTriangle {
+        MeshTriangle {
+            triangle,
+            material_id,
+        }
+    }
+
+    pub(crate) fn into_object(self) -> Object {
+        Object::new(Rc::new(RefCell::new(self.triangle)), self.material_id)
+    }
+}
+
+impl Intersect for MeshTriangle {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        self.triangle.intersect(ray)
+    }
+}
+
+impl BoundingBox for MeshTriangle {
+    fn bounding_box(&self) -> AlighnedBox {
+        self.triangle.bounding_box()
+    }
+}
+
+impl Transform for MeshTriangle {
+    fn transform(&mut self, transformation: Transformation) {
+        self.triangle.transform(transformation)
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::Display;
+
+use crate::basic_geometry::{normal::Normal, point::Point, Intersection};
+
+use super::material::Material;
+
+// Everything the primary ray hit through the picked pixel.
+pub(crate) struct PickReport<'a> {
+    pub(crate) pixel: (usize, usize),
+    pub(crate) object_index: usize,
+    pub(crate) geometry: String,
+    pub(crate) material_id: usize,
+    pub(crate) material: &'a Material,
+    pub(crate) intersection: Intersection,
+    pub(crate) point: Point,
+    pub(crate) normal: Normal,
+}
+
+impl<'a> PickReport<'a> {
+    // Weights of the triangle vertices at the hit point.
+    pub(crate) fn barycentrics(&self) -> Option<[f64; 3]> {
+        match self.intersection {
+            Intersection::TriangleIntesersect(_, u, v)
+            | Intersection::InstanceIntersect(_, _, u, v) => Some([1.0 - u - v, u, v]),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Display for PickReport<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Pixel: {:?}", self.pixel)?;
+        writeln!(f, "Object index: {}", self.object_index)?;
+        writeln!(f, "Geometry: {}", self.geometry)?;
+        writeln!(f, "Distance: {}", self.intersection.distance())?;
+        writeln!(
+            f,
+            "Hit point: ({}, {}, {})",
+            self.point.x, self.point.y, self.point.z
+        )?;
+        if let Some([w, u, v]) = self.barycentrics() {
+            writeln!(f, "Barycentrics: ({}, {}, {})", w, u, v)?;
+        }
+        writeln!(
+            f,
+            "Shading normal: ({}, {}, {})",
+            self.normal.x, self.normal.y, self.normal.z
+        )?;
+        write!(f, "Material #{}: {}", self.material_id, self.material)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_contains_triangle_data() {
+        let material = Material::lambert();
+        let report = PickReport {
+            pixel: (1, 2),
+            object_index: 7,
+            geometry: "Triangle".to_string(),
+            material_id: 0,
+            material: &material,
+            intersection: Intersection::TriangleIntesersect(4.0, 0.25, 0.5),
+            point: Point::new(0., 0., 0.),
+            normal: Normal::new(0., 0., 1.),
+        };
+        assert_eq!(report.barycentrics(), Some([0.25, 0.25, 0.5]));
+        let text = report.to_string();
+        assert!(text.contains("Object index: 7"));
+        assert!(text.contains("Distance: 4"));
+        assert!(text.contains("Barycentrics: (0.25, 0.25, 0.5)"));
+        assert!(text.contains("lambert"));
+    }
+}
@@ -1,12 +1,22 @@
-use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::anyhow;
 
 use super::light::Light;
 use super::object::Object;
 use super::ObjectContainer;
+use crate::basic_geometry::alighned_box::AlighnedBox;
+use crate::basic_geometry::normal::Normal;
+use crate::basic_geometry::point::Point;
 use crate::basic_geometry::ray::Ray;
-use crate::basic_geometry::{Intersect, Intersection};
+use crate::basic_geometry::ray_packet::RayPacket;
+use crate::basic_geometry::{Intersect, Intersection, Transform, Transformation};
+use crate::complex_structures::bvh::builder::BvhBuilder;
+use crate::complex_structures::bvh::bvh4::Bvh4;
 use crate::complex_structures::bvh::BVHTree;
-use crate::io::Input;
+use crate::complex_structures::grid::Grid;
+use crate::complex_structures::kd_tree::KdTree;
+use crate::complex_structures::BoundingBox;
 use crate::ray_tracer::material::Material;
 
 pub(crate) struct LinearTracer {
@@ -17,12 +27,6 @@ impl LinearTracer {
     pub(crate) fn new(objects: Vec<Object>) -> LinearTracer {
         LinearTracer { objects }
     }
-
-    pub(crate) fn from_obj_file(path: PathBuf) -> anyhow::Result<(LinearTracer, Vec<Material>)> {
-        let loader = crate::io::obj_file::ObjectFile::new(path);
-        let (objects, materials) = loader.load()?;
-        Ok((LinearTracer::new(objects), materials))
-    }
 }
 
 impl ObjectContainer for LinearTracer {
@@ -34,14 +38,258 @@ impl ObjectContainer for LinearTracer {
             .min_by(|&(_, a), &(_, b)| a.distance().total_cmp(&b.distance()))
     }
 
-    fn object_by_index(&self, index: usize) -> &Object {
-        &self.objects[index]
+    fn occluded(&self, ray: &Ray, max_distance: f64) -> bool {
+        self.objects
+            .iter()
+            .any(|object| object.occluded(ray, max_distance))
+    }
+
+    fn normal_at_point(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.objects[index].normal_at_point(point, intersection, time)
+    }
+
+    fn geometric_normal(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        self.objects[index].geometric_normal(point, intersection, time)
+    }
+
+    fn material_at(&self, index: usize, intersection: Intersection) -> usize {
+        self.objects[index].material_at(intersection)
+    }
+
+    fn texture_coordinates(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Option<(f64, f64)> {
+        self.objects[index].texture_coordinates(point, intersection, time)
+    }
+
+    fn describe(&self, index: usize, intersection: Intersection) -> String {
+        self.objects[index].describe(intersection)
+    }
+
+    fn objects_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    fn total_primitives_count(&self) -> usize {
+        self.objects.iter().map(Object::primitives_count).sum()
+    }
+
+    fn total_nodes_count(&self) -> usize {
+        self.objects.iter().map(Object::nodes_count).sum()
     }
+
+    fn nodes_count(&self) -> usize {
+        0
+    }
+
+    fn name(&self) -> &'static str {
+        "Linear"
+    }
+
+    fn bounding_box(&self) -> AlighnedBox {
+        self.objects
+            .iter()
+            .fold(AlighnedBox::default(), |acc, object| {
+                acc.union(&object.bounding_box())
+            })
+    }
+
+    fn transform_object(&mut self, index: usize, transformation: Transformation) {
+        self.objects[index].transform(transformation);
+    }
+}
+
+// Objects without the finite bounds, like the infinite planes, can't go into the trees,
+// they are tested one by one next to them. Their indices follow the ones of the tree.
+struct WithUnbounded {
+    bounded: Box<dyn ObjectContainer>,
+    unbounded: LinearTracer,
 }
 
+impl WithUnbounded {
+    // Container of the object and its index inside of it.
+    fn part(&self, index: usize) -> (&dyn ObjectContainer, usize) {
+        match index.checked_sub(self.bounded.objects_count()) {
+            Some(index) => (&self.unbounded, index),
+            None => (self.bounded.as_ref(), index),
+        }
+    }
+
+    fn closest(
+        &self,
+        bounded: Option<(usize, Intersection)>,
+        unbounded: Option<(usize, Intersection)>,
+    ) -> Option<(usize, Intersection)> {
+        let unbounded = unbounded.map(|(i, hit)| (i + self.bounded.objects_count(), hit));
+        match (bounded, unbounded) {
+            (Some(a), Some(b)) if b.1.distance() < a.1.distance() => Some(b),
+            (Some(a), _) => Some(a),
+            (None, b) => b,
+        }
+    }
+}
+
+impl ObjectContainer for WithUnbounded {
+    fn trace(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        self.closest(self.bounded.trace(ray), self.unbounded.trace(ray))
+    }
+
+    fn trace_packet(&self, packet: &RayPacket) -> [Option<(usize, Intersection)>; 4] {
+        let bounded = self.bounded.trace_packet(packet);
+        let unbounded = self.unbounded.trace_packet(packet);
+        std::array::from_fn(|lane| self.closest(bounded[lane], unbounded[lane]))
+    }
+
+    fn occluded(&self, ray: &Ray, max_distance: f64) -> bool {
+        self.bounded.occluded(ray, max_distance) || self.unbounded.occluded(ray, max_distance)
+    }
+
+    fn normal_at_point(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        let (part, index) = self.part(index);
+        part.normal_at_point(index, point, intersection, time)
+    }
+
+    fn geometric_normal(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Normal {
+        let (part, index) = self.part(index);
+        part.geometric_normal(index, point, intersection, time)
+    }
+
+    fn material_at(&self, index: usize, intersection: Intersection) -> usize {
+        let (part, index) = self.part(index);
+        part.material_at(index, intersection)
+    }
+
+    fn texture_coordinates(
+        &self,
+        index: usize,
+        point: &Point,
+        intersection: Intersection,
+        time: f64,
+    ) -> Option<(f64, f64)> {
+        let (part, index) = self.part(index);
+        part.texture_coordinates(index, point, intersection, time)
+    }
+
+    fn describe(&self, index: usize, intersection: Intersection) -> String {
+        let (part, index) = self.part(index);
+        part.describe(index, intersection)
+    }
+
+    fn objects_count(&self) -> usize {
+        self.bounded.objects_count() + self.unbounded.objects_count()
+    }
+
+    fn unique_objects_count(&self) -> usize {
+        self.bounded.unique_objects_count() + self.unbounded.unique_objects_count()
+    }
+
+    fn total_primitives_count(&self) -> usize {
+        self.bounded.total_primitives_count() + self.unbounded.total_primitives_count()
+    }
+
+    fn total_nodes_count(&self) -> usize {
+        self.bounded.total_nodes_count() + self.unbounded.total_nodes_count()
+    }
+
+    fn nodes_count(&self) -> usize {
+        self.bounded.nodes_count()
+    }
+
+    fn name(&self) -> &'static str {
+        self.bounded.name()
+    }
+
+    // Only the finite part, the camera frames the model and not the ground.
+    fn bounding_box(&self) -> AlighnedBox {
+        self.bounded.bounding_box()
+    }
+
+    fn transform_object(&mut self, index: usize, transformation: Transformation) {
+        match index.checked_sub(self.bounded.objects_count()) {
+            Some(index) => self.unbounded.transform_object(index, transformation),
+            None => self.bounded.transform_object(index, transformation),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum Tracing {
     Linear,
     Bvh,
+    Bvh4,
+    KdTree,
+    Grid,
+}
+
+impl Tracing {
+    pub(crate) fn container(
+        self,
+        objects: Vec<Object>,
+        builder: BvhBuilder,
+    ) -> Box<dyn ObjectContainer> {
+        let (objects, unbounded): (Vec<_>, Vec<_>) = objects
+            .into_iter()
+            .partition(|object| object.bounding_box().is_bounded());
+        let container: Box<dyn ObjectContainer> = match self {
+            Tracing::Linear => Box::new(LinearTracer::new(objects)),
+            Tracing::Bvh => Box::new(BVHTree::new(objects, builder)),
+            Tracing::Bvh4 => Box::new(Bvh4::new(objects, builder)),
+            Tracing::KdTree => Box::new(KdTree::new(objects)),
+            Tracing::Grid => Box::new(Grid::new(objects)),
+        };
+        if unbounded.is_empty() {
+            container
+        } else {
+            Box::new(WithUnbounded {
+                bounded: container,
+                unbounded: LinearTracer::new(unbounded),
+            })
+        }
+    }
+}
+
+// `linear`, `bvh`, `bvh4`, `kdtree` or `grid`.
+impl FromStr for Tracing {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Tracing> {
+        match s {
+            "linear" => Ok(Tracing::Linear),
+            "bvh" => Ok(Tracing::Bvh),
+            "bvh4" => Ok(Tracing::Bvh4),
+            "kdtree" => Ok(Tracing::KdTree),
+            "grid" => Ok(Tracing::Grid),
+            _ => Err(anyhow!("Unknown acceleration structure {}", s)),
+        }
+    }
 }
 
 pub(crate) struct Scene {
@@ -59,25 +307,6 @@ impl Scene {
         }
     }
 
-    pub(crate) fn from_obj_file(path: PathBuf, t: Tracing) -> anyhow::Result<Scene> {
-        let (container, materials): (Box<dyn ObjectContainer>, Vec<Material>) = match t {
-            Tracing::Linear => {
-                let (container, materials) = LinearTracer::from_obj_file(path)?;
-                (Box::new(container), materials)
-            }
-            Tracing::Bvh => {
-                let (container, materials) = BVHTree::from_obj_file(path)?;
-                (Box::new(container), materials)
-            }
-        };
-
-        Ok(Scene {
-            objects: container,
-            lights: Vec::new(),
-            materials,
-        })
-    }
-
     pub(crate) fn add_light(&mut self, light: Light) {
         self.lights.push(light);
     }
@@ -90,6 +319,10 @@ impl Scene {
         self.objects.as_ref()
     }
 
+    pub(crate) fn objects_mut(&mut self) -> &mut dyn ObjectContainer {
+        self.objects.as_mut()
+    }
+
     pub(crate) fn materials(&self, id: usize) -> &Material {
         &self.materials[id]
     }
@@ -1,4 +1,4 @@
-use crate::basic_geometry::{point::Point, Transform, Transformation};
+use crate::basic_geometry::{point::Point, transform::Transform3, Transform, Transformation};
 
 pub(crate) struct ViewFrame {
     origin: Point,
@@ -15,18 +15,35 @@ impl ViewFrame {
         }
     }
 
+    pub(crate) fn origin(&self) -> Point {
+        self.origin
+    }
+
+    pub(crate) fn width(&self) -> f64 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> f64 {
+        self.height
+    }
+
+    pub(crate) fn resize(&mut self, width: f64, height: f64) {
+        self.width = width;
+        self.height = height;
+    }
+
     pub(crate) fn point_on_pixel(
         &self,
-        x: usize,
-        y: usize,
+        x: f64,
+        y: f64,
         image_width: usize,
         image_height: usize,
     ) -> Point {
         let x_factor = self.width / (image_width as f64);
         let y_factor = self.height / (image_height as f64);
 
-        let x_offset = x as f64 * x_factor;
-        let y_offset = y as f64 * y_factor;
+        let x_offset = x * x_factor;
+        let y_offset = y * y_factor;
         Point::new(
             self.origin.x - self.width / 2.0 + x_offset,
             self.origin.y - self.height / 2.0 + y_offset,
@@ -37,8 +54,7 @@ impl ViewFrame {
 
 impl Transform for ViewFrame {
     fn transform(&mut self, transform: Transformation) {
-        let matrix = transform.transformation_to_matrix();
-        self.origin = matrix * self.origin;
+        self.origin = Transform3::from(transform).point(self.origin);
 
         if let Transformation::Scale(scale) = transform {
             self.width *= scale.x;
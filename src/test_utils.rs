@@ -0,0 +1,5 @@
+use crate::basic_geometry::vector::Vector;
+
+pub(crate) fn close(a: Vector, b: Vector) -> bool {
+    (a - b).length() < 1e-9
+}